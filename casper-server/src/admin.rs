@@ -0,0 +1,229 @@
+use std::collections::HashMap;
+
+use anyhow::{bail, Result};
+use ntex::http::StatusCode;
+use ntex::web::types::{Json, Path, State};
+use ntex::web::{self, HttpResponse, ServiceConfig};
+use serde_json::json;
+use tracing::{error, warn};
+
+use crate::config::Config;
+use crate::context::AppContext;
+use crate::middleware::auth::{normalize_path, path_matches};
+use crate::storage::Storage;
+
+/// Checks that the admin endpoints (if enabled) are protected by an `auth` route
+pub(crate) fn validate_config(config: &Config) -> Result<()> {
+    let Some(admin) = &config.admin else {
+        return Ok(());
+    };
+    let routes = config
+        .auth
+        .as_ref()
+        .map(|a| &a.routes[..])
+        .unwrap_or_default();
+    let path = normalize_path(&admin.path);
+    if !routes.iter().any(|r| path_matches(&path, &r.path)) {
+        bail!(
+            "admin endpoints `{}` must be protected by an `auth` route",
+            admin.path
+        );
+    }
+    Ok(())
+}
+
+/// Registers admin endpoints under the `path` prefix
+pub(crate) fn configure(path: &str, cfg: &mut ServiceConfig) {
+    let path = path.trim_end_matches('/');
+    cfg.route(
+        &format!("{path}/storage/{{name}}/reconnect"),
+        web::post().to(reconnect_storage),
+    );
+    cfg.route(&format!("{path}/routes"), web::get().to(get_routes));
+    cfg.route(&format!("{path}/routes"), web::put().to(replace_routes));
+}
+
+/// Returns the current routing table
+async fn get_routes(app_ctx: State<AppContext>) -> HttpResponse {
+    let routes = app_ctx.routes().snapshot();
+    HttpResponse::Ok().json(&routes.iter().collect::<HashMap<_, _>>())
+}
+
+/// Atomically replaces the routing table (shared between workers) with the new one
+async fn replace_routes(
+    routes: Json<HashMap<String, String>>,
+    app_ctx: State<AppContext>,
+) -> HttpResponse {
+    match app_ctx.routes().replace(routes.into_inner()) {
+        Ok(()) => get_routes(app_ctx).await,
+        Err(err) => {
+            warn!("Failed to replace routes: {err:#}");
+            HttpResponse::BadRequest().json(&json!({
+                "error": "invalid routes",
+            }))
+        }
+    }
+}
+
+/// Forces reconnection of the storage backend and returns its status
+async fn reconnect_storage(name: Path<String>, app_ctx: State<AppContext>) -> HttpResponse {
+    let name = name.into_inner();
+    let Some(backend) = app_ctx.storage_backend(&name) else {
+        return HttpResponse::NotFound().json(&json!({
+            "name": name,
+            "error": "storage not found",
+        }));
+    };
+
+    let result = backend.reconnect().await;
+    let status = if result.is_ok() { "ok" } else { "error" };
+    storage_reconnect_counter_add!(1, "name" => name.clone(), "status" => status);
+
+    let connected = backend.is_connected();
+    match result {
+        Ok(()) => HttpResponse::Ok().json(&json!({
+            "name": name,
+            "connected": connected,
+        })),
+        Err(err) => {
+            error!("Failed to reconnect storage '{name}': {err:?}");
+            HttpResponse::build(StatusCode::SERVICE_UNAVAILABLE).json(&json!({
+                "name": name,
+                "connected": connected,
+                "error": "reconnect failed",
+            }))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use ntex::http::StatusCode;
+    use ntex::web::{test, App};
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::storage::Backend;
+
+    #[test]
+    fn test_validate_config() {
+        let config: Config = serde_yaml::from_str("admin: {}").unwrap();
+        assert!(validate_config(&config).is_err());
+
+        let config: Config = serde_yaml::from_str(
+            r#"
+            admin:
+              path: /admin
+            auth:
+              routes:
+                - path: /admin
+                  api_key:
+                    keys: ["key"]
+        "#,
+        )
+        .unwrap();
+        assert!(validate_config(&config).is_ok());
+
+        // A route with a common prefix does not protect the admin endpoints
+        let config: Config = serde_yaml::from_str(
+            r#"
+            admin:
+              path: /administrator
+            auth:
+              routes:
+                - path: /admin
+                  api_key:
+                    keys: ["key"]
+        "#,
+        )
+        .unwrap();
+        assert!(validate_config(&config).is_err());
+    }
+
+    #[ntex::test]
+    async fn test_routes() {
+        let config: Config = serde_yaml::from_str("routes: { /api: http://api }").unwrap();
+        let context = AppContext::builder()
+            .with_config(Arc::new(config))
+            .build()
+            .unwrap();
+        let routes = context.routes().clone();
+
+        let app = test::init_service(
+            App::new()
+                .state(context)
+                .configure(|cfg| configure("/admin", cfg)),
+        )
+        .await;
+
+        let req = test::TestRequest::get().uri("/admin/routes").to_request();
+        let body: Value = test::read_response_json(&app, req).await;
+        assert_eq!(body, json!({"/api": "http://api"}));
+
+        let req = test::TestRequest::put()
+            .uri("/admin/routes")
+            .set_json(&json!({"/api/v2": "http://api2"}))
+            .to_request();
+        let body: Value = test::read_response_json(&app, req).await;
+        assert_eq!(body, json!({"/api/v2": "http://api2"}));
+        assert_eq!(
+            routes.snapshot().lookup("/api/v2/a"),
+            Some(("/api/v2", "http://api2"))
+        );
+        assert_eq!(routes.snapshot().lookup("/api"), None);
+
+        // Invalid routes
+        let req = test::TestRequest::put()
+            .uri("/admin/routes")
+            .set_json(&json!({"api": "http://api"}))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        let body: Value = serde_json::from_slice(&test::read_body(resp).await).unwrap();
+        assert_eq!(body, json!({"error": "invalid routes"}));
+    }
+
+    #[ntex::test]
+    async fn test_reconnect_storage() {
+        let backend_config = json!({
+            "backend": "memory",
+            "max_size": 1024,
+        });
+        let backend = Backend::new("memory".to_string(), backend_config).unwrap();
+        let context = AppContext::builder()
+            .with_config(Arc::new(Config::default()))
+            .with_storage_backends(vec![backend.clone()])
+            .build()
+            .unwrap();
+
+        let app = test::init_service(
+            App::new()
+                .state(context)
+                .configure(|cfg| configure("/admin/", cfg)),
+        )
+        .await;
+
+        // Simulate a backend that lost its connection
+        let Backend::Memory(memory) = &backend else {
+            unreachable!()
+        };
+        memory.disconnect();
+        assert!(!backend.is_connected());
+
+        let req = test::TestRequest::post()
+            .uri("/admin/storage/memory/reconnect")
+            .to_request();
+        let body: Value = test::read_response_json(&app, req).await;
+        assert_eq!(body, json!({"name": "memory", "connected": true}));
+        assert!(backend.is_connected());
+
+        // Unknown storage
+        let req = test::TestRequest::post()
+            .uri("/admin/storage/unknown/reconnect")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    }
+}
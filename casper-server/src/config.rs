@@ -2,11 +2,11 @@ use std::collections::HashMap;
 use std::env;
 use std::fs;
 use std::os::unix::prelude::OsStrExt;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 use anyhow::Result;
 use mlua::{Lua, LuaSerdeExt, Value};
-use serde::Deserialize;
+use serde::{Deserialize, Deserializer};
 
 #[derive(Debug, Deserialize, Default)]
 pub struct Config {
@@ -16,6 +16,13 @@ pub struct Config {
     pub http: HttpConfig,
     pub metrics: Option<MetricsConfig>,
     pub tracing: Option<TracingConfig>,
+    pub auth: Option<AuthConfig>,
+    pub admin: Option<AdminConfig>,
+    pub health: Option<HealthConfig>,
+    pub upstreams: Option<UpstreamsConfig>,
+    /// Initial routing rules (path prefix -> target) exposed to Lua as `core.routes`
+    #[serde(default)]
+    pub routes: HashMap<String, String>,
     #[serde(default)]
     pub storage: HashMap<String, serde_json::Value>,
 }
@@ -33,6 +40,25 @@ pub struct MainConfig {
 
     #[serde(default)]
     pub max_background_tasks: Option<u64>,
+
+    /// Abort startup if any storage backend cannot connect within `storage_connect_timeout`
+    #[serde(default)]
+    pub require_storage_on_start: bool,
+
+    /// Time (in seconds) to wait for storage backends to connect in the strict mode
+    #[serde(
+        default = "MainConfig::default_storage_connect_timeout",
+        deserialize_with = "deserialize_seconds"
+    )]
+    pub storage_connect_timeout: f64,
+
+    /// Time (in seconds) to wait for in-flight requests (and their streamed bodies) to complete
+    /// on `SIGTERM`/`SIGINT`, `SIGQUIT` stops the server immediately
+    #[serde(
+        default = "MainConfig::default_shutdown_timeout",
+        deserialize_with = "deserialize_seconds"
+    )]
+    pub shutdown_timeout: f64,
 }
 
 #[derive(Debug, Deserialize, Default)]
@@ -40,7 +66,71 @@ pub struct HttpConfig {
     pub filters: Vec<Filter>,
     pub handler: Option<LuaCode>,
     pub access_log: Option<LuaCode>,
+    /// Format of the access log: `text` (default) leaves logging to the `access_log` handler,
+    /// `json` also writes one JSON object per request to stdout
+    #[serde(default)]
+    pub access_log_format: LogFormat,
     pub error_log: Option<LuaCode>,
+    /// Maximum length of the request target (URI), longer requests are rejected with `414`
+    pub max_uri_length: Option<usize>,
+    /// Maximum number of requests processed concurrently by a worker, excess requests are
+    /// rejected with `503` (and `Retry-After` header)
+    pub max_concurrent_requests: Option<usize>,
+    /// Reject requests with `Expect: 100-continue` header with `417` instead of sending
+    /// the `100 Continue` interim response
+    #[serde(default)]
+    pub reject_expect: bool,
+    /// `Content-Type` (e.g. `text/plain; charset=utf-8`) to set for proxied and stored responses
+    /// missing one
+    pub default_content_type: Option<String>,
+    /// Add `X-Cache-Age` and `X-Cache-Lifetime` headers (in seconds) to stored responses
+    #[serde(default)]
+    pub cache_debug_headers: bool,
+    /// Merge small chunks of streaming request and response bodies read in Lua into chunks
+    /// of at least this size (in bytes). Chunks are passed through as received by default.
+    pub body_chunk_size: Option<usize>,
+    /// Headers to always strip from responses sent to clients (after all middleware).
+    /// Names are case-insensitive, a trailing `*` matches any suffix (e.g. `X-Internal-*`)
+    #[serde(default)]
+    pub response_header_denylist: Vec<String>,
+    /// Rewrite of the request path applied when proxying to upstreams (can be overridden in Lua)
+    pub upstream_path_rewrite: Option<PathRewriteConfig>,
+    /// Custom pages (by status code) served instead of the default bodies of proxy errors
+    /// (`502`, `503` and `504`)
+    #[serde(default)]
+    pub error_pages: HashMap<u16, ErrorPageConfig>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct ErrorPageConfig {
+    /// Inline page body, `{{ request_id }}` is replaced with the request id
+    pub body: Option<String>,
+    /// Path to a file with the page body (instead of `body`)
+    pub file: Option<PathBuf>,
+    /// Response headers (`Content-Type` is `text/html` by default)
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct PathRewriteConfig {
+    /// Path prefix to remove (only if matches whole path segments)
+    pub strip_prefix: Option<String>,
+    /// Path prefix to prepend
+    pub add_prefix: Option<String>,
+    /// Regular expression to replace the first match of in the path
+    pub regex: Option<String>,
+    /// Replacement for the `regex` match (supports `$1` style capture references)
+    #[serde(default)]
+    pub replacement: String,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    #[default]
+    Text,
+    Json,
 }
 
 #[derive(Debug, Deserialize)]
@@ -57,7 +147,13 @@ pub struct LuaCode {
 #[derive(Clone, Debug, Deserialize)]
 pub struct MetricsConfig {
     pub counters: Option<HashMap<String, MetricCounterConfig>>,
+    pub histograms: Option<HashMap<String, MetricHistogramConfig>>,
+    pub gauges: Option<HashMap<String, MetricGaugeConfig>>,
+    /// Maximum number of distinct label sets per user-defined counter,
+    /// extra ones are reported with `__overflow__` label values
+    pub max_label_values: Option<usize>,
     pub extra_labels: Option<HashMap<String, String>>,
+    pub stats_reporter: Option<StatsReporterConfig>,
 }
 
 #[derive(Clone, Debug, Deserialize)]
@@ -66,6 +162,29 @@ pub struct MetricCounterConfig {
     pub description: Option<String>,
 }
 
+#[derive(Clone, Debug, Deserialize)]
+pub struct MetricHistogramConfig {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    /// Bucket boundaries (latency buckets in seconds by default)
+    pub buckets: Option<Vec<f64>>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct MetricGaugeConfig {
+    pub name: Option<String>,
+    pub description: Option<String>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct StatsReporterConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Interval (in seconds) between two stats log lines
+    #[serde(default = "StatsReporterConfig::default_interval")]
+    pub interval: f64,
+}
+
 #[derive(Clone, Debug, Default, Deserialize)]
 pub struct TracingConfig {
     #[serde(default)]
@@ -75,6 +194,128 @@ pub struct TracingConfig {
     pub mode: Option<String>, // only one value is supported: "firehose"
 }
 
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct AuthConfig {
+    #[serde(default)]
+    pub routes: Vec<AuthRouteConfig>,
+    pub cache_bypass: Option<CacheBypassConfig>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct AuthRouteConfig {
+    /// Path prefix of the protected route
+    pub path: String,
+    pub api_key: Option<ApiKeyAuthConfig>,
+    pub hmac: Option<HmacAuthConfig>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct ApiKeyAuthConfig {
+    /// Request header to read the key from
+    #[serde(default = "ApiKeyAuthConfig::default_header")]
+    pub header: Option<String>,
+    /// Query parameter to read the key from
+    pub query: Option<String>,
+    pub keys: Vec<String>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct HmacAuthConfig {
+    pub secret: String,
+    /// Request header with hex-encoded HMAC-SHA256 signature
+    #[serde(default = "HmacAuthConfig::default_header")]
+    pub header: String,
+    /// Request header with unix timestamp (in seconds) of the request
+    #[serde(default = "HmacAuthConfig::default_timestamp_header")]
+    pub timestamp_header: String,
+    /// Maximum allowed difference (in seconds) between the request timestamp and current time
+    #[serde(default = "HmacAuthConfig::default_max_skew")]
+    pub max_skew: u64,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct CacheBypassConfig {
+    /// Request header to force cache bypass (and fresh upstream fetch) for debugging
+    #[serde(default = "CacheBypassConfig::default_header")]
+    pub header: String,
+    /// Credentials the request must be authenticated with to bypass the cache
+    pub api_key: Option<ApiKeyAuthConfig>,
+    pub hmac: Option<HmacAuthConfig>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct AdminConfig {
+    /// Path prefix of the admin endpoints (must be protected by an `auth` route)
+    #[serde(default = "AdminConfig::default_path")]
+    pub path: String,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct HealthConfig {
+    /// Path of the health check endpoint (pings all storage backends)
+    #[serde(default = "HealthConfig::default_path")]
+    pub path: String,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct UpstreamsConfig {
+    /// Upstream targets allowed to proxy requests to: host names (`*.example.com` matches any
+    /// subdomain), IP addresses or CIDRs.
+    /// Private and link-local addresses must be allowed explicitly (not by a wider network).
+    /// It also applies to the Lua HTTP client and to addresses the host names resolve to.
+    pub allowlist: Vec<String>,
+    /// Upstreams (absolute uris) to open idle connections to when a worker starts
+    #[serde(default)]
+    pub warmup: Vec<String>,
+    /// Number of idle connections to open to each warmup upstream (per worker)
+    #[serde(default = "UpstreamsConfig::default_warmup_connections")]
+    pub warmup_connections: usize,
+    /// Time (in seconds) to wait for a response to a warmup request
+    #[serde(
+        default = "UpstreamsConfig::default_warmup_timeout",
+        deserialize_with = "deserialize_seconds"
+    )]
+    pub warmup_timeout: f64,
+    /// Interval (in seconds) to repeat warmup requests to keep connections from being closed
+    /// as idle, `0` warms up connections only once
+    #[serde(
+        default = "UpstreamsConfig::default_warmup_interval",
+        deserialize_with = "deserialize_seconds"
+    )]
+    pub warmup_interval: f64,
+}
+
+impl UpstreamsConfig {
+    const fn default_warmup_connections() -> usize {
+        2
+    }
+
+    const fn default_warmup_timeout() -> f64 {
+        5.0
+    }
+
+    // Less than the client pool keep-alive (15 seconds)
+    const fn default_warmup_interval() -> f64 {
+        10.0
+    }
+}
+
+/// Deserializes a duration (in seconds), rejecting negative and non-finite values
+pub(crate) fn deserialize_seconds<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de> + Into<f64> + Copy + std::fmt::Display,
+{
+    let secs = T::deserialize(deserializer)?;
+    let value: f64 = secs.into();
+    if !value.is_finite() || value < 0.0 {
+        return Err(serde::de::Error::custom(format!(
+            "invalid duration `{secs}`, must be a non-negative number of seconds"
+        )));
+    }
+    Ok(secs)
+}
+
 pub(crate) fn read_config<P: AsRef<Path> + ?Sized>(path: &P) -> Result<Config> {
     let data = fs::read(path.as_ref())?;
     match path.as_ref().file_name() {
@@ -100,6 +341,9 @@ impl Default for MainConfig {
             workers: Self::default_workers(),
             listen: Self::default_listen(),
             max_background_tasks: None,
+            require_storage_on_start: false,
+            storage_connect_timeout: Self::default_storage_connect_timeout(),
+            shutdown_timeout: Self::default_shutdown_timeout(),
         }
     }
 }
@@ -112,6 +356,58 @@ impl MainConfig {
     fn default_listen() -> String {
         "127.0.0.1:8080".to_string()
     }
+
+    const fn default_storage_connect_timeout() -> f64 {
+        10.0
+    }
+
+    const fn default_shutdown_timeout() -> f64 {
+        30.0
+    }
+}
+
+impl AdminConfig {
+    fn default_path() -> String {
+        "/admin".to_string()
+    }
+}
+
+impl HealthConfig {
+    fn default_path() -> String {
+        "/health".to_string()
+    }
+}
+
+impl StatsReporterConfig {
+    const fn default_interval() -> f64 {
+        60.0
+    }
+}
+
+impl ApiKeyAuthConfig {
+    fn default_header() -> Option<String> {
+        Some("X-Api-Key".to_string())
+    }
+}
+
+impl CacheBypassConfig {
+    fn default_header() -> String {
+        "X-Casper-Bypass".to_string()
+    }
+}
+
+impl HmacAuthConfig {
+    fn default_header() -> String {
+        "X-Signature".to_string()
+    }
+
+    fn default_timestamp_header() -> String {
+        "X-Timestamp".to_string()
+    }
+
+    const fn default_max_skew() -> u64 {
+        300
+    }
 }
 
 fn configure_lua(lua: &Lua) -> Result<()> {
@@ -6,10 +6,15 @@ use std::sync::Arc;
 
 use anyhow::{Context, Result};
 use mlua::{Function, Lua, LuaOptions, StdLib as LuaStdLib, Table};
+use ntex::http::header::HeaderValue;
 
 use crate::config::Config;
-use crate::lua::{self, LuaStorage};
-use crate::storage::{Backend, Storage};
+use crate::http::{ErrorPages, PathRewrite, UpstreamAllowlist};
+use crate::lua::{self, LuaRoutes, LuaStorage};
+use crate::metrics::ActiveCounter;
+use crate::routes::RoutingTable;
+use crate::storage::{Backend, Storage, StorePolicy};
+use crate::types::{BodyChunkSize, CacheDebugHeaders, DefaultContentType};
 
 // TODO: Move to config
 const LUA_THREAD_POOL_SIZE: usize = 1024;
@@ -23,6 +28,7 @@ pub struct AppContext(Rc<AppContextInner>);
 pub struct AppContextBuilder {
     config: Arc<Config>,
     storage_backends: Vec<Backend>,
+    routes: Option<RoutingTable>,
 }
 
 pub struct Filter {
@@ -41,7 +47,11 @@ pub struct AppContextInner {
     pub access_log: Option<Function>,
     pub error_log: Option<Function>,
 
+    /// Number of requests being processed by this worker
+    pub active_requests: ActiveCounter,
+
     storage_backends: Vec<Backend>,
+    routes: RoutingTable,
 }
 
 impl Deref for AppContext {
@@ -73,10 +83,21 @@ impl AppContextBuilder {
         self
     }
 
+    /// Sets the routing table shared between workers (otherwise created from the config)
+    pub fn with_routes(mut self, routes: RoutingTable) -> Self {
+        self.routes = Some(routes);
+        self
+    }
+
     pub fn build(self) -> Result<AppContext> {
         let storage_backends = self.storage_backends;
+        let routes = match self.routes {
+            Some(routes) => routes,
+            None => RoutingTable::new(self.config.routes.clone())?,
+        };
 
-        AppContextInner::new(self.config, storage_backends).map(|inner| AppContext(Rc::new(inner)))
+        AppContextInner::new(self.config, storage_backends, routes)
+            .map(|inner| AppContext(Rc::new(inner)))
     }
 }
 
@@ -84,6 +105,21 @@ impl AppContext {
     pub fn builder() -> AppContextBuilder {
         AppContextBuilder::new()
     }
+
+    /// Returns all storage backends
+    pub fn storage_backends(&self) -> &[Backend] {
+        &self.storage_backends
+    }
+
+    /// Returns storage backend by name
+    pub fn storage_backend(&self, name: &str) -> Option<&Backend> {
+        self.storage_backends.iter().find(|b| b.name() == name)
+    }
+
+    /// Returns the routing table
+    pub fn routes(&self) -> &RoutingTable {
+        &self.routes
+    }
 }
 
 impl Drop for AppContextInner {
@@ -93,7 +129,11 @@ impl Drop for AppContextInner {
 }
 
 impl AppContextInner {
-    fn new(config: Arc<Config>, storage_backends: Vec<Backend>) -> Result<Self> {
+    fn new(
+        config: Arc<Config>,
+        storage_backends: Vec<Backend>,
+        routes: RoutingTable,
+    ) -> Result<Self> {
         let lua_options = LuaOptions::new().thread_pool_size(LUA_THREAD_POOL_SIZE);
         let lua = Lua::new_with(LuaStdLib::ALL_SAFE, lua_options)
             .with_context(|| "Failed to create Lua instance")?;
@@ -106,7 +146,9 @@ impl AppContextInner {
             handler: None,
             access_log: None,
             error_log: None,
+            active_requests: ActiveCounter::default(),
             storage_backends,
+            routes,
         };
 
         Self::init_lua(&mut worker_ctx)
@@ -134,11 +176,60 @@ impl AppContextInner {
 
         // Create storage backends
         let storage = lua.create_table()?;
-        for backend in self.storage_backends.drain(..) {
-            storage.set(backend.name(), LuaStorage::new(backend))?;
+        for backend in self.storage_backends.iter().cloned() {
+            let name = backend.name();
+            let store_policy = match self.config.storage.get(&name) {
+                Some(conf) => StorePolicy::from_config(conf)
+                    .with_context(|| format!("invalid store policy for storage `{name}`"))?,
+                None => StorePolicy::default(),
+            };
+            storage.set(
+                name,
+                LuaStorage::new(backend).with_store_policy(store_policy),
+            )?;
         }
         core.set("storage", storage)?;
 
+        // Attach routing table
+        core.set("routes", LuaRoutes::new(self.routes.clone()))?;
+
+        // Set default content type for responses missing one
+        if let Some(content_type) = &self.config.http.default_content_type {
+            let content_type = HeaderValue::from_str(content_type)
+                .with_context(|| format!("invalid default content type `{content_type}`"))?;
+            lua.set_app_data(DefaultContentType(content_type));
+        }
+
+        // Expose age and freshness lifetime of stored responses
+        if self.config.http.cache_debug_headers {
+            lua.set_app_data(CacheDebugHeaders);
+        }
+
+        // Merge small chunks of streaming bodies read in Lua
+        if let Some(chunk_size) = self.config.http.body_chunk_size.filter(|&size| size > 0) {
+            lua.set_app_data(BodyChunkSize(chunk_size));
+        }
+
+        // Restrict upstream targets
+        if let Some(upstreams) = &self.config.upstreams {
+            let allowlist = UpstreamAllowlist::new(&upstreams.allowlist)
+                .context("invalid upstreams allowlist")?;
+            lua.set_app_data(allowlist);
+        }
+
+        // Rewrite upstream request paths
+        if let Some(rewrite) = &self.config.http.upstream_path_rewrite {
+            let rewrite = PathRewrite::new(rewrite).context("invalid upstream path rewrite")?;
+            lua.set_app_data(rewrite);
+        }
+
+        // Custom pages of proxy errors
+        if !self.config.http.error_pages.is_empty() {
+            let error_pages =
+                ErrorPages::new(&self.config.http.error_pages).context("invalid error pages")?;
+            lua.set_app_data(error_pages);
+        }
+
         // Start task scheduler
         let max_background_tasks = self.config.main.max_background_tasks;
         lua::tasks::start_task_scheduler(lua, max_background_tasks);
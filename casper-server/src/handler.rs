@@ -1,19 +1,34 @@
+use std::cell::Cell;
 use std::collections::HashMap;
+use std::error::Error as StdError;
+use std::future::Future;
+use std::mem;
 use std::ops::Deref;
-use std::time::Instant;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
 
 use anyhow::{anyhow, Result};
 use mlua::Value;
-use ntex::http::StatusCode;
+use ntex::http::body::{BodySize, MessageBody};
+use ntex::http::header::{HeaderMap, HeaderValue, CONTENT_LENGTH, RETRY_AFTER, TRANSFER_ENCODING};
+use ntex::http::{StatusCode, Uri};
+use ntex::util::Bytes;
 use ntex::web::error::InternalError;
 use ntex::web::types::State;
 use opentelemetry::{Key as OTKey, Value as OTValue};
+use pin_project_lite::pin_project;
 use scopeguard::defer;
 use tracing::{error, instrument};
 
 use crate::context::AppContext;
+use crate::lua::storage::with_cache_bypass;
 use crate::lua::{LuaBody, LuaRequest, LuaResponse};
-use crate::types::LuaContext;
+use crate::metrics::ActiveCounterGuard;
+use crate::types::{CacheBypassExt, LabelsExt, LuaContext};
+
+/// `Retry-After` value (in seconds) of responses to requests rejected due to overload
+const RETRY_AFTER_SECS: &str = "1";
 
 #[instrument(skip_all, fields(method = %req.method(), uri = %req.uri(), host = %req.host()))]
 pub(crate) async fn handler(
@@ -21,9 +36,21 @@ pub(crate) async fn handler(
     app_ctx: State<AppContext>,
 ) -> Result<LuaResponse, InternalError<anyhow::Error>> {
     let start = Instant::now();
-    let _req_guard = active_request_guard!();
+    let req_guard = active_request_guard!();
     let lua = &app_ctx.lua;
 
+    // Shed load when the worker is already processing too many requests
+    let overloaded = (app_ctx.config.http.max_concurrent_requests)
+        .is_some_and(|max| app_ctx.active_requests.get() >= max as u64);
+    if overloaded {
+        rejected_requests_counter_add!(1, "reason" => "overloaded");
+        let mut resp = LuaResponse::new(LuaBody::Bytes("Service Unavailable".into()));
+        *resp.status_mut() = StatusCode::SERVICE_UNAVAILABLE;
+        (resp.headers_mut()).insert(RETRY_AFTER, HeaderValue::from_static(RETRY_AFTER_SECS));
+        return Ok(resp);
+    }
+    let _worker_req_guard = app_ctx.active_requests.inc();
+
     // Create labels container for metrics
     let mut attrs_map: HashMap<OTKey, OTValue> = HashMap::new();
     attrs_map.insert("method".into(), req.method().to_string().into());
@@ -31,8 +58,34 @@ pub(crate) async fn handler(
     // Create Lua context table
     let lua_ctx = LuaContext::new(lua);
 
-    // Execute inner handler to get response
-    let mut resp_result = handler_inner(req, app_ctx, &lua_ctx).await;
+    // Time spent executing Lua code (excluding awaited I/O)
+    let cpu_time = Cell::new(Duration::ZERO);
+
+    // Authenticated requests can be marked (by the `Auth` middleware) to bypass the cache
+    let cache_bypass = req
+        .orig_req()
+        .is_some_and(|req| req.extensions().contains::<CacheBypassExt>());
+
+    // Reject too long URIs and requests with ambiguous framing before running Lua code
+    // Otherwise execute inner handler to get response
+    let mut resp_result = match app_ctx.config.http.max_uri_length {
+        Some(max_len) if request_target_len(req.uri()) > max_len => {
+            rejected_requests_counter_add!(1, "reason" => "uri_too_long");
+            let mut resp = LuaResponse::new(LuaBody::Bytes("URI Too Long".into()));
+            *resp.status_mut() = StatusCode::URI_TOO_LONG;
+            Ok(resp)
+        }
+        _ if has_ambiguous_framing(req.headers()) => {
+            rejected_requests_counter_add!(1, "reason" => "ambiguous_framing");
+            let mut resp = LuaResponse::new(LuaBody::Bytes("Bad Request".into()));
+            *resp.status_mut() = StatusCode::BAD_REQUEST;
+            Ok(resp)
+        }
+        _ if cache_bypass => {
+            with_cache_bypass(handler_inner(req, app_ctx, &lua_ctx, &cpu_time)).await
+        }
+        _ => handler_inner(req, app_ctx, &lua_ctx, &cpu_time).await,
+    };
 
     // Collect response labels
     match resp_result {
@@ -43,10 +96,16 @@ pub(crate) async fn handler(
             attrs_map.insert("status".into(), (resp.status().as_u16() as i64).into());
             // Read labels set by Lua and attach them
             if let Some(lua_labels) = resp.take_labels() {
-                for (k, v) in lua_labels {
-                    attrs_map.insert(k, v);
+                for (k, v) in &lua_labels {
+                    attrs_map.insert(k.clone(), v.clone());
                 }
+                // Keep them for the access log
+                resp.extensions_mut().insert(LabelsExt(lua_labels));
             }
+            if cache_bypass {
+                attrs_map.insert("cache_status".into(), "bypass".into());
+            }
+            guard_streamed_body(resp, req_guard);
         }
         Err(ref err) => {
             attrs_map.insert("status".into(), 0.into());
@@ -55,14 +114,124 @@ pub(crate) async fn handler(
     }
     requests_counter_inc!(attrs_map);
     requests_histogram_rec!(start, attrs_map);
+    // Rejected requests do not run Lua code
+    if !cpu_time.get().is_zero() {
+        lua_handler_cpu_histogram_rec!(cpu_time.get(), attrs_map);
+    }
 
     resp_result.map_err(|err| InternalError::new(err, StatusCode::INTERNAL_SERVER_ERROR))
 }
 
+/// Returns length of the request target as it was sent by client (origin or absolute form)
+fn request_target_len(uri: &Uri) -> usize {
+    let scheme_len = uri.scheme_str().map(|s| s.len() + 3).unwrap_or(0); // "://"
+    let authority_len = uri.authority().map(|a| a.as_str().len()).unwrap_or(0);
+    let path_and_query_len = uri
+        .path_and_query()
+        .map(|pq| pq.as_str().len())
+        .unwrap_or(0);
+    scheme_len + authority_len + path_and_query_len
+}
+
+/// Checks if the request body framing is ambiguous and can be interpreted differently
+/// by upstream services (request smuggling).
+///
+/// This is the case when both `Content-Length` and `Transfer-Encoding` are present,
+/// or `Content-Length` values are invalid or conflicting.
+fn has_ambiguous_framing(headers: &HeaderMap) -> bool {
+    if headers.contains_key(CONTENT_LENGTH) && headers.contains_key(TRANSFER_ENCODING) {
+        return true;
+    }
+    let mut content_length = None;
+    for value in headers.get_all(CONTENT_LENGTH) {
+        let Ok(value) = value.to_str() else {
+            return true;
+        };
+        // Values can be also comma-separated
+        for len in value.split(',').map(|len| len.trim()) {
+            if len.is_empty() || !len.bytes().all(|b| b.is_ascii_digit()) {
+                return true;
+            }
+            if content_length.is_some_and(|cl| cl != len) {
+                return true;
+            }
+            content_length = Some(len);
+        }
+    }
+    false
+}
+
+/// Keeps the request counted as active until its streamed body is sent (to drain it on shutdown)
+fn guard_streamed_body(resp: &mut LuaResponse, guard: ActiveCounterGuard) {
+    let body = LuaBody::from(mem::take(resp.body_mut()));
+    let body = match body {
+        LuaBody::None | LuaBody::Bytes(_) => body,
+        body => {
+            let (timeout, max_size) = (body.timeout(), body.max_size());
+            let body = Box::new(GuardedBody {
+                body,
+                _guard: guard,
+            });
+            LuaBody::Body {
+                body,
+                timeout,
+                max_size,
+            }
+        }
+    };
+    *resp.body_mut() = body.into();
+}
+
+struct GuardedBody {
+    body: LuaBody,
+    _guard: ActiveCounterGuard,
+}
+
+impl MessageBody for GuardedBody {
+    fn size(&self) -> BodySize {
+        self.body.size()
+    }
+
+    fn poll_next_chunk(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Bytes, Box<dyn StdError>>>> {
+        self.body.poll_next_chunk(cx)
+    }
+}
+
+pin_project! {
+    /// Future that accumulates time spent polling the inner future.
+    ///
+    /// Time spent waiting for the inner future to be woken up (e.g. awaiting I/O) is excluded.
+    struct PollTimed<'a, F> {
+        #[pin]
+        inner: F,
+        elapsed: &'a Cell<Duration>,
+    }
+}
+
+impl<F: Future> Future for PollTimed<'_, F> {
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let start = Instant::now();
+        let result = this.inner.poll(cx);
+        this.elapsed.set(this.elapsed.get() + start.elapsed());
+        result
+    }
+}
+
+fn poll_timed<F: Future>(inner: F, elapsed: &Cell<Duration>) -> PollTimed<'_, F> {
+    PollTimed { inner, elapsed }
+}
+
 pub(crate) async fn handler_inner(
     req: LuaRequest,
     app_ctx: State<AppContext>,
     lua_ctx: &LuaContext,
+    cpu_time: &Cell<Duration>,
 ) -> Result<LuaResponse> {
     let lua = app_ctx.lua.clone();
 
@@ -83,10 +252,8 @@ pub(crate) async fn handler_inner(
             filter_histogram_rec!(start, "name" => name.clone(), "phase" => "on_request");
         }
 
-        match on_request
-            .call_async::<Value>((&lua_req, lua_ctx.deref()))
-            .await
-        {
+        let on_request_fut = on_request.call_async::<Value>((&lua_req, lua_ctx.deref()));
+        match poll_timed(on_request_fut, cpu_time).await {
             // Early Response?
             Ok(Value::UserData(resp)) if resp.is::<LuaResponse>() => {
                 early_resp = Some(resp);
@@ -113,20 +280,23 @@ pub(crate) async fn handler_inner(
     // Otherwise call handler function
     let lua_resp = match (early_resp, &app_ctx.handler) {
         (Some(resp), _) => resp,
-        (None, Some(handler)) => match handler.call_async((&lua_req, lua_ctx.deref())).await {
-            Ok(Value::UserData(resp)) if resp.is::<LuaResponse>() => resp,
-            Ok(r) => {
-                handler_error_counter_add!(1);
-                return Err(anyhow!(
-                    "handler error: invalid return type '{}'",
-                    r.type_name()
-                ));
-            }
-            Err(err) => {
-                handler_error_counter_add!(1);
-                return Err(anyhow!("handler panic: {err:#}"));
+        (None, Some(handler)) => {
+            let handler_fut = handler.call_async((&lua_req, lua_ctx.deref()));
+            match poll_timed(handler_fut, cpu_time).await {
+                Ok(Value::UserData(resp)) if resp.is::<LuaResponse>() => resp,
+                Ok(r) => {
+                    handler_error_counter_add!(1);
+                    return Err(anyhow!(
+                        "handler error: invalid return type '{}'",
+                        r.type_name()
+                    ));
+                }
+                Err(err) => {
+                    handler_error_counter_add!(1);
+                    return Err(anyhow!("handler panic: {err:#}"));
+                }
             }
-        },
+        }
         (None, None) => {
             let mut resp = LuaResponse::new(LuaBody::Bytes("Not Found".into()));
             *resp.status_mut() = StatusCode::NOT_FOUND;
@@ -152,10 +322,8 @@ pub(crate) async fn handler_inner(
             filter_histogram_rec!(start, "name" => name.clone(), "phase" => "on_response");
         }
 
-        if let Err(err) = on_response
-            .call_async::<()>((&lua_resp, lua_ctx.deref()))
-            .await
-        {
+        let on_response_fut = on_response.call_async::<()>((&lua_resp, lua_ctx.deref()));
+        if let Err(err) = poll_timed(on_response_fut, cpu_time).await {
             filter_error_counter_add!(1, "name" => name.clone(), "phase" => "on_response");
             return Err(anyhow!("filter '{name}'::on-response error: {err:#}"));
         }
@@ -165,3 +333,263 @@ pub(crate) async fn handler_inner(
 
     Ok(resp)
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use futures::future::join_all;
+    use ntex::http::header::RETRY_AFTER;
+    use ntex::http::StatusCode;
+    use ntex::web::{self, test, App};
+
+    use super::handler;
+    use crate::config::Config;
+    use crate::context::AppContext;
+    use crate::middleware::Auth;
+    use crate::storage::Backend;
+
+    #[ntex::test]
+    async fn test_max_uri_length() {
+        let mut config = Config::default();
+        config.http.max_uri_length = Some(16);
+        let context = AppContext::builder()
+            .with_config(Arc::new(config))
+            .build()
+            .unwrap();
+
+        let app =
+            test::init_service(App::new().state(context).default_service(web::to(handler))).await;
+
+        // Request target within the limit (no handler configured)
+        let req = test::TestRequest::with_uri("/0123456789abcde").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+        // Request target exceeds the limit
+        let req = test::TestRequest::with_uri("/0123456789?abcde").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::URI_TOO_LONG);
+    }
+
+    #[ntex::test]
+    async fn test_max_concurrent_requests() {
+        let config: Config = serde_yaml::from_str(
+            r#"
+            http:
+              filters: []
+              max_concurrent_requests: 2
+              handler:
+                code: |
+                  local core = require("core")
+                  return function(req)
+                    core.sleep(0.1)
+                    return core.Response.new({ body = "ok" })
+                  end
+            "#,
+        )
+        .unwrap();
+        let context = AppContext::builder()
+            .with_config(Arc::new(config))
+            .build()
+            .unwrap();
+
+        let app =
+            test::init_service(App::new().state(context).default_service(web::to(handler))).await;
+
+        let call = || async {
+            let req = test::TestRequest::with_uri("/").to_request();
+            let resp = test::call_service(&app, req).await;
+            let retry_after = resp.headers().get(RETRY_AFTER).cloned();
+            (resp.status(), retry_after)
+        };
+
+        // Excess concurrent requests are rejected
+        let results = join_all((0..4).map(|_| call())).await;
+        let ok = results.iter().filter(|(s, _)| *s == StatusCode::OK);
+        assert_eq!(ok.count(), 2);
+        let rejected = (results.iter())
+            .filter(|(s, _)| *s == StatusCode::SERVICE_UNAVAILABLE)
+            .collect::<Vec<_>>();
+        assert_eq!(rejected.len(), 2);
+        assert!(rejected
+            .iter()
+            .all(|(_, retry_after)| retry_after.is_some()));
+
+        // Slots are released after completion
+        assert_eq!(call().await.0, StatusCode::OK);
+    }
+
+    #[ntex::test]
+    async fn test_ambiguous_framing() {
+        let context = AppContext::builder()
+            .with_config(Arc::new(Config::default()))
+            .build()
+            .unwrap();
+
+        let app =
+            test::init_service(App::new().state(context).default_service(web::to(handler))).await;
+
+        let call = |headers: &[(&'static str, &'static str)]| {
+            let mut req = test::TestRequest::post().uri("/");
+            for &(name, value) in headers {
+                req = req.header(name, value);
+            }
+            let app = &app;
+            async move { test::call_service(app, req.to_request()).await.status() }
+        };
+
+        // Valid framing (no handler configured)
+        assert_eq!(call(&[]).await, StatusCode::NOT_FOUND);
+        assert_eq!(
+            call(&[("content-length", "0")]).await,
+            StatusCode::NOT_FOUND
+        );
+        let headers = [("content-length", "0"), ("content-length", "0")];
+        assert_eq!(call(&headers).await, StatusCode::NOT_FOUND);
+        let headers = [("transfer-encoding", "chunked")];
+        assert_eq!(call(&headers).await, StatusCode::NOT_FOUND);
+
+        // Both Content-Length and Transfer-Encoding
+        let headers = [("content-length", "5"), ("transfer-encoding", "chunked")];
+        assert_eq!(call(&headers).await, StatusCode::BAD_REQUEST);
+
+        // Conflicting or invalid Content-Length
+        let headers = [("content-length", "5"), ("content-length", "6")];
+        assert_eq!(call(&headers).await, StatusCode::BAD_REQUEST);
+        let headers = [("content-length", "5, 6")];
+        assert_eq!(call(&headers).await, StatusCode::BAD_REQUEST);
+        let headers = [("content-length", "-1")];
+        assert_eq!(call(&headers).await, StatusCode::BAD_REQUEST);
+    }
+
+    #[ntex::test]
+    async fn test_cache_bypass() {
+        let config: Config = serde_yaml::from_str(
+            r#"
+            http:
+              filters: []
+              handler:
+                code: |
+                  local core = require("core")
+                  local storage = core.storage.memory
+                  return function(req)
+                    if storage:get_response("key") then
+                      return core.Response.new({ body = "hit" })
+                    end
+                    local resp = core.Response.new({ body = "cached" })
+                    storage:store_response({ key = "key", response = resp, ttl = 60 })
+                    return core.Response.new({ body = "miss" })
+                  end
+            auth:
+              cache_bypass:
+                api_key:
+                  keys: ["secret"]
+        "#,
+        )
+        .unwrap();
+        let auth_config = config.auth.clone();
+        let backend_config = serde_json::json!({"backend": "memory", "max_size": 1000});
+        let backend = Backend::new("memory".to_string(), backend_config).unwrap();
+        let context = AppContext::builder()
+            .with_config(Arc::new(config))
+            .with_storage_backends(vec![backend])
+            .build()
+            .unwrap();
+
+        let app = test::init_service(
+            App::new()
+                .state(context)
+                .wrap(Auth::new(auth_config))
+                .default_service(web::to(handler)),
+        )
+        .await;
+
+        let call = |headers: &[(&'static str, &'static str)]| {
+            let mut req = test::TestRequest::with_uri("/");
+            for &(name, value) in headers {
+                req = req.header(name, value);
+            }
+            let app = &app;
+            async move {
+                let resp = test::call_service(app, req.to_request()).await;
+                assert_eq!(resp.status(), StatusCode::OK);
+                test::read_body(resp).await
+            }
+        };
+
+        assert_eq!(call(&[]).await, "miss");
+        assert_eq!(call(&[]).await, "hit");
+
+        // Bypass header without credentials or with invalid ones is ignored
+        assert_eq!(call(&[("x-casper-bypass", "1")]).await, "hit");
+        let headers = [("x-casper-bypass", "1"), ("x-api-key", "invalid")];
+        assert_eq!(call(&headers).await, "hit");
+
+        // Authenticated request bypasses the cache
+        let headers = [("x-casper-bypass", "1"), ("x-api-key", "secret")];
+        assert_eq!(call(&headers).await, "miss");
+
+        // Credentials without the bypass header do not affect caching
+        assert_eq!(call(&[("x-api-key", "secret")]).await, "hit");
+    }
+
+    #[ntex::test]
+    async fn test_lua_handler_cpu_time() {
+        let config: Config = serde_yaml::from_str(
+            r#"
+            http:
+              filters: []
+              handler:
+                code: |
+                  local core = require("core")
+                  return function(req)
+                    -- I/O bound part is not counted
+                    core.sleep(0.3)
+                    local x = 0
+                    for i = 1, 3000000 do
+                      x = x + i % 7
+                    end
+                    local resp = core.Response.new({ body = tostring(x) })
+                    -- Distinguish the series from other tests running in parallel
+                    resp:set_label("test", "lua_handler_cpu_time")
+                    return resp
+                  end
+        "#,
+        )
+        .unwrap();
+        let context = AppContext::builder()
+            .with_config(Arc::new(config))
+            .build()
+            .unwrap();
+
+        let app =
+            test::init_service(App::new().state(context).default_service(web::to(handler))).await;
+
+        let read_histogram = || {
+            prometheus::default_registry()
+                .gather()
+                .into_iter()
+                .filter(|family| family.get_name() == "lua_handler_cpu_seconds")
+                .flat_map(|mut family| family.take_metric())
+                .filter(|metric| {
+                    (metric.get_label().iter())
+                        .any(|l| l.get_name() == "test" && l.get_value() == "lua_handler_cpu_time")
+                })
+                .map(|mut metric| metric.take_histogram())
+                .map(|h| (h.get_sample_count(), h.get_sample_sum()))
+                .next()
+                .unwrap_or_default()
+        };
+
+        let (count, sum) = read_histogram();
+        let req = test::TestRequest::with_uri("/").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        let (new_count, new_sum) = read_histogram();
+        assert_eq!(new_count, count + 1);
+        let cpu_time = new_sum - sum;
+        assert!(cpu_time > 0.0 && cpu_time < 0.3, "cpu time: {cpu_time}");
+    }
+}
@@ -0,0 +1,82 @@
+use futures::future::join_all;
+use ntex::http::StatusCode;
+use ntex::web::types::State;
+use ntex::web::{self, HttpResponse, ServiceConfig};
+use serde_json::{json, Map, Value};
+use tracing::warn;
+
+use crate::context::AppContext;
+use crate::storage::Storage;
+
+/// Registers the health check endpoint under the `path`
+pub(crate) fn configure(path: &str, cfg: &mut ServiceConfig) {
+    cfg.route(path, web::get().to(health));
+}
+
+/// Pings all storage backends and returns their status and latency.
+///
+/// Responds with `503` if any of the backends is not healthy.
+async fn health(app_ctx: State<AppContext>) -> HttpResponse {
+    let backends = app_ctx.storage_backends();
+    let results = join_all(backends.iter().map(|backend| backend.ping())).await;
+
+    let mut healthy = true;
+    let mut statuses = Map::new();
+    for (backend, result) in backends.iter().zip(results) {
+        let status = match result {
+            Ok(latency) => json!({
+                "ok": true,
+                "latency_ms": latency.as_secs_f64() * 1000.0,
+            }),
+            Err(err) => {
+                healthy = false;
+                // Error details are not exposed to (unauthenticated) callers
+                warn!("Storage '{}' is not healthy: {err:#}", backend.name());
+                json!({"ok": false})
+            }
+        };
+        statuses.insert(backend.name(), status);
+    }
+
+    let status = match healthy {
+        true => StatusCode::OK,
+        false => StatusCode::SERVICE_UNAVAILABLE,
+    };
+    HttpResponse::build(status).json(&Value::Object(statuses))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use ntex::web::{test, App};
+    use serde_json::json;
+
+    use super::*;
+    use crate::config::Config;
+    use crate::storage::Backend;
+
+    #[ntex::test]
+    async fn test_health() {
+        let backend_config = json!({"backend": "memory", "max_size": 1000});
+        let backend = Backend::new("memory".to_string(), backend_config).unwrap();
+        let context = AppContext::builder()
+            .with_config(Arc::new(Config::default()))
+            .with_storage_backends(vec![backend])
+            .build()
+            .unwrap();
+
+        let app = test::init_service(
+            App::new()
+                .state(context)
+                .configure(|cfg| configure("/healthz", cfg)),
+        )
+        .await;
+
+        let req = test::TestRequest::with_uri("/healthz").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let body: Value = serde_json::from_slice(&test::read_body(resp).await).unwrap();
+        assert_eq!(body, json!({"memory": {"ok": true, "latency_ms": 0.0}}));
+    }
+}
@@ -0,0 +1,264 @@
+use std::io;
+use std::net::IpAddr;
+use std::rc::Rc;
+
+use anyhow::{bail, Result};
+use ipnet::IpNet;
+use ntex::connect::openssl::SslConnector;
+use ntex::connect::{Connect, ConnectError, Connector as TcpConnector, Resolver};
+use ntex::http::client::Connector as HttpConnector;
+use ntex::http::Uri;
+use ntex::service::{Service, ServiceCtx};
+use openssl::ssl::{SslConnector as OpenSslConnector, SslMethod, SslVerifyMode};
+
+// Private, loopback, link-local, multicast and other non-routable ranges
+// Requests to them are blocked unless explicitly allowed by a narrower (or the same) pattern
+const BLOCKED_RANGES: [&str; 15] = [
+    "0.0.0.0/8",
+    "10.0.0.0/8",
+    "100.64.0.0/10",
+    "127.0.0.0/8",
+    "169.254.0.0/16",
+    "172.16.0.0/12",
+    "192.168.0.0/16",
+    "198.18.0.0/15",
+    "224.0.0.0/4",
+    "255.255.255.255/32",
+    "::/128",
+    "::1/128",
+    "64:ff9b::/96",
+    "fc00::/7",
+    "fe80::/10",
+];
+
+#[derive(Clone, Debug)]
+enum Pattern {
+    // Exact host name or a wildcard (`*.example.com`) matching any subdomain
+    Host(String),
+    Net(IpNet),
+}
+
+/// Allowlist of upstream targets that Lua code is allowed to proxy requests to
+#[derive(Clone, Debug)]
+pub struct UpstreamAllowlist {
+    patterns: Rc<[Pattern]>,
+    blocked: Rc<[IpNet]>,
+}
+
+impl UpstreamAllowlist {
+    /// Creates a new allowlist from host name, IP address or CIDR patterns.
+    pub fn new(patterns: &[String]) -> Result<Self> {
+        let patterns = patterns
+            .iter()
+            .map(|pattern| {
+                let pattern = pattern.trim().to_ascii_lowercase();
+                if let Ok(net) = pattern.parse::<IpNet>() {
+                    return Ok(Pattern::Net(net));
+                }
+                if let Ok(ip) = pattern.parse::<IpAddr>() {
+                    return Ok(Pattern::Net(IpNet::from(ip)));
+                }
+                let name = pattern.strip_prefix("*.").unwrap_or(&pattern);
+                if name.is_empty() || name.contains(['*', '/', ':']) {
+                    bail!("invalid upstream pattern `{pattern}`");
+                }
+                Ok(Pattern::Host(pattern))
+            })
+            .collect::<Result<_>>()?;
+        let blocked = BLOCKED_RANGES
+            .iter()
+            .map(|net| net.parse().expect("invalid blocked range"))
+            .collect();
+        Ok(UpstreamAllowlist { patterns, blocked })
+    }
+
+    /// Checks that the (absolute) upstream uri is allowed.
+    ///
+    /// Returns an error message if the target is not allowed.
+    pub fn check(&self, uri: &Uri) -> Result<(), String> {
+        let host = uri.host().ok_or("upstream host is missing")?;
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        if self.is_allowed(host) {
+            return Ok(());
+        }
+        Err(format!("upstream `{host}` is not allowed"))
+    }
+
+    /// Makes the HTTP client connector to connect only to allowed addresses
+    /// (see [`AllowlistConnector`]).
+    pub fn guard_connector(&self, connector: HttpConnector) -> HttpConnector {
+        // Same TLS settings as the default connector has
+        let mut ssl =
+            OpenSslConnector::builder(SslMethod::tls()).expect("Failed to create SSL connector");
+        let _ = ssl.set_alpn_protos(b"\x02h2\x08http/1.1");
+        ssl.set_verify(SslVerifyMode::NONE);
+        let ssl_connector = SslConnector::new(ssl.build());
+
+        connector
+            .connector(AllowlistConnector::new(TcpConnector::new(), self.clone()))
+            .secure_connector(AllowlistConnector::new(ssl_connector, self.clone()))
+    }
+
+    /// Checks that the resolved upstream address is not in a blocked range
+    /// (unless the range is explicitly allowed).
+    pub fn check_addr(&self, ip: IpAddr) -> Result<(), String> {
+        let ip = canonical_ip(ip);
+        let Some(blocked) = self.blocked.iter().find(|net| net.contains(&ip)) else {
+            return Ok(());
+        };
+        let allowed = self.patterns.iter().any(|pattern| match pattern {
+            Pattern::Net(net) => net.contains(&ip) && blocked.contains(net),
+            Pattern::Host(_) => false,
+        });
+        match allowed {
+            true => Ok(()),
+            false => Err(format!("upstream address `{ip}` is not allowed")),
+        }
+    }
+
+    fn is_allowed(&self, host: &str) -> bool {
+        let ip = match host.parse::<IpAddr>() {
+            Ok(ip) => canonical_ip(ip),
+            Err(_) => {
+                let host = host.trim_end_matches('.').to_ascii_lowercase();
+                return self.patterns.iter().any(|pattern| match pattern {
+                    Pattern::Host(name) => match name.strip_prefix("*.") {
+                        Some(suffix) => host
+                            .strip_suffix(suffix)
+                            .is_some_and(|prefix| prefix.ends_with('.')),
+                        None => *name == host,
+                    },
+                    Pattern::Net(_) => false,
+                });
+            }
+        };
+
+        let blocked = self.blocked.iter().find(|net| net.contains(&ip));
+        self.patterns.iter().any(|pattern| match pattern {
+            Pattern::Net(net) if net.contains(&ip) => {
+                // Blocked ranges must be allowed explicitly (not by a wider network)
+                blocked.is_none_or(|blocked| blocked.contains(net))
+            }
+            _ => false,
+        })
+    }
+}
+
+/// Converts IPv4-mapped IPv6 addresses to IPv4
+fn canonical_ip(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V6(ip) => (ip.to_ipv4_mapped())
+            .map(IpAddr::V4)
+            .unwrap_or(IpAddr::V6(ip)),
+        ip => ip,
+    }
+}
+
+/// Connector resolving the upstream host name and connecting only to the allowed addresses.
+///
+/// Protects against allowed host names resolving to blocked addresses (e.g. DNS rebinding).
+pub struct AllowlistConnector<S> {
+    inner: S,
+    allowlist: UpstreamAllowlist,
+}
+
+impl<S> AllowlistConnector<S> {
+    pub fn new(inner: S, allowlist: UpstreamAllowlist) -> Self {
+        AllowlistConnector { inner, allowlist }
+    }
+}
+
+impl<S> Service<Connect<Uri>> for AllowlistConnector<S>
+where
+    S: Service<Connect<Uri>, Error = ConnectError>,
+{
+    type Response = S::Response;
+    type Error = ConnectError;
+
+    async fn call(
+        &self,
+        req: Connect<Uri>,
+        ctx: ServiceCtx<'_, Self>,
+    ) -> Result<Self::Response, Self::Error> {
+        let mut req = Resolver::new().lookup(req).await?;
+        let (mut addrs, mut last_err) = (Vec::new(), None);
+        for addr in req.take_addrs() {
+            match self.allowlist.check_addr(addr.ip()) {
+                Ok(()) => addrs.push(addr),
+                Err(err) => last_err = Some(err),
+            }
+        }
+        if let (true, Some(err)) = (addrs.is_empty(), last_err) {
+            rejected_upstreams_counter_add!(1);
+            return Err(io::Error::new(io::ErrorKind::PermissionDenied, err).into());
+        }
+        ctx.call(&self.inner, req.set_addrs(addrs)).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_allowlist() {
+        let patterns = [
+            "api.example.com",
+            "*.internal.io",
+            "0.0.0.0/0",
+            "10.1.0.0/16",
+        ];
+        let allowlist = UpstreamAllowlist::new(&patterns.map(|p| p.to_string())).unwrap();
+        let check = |uri: &str| allowlist.check(&uri.parse::<Uri>().unwrap());
+
+        // Allowed
+        assert!(check("http://api.example.com/path").is_ok());
+        assert!(check("https://API.example.com:8443").is_ok());
+        assert!(check("http://svc.internal.io").is_ok());
+        assert!(check("http://a.b.internal.io").is_ok());
+        assert!(check("http://1.2.3.4:8080").is_ok());
+
+        // Denied
+        assert_eq!(
+            check("http://evil.com").unwrap_err(),
+            "upstream `evil.com` is not allowed"
+        );
+        assert!(check("http://example.com").is_err());
+        assert!(check("http://internal.io").is_err());
+        assert!(check("http://notinternal.io").is_err());
+        assert!(check("http://[2001:db8::1]").is_err()); // no IPv6 networks are allowed
+        assert_eq!(check("/path").unwrap_err(), "upstream host is missing");
+
+        // Private ranges are not allowed by a wider network
+        assert!(check("http://127.0.0.1").is_err());
+        assert!(check("http://169.254.169.254/latest/meta-data").is_err());
+        assert!(check("http://192.168.1.1").is_err());
+        assert!(check("http://10.2.0.1").is_err());
+        assert!(check("http://[::ffff:127.0.0.1]").is_err());
+        // ...but can be allowed explicitly
+        assert!(check("http://10.1.2.3").is_ok());
+
+        // Resolved addresses are checked against blocked ranges only
+        let check_addr = |ip: &str| allowlist.check_addr(ip.parse().unwrap());
+        assert!(check_addr("93.184.216.34").is_ok());
+        assert!(check_addr("10.1.2.3").is_ok()); // explicitly allowed
+        assert_eq!(
+            check_addr("127.0.0.1").unwrap_err(),
+            "upstream address `127.0.0.1` is not allowed"
+        );
+        for ip in [
+            "10.2.0.1",
+            "198.18.0.1",
+            "224.0.0.251",
+            "255.255.255.255",
+            "::ffff:169.254.169.254",
+            "64:ff9b::7f00:1",
+        ] {
+            assert!(check_addr(ip).is_err(), "{ip} must be blocked");
+        }
+
+        // Invalid patterns
+        let err = UpstreamAllowlist::new(&["*.*.com".to_string()]).unwrap_err();
+        assert_eq!(err.to_string(), "invalid upstream pattern `*.*.com`");
+    }
+}
@@ -0,0 +1,143 @@
+use std::fmt;
+
+use serde::Deserialize;
+
+/// `Cache-Control` response directives
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CacheControl {
+    pub max_age: Option<u64>,
+    pub s_maxage: Option<u64>,
+    pub stale_while_revalidate: Option<u64>,
+    pub stale_if_error: Option<u64>,
+    pub public: bool,
+    pub private: bool,
+    pub no_cache: bool,
+    pub no_store: bool,
+    pub no_transform: bool,
+    pub must_revalidate: bool,
+    pub proxy_revalidate: bool,
+    pub immutable: bool,
+}
+
+/// Formats the header value resolving mutually exclusive directives:
+///     - `no-store` disables caching, so all other directives are dropped
+///     - `private` wins over `public` (and drops directives for shared caches)
+///     - `no-cache` requires revalidation, so the response cannot be `immutable`
+impl fmt::Display for CacheControl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.no_store {
+            return f.write_str("no-store");
+        }
+
+        let mut directives = Vec::new();
+        if self.private {
+            directives.push("private".to_string());
+        } else if self.public {
+            directives.push("public".to_string());
+        }
+        let flags = [
+            ("no-cache", self.no_cache),
+            ("no-transform", self.no_transform),
+            ("must-revalidate", self.must_revalidate),
+            ("proxy-revalidate", self.proxy_revalidate && !self.private),
+            ("immutable", self.immutable && !self.no_cache),
+        ];
+        for (name, _) in flags.into_iter().filter(|(_, enabled)| *enabled) {
+            directives.push(name.to_string());
+        }
+        let values = [
+            ("max-age", self.max_age),
+            ("s-maxage", self.s_maxage.filter(|_| !self.private)),
+            ("stale-while-revalidate", self.stale_while_revalidate),
+            ("stale-if-error", self.stale_if_error),
+        ];
+        for (name, value) in values {
+            if let Some(value) = value {
+                directives.push(format!("{name}={value}"));
+            }
+        }
+        f.write_str(&directives.join(", "))
+    }
+}
+
+/// Parses a `Cache-Control` header value into a list of (lowercased) directives
+/// with optional (unquoted) arguments.
+pub fn parse_directives(value: &str) -> Vec<(String, Option<String>)> {
+    value
+        .split(',')
+        .filter_map(|directive| {
+            let (name, arg) = match directive.split_once('=') {
+                Some((name, arg)) => (name, Some(arg.trim().trim_matches('"').to_string())),
+                None => (directive, None),
+            };
+            let name = name.trim().to_ascii_lowercase();
+            (!name.is_empty()).then_some((name, arg))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build() {
+        let cc = |cc: CacheControl| cc.to_string();
+
+        assert_eq!(cc(CacheControl::default()), "");
+        let value = cc(CacheControl {
+            public: true,
+            max_age: Some(60),
+            s_maxage: Some(300),
+            stale_while_revalidate: Some(30),
+            ..Default::default()
+        });
+        assert_eq!(
+            value,
+            "public, max-age=60, s-maxage=300, stale-while-revalidate=30"
+        );
+
+        // `no-store` overrides everything
+        let value = cc(CacheControl {
+            public: true,
+            max_age: Some(60),
+            no_store: true,
+            ..Default::default()
+        });
+        assert_eq!(value, "no-store");
+
+        // `private` wins over `public` and drops shared cache directives
+        let value = cc(CacheControl {
+            public: true,
+            private: true,
+            max_age: Some(60),
+            s_maxage: Some(300),
+            proxy_revalidate: true,
+            ..Default::default()
+        });
+        assert_eq!(value, "private, max-age=60");
+
+        // `no-cache` response is not `immutable`
+        let value = cc(CacheControl {
+            no_cache: true,
+            immutable: true,
+            must_revalidate: true,
+            ..Default::default()
+        });
+        assert_eq!(value, "no-cache, must-revalidate");
+    }
+
+    #[test]
+    fn test_parse_directives() {
+        let directives = parse_directives(r#"Public, max-age=60, , community="UCI""#);
+        assert_eq!(
+            directives,
+            vec![
+                ("public".to_string(), None),
+                ("max-age".to_string(), Some("60".to_string())),
+                ("community".to_string(), Some("UCI".to_string())),
+            ]
+        );
+    }
+}
@@ -0,0 +1,157 @@
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures::Stream;
+use ntex::util::{Bytes, BytesMut};
+use pin_project_lite::pin_project;
+
+pin_project! {
+    /// Stream that merges already available chunks of the inner stream
+    /// until at least `chunk_size` bytes are collected.
+    ///
+    /// It never waits for more data: if the inner stream is not ready,
+    /// the collected bytes are returned immediately.
+    pub struct CoalescedStream<S, E> {
+        #[pin]
+        inner: S,
+        chunk_size: usize,
+        // Error received after collecting some data, returned on the next poll
+        error: Option<E>,
+        done: bool,
+    }
+}
+
+impl<S, E> CoalescedStream<S, E>
+where
+    S: Stream<Item = Result<Bytes, E>>,
+{
+    pub fn new(inner: S, chunk_size: usize) -> Self {
+        CoalescedStream {
+            inner,
+            chunk_size,
+            error: None,
+            done: false,
+        }
+    }
+}
+
+impl<S, E> Stream for CoalescedStream<S, E>
+where
+    S: Stream<Item = Result<Bytes, E>>,
+{
+    type Item = Result<Bytes, E>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+        if let Some(err) = this.error.take() {
+            return Poll::Ready(Some(Err(err)));
+        }
+        if *this.done {
+            return Poll::Ready(None);
+        }
+
+        // The first chunk is kept as is to avoid copying if nothing else is available
+        let mut first: Option<Bytes> = None;
+        let mut buffer = BytesMut::new();
+        loop {
+            let collected = match &first {
+                Some(first) if buffer.is_empty() => first.len(),
+                Some(_) => buffer.len(),
+                None => 0,
+            };
+            if first.is_some() && collected >= *this.chunk_size {
+                break;
+            }
+            match this.inner.as_mut().poll_next(cx) {
+                Poll::Ready(Some(Ok(chunk))) if chunk.is_empty() => {}
+                Poll::Ready(Some(Ok(chunk))) => match &first {
+                    None => first = Some(chunk),
+                    Some(first) => {
+                        if buffer.is_empty() {
+                            buffer.extend_from_slice(first);
+                        }
+                        buffer.extend_from_slice(&chunk);
+                    }
+                },
+                Poll::Ready(Some(Err(err))) => {
+                    if first.is_none() {
+                        return Poll::Ready(Some(Err(err)));
+                    }
+                    *this.error = Some(err);
+                    break;
+                }
+                Poll::Ready(None) => {
+                    *this.done = true;
+                    break;
+                }
+                Poll::Pending if first.is_none() => return Poll::Pending,
+                Poll::Pending => break,
+            }
+        }
+
+        match first {
+            Some(_) if !buffer.is_empty() => Poll::Ready(Some(Ok(buffer.freeze()))),
+            Some(first) => Poll::Ready(Some(Ok(first))),
+            None => Poll::Ready(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use futures::{stream, StreamExt, TryStreamExt};
+
+    use super::*;
+
+    #[ntex::test]
+    async fn test_coalesce_ready_chunks() {
+        let data = (0..100_000).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+        let chunks = data
+            .chunks(100)
+            .map(|c| Ok::<_, ()>(Bytes::copy_from_slice(c)))
+            .collect::<Vec<_>>();
+
+        let output = CoalescedStream::new(stream::iter(chunks.clone()), 16 * 1024)
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        assert_eq!(output.len(), 7);
+        // Chunks are merged until the size is reached (16400 bytes of 100 bytes chunks)
+        assert!(output[..6].iter().all(|c| c.len() == 16400));
+        assert_eq!(output.concat(), data);
+
+        // Disabled coalescing
+        let output = CoalescedStream::new(stream::iter(chunks), 0)
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        assert_eq!(output.len(), 1000);
+        assert_eq!(output.concat(), data);
+    }
+
+    #[ntex::test]
+    async fn test_coalesce_does_not_wait() {
+        // Chunks arrive in two bursts
+        let burst = || stream::iter(["a", "b", "c"].map(|s| Ok::<_, ()>(Bytes::from(s))));
+        let delay = stream::once(ntex::time::sleep(Duration::from_millis(10)))
+            .filter_map(|_| async { None });
+        let chunks = burst().chain(delay).chain(burst());
+
+        let output = CoalescedStream::new(chunks, 1024)
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        assert_eq!(output, vec!["abc", "abc"]);
+    }
+
+    #[ntex::test]
+    async fn test_coalesce_error() {
+        let chunks = stream::iter([Ok(Bytes::from("a")), Ok(Bytes::from("b")), Err("error")]);
+        let mut output = CoalescedStream::new(chunks, 1024);
+        assert_eq!(output.next().await, Some(Ok(Bytes::from("ab"))));
+        assert_eq!(output.next().await, Some(Err("error")));
+        assert_eq!(output.next().await, None);
+    }
+}
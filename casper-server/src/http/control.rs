@@ -0,0 +1,87 @@
+use std::io;
+
+use ntex::http::h1::{Control, ControlAck};
+use ntex::http::{Response, ResponseError};
+use ntex::io::Filter;
+use ntex::service::{fn_service, ServiceFactory};
+
+/// Creates HTTP/1 control service.
+///
+/// Requests with `Expect: 100-continue` header get the `100 Continue` interim response
+/// before reading the body, or rejected with `417 Expectation Failed` if `reject_expect` is set.
+pub fn h1_control<F, E>(
+    reject_expect: bool,
+) -> impl ServiceFactory<Control<F, E>, Response = ControlAck, Error = io::Error, InitError = ()>
+where
+    F: Filter,
+    E: ResponseError,
+{
+    fn_service(move |control: Control<F, E>| async move {
+        Ok(match control {
+            Control::Expect(expect) if reject_expect => {
+                expect.fail_with(Response::ExpectationFailed().finish())
+            }
+            control => control.ack(),
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use ntex::http::{test, HttpService, Request};
+    use ntex::util::BytesMut;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpStream;
+
+    use super::*;
+
+    fn start_server(reject_expect: bool) -> test::TestServer {
+        test::server(move || {
+            HttpService::build()
+                .h1_control(h1_control(reject_expect))
+                .h1(|mut req: Request| async move {
+                    let mut payload = req.take_payload();
+                    let mut body = BytesMut::new();
+                    while let Some(chunk) = payload.recv().await {
+                        body.extend_from_slice(&chunk.unwrap());
+                    }
+                    Ok::<_, io::Error>(Response::Ok().body(body.freeze()))
+                })
+        })
+    }
+
+    async fn read_some(stream: &mut TcpStream) -> String {
+        let mut buf = vec![0; 1024];
+        let n = stream.read(&mut buf).await.unwrap();
+        String::from_utf8_lossy(&buf[..n]).into_owned()
+    }
+
+    #[ntex::test]
+    async fn test_expect_continue() {
+        let srv = start_server(false);
+        let mut stream = TcpStream::connect(srv.addr()).await.unwrap();
+        let head = "POST / HTTP/1.1\r\nhost: localhost\r\n\
+            content-length: 5\r\nexpect: 100-continue\r\n\r\n";
+        stream.write_all(head.as_bytes()).await.unwrap();
+
+        // Interim response is sent before reading the body
+        let resp = read_some(&mut stream).await;
+        assert_eq!(resp, "HTTP/1.1 100 Continue\r\n\r\n");
+        stream.write_all(b"hello").await.unwrap();
+        let resp = read_some(&mut stream).await;
+        assert!(resp.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(resp.ends_with("\r\n\r\nhello"));
+    }
+
+    #[ntex::test]
+    async fn test_reject_expect() {
+        let srv = start_server(true);
+        let mut stream = TcpStream::connect(srv.addr()).await.unwrap();
+        let head = "POST / HTTP/1.1\r\nhost: localhost\r\n\
+            content-length: 5\r\nexpect: 100-continue\r\n\r\n";
+        stream.write_all(head.as_bytes()).await.unwrap();
+
+        let resp = read_some(&mut stream).await;
+        assert!(resp.starts_with("HTTP/1.1 417 Expectation Failed\r\n"));
+    }
+}
@@ -0,0 +1,463 @@
+use std::error::Error as StdError;
+use std::io::{Error as IoError, ErrorKind, Write};
+use std::mem;
+
+use flate2::write::{GzDecoder, GzEncoder};
+use flate2::Compression;
+use futures::future::poll_fn;
+use futures::stream::{self, Stream};
+use ntex::http::body::MessageBody;
+use ntex::util::Bytes;
+use tokio::task::spawn_blocking;
+use zstd::stream::raw::{Decoder as ZstdRawDecoder, Operation};
+
+// Chunks bigger than this are transcoded in a separate thread
+const TRANSCODE_INPLACE_THRESHOLD: usize = 16 * 1024;
+
+// Brotli encoder params (quality 11 is too slow for on-the-fly compression)
+pub const BROTLI_DEFAULT_QUALITY: u32 = 5;
+const BROTLI_LGWIN: u32 = 22;
+const BROTLI_BUFFER_SIZE: usize = 4096;
+
+/// Supported `Content-Encoding` values
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ContentEncoding {
+    Identity,
+    Gzip,
+    Zstd,
+    Brotli,
+}
+
+impl ContentEncoding {
+    /// Parses a `Content-Encoding` header value.
+    ///
+    /// Returns `None` if the encoding is not supported.
+    pub fn parse(value: Option<&str>) -> Option<Self> {
+        match value.map(|v| v.trim().to_ascii_lowercase()).as_deref() {
+            None | Some("") | Some("identity") => Some(ContentEncoding::Identity),
+            Some("gzip") | Some("x-gzip") => Some(ContentEncoding::Gzip),
+            Some("zstd") => Some(ContentEncoding::Zstd),
+            Some("br") => Some(ContentEncoding::Brotli),
+            _ => None,
+        }
+    }
+
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            ContentEncoding::Identity => "identity",
+            ContentEncoding::Gzip => "gzip",
+            ContentEncoding::Zstd => "zstd",
+            ContentEncoding::Brotli => "br",
+        }
+    }
+
+    /// Picks an encoding acceptable by the client according to the `Accept-Encoding` header.
+    ///
+    /// The `current` encoding is preferred (if acceptable) to avoid transcoding.
+    /// Returns `None` if none of the supported encodings is acceptable.
+    pub fn negotiate(accept_encoding: &str, current: ContentEncoding) -> Option<Self> {
+        let mut wildcard = None;
+        let mut qvalues = Vec::new();
+        for item in accept_encoding.split(',') {
+            let mut parts = item.split(';');
+            let name = parts.next().unwrap_or_default().trim().to_ascii_lowercase();
+            let q = parts
+                .filter_map(|p| p.trim().strip_prefix("q="))
+                .find_map(|q| q.trim().parse::<f32>().ok())
+                .unwrap_or(1.0);
+            match name.as_str() {
+                "" => {}
+                "*" => wildcard = Some(q),
+                name => qvalues.push((name.to_string(), q)),
+            }
+        }
+
+        let qvalue = |encoding: ContentEncoding| {
+            let explicit = qvalues
+                .iter()
+                .find(|(name, _)| ContentEncoding::parse(Some(name)) == Some(encoding));
+            match (explicit, wildcard) {
+                (Some((_, q)), _) => *q,
+                (None, Some(q)) => q,
+                // Identity is always acceptable unless explicitly excluded (but least preferred)
+                (None, None) if encoding == ContentEncoding::Identity => f32::MIN_POSITIVE,
+                (None, None) => 0.0,
+            }
+        };
+
+        if qvalue(current) > 0.0 {
+            return Some(current);
+        }
+        let mut best = None;
+        for encoding in [
+            ContentEncoding::Zstd,
+            ContentEncoding::Gzip,
+            ContentEncoding::Brotli,
+            ContentEncoding::Identity,
+        ] {
+            let q = qvalue(encoding);
+            if q > 0.0 && best.is_none_or(|(_, best_q)| q > best_q) {
+                best = Some((encoding, q));
+            }
+        }
+        best.map(|(encoding, _)| encoding)
+    }
+}
+
+enum Decoder {
+    Gzip(GzDecoder<Vec<u8>>),
+    Zstd(ZstdDecoder),
+    Brotli(Box<brotli::DecompressorWriter<Vec<u8>>>),
+}
+
+enum Encoder {
+    Gzip(GzEncoder<Vec<u8>>),
+    Zstd(zstd::stream::write::Encoder<'static, Vec<u8>>),
+    Brotli(Box<brotli::CompressorWriter<Vec<u8>>>),
+}
+
+/// Streaming zstd decoder that keeps track of frame boundaries
+///
+/// Unlike `zstd::stream::write::Decoder`, it can tell whether the input ended in the middle
+/// of a frame (e.g. truncated upstream body).
+struct ZstdDecoder {
+    raw: ZstdRawDecoder<'static>,
+    buffer: Box<[u8]>,
+    frame_complete: bool,
+}
+
+impl ZstdDecoder {
+    fn new() -> Result<Self, IoError> {
+        Ok(ZstdDecoder {
+            raw: ZstdRawDecoder::new()?,
+            buffer: vec![0; zstd::zstd_safe::DCtx::out_size()].into_boxed_slice(),
+            frame_complete: true,
+        })
+    }
+
+    fn decode(&mut self, data: &[u8]) -> Result<Vec<u8>, IoError> {
+        let mut output = Vec::new();
+        let mut input = data;
+        loop {
+            let status = self.raw.run_on_buffers(input, &mut self.buffer)?;
+            output.extend_from_slice(&self.buffer[..status.bytes_written]);
+            input = &input[status.bytes_read..];
+            // Zero hint means that a frame was just finished
+            if status.bytes_read > 0 || status.bytes_written > 0 {
+                self.frame_complete = status.remaining == 0;
+            }
+            if input.is_empty() && status.bytes_written < self.buffer.len() {
+                return Ok(output);
+            }
+        }
+    }
+
+    fn finish(&mut self) -> Result<Vec<u8>, IoError> {
+        let output = self.decode(&[])?;
+        if !self.frame_complete {
+            return Err(IoError::new(
+                ErrorKind::UnexpectedEof,
+                "incomplete zstd frame",
+            ));
+        }
+        Ok(output)
+    }
+}
+
+/// Incrementally decodes data from one encoding and encodes to another
+pub struct Transcoder {
+    decoder: Option<Decoder>,
+    encoder: Option<Encoder>,
+}
+
+impl Transcoder {
+    pub fn new(from: ContentEncoding, to: ContentEncoding) -> Result<Self, IoError> {
+        Self::with_quality(from, to, BROTLI_DEFAULT_QUALITY)
+    }
+
+    /// Creates a new transcoder using the given Brotli quality (0-11) for encoding
+    pub fn with_quality(
+        from: ContentEncoding,
+        to: ContentEncoding,
+        brotli_quality: u32,
+    ) -> Result<Self, IoError> {
+        let decoder = match from {
+            ContentEncoding::Identity => None,
+            ContentEncoding::Gzip => Some(Decoder::Gzip(GzDecoder::new(Vec::new()))),
+            ContentEncoding::Zstd => Some(Decoder::Zstd(ZstdDecoder::new()?)),
+            ContentEncoding::Brotli => Some(Decoder::Brotli(Box::new(
+                brotli::DecompressorWriter::new(Vec::new(), BROTLI_BUFFER_SIZE),
+            ))),
+        };
+        let encoder = match to {
+            ContentEncoding::Identity => None,
+            ContentEncoding::Gzip => Some(Encoder::Gzip(GzEncoder::new(
+                Vec::new(),
+                Compression::default(),
+            ))),
+            ContentEncoding::Zstd => Some(Encoder::Zstd(zstd::stream::write::Encoder::new(
+                Vec::new(),
+                0,
+            )?)),
+            ContentEncoding::Brotli => {
+                Some(Encoder::Brotli(Box::new(brotli::CompressorWriter::new(
+                    Vec::new(),
+                    BROTLI_BUFFER_SIZE,
+                    brotli_quality.min(11),
+                    BROTLI_LGWIN,
+                ))))
+            }
+        };
+        Ok(Transcoder { decoder, encoder })
+    }
+
+    /// Transcodes next chunk of data, returning the available output (can be empty)
+    pub fn feed(&mut self, data: &[u8]) -> Result<Bytes, IoError> {
+        let decoded = match &mut self.decoder {
+            None => data.to_vec(),
+            Some(Decoder::Gzip(dec)) => {
+                dec.write_all(data)?;
+                dec.flush()?;
+                mem::take(dec.get_mut())
+            }
+            Some(Decoder::Zstd(dec)) => dec.decode(data)?,
+            Some(Decoder::Brotli(dec)) => {
+                dec.write_all(data)?;
+                dec.flush()?;
+                mem::take(dec.get_mut())
+            }
+        };
+        self.encode(decoded)
+    }
+
+    /// Finishes transcoding, returning the rest of the output
+    pub fn finish(mut self) -> Result<Bytes, IoError> {
+        let decoded = match self.decoder.take() {
+            None => Vec::new(),
+            Some(Decoder::Gzip(dec)) => dec.finish()?,
+            Some(Decoder::Zstd(mut dec)) => dec.finish()?,
+            Some(Decoder::Brotli(dec)) => dec
+                .into_inner()
+                .map_err(|_| IoError::new(ErrorKind::InvalidData, "incomplete brotli stream"))?,
+        };
+        let mut output = self.encode(decoded)?.to_vec();
+        match self.encoder.take() {
+            None => {}
+            Some(Encoder::Gzip(enc)) => output.extend(enc.finish()?),
+            Some(Encoder::Zstd(enc)) => output.extend(enc.finish()?),
+            Some(Encoder::Brotli(enc)) => output.extend(enc.into_inner()),
+        }
+        Ok(Bytes::from(output))
+    }
+
+    fn encode(&mut self, data: Vec<u8>) -> Result<Bytes, IoError> {
+        let output = match &mut self.encoder {
+            None => data,
+            Some(Encoder::Gzip(enc)) => {
+                enc.write_all(&data)?;
+                mem::take(enc.get_mut())
+            }
+            Some(Encoder::Zstd(enc)) => {
+                enc.write_all(&data)?;
+                mem::take(enc.get_mut())
+            }
+            Some(Encoder::Brotli(enc)) => {
+                enc.write_all(&data)?;
+                mem::take(enc.get_mut())
+            }
+        };
+        Ok(Bytes::from(output))
+    }
+}
+
+/// Compresses (identity encoded) data with Brotli using the given quality (0-11)
+pub async fn compress_with_brotli(data: Bytes, quality: u32) -> Result<Bytes, IoError> {
+    let inplace = data.len() <= TRANSCODE_INPLACE_THRESHOLD;
+    let compress = move || {
+        let mut t =
+            Transcoder::with_quality(ContentEncoding::Identity, ContentEncoding::Brotli, quality)?;
+        let mut output = t.feed(&data)?.to_vec();
+        output.extend(t.finish()?);
+        Ok(Bytes::from(output))
+    };
+    if inplace {
+        return compress();
+    }
+    spawn_blocking(compress).await?
+}
+
+/// Transcodes the body chunk-by-chunk without buffering it
+pub fn transcode_stream<B>(
+    body: B,
+    from: ContentEncoding,
+    to: ContentEncoding,
+) -> Result<impl Stream<Item = Result<Bytes, Box<dyn StdError>>> + Unpin, IoError>
+where
+    B: MessageBody + 'static,
+{
+    let transcoder = Transcoder::new(from, to)?;
+    let stream = stream::try_unfold(
+        (body, Some(transcoder)),
+        |(mut body, mut transcoder)| async move {
+            loop {
+                let Some(mut t) = transcoder.take() else {
+                    return Ok(None);
+                };
+                let (t, output) = match poll_fn(|cx| body.poll_next_chunk(cx)).await {
+                    Some(chunk) => {
+                        let chunk = chunk?;
+                        if chunk.len() <= TRANSCODE_INPLACE_THRESHOLD {
+                            let output = t.feed(&chunk)?;
+                            (Some(t), output)
+                        } else {
+                            spawn_blocking(move || t.feed(&chunk).map(|output| (Some(t), output)))
+                                .await??
+                        }
+                    }
+                    None => (None, t.finish()?),
+                };
+                transcoder = t;
+                if !output.is_empty() {
+                    return Ok(Some((output, (body, transcoder))));
+                }
+            }
+        },
+    );
+    Ok(Box::pin(stream))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+    use std::time::Duration;
+
+    use futures::stream::{self, StreamExt, TryStreamExt};
+    use ntex::http::body::BoxedBodyStream;
+    use rand::distributions::{Alphanumeric, DistString};
+
+    use super::*;
+    use crate::utils::zstd::compress_with_zstd;
+
+    #[test]
+    fn test_negotiate() {
+        use ContentEncoding::*;
+
+        // Current encoding is acceptable
+        assert_eq!(ContentEncoding::negotiate("gzip, zstd", Zstd), Some(Zstd));
+        assert_eq!(ContentEncoding::negotiate("", Identity), Some(Identity));
+        assert_eq!(ContentEncoding::negotiate("*", Zstd), Some(Zstd));
+
+        // Transcoding is required
+        assert_eq!(ContentEncoding::negotiate("gzip", Zstd), Some(Gzip));
+        assert_eq!(ContentEncoding::negotiate("GZIP;q=0.5", Zstd), Some(Gzip));
+        assert_eq!(ContentEncoding::negotiate("", Zstd), Some(Identity));
+        assert_eq!(ContentEncoding::negotiate("br", Zstd), Some(Brotli));
+        assert_eq!(ContentEncoding::negotiate("br, gzip", Zstd), Some(Gzip));
+        assert_eq!(
+            ContentEncoding::negotiate("gzip;q=0.5, zstd;q=0", Identity),
+            Some(Identity)
+        );
+        assert_eq!(
+            ContentEncoding::negotiate("gzip;q=0.5, identity;q=0", Zstd),
+            Some(Gzip)
+        );
+        assert_eq!(
+            ContentEncoding::negotiate("gzip;q=0.5, zstd;q=0.8, identity;q=0", Identity),
+            Some(Zstd)
+        );
+
+        // Nothing is acceptable
+        assert_eq!(ContentEncoding::negotiate("identity;q=0", Zstd), None);
+        assert_eq!(ContentEncoding::negotiate("*;q=0", Gzip), None);
+    }
+
+    fn gunzip(data: &[u8]) -> Vec<u8> {
+        let mut output = Vec::new();
+        flate2::read::GzDecoder::new(data)
+            .read_to_end(&mut output)
+            .unwrap();
+        output
+    }
+
+    #[test]
+    fn test_transcoder() {
+        let data = b"hello, world".repeat(100);
+        let gzipped = {
+            let mut t = Transcoder::new(ContentEncoding::Identity, ContentEncoding::Gzip).unwrap();
+            let mut output = t.feed(&data).unwrap().to_vec();
+            output.extend(t.finish().unwrap());
+            output
+        };
+        assert_eq!(gunzip(&gzipped), data);
+
+        // gzip -> zstd -> identity
+        let mut t = Transcoder::new(ContentEncoding::Gzip, ContentEncoding::Zstd).unwrap();
+        let mut zstd_data = Vec::new();
+        for chunk in gzipped.chunks(10) {
+            zstd_data.extend(t.feed(chunk).unwrap());
+        }
+        zstd_data.extend(t.finish().unwrap());
+        assert_eq!(zstd::stream::decode_all(&zstd_data[..]).unwrap(), data);
+
+        // zstd -> brotli -> identity
+        let mut t = Transcoder::new(ContentEncoding::Zstd, ContentEncoding::Brotli).unwrap();
+        let mut br_data = t.feed(&zstd_data).unwrap().to_vec();
+        br_data.extend(t.finish().unwrap());
+        let mut t = Transcoder::new(ContentEncoding::Brotli, ContentEncoding::Identity).unwrap();
+        let mut output = Vec::new();
+        for chunk in br_data.chunks(10) {
+            output.extend(t.feed(chunk).unwrap());
+        }
+        output.extend(t.finish().unwrap());
+        assert_eq!(output, data);
+
+        // Invalid data
+        let mut t = Transcoder::new(ContentEncoding::Zstd, ContentEncoding::Gzip).unwrap();
+        assert!(t.feed(b"not a zstd frame").is_err());
+
+        // Truncated zstd frame
+        let mut t = Transcoder::new(ContentEncoding::Zstd, ContentEncoding::Gzip).unwrap();
+        t.feed(&zstd_data[..zstd_data.len() - 4]).unwrap();
+        let err = t.finish().unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+
+        // Empty zstd body
+        let t = Transcoder::new(ContentEncoding::Zstd, ContentEncoding::Identity).unwrap();
+        assert!(t.finish().unwrap().is_empty());
+    }
+
+    #[ntex::test]
+    async fn test_transcode_stream() {
+        let raw_data = Alphanumeric.sample_string(&mut rand::thread_rng(), 1024 * 1024);
+        let data = Bytes::from(raw_data);
+        let compressed = compress_with_zstd(data.clone(), 0).await.unwrap();
+        let chunks = compressed
+            .chunks(32 * 1024)
+            .map(Bytes::copy_from_slice)
+            .collect::<Vec<_>>();
+
+        // Full body
+        let body =
+            BoxedBodyStream::new(stream::iter(chunks.clone()).map(Ok::<_, Box<dyn StdError>>));
+        let stream = transcode_stream(body, ContentEncoding::Zstd, ContentEncoding::Gzip).unwrap();
+        let output = stream.try_collect::<Vec<_>>().await.unwrap();
+        assert!(output.len() > 1);
+        assert_eq!(gunzip(&output.concat()), data);
+
+        // Body that never ends must not be buffered
+        let half = chunks.len() / 2;
+        let body = BoxedBodyStream::new(
+            stream::iter(chunks.into_iter().take(half))
+                .map(Ok)
+                .chain(stream::pending()),
+        );
+        let mut stream =
+            transcode_stream(body, ContentEncoding::Zstd, ContentEncoding::Gzip).unwrap();
+        let chunk = tokio::time::timeout(Duration::from_secs(5), stream.next())
+            .await
+            .expect("body must be transcoded without buffering")
+            .unwrap()
+            .unwrap();
+        assert!(!chunk.is_empty());
+    }
+}
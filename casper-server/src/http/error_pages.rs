@@ -0,0 +1,169 @@
+use std::collections::HashMap;
+use std::fs;
+use std::rc::Rc;
+
+use anyhow::{bail, Context as _, Result};
+use ntex::http::header::{HeaderMap, HeaderName, HeaderValue, CONTENT_TYPE};
+use ntex::http::StatusCode;
+use opentelemetry::trace::TraceContextExt as _;
+use opentelemetry::Context;
+
+use crate::config::ErrorPageConfig;
+use crate::lua::{LuaBody, LuaResponse};
+
+const REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+
+#[derive(Debug)]
+struct ErrorPage {
+    body: String,
+    headers: HeaderMap,
+}
+
+/// Custom pages served instead of the default bodies of proxy errors
+#[derive(Clone, Debug)]
+pub struct ErrorPages(Rc<HashMap<StatusCode, ErrorPage>>);
+
+impl ErrorPages {
+    /// Creates error pages from the config, reading bodies from files if needed
+    pub fn new(config: &HashMap<u16, ErrorPageConfig>) -> Result<Self> {
+        let mut pages = HashMap::with_capacity(config.len());
+        for (&status, page) in config {
+            let status = StatusCode::from_u16(status)
+                .with_context(|| format!("invalid error page status `{status}`"))?;
+            let body = match (&page.body, &page.file) {
+                (Some(body), None) => body.clone(),
+                (None, Some(file)) => fs::read_to_string(file)
+                    .with_context(|| format!("failed to read error page `{}`", file.display()))?,
+                _ => bail!("error page `{status}` must have either `body` or `file`"),
+            };
+            let mut headers = HeaderMap::with_capacity(page.headers.len() + 1);
+            for (name, value) in &page.headers {
+                let name = HeaderName::try_from(name.as_str())
+                    .with_context(|| format!("invalid error page header name `{name}`"))?;
+                let value = HeaderValue::try_from(value.as_str())
+                    .with_context(|| format!("invalid error page header value `{value}`"))?;
+                headers.append(name, value);
+            }
+            if !headers.contains_key(CONTENT_TYPE) {
+                headers.insert(CONTENT_TYPE, HeaderValue::from_static("text/html"));
+            }
+            pages.insert(status, ErrorPage { body, headers });
+        }
+        Ok(ErrorPages(Rc::new(pages)))
+    }
+
+    /// Renders the error page for the given status (if configured)
+    ///
+    /// `{{ request_id }}` in the page body is replaced with the request id.
+    pub fn render(&self, status: StatusCode, request_id: &str) -> Option<LuaResponse> {
+        let page = self.0.get(&status)?;
+        let body = render_template(&page.body, |name| match name {
+            "request_id" => Some(request_id),
+            "status" => Some(status.as_str()),
+            _ => None,
+        });
+        let mut resp = LuaResponse::new(LuaBody::from(body));
+        *resp.status_mut() = status;
+        *resp.headers_mut() = page.headers.clone();
+        Some(resp)
+    }
+}
+
+/// Returns the request id from the `X-Request-Id` header or the trace id of the current span
+///
+/// Only ids of up to 128 alphanumeric characters (or `.`, `_`, `-`) are accepted from the header.
+pub fn request_id(headers: &HeaderMap) -> String {
+    let request_id = headers.get(REQUEST_ID_HEADER).map(|v| v.as_bytes());
+    if let Some(request_id) = request_id.filter(|id| is_valid_request_id(id)) {
+        return String::from_utf8_lossy(request_id).into_owned();
+    }
+    let cx = Context::current();
+    let span_context = cx.span().span_context().clone();
+    match span_context.is_valid() {
+        true => span_context.trace_id().to_string(),
+        false => "-".to_string(),
+    }
+}
+
+fn is_valid_request_id(id: &[u8]) -> bool {
+    (1..=128).contains(&id.len())
+        && (id.iter()).all(|&b| b.is_ascii_alphanumeric() || matches!(b, b'.' | b'_' | b'-'))
+}
+
+/// Escapes HTML special characters
+fn escape_html(s: &str) -> String {
+    let mut output = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => output.push_str("&amp;"),
+            '<' => output.push_str("&lt;"),
+            '>' => output.push_str("&gt;"),
+            '"' => output.push_str("&quot;"),
+            '\'' => output.push_str("&#39;"),
+            c => output.push(c),
+        }
+    }
+    output
+}
+
+/// Replaces `{{ name }}` tags with their (HTML-escaped) values, unknown tags are left as is
+fn render_template<'a>(template: &str, var: impl Fn(&str) -> Option<&'a str>) -> String {
+    let mut output = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        let Some(end) = rest[start..].find("}}") else {
+            break;
+        };
+        let (tag, name) = (
+            &rest[start..start + end + 2],
+            rest[start + 2..start + end].trim(),
+        );
+        output.push_str(&rest[..start]);
+        match var(name) {
+            Some(value) => output.push_str(&escape_html(value)),
+            None => output.push_str(tag),
+        }
+        rest = &rest[start + end + 2..];
+    }
+    output.push_str(rest);
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_template() {
+        let var = |name: &str| (name == "id").then_some("abc");
+        assert_eq!(render_template("id: {{ id }}!", var), "id: abc!");
+        assert_eq!(render_template("{{id}}{{id}}", var), "abcabc");
+        assert_eq!(
+            render_template("{{ other }} {{ id", var),
+            "{{ other }} {{ id"
+        );
+
+        // Values are HTML-escaped
+        let var = |_: &str| Some("<script>alert('x' & \"y\")</script>");
+        assert_eq!(
+            render_template("{{ id }}", var),
+            "&lt;script&gt;alert(&#39;x&#39; &amp; &quot;y&quot;)&lt;/script&gt;"
+        );
+    }
+
+    #[test]
+    fn test_request_id() {
+        let mut headers = HeaderMap::new();
+        headers.insert(REQUEST_ID_HEADER, HeaderValue::from_static("abc-123_x.y"));
+        assert_eq!(request_id(&headers), "abc-123_x.y");
+
+        // Invalid ids fall back to the trace id (no active span here)
+        for id in ["<script>", "a b", ""] {
+            headers.insert(REQUEST_ID_HEADER, HeaderValue::from_static(id));
+            assert_eq!(request_id(&headers), "-");
+        }
+        let long_id = HeaderValue::try_from("a".repeat(129)).unwrap();
+        headers.insert(REQUEST_ID_HEADER, long_id);
+        assert_eq!(request_id(&headers), "-");
+    }
+}
@@ -3,7 +3,11 @@ use std::error::Error as StdError;
 use ntex::http::body::MessageBody;
 use ntex::util::{Bytes, BytesMut};
 
+pub use allowlist::UpstreamAllowlist;
+pub use coalesce::CoalescedStream;
+pub use error_pages::ErrorPages;
 pub use proxy::{filter_hop_headers, proxy_to_upstream};
+pub use rewrite::PathRewrite;
 
 pub async fn buffer_body(mut body: impl MessageBody) -> Result<Bytes, Box<dyn StdError>> {
     let mut bytes = BytesMut::new();
@@ -13,6 +17,16 @@ pub async fn buffer_body(mut body: impl MessageBody) -> Result<Bytes, Box<dyn St
     Ok(bytes.freeze())
 }
 
+pub(crate) mod allowlist;
+pub(crate) mod cache_control;
+pub(crate) mod coalesce;
+pub(crate) mod control;
+pub(crate) mod encoding;
+pub(crate) mod error_pages;
+pub(crate) mod multipart;
 pub(crate) mod proxy;
+pub(crate) mod range;
+pub(crate) mod rewrite;
 pub(crate) mod trace;
+pub(crate) mod warmup;
 pub(crate) mod websocket;
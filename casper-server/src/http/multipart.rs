@@ -0,0 +1,95 @@
+use std::convert::Infallible;
+
+use mime::Mime;
+use ntex::util::Bytes;
+
+/// Part of a `multipart/form-data` body
+#[derive(Debug)]
+pub struct FormPart {
+    pub name: Option<String>,
+    pub filename: Option<String>,
+    pub content_type: Option<String>,
+    pub data: Vec<u8>,
+}
+
+/// Parses (buffered) `multipart/form-data` body into a list of parts.
+///
+/// Nested multipart parts (e.g. `multipart/mixed`) are rejected.
+pub async fn parse_form_data(content_type: &str, body: Bytes) -> Result<Vec<FormPart>, String> {
+    let mime = content_type
+        .parse::<Mime>()
+        .map_err(|_| "invalid content type".to_string())?;
+    if mime.type_() != mime::MULTIPART || mime.subtype() != mime::FORM_DATA {
+        return Err("content type is not multipart/form-data".to_string());
+    }
+    let boundary = multer::parse_boundary(content_type)
+        .map_err(|_| "missing multipart boundary".to_string())?;
+
+    let stream = futures::stream::once(async move { Ok::<_, Infallible>(body.to_vec()) });
+    let mut multipart = multer::Multipart::new(stream, boundary);
+    let mut parts = Vec::new();
+    while let Some(field) = multipart
+        .next_field()
+        .await
+        .map_err(|err| err.to_string())?
+    {
+        let content_type = field.content_type();
+        if content_type.is_some_and(|m| m.type_() == mime::MULTIPART) {
+            return Err("nested multipart parts are not supported".to_string());
+        }
+        let content_type = content_type.map(|m| m.to_string());
+        let name = field.name().map(|s| s.to_string());
+        let filename = field.file_name().map(|s| s.to_string());
+        let data = field.bytes().await.map_err(|err| err.to_string())?;
+        parts.push(FormPart {
+            name,
+            filename,
+            content_type,
+            data: data.into(),
+        });
+    }
+    Ok(parts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONTENT_TYPE: &str = "multipart/form-data; boundary=X-BOUNDARY";
+
+    #[ntex::test]
+    async fn test_parse_form_data() {
+        let body = "--X-BOUNDARY\r\n\
+            Content-Disposition: form-data; name=\"title\"\r\n\r\n\
+            hello\r\n\
+            --X-BOUNDARY\r\n\
+            Content-Disposition: form-data; name=\"file\"; filename=\"a.txt\"\r\n\
+            Content-Type: text/plain\r\n\r\n\
+            file content\r\n\
+            --X-BOUNDARY--\r\n";
+        let parts = parse_form_data(CONTENT_TYPE, Bytes::from(body))
+            .await
+            .unwrap();
+        assert_eq!(parts.len(), 2);
+        assert_eq!(parts[0].name.as_deref(), Some("title"));
+        assert_eq!(parts[0].filename, None);
+        assert_eq!(parts[0].data, b"hello");
+        assert_eq!(parts[1].filename.as_deref(), Some("a.txt"));
+        assert_eq!(parts[1].content_type.as_deref(), Some("text/plain"));
+        assert_eq!(parts[1].data, b"file content");
+
+        // Errors
+        let err = parse_form_data("multipart/form-data", Bytes::from(body)).await;
+        assert_eq!(err.unwrap_err(), "missing multipart boundary");
+        let err = parse_form_data("text/plain", Bytes::from(body)).await;
+        assert_eq!(err.unwrap_err(), "content type is not multipart/form-data");
+
+        let body = "--X-BOUNDARY\r\n\
+            Content-Disposition: form-data; name=\"files\"\r\n\
+            Content-Type: multipart/mixed; boundary=Y\r\n\r\n\
+            --Y--\r\n\
+            --X-BOUNDARY--\r\n";
+        let err = parse_form_data(CONTENT_TYPE, Bytes::from(body)).await;
+        assert_eq!(err.unwrap_err(), "nested multipart parts are not supported");
+    }
+}
@@ -1,11 +1,14 @@
 use std::mem;
+use std::time::Instant;
+
+use futures::StreamExt as _;
 
 use mlua::{ExternalError, ExternalResult, Result as LuaResult};
 use ntex::http::client::error::SendRequestError;
 use ntex::http::client::Client as HttpClient;
 use ntex::http::header::{self, HeaderMap, HeaderName, HeaderValue};
 use ntex::http::uri::{InvalidUri, InvalidUriParts, Scheme, Uri};
-use ntex::http::StatusCode;
+use ntex::http::{Method, Payload, StatusCode};
 use opentelemetry::trace::{self, TraceContextExt as _, Tracer as _};
 use opentelemetry::{global, Context, KeyValue};
 use opentelemetry_semantic_conventions::trace::{
@@ -14,8 +17,12 @@ use opentelemetry_semantic_conventions::trace::{
 use scopeguard::defer;
 use tracing::{debug, instrument, Span};
 
+use crate::http::allowlist::UpstreamAllowlist;
+use crate::http::error_pages::{self, ErrorPages};
+use crate::http::rewrite::PathRewrite;
 use crate::http::trace::{ParentSamplingDecision, RequestHeaderCarrierMut};
 use crate::lua::{LuaBody, LuaRequest, LuaResponse};
+use crate::types::HeadResponseExt;
 
 #[allow(clippy::declare_interior_mutable_const)]
 const HOP_BY_HOP_HEADERS: [HeaderName; 8] = [
@@ -24,6 +31,8 @@ const HOP_BY_HOP_HEADERS: [HeaderName; 8] = [
     header::PROXY_AUTHENTICATE,
     header::PROXY_AUTHORIZATION,
     header::TE,
+    // Trailers cannot be forwarded: the HTTP/1 codec neither parses nor emits them,
+    // so the `Trailer` header announcing them is dropped (and `TE: trailers` is not sent)
     header::TRAILER,
     header::TRANSFER_ENCODING,
     header::UPGRADE,
@@ -45,19 +54,38 @@ pub fn filter_hop_headers(headers: &mut HeaderMap) {
 }
 
 /// Proxy request to upstream service.
+///
+/// If `allowlist` is set, the target must be allowed by it.
+/// If `rewrite` is set, it's applied to the request path after merging with the upstream uri.
+/// If `error_pages` are set, they are served (when configured) instead of the default
+/// bodies of proxy errors.
 #[instrument(skip_all, fields(method = %req.method(), uri))]
 pub async fn proxy_to_upstream(
     client: HttpClient,
     mut req: LuaRequest,
     upstream: Option<&str>,
+    allowlist: Option<&UpstreamAllowlist>,
+    rewrite: Option<&PathRewrite>,
+    error_pages: Option<&ErrorPages>,
 ) -> LuaResult<LuaResponse> {
     // Merge request uri with the upstream uri
     if let Some(upstream) = upstream {
         let new_uri = merge_uri(req.uri().clone(), upstream).into_lua_err()?;
         *req.uri_mut() = new_uri;
     }
+    if let Some(rewrite) = rewrite {
+        let new_uri = rewrite.rewrite_uri(req.uri().clone()).into_lua_err()?;
+        *req.uri_mut() = new_uri;
+    }
     Span::current().record("uri", req.uri().to_string());
 
+    if let Some(allowlist) = allowlist {
+        if let Err(err) = allowlist.check(req.uri()) {
+            rejected_upstreams_counter_add!(1);
+            return Err(err.into_lua_err());
+        }
+    }
+
     // Special case to handle websocket upgrade requests
     if super::websocket::is_websocket_upgrade(&req) {
         return super::websocket::proxy_websocket_upgrade(&req).await;
@@ -88,6 +116,9 @@ pub async fn proxy_to_upstream(
         }
     }
 
+    // The request is consumed, so the request id is captured in advance
+    let request_id = error_pages.map(|_| error_pages::request_id(req.headers()));
+
     match forward_to_upstream(client, req).await {
         Ok(resp) => {
             let span = cx.span();
@@ -118,6 +149,10 @@ pub async fn proxy_to_upstream(
             let status_i64 = status.as_u16() as i64;
             span.set_attribute(KeyValue::new(HTTP_RESPONSE_STATUS_CODE, status_i64));
 
+            let request_id = request_id.as_deref().unwrap_or_default();
+            if let Some(resp) = error_pages.and_then(|pages| pages.render(status, request_id)) {
+                return Ok(resp);
+            }
             let mut resp = LuaResponse::new(LuaBody::from(err.to_string()));
             *resp.status_mut() = status;
             resp.headers_mut()
@@ -156,6 +191,7 @@ async fn forward_to_upstream(
     client: HttpClient,
     mut req: LuaRequest,
 ) -> Result<LuaResponse, SendRequestError> {
+    let is_head = req.method() == Method::HEAD;
     let mut client_req = client.request(req.method().clone(), req.uri());
 
     if let Some(timeout) = req.timeout() {
@@ -168,14 +204,43 @@ async fn forward_to_upstream(
     // Add headers
     let mut headers = mem::take(req.headers_mut());
     filter_hop_headers(&mut headers);
+    // The expectation is already met by the server (the body is being received),
+    // the client cannot handle interim responses from upstream
+    headers.remove(header::EXPECT);
     *client_req.headers_mut() = headers;
 
     // Proxy to an upstream service
     let body: LuaBody = req.take_body().into();
-    let upstream_resp = client_req.send_body(body).await?;
+    let start = Instant::now();
+    let mut upstream_resp = client_req.send_body(body).await?;
+
+    // Record time to the first byte of the response body
+    let upstream = req
+        .uri()
+        .authority()
+        .map(|auth| auth.to_string())
+        .unwrap_or_default();
+    match upstream_resp.take_payload() {
+        Payload::None => upstream_ttfb_histogram_rec!(start, "upstream" => upstream),
+        payload => {
+            let mut upstream = Some(upstream);
+            let payload = payload.inspect(move |_| {
+                if let Some(upstream) = upstream.take() {
+                    upstream_ttfb_histogram_rec!(start, "upstream" => upstream);
+                }
+            });
+            upstream_resp.set_payload(Payload::Stream(Box::pin(payload)));
+        }
+    }
 
     let mut resp = LuaResponse::from(upstream_resp);
     filter_hop_headers(resp.headers_mut());
 
+    // Some upstreams incorrectly send a body for `HEAD` requests, drop it
+    if is_head {
+        *resp.body_mut() = LuaBody::None.into();
+        resp.extensions_mut().insert(HeadResponseExt);
+    }
+
     Ok(resp)
 }
@@ -0,0 +1,110 @@
+use std::ops::Range;
+
+/// Outcome of evaluating a `Range` request header against a representation
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ByteRange {
+    /// The header is ignored, the full representation must be sent
+    Full,
+    /// A single satisfiable range
+    Partial(Range<u64>),
+    /// None of the requested ranges overlap the representation
+    Unsatisfiable,
+}
+
+/// Evaluates a `Range` header value against a representation of `len` bytes.
+///
+/// Only single byte ranges are supported, any other (or invalid) value is ignored.
+pub fn parse_byte_range(value: &str, len: u64) -> ByteRange {
+    let Some(spec) = value.trim().strip_prefix("bytes=") else {
+        return ByteRange::Full;
+    };
+    if spec.contains(',') {
+        return ByteRange::Full;
+    }
+    let Some((start, end)) = spec.trim().split_once('-') else {
+        return ByteRange::Full;
+    };
+    let (start, end) = (start.trim(), end.trim());
+
+    // Suffix range (e.g. `bytes=-500`)
+    if start.is_empty() {
+        return match end.parse::<u64>() {
+            Ok(0) => ByteRange::Unsatisfiable,
+            Ok(_) if len == 0 => ByteRange::Unsatisfiable,
+            Ok(suffix) => ByteRange::Partial(len.saturating_sub(suffix)..len),
+            Err(_) => ByteRange::Full,
+        };
+    }
+
+    let Ok(start) = start.parse::<u64>() else {
+        return ByteRange::Full;
+    };
+    let end = match end {
+        "" => len.saturating_sub(1),
+        end => match end.parse::<u64>() {
+            Ok(end) if end >= start => end.min(len.saturating_sub(1)),
+            _ => return ByteRange::Full,
+        },
+    };
+    if start >= len {
+        return ByteRange::Unsatisfiable;
+    }
+    ByteRange::Partial(start..end + 1)
+}
+
+/// Checks the `If-Range` header value against the representation validators.
+///
+/// Entity tags are compared using the strong comparison (weak tags never match),
+/// dates must exactly match the `Last-Modified` value.
+pub fn if_range_matches(value: &str, etag: Option<&str>, last_modified: Option<&str>) -> bool {
+    let value = value.trim();
+    if value.starts_with('"') || value.starts_with("W/") {
+        return match etag.map(str::trim) {
+            Some(etag) => !value.starts_with("W/") && !etag.starts_with("W/") && value == etag,
+            None => false,
+        };
+    }
+    last_modified.is_some_and(|lm| lm.trim() == value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_byte_range() {
+        assert_eq!(parse_byte_range("bytes=0-4", 10), ByteRange::Partial(0..5));
+        assert_eq!(parse_byte_range("bytes=5-", 10), ByteRange::Partial(5..10));
+        assert_eq!(
+            parse_byte_range("bytes=5-100", 10),
+            ByteRange::Partial(5..10)
+        );
+        assert_eq!(parse_byte_range("bytes=-3", 10), ByteRange::Partial(7..10));
+        assert_eq!(parse_byte_range("bytes=-30", 10), ByteRange::Partial(0..10));
+
+        assert_eq!(parse_byte_range("bytes=10-", 10), ByteRange::Unsatisfiable);
+        assert_eq!(parse_byte_range("bytes=-0", 10), ByteRange::Unsatisfiable);
+
+        // Ignored values
+        assert_eq!(parse_byte_range("items=0-4", 10), ByteRange::Full);
+        assert_eq!(parse_byte_range("bytes=0-1, 4-5", 10), ByteRange::Full);
+        assert_eq!(parse_byte_range("bytes=5-1", 10), ByteRange::Full);
+        assert_eq!(parse_byte_range("bytes=abc", 10), ByteRange::Full);
+    }
+
+    #[test]
+    fn test_if_range_matches() {
+        let lm = "Wed, 21 Oct 2015 07:28:00 GMT";
+        assert!(if_range_matches(r#""abc""#, Some(r#""abc""#), None));
+        assert!(!if_range_matches(r#""abc""#, Some(r#""xyz""#), None));
+        assert!(!if_range_matches(r#"W/"abc""#, Some(r#"W/"abc""#), None));
+        assert!(!if_range_matches(r#""abc""#, None, Some(lm)));
+        assert!(if_range_matches(lm, Some(r#""abc""#), Some(lm)));
+        assert!(!if_range_matches(
+            lm,
+            None,
+            Some("Thu, 22 Oct 2015 07:28:00 GMT")
+        ));
+        assert!(!if_range_matches(lm, None, None));
+    }
+}
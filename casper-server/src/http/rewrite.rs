@@ -0,0 +1,156 @@
+use anyhow::{Context as _, Result};
+use mini_moka::sync::Cache;
+use ntex::http::uri::{PathAndQuery, Uri};
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+use crate::config::PathRewriteConfig;
+use crate::http::proxy::UriError;
+
+const REGEX_CACHE_SIZE: u64 = 128;
+
+// Compiled regexes of path rewrites (overrides from Lua are constructed on every request)
+static REGEX_CACHE: Lazy<Cache<String, Regex>> = Lazy::new(|| Cache::new(REGEX_CACHE_SIZE));
+
+/// Rewrite of the upstream request path.
+///
+/// Steps are applied in order: prefix strip, regex replace and prefix add.
+/// The query string is always preserved.
+#[derive(Clone, Debug, Default)]
+pub struct PathRewrite {
+    strip_prefix: Option<String>,
+    regex: Option<(Regex, String)>,
+    add_prefix: Option<String>,
+}
+
+impl PathRewrite {
+    pub fn new(config: &PathRewriteConfig) -> Result<Self> {
+        let regex = match &config.regex {
+            Some(re) => {
+                let regex = match REGEX_CACHE.get(re) {
+                    Some(regex) => regex,
+                    None => {
+                        let regex =
+                            Regex::new(re).with_context(|| format!("invalid regex `{re}`"))?;
+                        REGEX_CACHE.insert(re.clone(), regex.clone());
+                        regex
+                    }
+                };
+                Some((regex, config.replacement.clone()))
+            }
+            None => None,
+        };
+        let normalize = |prefix: &String| {
+            let prefix = prefix.trim().trim_end_matches('/');
+            (!prefix.is_empty()).then(|| format!("/{}", prefix.trim_start_matches('/')))
+        };
+        Ok(PathRewrite {
+            strip_prefix: config.strip_prefix.as_ref().and_then(normalize),
+            regex,
+            add_prefix: config.add_prefix.as_ref().and_then(normalize),
+        })
+    }
+
+    /// Rewrites the path of the given uri
+    pub fn rewrite_uri(&self, uri: Uri) -> Result<Uri, UriError> {
+        let mut parts = uri.into_parts();
+        if let Some(path_and_query) = &parts.path_and_query {
+            let path = self.rewrite_path(path_and_query.path());
+            let new_path_and_query = match path_and_query.query() {
+                Some(query) => format!("{path}?{query}"),
+                None => path,
+            };
+            parts.path_and_query = Some(PathAndQuery::try_from(new_path_and_query)?);
+        }
+        Ok(Uri::from_parts(parts)?)
+    }
+
+    fn rewrite_path(&self, path: &str) -> String {
+        let mut path = path.to_string();
+        if let Some(prefix) = &self.strip_prefix {
+            // Strip the prefix only at the segment boundary
+            if let Some(rest) = path.strip_prefix(prefix.as_str()) {
+                if rest.is_empty() || rest.starts_with('/') {
+                    path = format!("/{}", rest.trim_start_matches('/'));
+                }
+            }
+        }
+        if let Some((regex, replacement)) = &self.regex {
+            path = regex.replace(&path, replacement.as_str()).into_owned();
+        }
+        if let Some(prefix) = &self.add_prefix {
+            path = format!("{prefix}/{}", path.trim_start_matches('/'));
+        }
+        path
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rewrite(config: PathRewriteConfig, uri: &str) -> String {
+        let rewrite = PathRewrite::new(&config).unwrap();
+        rewrite
+            .rewrite_uri(uri.parse().unwrap())
+            .unwrap()
+            .to_string()
+    }
+
+    #[test]
+    fn test_strip_prefix() {
+        let config = || PathRewriteConfig {
+            strip_prefix: Some("/api/".into()),
+            ..Default::default()
+        };
+        assert_eq!(rewrite(config(), "/api/v1/users?id=1"), "/v1/users?id=1");
+        assert_eq!(rewrite(config(), "http://a.com/api"), "http://a.com/");
+        assert_eq!(rewrite(config(), "/api?x=1"), "/?x=1");
+        // Prefix must match whole segments
+        assert_eq!(rewrite(config(), "/apix/v1"), "/apix/v1");
+        assert_eq!(rewrite(config(), "/v1/api/x"), "/v1/api/x");
+    }
+
+    #[test]
+    fn test_add_prefix() {
+        let config = || PathRewriteConfig {
+            add_prefix: Some("backend".into()),
+            ..Default::default()
+        };
+        assert_eq!(rewrite(config(), "/users?id=1&x"), "/backend/users?id=1&x");
+        assert_eq!(rewrite(config(), "http://a.com/"), "http://a.com/backend/");
+        assert_eq!(rewrite(config(), "/?x=1"), "/backend/?x=1");
+
+        // Strip and add prefix
+        let config = PathRewriteConfig {
+            strip_prefix: Some("/public".into()),
+            add_prefix: Some("/internal/".into()),
+            ..Default::default()
+        };
+        assert_eq!(rewrite(config, "/public/a/b?c=d"), "/internal/a/b?c=d");
+    }
+
+    #[test]
+    fn test_regex_rewrite() {
+        let config = || PathRewriteConfig {
+            regex: Some(r"^/v(\d+)/(\w+)".into()),
+            replacement: "/$2/version-$1".into(),
+            ..Default::default()
+        };
+        assert_eq!(
+            rewrite(config(), "/v2/users/1?a=b"),
+            "/users/version-2/1?a=b"
+        );
+        assert_eq!(rewrite(config(), "/other?v1"), "/other?v1");
+
+        let config = PathRewriteConfig {
+            regex: Some("(".into()),
+            ..Default::default()
+        };
+        assert!(PathRewrite::new(&config).is_err());
+
+        // Compiled regex is cached (invalid ones are not)
+        assert!(REGEX_CACHE.contains_key(&r"^/v(\d+)/(\w+)".to_string()));
+        assert!(!REGEX_CACHE.contains_key(&"(".to_string()));
+    }
+}
@@ -0,0 +1,207 @@
+use std::time::Duration;
+
+use anyhow::{Context as _, Result};
+use futures::future::join_all;
+use ntex::http::client::Client as HttpClient;
+use ntex::http::Uri;
+use tracing::warn;
+
+use crate::config::UpstreamsConfig;
+use crate::http::allowlist::UpstreamAllowlist;
+
+/// Parses upstreams to warm up connections to, each of them must be allowed by the allowlist
+pub fn warmup_targets(config: &UpstreamsConfig) -> Result<Vec<Uri>> {
+    let allowlist =
+        UpstreamAllowlist::new(&config.allowlist).context("invalid upstreams allowlist")?;
+    config
+        .warmup
+        .iter()
+        .map(|target| {
+            let uri = target
+                .parse::<Uri>()
+                .with_context(|| format!("invalid warmup upstream `{target}`"))?;
+            anyhow::ensure!(
+                uri.scheme().is_some() && uri.host().is_some(),
+                "warmup upstream `{target}` must be an absolute uri"
+            );
+            allowlist.check(&uri).map_err(anyhow::Error::msg)?;
+            Ok(uri)
+        })
+        .collect()
+}
+
+/// Opens `connections` idle (keep-alive) connections to the upstream in the client pool.
+///
+/// Connections are established by sending concurrent `HEAD` requests to the upstream uri,
+/// they are returned to the pool when responses are received.
+/// Returns the number of successfully warmed connections.
+pub async fn warmup_connections(
+    client: &HttpClient,
+    upstream: &Uri,
+    connections: usize,
+    timeout: Duration,
+) -> usize {
+    let requests = (0..connections).map(|_| async {
+        let mut resp = client
+            .head(upstream)
+            .timeout(timeout)
+            .send()
+            .await
+            .map_err(|err| err.to_string())?;
+        // Read the (empty) body to release the connection back to the pool
+        resp.body().await.map_err(|err| err.to_string())?;
+        Ok::<_, String>(())
+    });
+
+    let mut warmed = 0;
+    for result in join_all(requests).await {
+        match result {
+            Ok(()) => warmed += 1,
+            Err(err) => warn!("failed to warm up connection to `{upstream}`: {err}"),
+        }
+    }
+    let authority = upstream
+        .authority()
+        .map(|a| a.to_string())
+        .unwrap_or_default();
+    upstream_warm_connections_add!(warmed as u64, "upstream" => authority);
+    warmed
+}
+
+/// Warms up connections to the upstream and (if `interval` is set) repeats it periodically
+/// to reuse idle connections before the pool closes them and to reopen closed ones.
+pub async fn keep_warm(
+    client: HttpClient,
+    upstream: Uri,
+    connections: usize,
+    timeout: Duration,
+    interval: Option<Duration>,
+) {
+    loop {
+        warmup_connections(&client, &upstream, connections, timeout).await;
+        match interval {
+            Some(interval) => tokio::time::sleep(interval).await,
+            None => return,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+
+    use ntex::web::{self, test, App};
+
+    use super::*;
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    #[ntex::test]
+    async fn test_warmup_connections() {
+        // Remote addresses of connections the requests are received on
+        let peers = Arc::new(Mutex::new(Vec::new()));
+        let peers2 = peers.clone();
+        let mock_server = test::server(move || {
+            let peers = peers2.clone();
+            App::new().service(web::resource("/").to(move |req: web::HttpRequest| {
+                peers
+                    .lock()
+                    .unwrap()
+                    .push((req.method().clone(), req.peer_addr().unwrap()));
+                async { "hello" }
+            }))
+        });
+        let upstream = format!("http://{}/", mock_server.addr()).parse().unwrap();
+
+        let client = HttpClient::new();
+        assert_eq!(warmup_connections(&client, &upstream, 3, TIMEOUT).await, 3);
+        let warm_peers = peers
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(_, addr)| *addr)
+            .collect::<HashSet<_>>();
+        assert_eq!(warm_peers.len(), 3);
+        assert!(peers
+            .lock()
+            .unwrap()
+            .iter()
+            .all(|(method, _)| method == "HEAD"));
+
+        // The first request reuses one of the warm connections
+        let mut resp = client.get(&upstream).send().await.unwrap();
+        assert_eq!(resp.body().await.unwrap(), "hello");
+        let (method, addr) = peers.lock().unwrap().last().cloned().unwrap();
+        assert_eq!(method, "GET");
+        assert!(warm_peers.contains(&addr));
+
+        // Unreachable upstream
+        let unreachable = "http://127.0.0.1:1/".parse().unwrap();
+        assert_eq!(
+            warmup_connections(&client, &unreachable, 2, TIMEOUT).await,
+            0
+        );
+
+        // Upstream accepting connections but never responding
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let stuck = format!("http://{}/", listener.local_addr().unwrap());
+        let stuck = stuck.parse().unwrap();
+        let timeout = Duration::from_millis(100);
+        let warmed = tokio::time::timeout(
+            Duration::from_secs(5),
+            warmup_connections(&client, &stuck, 2, timeout),
+        );
+        assert_eq!(warmed.await.unwrap(), 0);
+    }
+
+    #[ntex::test]
+    async fn test_keep_warm() {
+        let requests = Arc::new(AtomicUsize::new(0));
+        let requests2 = requests.clone();
+        let mock_server = test::server(move || {
+            let requests = requests2.clone();
+            App::new().service(web::resource("/").to(move || {
+                requests.fetch_add(1, Ordering::Relaxed);
+                async { "hello" }
+            }))
+        });
+        let upstream: Uri = format!("http://{}/", mock_server.addr()).parse().unwrap();
+        let client = HttpClient::new();
+
+        // Without interval connections are warmed up once
+        keep_warm(client.clone(), upstream.clone(), 2, TIMEOUT, None).await;
+        assert_eq!(requests.load(Ordering::Relaxed), 2);
+
+        // Warmup requests are repeated
+        let interval = Some(Duration::from_millis(10));
+        let task = ntex::rt::spawn(keep_warm(client, upstream, 2, TIMEOUT, interval));
+        for _ in 0..500 {
+            if requests.load(Ordering::Relaxed) >= 6 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        task.abort();
+        assert!(requests.load(Ordering::Relaxed) >= 6);
+    }
+
+    #[test]
+    fn test_warmup_targets() {
+        let config = |warmup: &[&str]| UpstreamsConfig {
+            allowlist: vec!["127.0.0.1".to_string(), "*.example.com".to_string()],
+            warmup: warmup.iter().map(|s| s.to_string()).collect(),
+            warmup_connections: 2,
+            warmup_timeout: 5.0,
+            warmup_interval: 0.0,
+        };
+
+        let targets = warmup_targets(&config(&["http://127.0.0.1:8080", "https://a.example.com"]));
+        assert_eq!(targets.unwrap().len(), 2);
+
+        let err = warmup_targets(&config(&["http://10.0.0.1"])).unwrap_err();
+        assert_eq!(err.to_string(), "upstream `10.0.0.1` is not allowed");
+        assert!(warmup_targets(&config(&["/path"])).is_err());
+    }
+}
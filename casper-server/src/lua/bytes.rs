@@ -1,7 +1,15 @@
-use mlua::{Lua, MetaMethod, Result as LuaResult, UserDataMethods};
-use ntex::util::Bytes;
+use mlua::{AnyUserData, Lua, MetaMethod, Result as LuaResult, Table, UserDataMethods};
+use ntex::util::{Bytes, BytesMut};
+
+use super::FlexBytes;
 
 /*
+--- @class bytes
+--- @tag module
+---
+--- Built-in module for working with bytes.
+local bytes = {}
+
 --- @class Bytes
 ---
 --- Built-in type for working with bytes
@@ -31,6 +39,36 @@ pub fn register_types(lua: &Lua) -> LuaResult<()> {
         */
         reg.add_method("is_empty", |_, this, ()| Ok(this.is_empty()));
 
+        /*
+        --- @within Bytes
+        --- Returns a slice of the bytes object that starts at `i` and continues until `j`.
+        ---
+        --- Indices follow the `string.sub` rules (1-based, negative values count from the end).
+        --- The slice shares memory with the original object (no copy is made).
+        function Bytes:sub(i: number, j: number?): Bytes
+            return nil :: any
+        end
+        */
+        reg.add_method("sub", |lua, this, (i, j): (i64, Option<i64>)| {
+            let (start, end) = slice_range(this.len(), i, j.unwrap_or(-1));
+            lua.create_any_userdata(this.slice(start..end))
+        });
+
+        /*
+        --- @within Bytes
+        --- Finds the first occurrence of `needle` in the bytes object.
+        ---
+        --- Returns the start and end indices (1-based) of the occurrence or nil if not found.
+        function Bytes:find(needle: Bytes | string): (number?, number?)
+            return nil :: any
+        end
+        */
+        reg.add_method("find", |_, this, needle: FlexBytes| {
+            let (pos, len) =
+                needle.borrow_bytes(|needle| (find_subslice(this, needle), needle.len()));
+            Ok(pos.map(|pos| (pos + 1, pos + len)).unzip())
+        });
+
         /*
         --- @within Bytes
         --- Returns the bytes object as a Lua string.
@@ -54,5 +92,86 @@ pub fn register_types(lua: &Lua) -> LuaResult<()> {
 }
 
 /*
-return {}
+--- @within bytes
+--- Concatenates a list of bytes objects (or strings) into a new bytes object.
+function bytes.concat(parts: {Bytes | string}): Bytes
+    return nil :: any
+end
 */
+fn concat(lua: &Lua, parts: Vec<FlexBytes>) -> LuaResult<AnyUserData> {
+    let len = parts.iter().map(|p| p.borrow_bytes(|b| b.len())).sum();
+    let mut buf = BytesMut::with_capacity(len);
+    for part in &parts {
+        part.borrow_bytes(|b| buf.extend_from_slice(b));
+    }
+    lua.create_any_userdata(buf.freeze())
+}
+
+/// Converts Lua-style (1-based, inclusive, possibly negative) indices to a byte range
+fn slice_range(len: usize, i: i64, j: i64) -> (usize, usize) {
+    let len = len as i64;
+    let normalize = |n: i64| if n < 0 { len + n + 1 } else { n };
+    let start = normalize(i).max(1);
+    let end = normalize(j).min(len);
+    if start > end {
+        return (0, 0);
+    }
+    (start as usize - 1, end as usize)
+}
+
+fn find_subslice(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    if needle.is_empty() {
+        return Some(0);
+    }
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+pub fn create_module(lua: &Lua) -> LuaResult<Table> {
+    lua.create_table_from([("concat", lua.create_function(concat)?)])
+}
+
+/*
+return bytes
+*/
+
+#[cfg(test)]
+mod tests {
+    use mlua::{chunk, Lua, Result};
+    use ntex::util::Bytes;
+
+    #[test]
+    fn test_bytes() -> Result<()> {
+        let lua = Lua::new();
+        super::register_types(&lua)?;
+
+        let bytes = super::create_module(&lua)?;
+        let data = lua.create_any_userdata(Bytes::from_static(b"hello, world"))?;
+        lua.load(chunk! {
+            local data = $data
+            assert(data:len() == 12)
+
+            // Slicing
+            assert(data:sub(1, 5):to_string() == "hello")
+            assert(data:sub(8):to_string() == "world")
+            assert(data:sub(-5, -1):to_string() == "world")
+            assert(data:sub(0, 100):to_string() == "hello, world", "out of range indices should be clamped")
+            assert(data:sub(5, 4):is_empty())
+            assert(data:sub(13):is_empty())
+            assert(data:sub(-100, 1):to_string() == "h")
+
+            // Search
+            local i, j = data:find("world")
+            assert(i == 8 and j == 12)
+            assert(data:find(data:sub(1, 2)) == 1)
+            assert(data:find("xyz") == nil)
+
+            // Concatenation
+            local result = $bytes.concat({data:sub(1, 5), " and ", data:sub(8)})
+            assert(result:to_string() == "hello and world")
+            assert($bytes.concat({}):is_empty())
+        })
+        .exec()
+    }
+}
@@ -1,10 +1,236 @@
+use std::collections::HashMap;
 use std::env;
+use std::ffi::c_void;
 use std::process;
-use std::time::Duration;
+use std::time::SystemTime;
 
-use mlua::{Lua, LuaSerdeExt, Result as LuaResult, Table};
+use mlua::{
+    ExternalError, Function, Lua, LuaSerdeExt, Result as LuaResult, Table, UserDataRef, Value,
+};
+use ntex::http::header::{HeaderMap, CACHE_CONTROL, DATE, EXPIRES, LAST_MODIFIED};
+use serde::Deserialize;
 
-use super::{LuaRequest, LuaResponse};
+use super::{LuaRequest, LuaResponse, LuaStorageChain};
+use crate::http::cache_control::{self, CacheControl};
+use crate::storage::Backend;
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum TrailingSlash {
+    Strip,
+    Add,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct CanonicalizePathOptions {
+    trailing_slash: Option<TrailingSlash>,
+    index: Option<String>,
+}
+
+/// Normalizes a request path to be used as part of a cache key.
+///
+/// The `index` file name (if set) is removed from the end of the path,
+/// then the trailing slash is stripped or added according to the `trailing_slash` mode.
+/// The query string (if any) is kept as is.
+fn canonicalize_path(path: &str, options: &CanonicalizePathOptions) -> String {
+    let (path, query) = match path.split_once('?') {
+        Some((path, query)) => (path, Some(query)),
+        None => (path, None),
+    };
+    let mut path = path.to_string();
+    if !path.starts_with('/') {
+        path.insert(0, '/');
+    }
+
+    // Remove index file name, leaving the directory path (e.g. `/foo/index.html` -> `/foo/`)
+    if let Some(index) = options.index.as_deref().filter(|i| !i.is_empty()) {
+        if path.rsplit('/').next() == Some(index) {
+            path.truncate(path.len() - index.len());
+        }
+    }
+
+    match options.trailing_slash {
+        Some(TrailingSlash::Strip) => {
+            let len = path.trim_end_matches('/').len();
+            path.truncate(len.max(1));
+        }
+        Some(TrailingSlash::Add) if !path.ends_with('/') => path.push('/'),
+        _ => {}
+    }
+    if let Some(query) = query {
+        path.push('?');
+        path.push_str(query);
+    }
+    path
+}
+
+#[derive(Debug, Deserialize)]
+struct DefaultKeyOptions {
+    #[serde(default = "DefaultKeyOptions::default_include_scheme_host")]
+    include_scheme_host: bool,
+}
+
+impl DefaultKeyOptions {
+    const fn default_include_scheme_host() -> bool {
+        true
+    }
+}
+
+impl Default for DefaultKeyOptions {
+    fn default() -> Self {
+        DefaultKeyOptions {
+            include_scheme_host: Self::default_include_scheme_host(),
+        }
+    }
+}
+
+/// Builds the default cache key parts for a request.
+///
+/// The key consists of the request scheme, host and path with query, so the same path
+/// on different hosts never collides. Single-host deployments can set `include_scheme_host`
+/// to `false` to key by path and query only.
+///
+/// Changing `include_scheme_host` changes every calculated key, which effectively
+/// invalidates all previously cached responses.
+fn default_key(req: &LuaRequest, options: &DefaultKeyOptions) -> Vec<String> {
+    let path_and_query = req
+        .uri()
+        .path_and_query()
+        .map(|pq| pq.as_str())
+        .unwrap_or("/")
+        .to_string();
+    if !options.include_scheme_host {
+        return vec![path_and_query];
+    }
+    let host = req.host().to_ascii_lowercase();
+    vec![req.scheme(), host, path_and_query]
+}
+
+#[derive(Debug, Deserialize)]
+struct HeuristicTtlOptions {
+    #[serde(default = "HeuristicTtlOptions::default_factor")]
+    factor: f64,
+    max: Option<u64>,
+}
+
+impl HeuristicTtlOptions {
+    const fn default_factor() -> f64 {
+        0.1
+    }
+}
+
+impl Default for HeuristicTtlOptions {
+    fn default() -> Self {
+        HeuristicTtlOptions {
+            factor: Self::default_factor(),
+            max: None,
+        }
+    }
+}
+
+/// Calculates a heuristic freshness lifetime (in seconds) of a response.
+///
+/// The lifetime is a `factor` of the time elapsed since `Last-Modified` until `Date`
+/// (or now if the response has no `Date` header), capped by `max` seconds.
+///
+/// Returns `None` if the response has explicit freshness information (`Expires`,
+/// `max-age` or `s-maxage`) or no valid `Last-Modified` header.
+fn heuristic_ttl(headers: &HeaderMap, options: &HeuristicTtlOptions) -> Option<u64> {
+    let explicit_ttl = (headers.get_all(CACHE_CONTROL))
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(cache_control::parse_directives)
+        .any(|(name, _)| name == "max-age" || name == "s-maxage");
+    if explicit_ttl || headers.contains_key(EXPIRES) {
+        return None;
+    }
+
+    let parse_date = |name| {
+        let value = headers.get(name)?.to_str().ok()?;
+        httpdate::parse_http_date(value).ok()
+    };
+    let last_modified = parse_date(LAST_MODIFIED)?;
+    let date = parse_date(DATE).unwrap_or_else(SystemTime::now);
+    // `Last-Modified` in the future gives zero lifetime
+    let age = date.duration_since(last_modified).unwrap_or_default();
+    let ttl = (age.as_secs_f64() * options.factor) as u64;
+    Some(options.max.map_or(ttl, |max| ttl.min(max)))
+}
+
+/// Parses a `Cache-Control` header value into a table of directives.
+///
+/// Directive names are lowercased with dashes replaced by underscores (e.g. `max_age`).
+/// Numeric arguments are converted to integers, directives without arguments are set to `true`.
+fn parse_cache_control(lua: &Lua, value: String) -> LuaResult<Table> {
+    let directives = cache_control::parse_directives(&value);
+    let table = lua.create_table_with_capacity(0, directives.len())?;
+    for (name, arg) in directives {
+        let name = name.replace('-', "_");
+        match arg {
+            None => table.raw_set(name, true)?,
+            Some(arg) => match arg.parse::<i64>() {
+                Ok(n) => table.raw_set(name, n)?,
+                Err(_) => table.raw_set(name, arg)?,
+            },
+        }
+    }
+    Ok(table)
+}
+
+/// Makes a deep copy of the value.
+///
+/// Tables are copied recursively (including keys), cycles are preserved.
+/// Metatables are shared with the original tables. Other values are returned as is.
+fn deepcopy(lua: &Lua, value: Value, seen: &mut HashMap<*const c_void, Table>) -> LuaResult<Value> {
+    let table = match value {
+        Value::Table(table) => table,
+        value => return Ok(value),
+    };
+    if let Some(copy) = seen.get(&table.to_pointer()) {
+        return Ok(Value::Table(copy.clone()));
+    }
+
+    let copy = lua.create_table()?;
+    seen.insert(table.to_pointer(), copy.clone());
+    for pair in table.pairs::<Value, Value>() {
+        let (key, value) = pair?;
+        let key = deepcopy(lua, key, seen)?;
+        let value = deepcopy(lua, value, seen)?;
+        copy.raw_set(key, value)?;
+    }
+    copy.set_metatable(table.metatable());
+    Ok(Value::Table(copy))
+}
+
+/// Returns a read-only proxy for the table.
+///
+/// Any attempt to set a field of the proxy raises an error.
+/// The proxy is shallow: nested tables are returned as is.
+fn freeze(lua: &Lua, table: Table) -> LuaResult<Table> {
+    let next: Function = lua.globals().raw_get("next")?;
+    let metatable = lua.create_table()?;
+    metatable.raw_set("__index", table.clone())?;
+    metatable.raw_set(
+        "__newindex",
+        lua.create_function(|_, (_, key): (Value, Value)| -> LuaResult<()> {
+            let key = key.to_string().unwrap_or_default();
+            Err(format!("attempt to modify a frozen table (key '{key}')").into_lua_err())
+        })?,
+    )?;
+    let inner = table.clone();
+    metatable.raw_set(
+        "__len",
+        lua.create_function(move |_, _: Value| Ok(inner.raw_len()))?,
+    )?;
+    metatable.raw_set(
+        "__iter",
+        lua.create_function(move |_, _: Value| Ok((next.clone(), table.clone())))?,
+    )?;
+    metatable.raw_set("__metatable", "frozen")?;
+
+    let proxy = lua.create_table()?;
+    proxy.set_metatable(Some(metatable));
+    Ok(proxy)
+}
 
 pub fn create_module(lua: &Lua) -> LuaResult<Table> {
     // Register data types
@@ -14,8 +240,13 @@ pub fn create_module(lua: &Lua) -> LuaResult<Table> {
 
     core.set("Request", lua.create_proxy::<LuaRequest>()?)?;
     core.set("Response", lua.create_proxy::<LuaResponse>()?)?;
+    core.set(
+        "StorageChain",
+        lua.create_proxy::<LuaStorageChain<Backend>>()?,
+    )?;
 
     // Modules
+    core.set("bytes", super::bytes::create_module(lua)?)?;
     core.set("crypto", super::crypto::create_module(lua)?)?;
     core.set("csv", super::csv::create_module(lua)?)?;
     core.set("datetime", super::datetime::create_module(lua)?)?;
@@ -24,8 +255,11 @@ pub fn create_module(lua: &Lua) -> LuaResult<Table> {
     core.set("json", super::json::create_module(lua)?)?;
     core.set("log", super::log::create_module(lua)?)?;
     core.set("metrics", super::metrics::create_module(lua)?)?;
+    core.set("ratelimit", super::ratelimit::create_module(lua)?)?;
     core.set("regex", super::regex::create_module(lua)?)?;
     core.set("tasks", super::tasks::create_module(lua)?)?;
+    core.set("template", super::template::create_module(lua)?)?;
+    core.set("time", super::time::create_module(lua)?)?;
     core.set("trace", super::trace::create_module(lua)?)?;
     core.set("udp", super::udp::create_module(lua)?)?;
     core.set("uri", super::uri::create_module(lua)?)?;
@@ -39,13 +273,7 @@ pub fn create_module(lua: &Lua) -> LuaResult<Table> {
     core.set("pid", process::id())?;
 
     // Helper functions
-    core.set(
-        "sleep",
-        lua.create_async_function(|_, secs: f64| async move {
-            tokio::time::sleep(Duration::from_secs_f64(secs)).await;
-            Ok(())
-        })?,
-    )?;
+    core.set("sleep", lua.create_async_function(super::time::sleep)?)?;
     core.set(
         "yield",
         lua.create_async_function(|_, ()| async {
@@ -53,6 +281,63 @@ pub fn create_module(lua: &Lua) -> LuaResult<Table> {
             Ok(())
         })?,
     )?;
+    core.set(
+        "canonicalize_path",
+        lua.create_function(|lua, (path, options): (String, Option<Value>)| {
+            let options = match options {
+                Some(options) => lua.from_value::<CanonicalizePathOptions>(options)?,
+                None => CanonicalizePathOptions::default(),
+            };
+            Ok(canonicalize_path(&path, &options))
+        })?,
+    )?;
+    core.set(
+        "default_key",
+        lua.create_function(
+            |lua, (req, options): (UserDataRef<LuaRequest>, Option<Value>)| {
+                let options = match options {
+                    Some(options) => lua.from_value::<DefaultKeyOptions>(options)?,
+                    None => DefaultKeyOptions::default(),
+                };
+                Ok(default_key(&req, &options))
+            },
+        )?,
+    )?;
+    core.set(
+        "build_cache_control",
+        lua.create_function(|lua, directives: Value| {
+            let cache_control = lua.from_value::<CacheControl>(directives)?;
+            Ok(cache_control.to_string())
+        })?,
+    )?;
+    core.set(
+        "parse_cache_control",
+        lua.create_function(parse_cache_control)?,
+    )?;
+    core.set(
+        "heuristic_ttl",
+        lua.create_function(
+            |lua, (resp, options): (UserDataRef<LuaResponse>, Option<Value>)| {
+                let options = match options {
+                    Some(options) => lua.from_value::<HeuristicTtlOptions>(options)?,
+                    None => HeuristicTtlOptions::default(),
+                };
+                if !options.factor.is_finite() || options.factor < 0.0 {
+                    return Err("`factor` must be a non-negative number".into_lua_err());
+                }
+                Ok(heuristic_ttl(resp.headers(), &options))
+            },
+        )?,
+    )?;
+    core.set(
+        "deepcopy",
+        lua.create_function(|lua, value: Value| deepcopy(lua, value, &mut HashMap::new()))?,
+    )?;
+    core.set("freeze", lua.create_function(freeze)?)?;
+    core.set(
+        "global_counter",
+        lua.create_function(super::counters::global_counter)?,
+    )?;
     core.set(
         "getenv",
         lua.create_function(|_, key: String| Ok(env::var(key).ok()))?,
@@ -64,3 +349,219 @@ pub fn create_module(lua: &Lua) -> LuaResult<Table> {
 
     Ok(core)
 }
+
+#[cfg(test)]
+mod tests {
+    use mlua::{chunk, Lua, Result};
+
+    #[test]
+    fn test_canonicalize_path() -> Result<()> {
+        let lua = Lua::new();
+
+        let core = super::create_module(&lua)?;
+        lua.load(chunk! {
+            local canonicalize_path = $core.canonicalize_path
+
+            // No options
+            assert(canonicalize_path("/foo/") == "/foo/")
+            assert(canonicalize_path("foo") == "/foo")
+
+            // Strip trailing slash
+            local strip = { trailing_slash = "strip" }
+            assert(canonicalize_path("/foo", strip) == "/foo")
+            assert(canonicalize_path("/foo/", strip) == "/foo")
+            assert(canonicalize_path("/foo//", strip) == "/foo")
+            assert(canonicalize_path("/", strip) == "/")
+
+            // Add trailing slash
+            local add = { trailing_slash = "add" }
+            assert(canonicalize_path("/foo", add) == "/foo/")
+            assert(canonicalize_path("/foo/", add) == "/foo/")
+            assert(canonicalize_path("/", add) == "/")
+
+            // Index handling
+            assert(canonicalize_path("/foo/index.html", { index = "index.html" }) == "/foo/")
+            assert(canonicalize_path("/foo/index.html", { index = "index.html", trailing_slash = "strip" }) == "/foo")
+            assert(canonicalize_path("/index.html", { index = "index.html", trailing_slash = "strip" }) == "/")
+            assert(canonicalize_path("/foo/myindex.html", { index = "index.html" }) == "/foo/myindex.html")
+            assert(canonicalize_path("/foo/index.html/bar", { index = "index.html", trailing_slash = "add" }) == "/foo/index.html/bar/")
+
+            // Query string is kept as is
+            assert(canonicalize_path("/foo/?a=/b/", strip) == "/foo?a=/b/")
+            assert(canonicalize_path("/foo?a=1", add) == "/foo/?a=1")
+            assert(canonicalize_path("/foo/index.html?a=index.html", { index = "index.html" }) == "/foo/?a=index.html")
+            assert(canonicalize_path("/?", strip) == "/?")
+
+            // Invalid mode
+            local ok, err = pcall(canonicalize_path, "/foo", { trailing_slash = "keep" })
+            assert(not ok and tostring(err):find("unknown variant") ~= nil)
+        })
+        .exec()
+    }
+
+    #[test]
+    fn test_default_key() -> Result<()> {
+        let lua = Lua::new();
+
+        let core = super::create_module(&lua)?;
+        lua.load(chunk! {
+            local core = $core
+            local function key(host, uri, options)
+                local req = core.Request.new({ uri = uri, headers = { host = host } })
+                return table.concat(core.default_key(req, options), "|")
+            end
+
+            // Scheme and host are included by default
+            assert(key("a.example", "https://a.example/x?y=1") == "https|a.example|/x?y=1")
+            assert(key("a.example", "/x") == "http|a.example|/x")
+            assert(key("a.example", "/x") ~= key("b.example", "/x"), "different hosts must not collide")
+            assert(key("a.example", "/x") == key("A.Example", "/x"), "host must be case-insensitive")
+            assert(key("a.example", "/x") ~= key("a.example", "https://a.example/x"))
+
+            // Same host collapses to the same key
+            assert(key("a.example", "/x?y=1") == key("a.example", "/x?y=1"))
+
+            // Exclude scheme and host for single-host deployments
+            local opts = { include_scheme_host = false }
+            assert(key("a.example", "/x", opts) == "/x")
+            assert(key("a.example", "/x", opts) == key("b.example", "https://b.example/x", opts))
+        })
+        .exec()
+    }
+
+    #[test]
+    fn test_cache_control() -> Result<()> {
+        let lua = Lua::new();
+
+        let core = super::create_module(&lua)?;
+        let header = r#"Public, max-age=60, no-transform, community="UCI""#;
+        lua.load(chunk! {
+            local core = $core
+            local build = core.build_cache_control
+
+            assert(build({ public = true, max_age = 60 }) == "public, max-age=60")
+            assert(build({ max_age = 0, no_cache = true }) == "no-cache, max-age=0")
+            assert(build({ public = true, max_age = 60, no_store = true }) == "no-store")
+            assert(build({ public = true, private = true, s_maxage = 60 }) == "private")
+            assert(build({}) == "")
+            local ok, err = pcall(build, { max_agee = 60 })
+            assert(not ok and tostring(err):find("unknown field") ~= nil)
+
+            local cc = core.parse_cache_control($header)
+            assert(cc.public == true and cc.no_transform == true)
+            assert(cc.max_age == 60)
+            assert(cc.community == "UCI")
+            assert(cc.private == nil)
+
+            local resp = core.Response.new()
+            resp:set_cache_control({ private = true, max_age = 30, must_revalidate = true })
+            assert(resp:header("cache-control") == "private, must-revalidate, max-age=30")
+            resp:set_cache_control({})
+            assert(resp:header("cache-control") == nil)
+        })
+        .exec()
+    }
+
+    #[test]
+    fn test_heuristic_ttl() -> Result<()> {
+        let lua = Lua::new();
+
+        let core = super::create_module(&lua)?;
+        lua.load(chunk! {
+            local core = $core
+            local resp = core.Response.new()
+            resp:set_header("date", "Wed, 21 Oct 2015 07:28:00 GMT")
+
+            // No `Last-Modified`
+            assert(core.heuristic_ttl(resp) == nil)
+
+            // 10 days since last modification
+            resp:set_header("last-modified", "Sun, 11 Oct 2015 07:28:00 GMT")
+            assert(core.heuristic_ttl(resp) == 86400)
+            assert(core.heuristic_ttl(resp, { factor = 0.05 }) == 43200)
+            assert(core.heuristic_ttl(resp, { max = 3600 }) == 3600)
+
+            // Modified in the future
+            resp:set_header("last-modified", "Thu, 22 Oct 2015 07:28:00 GMT")
+            assert(core.heuristic_ttl(resp) == 0)
+
+            // Relative to the current time if `Date` is missing
+            resp:set_header("last-modified", "Sun, 11 Oct 2015 07:28:00 GMT")
+            resp:del_header("date")
+            assert(core.heuristic_ttl(resp) > 86400)
+
+            // Explicit freshness information
+            resp:set_header("cache-control", "public, max-age=60")
+            assert(core.heuristic_ttl(resp) == nil)
+            resp:set_header("cache-control", "public")
+            assert(core.heuristic_ttl(resp) ~= nil)
+            resp:set_header("expires", "Thu, 22 Oct 2015 07:28:00 GMT")
+            assert(core.heuristic_ttl(resp) == nil)
+
+            local ok, err = pcall(core.heuristic_ttl, resp, { factor = -1 })
+            assert(not ok and tostring(err):find("must be a non%-negative number") ~= nil)
+        })
+        .exec()
+    }
+
+    #[test]
+    fn test_deepcopy() -> Result<()> {
+        let lua = Lua::new();
+
+        let core = super::create_module(&lua)?;
+        lua.load(chunk! {
+            local deepcopy = $core.deepcopy
+
+            local mt = { __index = { hello = "world" } }
+            local orig = setmetatable({ a = 1, nested = { list = {1, 2, 3} } }, mt)
+            orig.self = orig
+            local copy = deepcopy(orig)
+
+            // Copy is equal but independent
+            assert(copy ~= orig and copy.nested ~= orig.nested)
+            assert(copy.a == 1 and #copy.nested.list == 3)
+            copy.nested.list[1] = 100
+            copy.a = 2
+            assert(orig.nested.list[1] == 1 and orig.a == 1)
+
+            // Cycles and metatables are preserved
+            assert(copy.self == copy)
+            assert(getmetatable(copy) == mt and copy.hello == "world")
+
+            // Non-table values
+            assert(deepcopy(1) == 1 and deepcopy("abc") == "abc" and deepcopy(nil) == nil)
+        })
+        .exec()
+    }
+
+    #[test]
+    fn test_freeze() -> Result<()> {
+        let lua = Lua::new();
+
+        let core = super::create_module(&lua)?;
+        lua.load(chunk! {
+            local config = { name = "casper", list = {1, 2, 3}, 10, 20 }
+            local frozen = $core.freeze(config)
+
+            // Reading works
+            assert(frozen.name == "casper" and frozen.list[2] == 2)
+            assert(#frozen == 2)
+            local keys = 0
+            for k, v in frozen do
+                assert(config[k] == v)
+                keys += 1
+            end
+            assert(keys == 4)
+
+            // Mutation fails
+            local ok, err = pcall(function() frozen.name = "other" end)
+            assert(not ok and tostring(err):find("attempt to modify a frozen table") ~= nil)
+            ok = pcall(function() frozen.new_key = true end)
+            assert(not ok)
+            ok = pcall(setmetatable, frozen, {})
+            assert(not ok, "metatable must be protected")
+            assert(config.name == "casper" and config.new_key == nil)
+        })
+        .exec()
+    }
+}
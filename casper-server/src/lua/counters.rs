@@ -0,0 +1,106 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::Arc;
+
+use mlua::{Lua, Result, UserData, UserDataMethods};
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
+
+// Process-wide registry of counters shared by all workers
+static REGISTRY: Lazy<RwLock<HashMap<String, Arc<AtomicI64>>>> = Lazy::new(Default::default);
+
+/// Named counter shared by all workers of the process.
+///
+/// Unlike metrics, the counter value can be read back. Counters are never removed.
+#[derive(Clone, Debug)]
+pub struct GlobalCounter(Arc<AtomicI64>);
+
+impl GlobalCounter {
+    /// Returns the counter with the given name, creating it (starting from zero) if missing
+    pub fn get_or_create(name: &str) -> Self {
+        if let Some(counter) = REGISTRY.read().get(name) {
+            return GlobalCounter(counter.clone());
+        }
+        let mut registry = REGISTRY.write();
+        let counter = registry.entry(name.to_string()).or_default();
+        GlobalCounter(counter.clone())
+    }
+
+    /// Adds `n` (can be negative) to the counter and returns its new value
+    pub fn add(&self, n: i64) -> i64 {
+        self.0.fetch_add(n, Ordering::Relaxed).wrapping_add(n)
+    }
+
+    pub fn get(&self) -> i64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+impl UserData for GlobalCounter {
+    fn add_methods<M: UserDataMethods<Self>>(methods: &mut M) {
+        methods.add_method("inc", |_, this, ()| Ok(this.add(1)));
+        methods.add_method("add", |_, this, n: i64| Ok(this.add(n)));
+        methods.add_method("get", |_, this, ()| Ok(this.get()));
+    }
+}
+
+pub fn global_counter(_: &Lua, name: String) -> Result<GlobalCounter> {
+    Ok(GlobalCounter::get_or_create(&name))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use mlua::{chunk, Lua, Result};
+
+    #[test]
+    fn test_global_counter() -> Result<()> {
+        let lua = Lua::new();
+        lua.globals().set(
+            "global_counter",
+            lua.create_function(super::global_counter)?,
+        )?;
+
+        lua.load(chunk! {
+            local counter = global_counter("test_counter")
+            assert(counter:get() == 0)
+            assert(counter:inc() == 1)
+            assert(counter:add(10) == 11)
+            assert(counter:add(-3) == 8)
+            // The same counter is returned by name
+            assert(global_counter("test_counter"):get() == 8)
+            assert(global_counter("test_counter2"):get() == 0)
+        })
+        .exec()
+    }
+
+    #[test]
+    fn test_global_counter_workers() {
+        const WORKERS: i64 = 8;
+        const ITERATIONS: i64 = 10_000;
+
+        // Each worker has its own Lua VM running in a separate thread
+        let workers = (0..WORKERS)
+            .map(|_| {
+                thread::spawn(|| {
+                    let lua = Lua::new();
+                    let global_counter = lua.create_function(super::global_counter)?;
+                    lua.load(chunk! {
+                        for i = 1, $ITERATIONS do
+                            local counter = $global_counter("workers_counter")
+                            if i % 2 == 0 then counter:inc() else counter:add(2) end
+                        end
+                    })
+                    .exec()
+                })
+            })
+            .collect::<Vec<_>>();
+        for worker in workers {
+            worker.join().unwrap().unwrap();
+        }
+
+        let total = super::GlobalCounter::get_or_create("workers_counter").get();
+        assert_eq!(total, WORKERS * ITERATIONS / 2 * 3);
+    }
+}
@@ -10,6 +10,7 @@ use serde_json::{
 };
 
 use super::FlexBytes;
+use crate::utils;
 
 /*
 local bytes = require("@core/bytes")
@@ -101,6 +102,49 @@ fn json_digest(_: &Lua, input: FlexBytes) -> Result<StdResult<String, String>> {
     Ok(Ok(hex::encode(hash.as_bytes())))
 }
 
+//
+// Message authentication
+//
+
+/*
+--- @within crypto
+--- Returns the raw HMAC-SHA256 signature of the message.
+---
+--- @param key The secret key.
+--- @param message The message to sign.
+function crypto.hmac_sha256(key: Bytes | string, message: Bytes | string): string
+    return nil :: any
+end
+*/
+fn hmac_sha256(lua: &Lua, (key, message): (FlexBytes, FlexBytes)) -> Result<LuaString> {
+    let signature = key
+        .borrow_bytes(|k| message.borrow_bytes(|m| utils::crypto::hmac_sha256(k, m)))
+        .into_lua_err()?;
+    lua.create_string(signature)
+}
+
+/*
+--- @within crypto
+--- Verifies the raw HMAC-SHA256 signature of the message.
+--- The signatures are compared in constant time.
+---
+--- @param key The secret key.
+--- @param message The signed message.
+--- @param signature The raw signature to verify.
+function crypto.hmac_verify(key: Bytes | string, message: Bytes | string, signature: Bytes | string): boolean
+    return nil :: any
+end
+*/
+fn hmac_verify(
+    _: &Lua,
+    (key, message, signature): (FlexBytes, FlexBytes, FlexBytes),
+) -> Result<bool> {
+    let expected = key
+        .borrow_bytes(|k| message.borrow_bytes(|m| utils::crypto::hmac_sha256(k, m)))
+        .into_lua_err()?;
+    Ok(signature.borrow_bytes(|s| utils::crypto::constant_time_eq(s, &expected)))
+}
+
 //
 // Encryption
 //
@@ -227,6 +271,9 @@ pub fn create_module(lua: &Lua) -> Result<Table> {
         ("sha256", lua.create_function(sha256)?),
         ("blake3", lua.create_function(blake3)?),
         ("json_digest", lua.create_function(json_digest)?),
+        // Message authentication
+        ("hmac_sha256", lua.create_function(hmac_sha256)?),
+        ("hmac_verify", lua.create_function(hmac_verify)?),
         // Encryption
         ("encrypt", lua.create_async_function(encrypt)?),
         ("decrypt", lua.create_async_function(decrypt)?),
@@ -284,6 +331,35 @@ mod tests {
         .await
     }
 
+    #[ntex::test]
+    async fn test_hmac() -> Result<()> {
+        let lua = Lua::new();
+
+        let crypto = super::create_module(&lua)?;
+        lua.globals().set(
+            "hex_encode",
+            Function::wrap(|x: BString| Ok(hex::encode(x))),
+        )?;
+
+        // Test vector from RFC 4231 (test case 2)
+        lua.load(chunk! {
+            local key, message = "Jefe", "what do ya want for nothing?"
+            local signature = $crypto.hmac_sha256(key, message)
+            assert(
+                hex_encode(signature) == "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843",
+                "hmac-sha256 signature mismatch"
+            )
+            assert($crypto.hmac_verify(key, message, signature) == true, "signature must be valid")
+
+            // Tampered message, wrong key or truncated signature
+            assert($crypto.hmac_verify(key, message.."!", signature) == false, "tampered message must fail")
+            assert($crypto.hmac_verify("jefe", message, signature) == false, "wrong key must fail")
+            assert($crypto.hmac_verify(key, message, signature:sub(1, 16)) == false, "truncated signature must fail")
+        })
+        .exec_async()
+        .await
+    }
+
     #[ntex::test]
     async fn test_encrypt_decrypt() -> Result<()> {
         let lua = Lua::new();
@@ -2,11 +2,16 @@ use std::cell::RefCell;
 use std::io;
 use std::rc::Rc;
 
-use csv::ByteRecord;
-use mlua::{ExternalResult, Lua, Result, String as LuaString, Table};
-use ntex::util::BytesMut;
+use csv::{ByteRecord, QuoteStyle};
+use mlua::{
+    ErrorContext as _, ExternalResult, FromLua, Function, IntoLua, Lua, Result,
+    String as LuaString, Table, Value,
+};
+use ntex::util::{Bytes, BytesMut};
 
-// TODO: Full implementation of CSV writer and reader
+use super::LuaBody;
+
+// TODO: Full implementation of CSV reader
 
 /*
 --- @class CSV
@@ -84,8 +89,190 @@ fn encode_record(lua: &Lua, record: Table) -> Result<LuaString> {
     lua.create_string(s)
 }
 
+// Source of rows to encode: a Lua array or an iterator function
+enum Rows {
+    Array(Table, usize),
+    Iter(Function),
+}
+
+impl Rows {
+    fn next(&mut self) -> Result<Option<Table>> {
+        match self {
+            Rows::Array(rows, i) => {
+                *i += 1;
+                rows.raw_get(*i)
+            }
+            Rows::Iter(func) => func.call(()),
+        }
+    }
+}
+
+impl FromLua for Rows {
+    fn from_lua(value: Value, lua: &Lua) -> Result<Self> {
+        match value {
+            Value::Function(func) => Ok(Rows::Iter(func)),
+            value => Ok(Rows::Array(Table::from_lua(value, lua)?, 0)),
+        }
+    }
+}
+
+// Encodes rows one by one according to the options
+struct RowsEncoder {
+    writer: csv::Writer<BytesMutCell>,
+    output: BytesMutCell,
+    columns: Option<Vec<LuaString>>,
+    header: bool,
+    done: bool,
+}
+
+impl RowsEncoder {
+    fn new(opts: Option<Table>) -> Result<Self> {
+        let (mut delimiter, mut quote_style) = (b',', QuoteStyle::Necessary);
+        let (mut columns, mut header) = (None, None);
+        if let Some(opts) = opts {
+            if let Some(delim) = opts.raw_get::<Option<LuaString>>("delimiter")? {
+                match *delim.as_bytes() {
+                    [delim] => delimiter = delim,
+                    _ => return Err("delimiter must be a single byte").into_lua_err(),
+                }
+            }
+            if let Some(quote) = opts.raw_get::<Option<String>>("quote")? {
+                quote_style = match quote.as_str() {
+                    "necessary" => QuoteStyle::Necessary,
+                    "always" => QuoteStyle::Always,
+                    "non_numeric" => QuoteStyle::NonNumeric,
+                    "never" => QuoteStyle::Never,
+                    _ => return Err(format!("invalid quote style `{quote}`")).into_lua_err(),
+                };
+            }
+            columns = opts.raw_get::<Option<Vec<LuaString>>>("columns")?;
+            header = opts.raw_get::<Option<bool>>("header")?;
+        }
+
+        let output = BytesMutCell(Rc::new(RefCell::new(BytesMut::new())));
+        let writer = csv::WriterBuilder::new()
+            .delimiter(delimiter)
+            .quote_style(quote_style)
+            .flexible(true)
+            .has_headers(false)
+            .from_writer(output.clone());
+        // Header is emitted by default if columns are set
+        let header = header.unwrap_or(columns.is_some());
+        Ok(RowsEncoder {
+            writer,
+            output,
+            columns,
+            header,
+            done: false,
+        })
+    }
+
+    /// Encodes the header (if not yet) and the next row.
+    /// Returns `None` if there are no more rows.
+    fn encode_next(&mut self, rows: &mut Rows) -> Result<Option<Bytes>> {
+        if self.done {
+            return Ok(None);
+        }
+        if self.header {
+            self.header = false;
+            let mut rec = ByteRecord::new();
+            for column in self.columns.iter().flatten() {
+                rec.push_field(&column.as_bytes());
+            }
+            self.writer.write_byte_record(&rec).into_lua_err()?;
+        }
+
+        let Some(row) = rows.next()? else {
+            self.done = true;
+            self.writer.flush().into_lua_err()?;
+            let data = self.output.0.borrow_mut().split().freeze();
+            return Ok(Some(data).filter(|data| !data.is_empty()));
+        };
+        let mut rec = ByteRecord::new();
+        match &self.columns {
+            Some(columns) => {
+                for column in columns {
+                    match row.raw_get::<Option<LuaString>>(column)? {
+                        Some(field) => rec.push_field(&field.as_bytes()),
+                        None => rec.push_field(b""),
+                    }
+                }
+            }
+            None => {
+                for field in row.sequence_values::<LuaString>() {
+                    rec.push_field(&field?.as_bytes());
+                }
+            }
+        }
+        self.writer.write_byte_record(&rec).into_lua_err()?;
+        self.writer.flush().into_lua_err()?;
+        Ok(Some(self.output.0.borrow_mut().split().freeze()))
+    }
+}
+
+/*
+--- @within CSV
+--- Encodes rows into CSV (RFC 4180).
+---
+--- Rows are arrays of fields, or tables keyed by the `columns` option.
+--- Instead of an array of rows, an iterator function returning the next row (or `nil`)
+--- can be passed.
+---
+--- Options:
+---   - `delimiter`: field delimiter (default `,`)
+---   - `quote`: quoting style: `necessary` (default), `always`, `non_numeric` or `never`
+---   - `columns`: names of fields to pick from rows (in order)
+---   - `header`: emit the header row with column names (default `true` if `columns` are set)
+---   - `stream`: return a `Body` that encodes rows while reading instead of a string
+---
+--- #example
+---
+--- ```lua
+--- local csv = require("@core/csv")
+--- local data = csv.encode({{ name = "a", n = 1 }}, { columns = {"name", "n"} })
+--- assert(data == "name,n\na,1\n")
+--- ```
+---
+--- @param rows Rows to encode.
+--- @param options Encoding options.
+function csv.encode(rows: { any } | () -> any, options: {
+    delimiter: string?,
+    quote: ("necessary" | "always" | "non_numeric" | "never")?,
+    columns: { string }?,
+    header: boolean?,
+    stream: boolean?,
+}?): any
+    return nil :: any
+end
+*/
+fn encode(lua: &Lua, (mut rows, opts): (Rows, Option<Table>)) -> Result<Value> {
+    let stream = match &opts {
+        Some(opts) => opts.raw_get::<Option<bool>>("stream")?.unwrap_or_default(),
+        None => false,
+    };
+    let mut encoder = RowsEncoder::new(opts).context("invalid options")?;
+
+    if stream {
+        let next_chunk =
+            lua.create_function_mut(move |lua, ()| match encoder.encode_next(&mut rows)? {
+                Some(data) => Ok(Some(lua.create_string(data)?)),
+                None => Ok(None),
+            })?;
+        return LuaBody::from_lua(Value::Function(next_chunk), lua)?.into_lua(lua);
+    }
+
+    let mut data = BytesMut::new();
+    while let Some(chunk) = encoder.encode_next(&mut rows)? {
+        data.extend_from_slice(&chunk);
+    }
+    lua.create_string(data).map(Value::String)
+}
+
 pub fn create_module(lua: &Lua) -> Result<Table> {
-    lua.create_table_from([("encode_record", lua.create_function(encode_record)?)])
+    lua.create_table_from([
+        ("encode_record", lua.create_function(encode_record)?),
+        ("encode", lua.create_function(encode)?),
+    ])
 }
 
 /*
@@ -112,4 +299,80 @@ mod tests {
 
         Ok(())
     }
+
+    #[ntex::test]
+    async fn test_encode() -> Result<()> {
+        let lua = Lua::new();
+
+        lua.globals().set("csv", super::create_module(&lua)?)?;
+        let rows = vec![
+            vec!["name", "comment", "n"],
+            vec!["a", "hello, world", "1"],
+            vec!["b", "multi\nline \"quoted\"", "2.5"],
+            vec!["", "semi;colon", ""],
+        ];
+        let read_csv = |data: &[u8], delimiter: u8| {
+            csv::ReaderBuilder::new()
+                .has_headers(false)
+                .delimiter(delimiter)
+                .from_reader(data)
+                .records()
+                .map(|rec| rec.map(|rec| rec.iter().map(String::from).collect::<Vec<_>>()))
+                .collect::<std::result::Result<Vec<_>, _>>()
+                .unwrap()
+        };
+
+        // Array rows
+        let data: mlua::String = lua
+            .load(chunk! {
+                return csv.encode({
+                    {"name", "comment", "n"},
+                    {"a", "hello, world", 1},
+                    {"b", "multi\nline \"quoted\"", 2.5},
+                    {"", "semi;colon", ""},
+                })
+            })
+            .eval()?;
+        assert_eq!(read_csv(&data.as_bytes(), b','), rows);
+
+        // Keyed rows with header and custom delimiter
+        let data: mlua::String = lua
+            .load(chunk! {
+                return csv.encode({
+                    { name = "a", comment = "hello, world", n = 1 },
+                    { name = "b", comment = "multi\nline \"quoted\"", n = 2.5 },
+                    { comment = "semi;colon" },
+                }, { columns = {"name", "comment", "n"}, delimiter = ";" })
+            })
+            .eval()?;
+        assert!(data.to_str()?.contains("\"semi;colon\""));
+        assert_eq!(read_csv(&data.as_bytes(), b';'), rows);
+
+        // Streaming from an iterator
+        let data: mlua::String = lua
+            .load(chunk! {
+                local i = 0
+                local body = csv.encode(function()
+                    i += 1
+                    if i <= 3 then return { i, "x" } end
+                    return nil
+                end, { stream = true, quote = "always" })
+                return body:to_string()
+            })
+            .eval_async()
+            .await?;
+        assert_eq!(data, "\"1\",\"x\"\n\"2\",\"x\"\n\"3\",\"x\"\n");
+
+        // Header only and invalid options
+        lua.load(chunk! {
+            assert(csv.encode({}) == "")
+            assert(csv.encode({}, { columns = {"a", "b"} }) == "a,b\n")
+            assert(csv.encode({}, { columns = {"a", "b"}, header = false }) == "")
+            local ok, err = pcall(csv.encode, {}, { delimiter = "::" })
+            assert(not ok and tostring(err):find("delimiter must be a single byte"))
+        })
+        .exec()?;
+
+        Ok(())
+    }
 }
@@ -1,7 +1,11 @@
 use std::ops::Deref;
+use std::result::Result as StdResult;
+use std::time::{Duration, UNIX_EPOCH};
 
 use mlua::{Lua, MetaMethod, Result, Table, UserData, UserDataMethods, UserDataRef};
+use time::format_description;
 use time::OffsetDateTime;
+use time_tz::{timezones, OffsetDateTimeExt};
 
 #[derive(Clone, Copy, Debug)]
 struct DateTime(OffsetDateTime);
@@ -36,8 +40,51 @@ impl UserData for DateTime {
     }
 }
 
+fn timestamp_to_datetime(ts: f64) -> StdResult<OffsetDateTime, String> {
+    let nanos = (ts * 1e9) as i128;
+    OffsetDateTime::from_unix_timestamp_nanos(nanos).map_err(|err| err.to_string())
+}
+
+/// Formats a unix timestamp using the `strftime`-like format in the given IANA time zone (UTC by default)
+fn format(
+    _: &Lua,
+    (ts, fmt, tz): (f64, String, Option<String>),
+) -> Result<StdResult<String, String>> {
+    let mut datetime = lua_try!(timestamp_to_datetime(ts));
+    if let Some(tz) = tz {
+        let tz = lua_try!(timezones::get_by_name(&tz).ok_or(format!("unknown time zone `{tz}`")));
+        datetime = datetime.to_timezone(tz);
+    }
+    let fmt = lua_try!(format_description::parse_strftime_borrowed(&fmt));
+    Ok(Ok(lua_try!(datetime.format(&fmt))))
+}
+
+/// Formats a unix timestamp as an HTTP date (RFC 7231 IMF-fixdate), e.g. for `Expires` header
+fn to_http_date(_: &Lua, ts: f64) -> Result<StdResult<String, String>> {
+    let time = lua_try!(Duration::try_from_secs_f64(ts));
+    let time = lua_try!(UNIX_EPOCH
+        .checked_add(time)
+        .ok_or("timestamp is out of range"));
+    Ok(Ok(httpdate::fmt_http_date(time)))
+}
+
+/// Parses an HTTP date (in any of RFC 7231 formats) to a unix timestamp
+fn parse_http_date(_: &Lua, value: String) -> Result<StdResult<i64, String>> {
+    let time = lua_try!(httpdate::parse_http_date(&value));
+    let ts = match time.duration_since(UNIX_EPOCH) {
+        Ok(dur) => dur.as_secs() as i64,
+        Err(err) => -(err.duration().as_secs() as i64),
+    };
+    Ok(Ok(ts))
+}
+
 pub fn create_module(lua: &Lua) -> Result<Table> {
-    lua.create_table_from([("now", lua.create_function(DateTime::now)?)])
+    lua.create_table_from([
+        ("now", lua.create_function(DateTime::now)?),
+        ("format", lua.create_function(format)?),
+        ("to_http_date", lua.create_function(to_http_date)?),
+        ("parse_http_date", lua.create_function(parse_http_date)?),
+    ])
 }
 
 #[cfg(test)]
@@ -62,4 +109,47 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_format() -> Result<()> {
+        let lua = Lua::new();
+
+        let datetime = super::create_module(&lua)?;
+        lua.load(chunk! {
+            local ts = 1445412480 // Wed, 21 Oct 2015 07:28:00 GMT
+            local fmt = "%Y-%m-%d %H:%M:%S %z"
+            assert($datetime.format(ts, fmt) == "2015-10-21 07:28:00 +0000")
+            assert($datetime.format(ts, fmt, "UTC") == "2015-10-21 07:28:00 +0000")
+            assert($datetime.format(ts, fmt, "America/New_York") == "2015-10-21 03:28:00 -0400")
+            assert($datetime.format(ts + 0.5, "%a %b %e %T", "Asia/Tokyo") == "Wed Oct 21 16:28:00")
+
+            local s, err = $datetime.format(ts, fmt, "Mars/Olympus_Mons")
+            assert(s == nil and err == "unknown time zone `Mars/Olympus_Mons`")
+            s, err = $datetime.format(ts, "%Q")
+            assert(s == nil and err ~= nil)
+        })
+        .exec()
+    }
+
+    #[test]
+    fn test_http_date() -> Result<()> {
+        let lua = Lua::new();
+
+        let datetime = super::create_module(&lua)?;
+        lua.load(chunk! {
+            assert($datetime.to_http_date(1445412480) == "Wed, 21 Oct 2015 07:28:00 GMT")
+            assert($datetime.to_http_date(1445412480.9) == "Wed, 21 Oct 2015 07:28:00 GMT")
+
+            // All RFC 7231 formats are accepted
+            assert($datetime.parse_http_date("Wed, 21 Oct 2015 07:28:00 GMT") == 1445412480)
+            assert($datetime.parse_http_date("Wednesday, 21-Oct-15 07:28:00 GMT") == 1445412480)
+            assert($datetime.parse_http_date("Wed Oct 21 07:28:00 2015") == 1445412480)
+
+            local ts, err = $datetime.parse_http_date("tomorrow")
+            assert(ts == nil and err ~= nil)
+            ts, err = $datetime.to_http_date(-1)
+            assert(ts == nil and err ~= nil)
+        })
+        .exec()
+    }
 }
@@ -1,7 +1,11 @@
 use std::result::Result as StdResult;
-use std::time::UNIX_EPOCH;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-use mlua::{Function, Lua, Result, String as LuaString, Table, Value};
+use mlua::{Function, Lua, Result, String as LuaString, Table, UserData, UserDataMethods, Value};
+use tokio::task::JoinHandle;
+use tracing::warn;
+
+use super::tasks::TaskSpawner;
 
 /// Reads the entire contents of a file and returns a Lua string.
 ///
@@ -83,6 +87,98 @@ async fn tempdir_scope(_: Lua, f: Function) -> Result<StdResult<Value, String>>
     Ok(Ok(f.call_async(dir.path().display().to_string()).await?))
 }
 
+/// State of a watched file used to detect changes
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum FileState {
+    Missing,
+    Present {
+        modified: Option<SystemTime>,
+        len: u64,
+    },
+}
+
+impl FileState {
+    async fn read(path: &str) -> Self {
+        match tokio::fs::metadata(path).await {
+            Ok(metadata) => FileState::Present {
+                modified: metadata.modified().ok(),
+                len: metadata.len(),
+            },
+            Err(_) => FileState::Missing,
+        }
+    }
+}
+
+/// Handle of a file watcher returned by `fs.watch` (the watcher is stopped when dropped)
+struct FileWatcher(JoinHandle<()>);
+
+impl Drop for FileWatcher {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+impl UserData for FileWatcher {
+    fn add_methods<M: UserDataMethods<Self>>(methods: &mut M) {
+        methods.add_method("stop", |_, this, ()| {
+            this.0.abort();
+            Ok(())
+        });
+
+        methods.add_method("is_stopped", |_, this, ()| Ok(this.0.is_finished()));
+    }
+}
+
+/// Watches a file for changes and calls the callback (as a background task) on every change.
+///
+/// The file is polled every `interval` seconds (1 second by default). The callback receives
+/// the event name ("created", "modified" or "removed") and the path. A file replaced
+/// using atomic rename is reported as "modified".
+///
+/// The watcher runs until stopped, its handle is garbage collected or the task scheduler
+/// is shut down. It does not keep the Lua instance alive.
+async fn watch(
+    lua: Lua,
+    (path, callback, interval): (String, Function, Option<f64>),
+) -> Result<StdResult<FileWatcher, String>> {
+    let interval = lua_try!(Duration::try_from_secs_f64(interval.unwrap_or(1.0)));
+    let spawner = TaskSpawner::new(&lua)?;
+    let mut state = FileState::read(&path).await;
+
+    let join_handle = tokio::task::spawn_local(async move {
+        let mut ticker = tokio::time::interval(interval);
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        ticker.tick().await;
+        loop {
+            ticker.tick().await;
+            let new_state = FileState::read(&path).await;
+            if new_state == state {
+                continue;
+            }
+            let event = match (state, new_state) {
+                (FileState::Missing, _) => "created",
+                (_, FileState::Missing) => "removed",
+                _ => "modified",
+            };
+            state = new_state;
+
+            // The scheduler (that keeps the Lua instance alive) is stopped
+            if spawner.is_closed() {
+                break;
+            }
+            let result = callback
+                .bind((event, path.as_str()))
+                .and_then(|handler| spawner.spawn_handler("fs.watch", handler));
+            if let Err(err) = result {
+                warn!("stopping watcher of '{path}': {err}");
+                break;
+            }
+        }
+    });
+
+    Ok(Ok(FileWatcher(join_handle)))
+}
+
 pub fn create_module(lua: &Lua) -> Result<Table> {
     lua.create_table_from([
         ("read", lua.create_async_function(read)?),
@@ -90,11 +186,15 @@ pub fn create_module(lua: &Lua) -> Result<Table> {
         ("metadata", lua.create_async_function(metadata)?),
         ("read_dir", lua.create_async_function(read_dir)?),
         ("tempdir_scope", lua.create_async_function(tempdir_scope)?),
+        ("watch", lua.create_async_function(watch)?),
     ])
 }
 
 #[cfg(test)]
 mod tests {
+    use std::sync::Arc;
+    use std::time::Duration;
+
     use mlua::{chunk, Lua, Result};
 
     #[tokio::test]
@@ -139,4 +239,96 @@ mod tests {
         .exec_async()
         .await
     }
+
+    #[ntex::test]
+    async fn test_watch() -> Result<()> {
+        let lua = Lua::new();
+
+        let fs = super::create_module(&lua)?;
+        lua.globals()
+            .set("tasks", super::super::tasks::create_module(&lua)?)?;
+        // Watcher events are delivered through the channel to not depend on timings
+        let (events_tx, events_rx) = tokio::sync::mpsc::unbounded_channel::<String>();
+        let events_rx = Arc::new(tokio::sync::Mutex::new(events_rx));
+        lua.globals().set(
+            "notify",
+            lua.create_function(move |_, event: String| {
+                let _ = events_tx.send(event);
+                Ok(())
+            })?,
+        )?;
+        // Returns the next event or `nil` if nothing happens within `secs`
+        lua.globals().set(
+            "next_event",
+            lua.create_async_function(move |_, secs: f64| {
+                let events_rx = events_rx.clone();
+                async move {
+                    let mut events_rx = events_rx.lock().await;
+                    let event = events_rx.recv();
+                    Ok(tokio::time::timeout(Duration::from_secs_f64(secs), event)
+                        .await
+                        .ok()
+                        .flatten())
+                }
+            })?,
+        )?;
+        // Luau `os` library has no file operations
+        lua.globals().set(
+            "rename",
+            lua.create_function(|_, (from, to): (String, String)| {
+                std::fs::rename(from, to).map_err(mlua::Error::external)
+            })?,
+        )?;
+        lua.globals().set(
+            "remove",
+            lua.create_function(|_, path: String| {
+                std::fs::remove_file(path).map_err(mlua::Error::external)
+            })?,
+        )?;
+        super::super::tasks::start_task_scheduler(&lua, None);
+
+        lua.load(chunk! {
+            $fs.tempdir_scope(function (dir)
+                local path = dir .. "/data.txt"
+                local watcher = $fs.watch(path, function(event, p)
+                    assert(p == path)
+                    notify(event)
+                end, 0.02)
+
+                $fs.write(path, "hello")
+                assert(next_event(5) == "created")
+                $fs.write(path, "hello, world")
+                assert(next_event(5) == "modified")
+
+                // Atomic replace
+                $fs.write(dir .. "/data.tmp", "new data")
+                rename(dir .. "/data.tmp", path)
+                assert(next_event(5) == "modified")
+
+                remove(path)
+                assert(next_event(5) == "removed")
+
+                watcher:stop()
+                $fs.write(path, "hello")
+                assert(next_event(0.1) == nil)
+                assert(watcher:is_stopped())
+
+                // Dropped (garbage collected) watcher is stopped too
+                local watcher = $fs.watch(path, function(event)
+                    notify(event)
+                end, 0.02)
+                $fs.write(path, "hello, world")
+                assert(next_event(5) == "modified")
+                watcher = nil
+                collectgarbage("collect")
+                $fs.write(path, "hello")
+                assert(next_event(0.1) == nil)
+            end)
+        })
+        .exec_async()
+        .await?;
+
+        super::super::tasks::stop_task_scheduler(&lua);
+        Ok(())
+    }
 }
@@ -1,13 +1,17 @@
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
 use std::error::Error as StdError;
 use std::fmt;
+use std::future::Future;
 use std::mem;
+use std::pin::Pin;
 use std::rc::Rc;
 use std::task::{Context, Poll};
 use std::time::{Duration, Instant};
 
-use futures::{Stream, TryStreamExt};
+use futures::{FutureExt, Stream, TryStreamExt};
 use mlua::{
-    AnyUserData, Error as LuaError, ErrorContext as _, ExternalError, FromLua, Lua,
+    AnyUserData, Error as LuaError, ErrorContext as _, ExternalError, FromLua, Function, Lua,
     Result as LuaResult, String as LuaString, UserData, Value,
 };
 use ntex::http::body::{self, BodySize, BoxedBodyStream, MessageBody, ResponseBody, SizedStream};
@@ -16,10 +20,11 @@ use ntex::util::{Bytes, BytesMut};
 use tokio::time;
 use tracing::error;
 
-use crate::http::buffer_body;
+use crate::http::encoding::{transcode_stream, ContentEncoding, Transcoder};
+use crate::http::{buffer_body, CoalescedStream};
 use crate::lua::json::JsonObject;
-
-// TODO: Limit number of fetched bytes
+use crate::types::BodyChunkSize;
+use crate::utils::json::JsonFieldsScanner;
 
 #[derive(Default)]
 pub enum LuaBody {
@@ -29,11 +34,13 @@ pub enum LuaBody {
     Body {
         body: Box<dyn MessageBody>,
         timeout: Option<Duration>,
+        max_size: Option<usize>,
     },
     Payload {
         payload: Payload,
         length: Option<u64>,
         timeout: Option<Duration>,
+        max_size: Option<usize>,
     },
 }
 
@@ -56,6 +63,24 @@ impl LuaBody {
         }
     }
 
+    /// Returns maximum number of bytes allowed to fetch from the (non-buffered) body
+    pub fn max_size(&self) -> Option<usize> {
+        match self {
+            LuaBody::Body { max_size, .. } => *max_size,
+            LuaBody::Payload { max_size, .. } => *max_size,
+            _ => None,
+        }
+    }
+
+    /// Sets maximum number of bytes allowed to fetch from the (non-buffered) body
+    pub fn set_max_size(&mut self, size: Option<usize>) {
+        match self {
+            LuaBody::Body { max_size, .. } => *max_size = size,
+            LuaBody::Payload { max_size, .. } => *max_size = size,
+            _ => {}
+        }
+    }
+
     /// Reads the whole body into memory and returns the buffered data.
     /// The body is consumed and cannot be read again.
     pub async fn read(&mut self) -> LuaResult<Option<Bytes>> {
@@ -64,7 +89,10 @@ impl LuaBody {
             LuaBody::None => Ok(None),
             LuaBody::Bytes(bytes) => Ok(Some(bytes)),
             body => {
-                let buffer_fut = buffer_body(body);
+                let buffer_fut = match body.max_size() {
+                    Some(max_size) => buffer_body_with_limit(body, max_size).left_future(),
+                    None => buffer_body(body).right_future(),
+                };
                 let res = match timeout {
                     Some(timeout) => time::timeout(timeout, buffer_fut).await,
                     None => Ok(buffer_fut.await),
@@ -88,6 +116,144 @@ impl LuaBody {
         }
     }
 
+    /// Merges small chunks of the received (payload) body into chunks of at least `chunk_size`.
+    ///
+    /// Already available chunks are merged only, reading never waits for more data.
+    pub fn coalesce(self, chunk_size: usize) -> LuaBody {
+        match self {
+            LuaBody::Payload {
+                payload,
+                length,
+                timeout,
+                max_size,
+            } => LuaBody::Payload {
+                payload: Payload::Stream(Box::pin(CoalescedStream::new(payload, chunk_size))),
+                length,
+                timeout,
+                max_size,
+            },
+            body => body,
+        }
+    }
+
+    /// Wraps the body with the prefix and suffix.
+    ///
+    /// Non-buffered body is not read, the prefix and suffix are attached as a stream transform.
+    pub fn wrap(self, prefix: Bytes, suffix: Bytes) -> LuaBody {
+        let bytes = match self {
+            LuaBody::None => Bytes::new(),
+            LuaBody::Bytes(bytes) => bytes,
+            body => {
+                let (timeout, max_size) = (body.timeout(), body.max_size());
+                return LuaBody::Body {
+                    body: Box::new(WrappedBody {
+                        prefix: Some(prefix),
+                        inner: body,
+                        suffix: Some(suffix),
+                    }),
+                    timeout,
+                    max_size,
+                };
+            }
+        };
+        let mut data = BytesMut::with_capacity(prefix.len() + bytes.len() + suffix.len());
+        data.extend_from_slice(&prefix);
+        data.extend_from_slice(&bytes);
+        data.extend_from_slice(&suffix);
+        LuaBody::Bytes(data.freeze())
+    }
+
+    /// Passes every chunk of the body through the Lua function when the body is read.
+    ///
+    /// The function result replaces the chunk, `nil` ends the body.
+    /// The function is called synchronously, so it must not yield (e.g. do async calls).
+    pub fn transform(self, lua: &Lua, func: Function) -> LuaBody {
+        if let LuaBody::None = self {
+            return self;
+        }
+        let (timeout, max_size) = (self.timeout(), self.max_size());
+        LuaBody::Body {
+            body: Box::new(TransformedBody {
+                inner: self,
+                lua: lua.clone(),
+                func: Some(func),
+            }),
+            timeout,
+            max_size,
+        }
+    }
+
+    /// Transcodes the body from one content encoding to another.
+    ///
+    /// Buffered bodies up to `max_inplace_size` bytes are transcoded in memory,
+    /// otherwise the body is transcoded chunk-by-chunk as a stream.
+    pub fn transcode(
+        self,
+        from: ContentEncoding,
+        to: ContentEncoding,
+        max_inplace_size: usize,
+    ) -> LuaResult<LuaBody> {
+        match self {
+            LuaBody::None => Ok(LuaBody::None),
+            LuaBody::Bytes(bytes) if bytes.len() <= max_inplace_size => {
+                let mut transcoder = Transcoder::new(from, to)?;
+                let mut data = BytesMut::from(&transcoder.feed(&bytes)?[..]);
+                data.extend_from_slice(&transcoder.finish()?);
+                Ok(LuaBody::Bytes(data.freeze()))
+            }
+            body => {
+                let (timeout, max_size) = (body.timeout(), body.max_size());
+                let stream = transcode_stream(body, from, to)?;
+                Ok(LuaBody::Body {
+                    body: Box::new(BoxedBodyStream::new(stream)),
+                    timeout,
+                    max_size,
+                })
+            }
+        }
+    }
+
+    /// Lends the body to `f` as a stream, keeping every chunk read from it.
+    ///
+    /// Afterwards the body consists of the read chunks followed by the unread rest,
+    /// so it can be read again (e.g. sent to the client after storing it).
+    /// The body timeout (if set) bounds reading the lent stream.
+    pub async fn tee<F: Future>(&mut self, f: impl FnOnce(LuaBody) -> F) -> F::Output {
+        match self {
+            LuaBody::None => return f(LuaBody::None).await,
+            LuaBody::Bytes(bytes) => return f(LuaBody::Bytes(bytes.clone())).await,
+            _ => {}
+        }
+
+        let (size, timeout, max_size) = (self.size(), self.timeout(), self.max_size());
+        let state = Rc::new(RefCell::new(TeeState {
+            inner: mem::take(self),
+            chunks: VecDeque::new(),
+        }));
+        let body = TeeBody {
+            state: state.clone(),
+            deadline: timeout.map(|timeout| Box::pin(time::sleep(timeout))),
+        };
+        let output = f(LuaBody::Body {
+            body: Box::new(body),
+            timeout,
+            max_size,
+        })
+        .await;
+
+        let TeeState { inner, chunks } = mem::take(&mut *state.borrow_mut());
+        *self = LuaBody::Body {
+            body: Box::new(ReplayBody {
+                size,
+                chunks,
+                inner,
+            }),
+            timeout,
+            max_size,
+        };
+        output
+    }
+
     /// Buffers the whole body and parses it as JSON.
     pub async fn json(&mut self) -> LuaResult<serde_json::Value> {
         let bytes = self
@@ -98,6 +264,209 @@ impl LuaBody {
             .map_err(LuaError::external)
             .context("failed to parse JSON body")
     }
+
+    /// Reads the body as a JSON object extracting only the requested top-level fields.
+    ///
+    /// Unlike [`LuaBody::json`], the body is not buffered: values of other fields are skipped
+    /// while reading. Returns values in the same order as keys (`None` if the field is absent).
+    /// The body is consumed and cannot be read again.
+    pub async fn json_fields(
+        &mut self,
+        keys: Vec<String>,
+    ) -> LuaResult<Vec<Option<serde_json::Value>>> {
+        let (timeout, max_size) = (self.timeout(), self.max_size());
+        let mut body = mem::take(self);
+        let mut scanner = JsonFieldsScanner::new(keys);
+        let scan_fut = async {
+            let mut read_size = 0;
+            while let Some(chunk) = futures::future::poll_fn(|cx| body.poll_next_chunk(cx)).await {
+                let chunk = chunk.map_err(|err| LuaError::external(err.to_string()))?;
+                read_size += chunk.len();
+                if let Some(max_size) = max_size.filter(|&max| read_size > max) {
+                    return Err(LuaError::external(max_size_error(max_size)));
+                }
+                if scanner.feed(&chunk).map_err(LuaError::external)? {
+                    break;
+                }
+            }
+            Ok(())
+        };
+        match timeout {
+            Some(timeout) => time::timeout(timeout, scan_fut)
+                .await
+                .map_err(|_| LuaError::external("timeout reading body"))??,
+            None => scan_fut.await?,
+        }
+        scanner
+            .finish()
+            .map_err(LuaError::external)
+            .context("failed to parse JSON body")
+    }
+}
+
+fn max_size_error(max_size: usize) -> String {
+    format!("body exceeds max size of {max_size} bytes")
+}
+
+/// Buffers the body into memory, failing as soon as more than `max_size` bytes are received.
+async fn buffer_body_with_limit(
+    mut body: LuaBody,
+    max_size: usize,
+) -> Result<Bytes, Box<dyn StdError>> {
+    if let BodySize::Sized(len) = body.size() {
+        if len > max_size as u64 {
+            return Err(max_size_error(max_size).into());
+        }
+    }
+    let mut bytes = BytesMut::new();
+    while let Some(chunk) = futures::future::poll_fn(|cx| body.poll_next_chunk(cx)).await {
+        let chunk = chunk?;
+        if bytes.len() + chunk.len() > max_size {
+            return Err(max_size_error(max_size).into());
+        }
+        bytes.extend_from_slice(&chunk);
+    }
+    Ok(bytes.freeze())
+}
+
+/// Body with prefix and suffix attached to the inner body stream
+struct WrappedBody {
+    prefix: Option<Bytes>,
+    inner: LuaBody,
+    suffix: Option<Bytes>,
+}
+
+impl MessageBody for WrappedBody {
+    fn size(&self) -> BodySize {
+        let extra_len = self.prefix.as_ref().map(|b| b.len()).unwrap_or(0)
+            + self.suffix.as_ref().map(|b| b.len()).unwrap_or(0);
+        match self.inner.size() {
+            BodySize::None | BodySize::Empty => BodySize::Sized(extra_len as u64),
+            BodySize::Sized(len) => BodySize::Sized(len + extra_len as u64),
+            BodySize::Stream => BodySize::Stream,
+        }
+    }
+
+    fn poll_next_chunk(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Bytes, Box<dyn StdError>>>> {
+        if let Some(prefix) = self.prefix.take().filter(|b| !b.is_empty()) {
+            return Poll::Ready(Some(Ok(prefix)));
+        }
+        match futures::ready!(self.inner.poll_next_chunk(cx)) {
+            Some(chunk) => Poll::Ready(Some(chunk)),
+            None => Poll::Ready(self.suffix.take().filter(|b| !b.is_empty()).map(Ok)),
+        }
+    }
+}
+
+#[derive(Default)]
+struct TeeState {
+    inner: LuaBody,
+    chunks: VecDeque<Bytes>,
+}
+
+/// Body that keeps every chunk read from the inner body stream (see [`LuaBody::tee`])
+struct TeeBody {
+    state: Rc<RefCell<TeeState>>,
+    deadline: Option<Pin<Box<time::Sleep>>>,
+}
+
+impl MessageBody for TeeBody {
+    fn size(&self) -> BodySize {
+        self.state.borrow().inner.size()
+    }
+
+    fn poll_next_chunk(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Bytes, Box<dyn StdError>>>> {
+        let mut state = self.state.borrow_mut();
+        match state.inner.poll_next_chunk(cx) {
+            Poll::Ready(Some(Ok(chunk))) => {
+                state.chunks.push_back(chunk.clone());
+                Poll::Ready(Some(Ok(chunk)))
+            }
+            Poll::Ready(result) => Poll::Ready(result),
+            Poll::Pending => {
+                let timed_out =
+                    (self.deadline.as_mut()).is_some_and(|d| d.as_mut().poll(cx).is_ready());
+                match timed_out {
+                    true => Poll::Ready(Some(Err("timeout reading body".into()))),
+                    false => Poll::Pending,
+                }
+            }
+        }
+    }
+}
+
+/// Body replaying the chunks read by [`TeeBody`] followed by the rest of the inner body
+struct ReplayBody {
+    size: BodySize,
+    chunks: VecDeque<Bytes>,
+    inner: LuaBody,
+}
+
+impl MessageBody for ReplayBody {
+    fn size(&self) -> BodySize {
+        self.size
+    }
+
+    fn poll_next_chunk(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Bytes, Box<dyn StdError>>>> {
+        match self.chunks.pop_front() {
+            Some(chunk) => Poll::Ready(Some(Ok(chunk))),
+            None => self.inner.poll_next_chunk(cx),
+        }
+    }
+}
+
+/// Body with every chunk of the inner body stream passed through a Lua function
+struct TransformedBody {
+    inner: LuaBody,
+    lua: Lua,
+    // `None` when the body is ended by the function
+    func: Option<Function>,
+}
+
+impl MessageBody for TransformedBody {
+    fn size(&self) -> BodySize {
+        match self.inner.size() {
+            BodySize::None => BodySize::None,
+            _ => BodySize::Stream,
+        }
+    }
+
+    fn poll_next_chunk(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Bytes, Box<dyn StdError>>>> {
+        loop {
+            let Some(func) = &self.func else {
+                return Poll::Ready(None);
+            };
+            let chunk = match futures::ready!(self.inner.poll_next_chunk(cx)) {
+                Some(Ok(chunk)) => chunk,
+                result => return Poll::Ready(result),
+            };
+            let result = (self.lua.create_string(&chunk))
+                .and_then(|chunk| func.call::<Option<LuaString>>(chunk));
+            match result {
+                Ok(Some(chunk)) if chunk.as_bytes().is_empty() => continue,
+                Ok(Some(chunk)) => {
+                    return Poll::Ready(Some(Ok(Bytes::from(chunk.as_bytes().to_vec()))))
+                }
+                Ok(None) => self.func = None,
+                Err(err) => {
+                    error!("{err:#}");
+                    return Poll::Ready(Some(Err(Box::new(err))));
+                }
+            }
+        }
+    }
 }
 
 pub enum EitherBody {
@@ -135,8 +504,12 @@ impl EitherBody {
     pub(crate) fn to_userdata(&mut self, lua: &Lua) -> LuaResult<AnyUserData> {
         match self {
             EitherBody::Body(tmp_body) => {
+                let mut body = mem::take(tmp_body);
+                if let Some(chunk_size) = lua.app_data_ref::<BodyChunkSize>() {
+                    body = body.coalesce(chunk_size.0);
+                }
                 // Move body to Lua registry
-                let lua_body = lua.create_userdata(mem::take(tmp_body))?;
+                let lua_body = lua.create_userdata(body)?;
                 *self = EitherBody::UserData(lua_body.clone());
                 Ok(lua_body)
             }
@@ -154,6 +527,13 @@ impl EitherBody {
         }
     }
 
+    pub(crate) fn size(&self) -> BodySize {
+        match self {
+            EitherBody::Body(body) => body.size(),
+            EitherBody::UserData(ud) => borrow_body!(ud).size(),
+        }
+    }
+
     #[allow(clippy::await_holding_refcell_ref)]
     pub(crate) async fn buffer(&mut self) -> LuaResult<Option<Bytes>> {
         match self {
@@ -220,6 +600,7 @@ where
         LuaBody::Body {
             body: Box::new(body),
             timeout: None,
+            max_size: None,
         }
     }
 }
@@ -235,6 +616,7 @@ impl From<(Payload, Option<u64>)> for LuaBody {
             payload,
             length,
             timeout: None,
+            max_size: None,
         }
     }
 }
@@ -249,6 +631,7 @@ where
             ResponseBody::Body(body) => LuaBody::Body {
                 body: Box::new(body),
                 timeout: None,
+                max_size: None,
             },
             ResponseBody::Other(body::Body::None) => LuaBody::None,
             ResponseBody::Other(body::Body::Empty) => LuaBody::Bytes(Bytes::new()),
@@ -256,6 +639,7 @@ where
             ResponseBody::Other(body::Body::Message(body)) => LuaBody::Body {
                 body,
                 timeout: None,
+                max_size: None,
             },
         }
     }
@@ -375,6 +759,12 @@ impl UserData for LuaBody {
             Ok(())
         });
 
+        // Sets maximum number of bytes allowed to read from the body (`nil` to disable the limit)
+        methods.add_method_mut("set_max_size", |_, this, size: Option<usize>| {
+            this.set_max_size(size);
+            Ok(())
+        });
+
         // Discards the body without reading it
         methods.add_async_method_mut("discard", |_, mut this, ()| async move {
             *this = LuaBody::None;
@@ -392,12 +782,14 @@ impl UserData for LuaBody {
         // Returns iterator (function) to read body chunk by chunk
         methods.add_function("reader", |lua, ud: AnyUserData| {
             let body_key = Rc::new(lua.create_registry_value(ud)?);
+            let read_size = Rc::new(Cell::new(0));
             lua.create_async_function(move |lua, ()| {
                 let body_key = body_key.clone();
+                let read_size = read_size.clone();
                 async move {
                     let ud = lua.registry_value::<AnyUserData>(&body_key)?;
                     let mut this = ud.borrow_mut::<Self>()?;
-                    let timeout = this.timeout();
+                    let (timeout, max_size) = (this.timeout(), this.max_size());
                     let next_chunk = futures::future::poll_fn(|cx| this.poll_next_chunk(cx));
                     let bytes = match timeout {
                         Some(timeout) => {
@@ -416,6 +808,12 @@ impl UserData for LuaBody {
                             lua_try!(next_chunk.await.transpose())
                         }
                     };
+                    if let Some(bytes) = &bytes {
+                        read_size.set(read_size.get() + bytes.len());
+                        if let Some(max_size) = max_size.filter(|&max| read_size.get() > max) {
+                            return Ok(Err(max_size_error(max_size)));
+                        }
+                    }
                     let data = bytes.map(|b| lua.create_any_userdata(b)).transpose()?;
                     Ok(Ok(data))
                 }
@@ -435,6 +833,22 @@ impl UserData for LuaBody {
             Ok(Ok(JsonObject::from(json).into_lua(&lua)?))
         });
 
+        // Reads the body as a JSON object extracting only the requested top-level fields
+        // Returns a table with the found fields or `nil, error`
+        methods.add_async_method_mut(
+            "json_stream",
+            |lua, mut this, keys: Vec<String>| async move {
+                let values = lua_try!(this.json_fields(keys.clone()).await);
+                let fields = lua.create_table()?;
+                for (key, value) in keys.into_iter().zip(values) {
+                    if let Some(value) = value {
+                        fields.raw_set(key, JsonObject::from(value).into_lua(&lua)?)?;
+                    }
+                }
+                Ok(Ok(fields))
+            },
+        );
+
         methods.add_async_method_mut("to_string", |lua, mut this, ()| async move {
             let bytes = lua_try!(this.buffer().await);
             let data = bytes.map(|b| lua.create_string(&b)).transpose()?;
@@ -450,7 +864,7 @@ mod tests {
     use std::time::Duration;
 
     use mlua::{chunk, Lua, Result as LuaResult, Value};
-    use ntex::http::body::BoxedBodyStream;
+    use ntex::http::body::{BoxedBodyStream, SizedStream};
     use tokio_stream::{self as stream, StreamExt};
 
     use super::LuaBody;
@@ -473,6 +887,61 @@ mod tests {
         Ok(())
     }
 
+    #[ntex::test]
+    async fn test_wrap_body() {
+        use ntex::http::body::{BodySize, MessageBody, SizedStream};
+
+        use crate::http::buffer_body;
+
+        // Buffered body
+        let body = LuaBody::from("hello").wrap("<".into(), ">".into());
+        assert_eq!(body.size(), BodySize::Sized(7));
+        assert_eq!(buffer_body(body).await.unwrap(), "<hello>");
+
+        // Stream with known size
+        let chunks = stream::iter(["hel", "lo"].map(|s| Ok::<_, Box<dyn StdError>>(s.into())));
+        let body = LuaBody::Body {
+            body: Box::new(SizedStream::new(5, Box::pin(chunks))),
+            timeout: None,
+            max_size: None,
+        };
+        let body = body.wrap("<".into(), ">".into());
+        assert_eq!(body.size(), BodySize::Sized(7));
+        assert_eq!(buffer_body(body).await.unwrap(), "<hello>");
+
+        // Stream with unknown size
+        let chunks = stream::iter(["hel", "lo"].map(|s| Ok::<_, Box<dyn StdError>>(s.into())));
+        let body = LuaBody::from(BoxedBodyStream::new(chunks));
+        let body = body.wrap("".into(), ">".into());
+        assert_eq!(body.size(), BodySize::Stream);
+        assert_eq!(buffer_body(body).await.unwrap(), "hello>");
+    }
+
+    #[ntex::test]
+    async fn test_tee_body() {
+        use futures::future::poll_fn;
+        use ntex::http::body::{BodySize, MessageBody, SizedStream};
+
+        use crate::http::buffer_body;
+
+        let chunks = stream::iter(["he", "ll", "o"].map(|s| Ok::<_, Box<dyn StdError>>(s.into())));
+        let mut body = LuaBody::Body {
+            body: Box::new(SizedStream::new(5, Box::pin(chunks))),
+            timeout: None,
+            max_size: None,
+        };
+
+        // Only the first chunk is read by the borrower
+        let chunk = (body
+            .tee(|mut body| async move { poll_fn(|cx| body.poll_next_chunk(cx)).await }))
+        .await;
+        assert_eq!(chunk.unwrap().unwrap(), "he");
+
+        // The body is read again in full
+        assert_eq!(body.size(), BodySize::Sized(5));
+        assert_eq!(buffer_body(body).await.unwrap(), "hello");
+    }
+
     #[ntex::test]
     async fn test_bytes_body() -> LuaResult<()> {
         let lua = Lua::new();
@@ -543,6 +1012,91 @@ mod tests {
         Ok(())
     }
 
+    #[ntex::test]
+    async fn test_payload_body_chunks() -> LuaResult<()> {
+        use ntex::http::error::PayloadError;
+        use ntex::http::Payload;
+        use ntex::util::Bytes;
+
+        use super::EitherBody;
+        use crate::types::BodyChunkSize;
+
+        let lua = Lua::new();
+        super::super::super::bytes::register_types(&lua)?;
+        let chunk_size = 10_000;
+        lua.set_app_data(BodyChunkSize(chunk_size));
+
+        // Large body received in small chunks
+        let data = (0..1024 * 1024)
+            .map(|i| (i % 251) as u8)
+            .collect::<Vec<_>>();
+        let chunks = data
+            .chunks(1000)
+            .map(|c| Ok::<_, PayloadError>(Bytes::copy_from_slice(c)))
+            .collect::<Vec<_>>();
+        // `tokio_stream::iter` periodically yields, so `futures` version is used instead
+        let payload = Payload::from_stream(futures::stream::iter(chunks));
+        let mut body = EitherBody::Body(LuaBody::from((payload, Some(data.len() as u64))));
+        let body = body.to_userdata(&lua)?;
+
+        let output = lua
+            .load(chunk! {
+                local reader = $body:reader()
+                local chunks = {}
+                while true do
+                    local chunk = reader()
+                    if chunk == nil then break end
+                    table.insert(chunks, chunk:to_string())
+                end
+                for i = 1, #chunks - 1 do
+                    assert(#chunks[i] >= $chunk_size, "chunk is too small")
+                end
+                return table.concat(chunks)
+            })
+            .eval_async::<mlua::String>()
+            .await?;
+        assert_eq!(&*output.as_bytes(), &data[..]);
+
+        Ok(())
+    }
+
+    #[ntex::test]
+    async fn test_json_stream() -> LuaResult<()> {
+        let lua = Lua::new();
+
+        // Large object where only a few fields are needed
+        let mut chunks: Vec<Result<_, Box<dyn StdError>>> = vec![Ok(r#"{"items": ["#.into())];
+        for i in 0..10000 {
+            chunks.push(Ok(format!(r#"{{"id": {i}, "name": "item {i}"}},"#).into()));
+        }
+        chunks.push(Ok(r#"{}], "total": 10000, "meta": {"page": 1}}"#.into()));
+        let body = LuaBody::from(BoxedBodyStream::new(stream::iter(chunks)));
+
+        lua.load(chunk! {
+            local fields = $body:json_stream({"total", "missing", "meta"})
+            assert(fields.total == 10000)
+            assert(fields.missing == nil)
+            assert(fields.meta.page == 1)
+            // Body is consumed
+            assert($body:read() == nil)
+        })
+        .exec_async()
+        .await
+        .unwrap();
+
+        // Invalid JSON
+        let body = LuaBody::from(r#"{"total": 1"#);
+        lua.load(chunk! {
+            local fields, err = $body:json_stream({"other"})
+            assert(fields == nil and err:find("unexpected end") ~= nil)
+        })
+        .exec_async()
+        .await
+        .unwrap();
+
+        Ok(())
+    }
+
     #[ntex::test]
     async fn test_body_discard() -> LuaResult<()> {
         let lua = Lua::new();
@@ -691,6 +1245,60 @@ mod tests {
         Ok(())
     }
 
+    #[ntex::test]
+    async fn test_body_max_size() -> LuaResult<()> {
+        let lua = Lua::new();
+        super::super::super::bytes::register_types(&lua)?;
+
+        fn make_body_stream() -> LuaBody {
+            // The stream never ends, so it must be interrupted by the size limit
+            let stream = stream::iter(std::iter::repeat_with(|| Ok("hello".into())));
+            LuaBody::from(BoxedBodyStream::new(stream))
+        }
+
+        let mut body = make_body_stream();
+        body.set_max_size(Some(12));
+        lua.load(chunk! {
+            local _, err = $body:data()
+            assert(err == "body exceeds max size of 12 bytes", err)
+        })
+        .exec_async()
+        .await
+        .unwrap();
+
+        let body = make_body_stream();
+        lua.load(chunk! {
+            $body:set_max_size(10)
+            local reader = $body:reader()
+            assert(reader():to_string() == "hello")
+            assert(reader():to_string() == "hello")
+            local _, err = reader()
+            assert(err == "body exceeds max size of 10 bytes", err)
+        })
+        .exec_async()
+        .await
+        .unwrap();
+
+        // Known body size is checked before reading
+        let chunks = stream::iter(["hello"].map(|s| Ok::<_, Box<dyn StdError>>(s.into())));
+        let mut body = LuaBody::Body {
+            body: Box::new(SizedStream::new(100, Box::pin(chunks))),
+            timeout: None,
+            max_size: Some(50),
+        };
+        let err = body.read().await.unwrap_err();
+        assert!(err.to_string().contains("max size of 50 bytes"));
+
+        // Limit does not apply to the data within the limit
+        let mut body = LuaBody::from(BoxedBodyStream::new(stream::iter(
+            ["hello"].map(|s| Ok::<_, Box<dyn StdError>>(s.into())),
+        )));
+        body.set_max_size(Some(5));
+        assert_eq!(body.read().await?.unwrap(), "hello");
+
+        Ok(())
+    }
+
     #[ntex::test]
     async fn test_body_json() -> LuaResult<()> {
         let lua = Lua::new();
@@ -2,22 +2,51 @@ use std::mem;
 use std::time::Duration;
 
 use mlua::{
-    ExternalError, FromLua, Lua, Result as LuaResult, Table, UserData, UserDataMethods, Value,
+    ExternalError, ExternalResult, FromLua, Lua, Result as LuaResult, Table, UserData,
+    UserDataMethods, Value,
 };
-use ntex::http::client::{Client, Connector};
+use ntex::http::client::{Client, ClientBuilder, Connector};
+use ntex::http::header::{
+    AUTHORIZATION, CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_TYPE, LOCATION, TRANSFER_ENCODING,
+};
+use ntex::http::{Method, StatusCode, Uri};
 use ntex::time::Seconds;
 use tracing::{debug, instrument};
+use url::Url;
 
 use super::{LuaBody, LuaRequest, LuaResponse};
+use crate::http::UpstreamAllowlist;
 
 pub struct LuaHttpClient {
     client: Client,
     no_decompress: bool,
+    allowlist: Option<UpstreamAllowlist>,
 }
 
 impl LuaHttpClient {
+    /// Creates a new client restricted by the upstreams allowlist (if configured)
+    fn new(lua: &Lua, builder: ClientBuilder, connector: Connector) -> Self {
+        let allowlist = lua.app_data_ref::<UpstreamAllowlist>().map(|a| a.clone());
+        let connector = match &allowlist {
+            Some(allowlist) => allowlist.guard_connector(connector),
+            None => connector,
+        };
+        LuaHttpClient {
+            client: builder.connector(connector.finish()).finish(),
+            no_decompress: false,
+            allowlist,
+        }
+    }
+
     #[instrument(skip_all, fields(method = %req.method(), uri = %req.uri()))]
     async fn request(&self, mut req: LuaRequest) -> LuaResult<LuaResponse> {
+        if let Some(allowlist) = &self.allowlist {
+            if let Err(err) = allowlist.check(req.uri()) {
+                rejected_upstreams_counter_add!(1);
+                return Err(err.into_lua_err());
+            }
+        }
+
         let mut client_req = self.client.request(req.method().clone(), req.uri());
         if self.no_decompress {
             client_req = client_req.no_decompress();
@@ -43,21 +72,93 @@ impl LuaHttpClient {
 
         Ok(LuaResponse::from(resp))
     }
-}
 
-impl From<Client> for LuaHttpClient {
-    fn from(client: Client) -> Self {
-        LuaHttpClient {
-            client,
-            no_decompress: false,
+    /// Sends the request following up to `max_redirects` redirects.
+    ///
+    /// The request body is buffered to be re-sent on `307` and `308` redirects.
+    /// `303` (and `301`/`302` for `POST` requests) redirects are followed with a `GET` request
+    /// without body. `Authorization` header is not sent to a different origin.
+    /// Every redirect target must be allowed by the upstreams allowlist (if configured).
+    /// When the limit is reached, the last redirect response is returned.
+    async fn request_with_redirects(
+        &self,
+        mut req: LuaRequest,
+        max_redirects: usize,
+    ) -> LuaResult<LuaResponse> {
+        let mut body = LuaBody::from(req.take_body()).buffer().await?;
+        let mut redirects = 0;
+        loop {
+            let mut next_req = LuaRequest::new(body.clone().map(LuaBody::from).unwrap_or_default());
+            *next_req.method_mut() = req.method().clone();
+            *next_req.uri_mut() = req.uri().clone();
+            *next_req.headers_mut() = req.headers().clone();
+            next_req.set_timeout(req.timeout());
+            let resp = self.request(next_req).await?;
+
+            let location = resp.headers().get(LOCATION).and_then(|l| l.to_str().ok());
+            let location = match location {
+                Some(location) if is_redirect(resp.status()) && redirects < max_redirects => {
+                    resolve_location(req.uri(), location)?
+                }
+                _ => return Ok(resp),
+            };
+            redirects += 1;
+            debug!(status = resp.status().as_u16(), %location, "following redirect");
+
+            let status = resp.status();
+            if status == StatusCode::SEE_OTHER && req.method() != Method::HEAD
+                || matches!(status, StatusCode::MOVED_PERMANENTLY | StatusCode::FOUND)
+                    && req.method() == Method::POST
+            {
+                *req.method_mut() = Method::GET;
+                body = None;
+                for name in [
+                    CONTENT_LENGTH,
+                    CONTENT_TYPE,
+                    CONTENT_ENCODING,
+                    TRANSFER_ENCODING,
+                ] {
+                    req.headers_mut().remove(name);
+                }
+            }
+            if location.scheme() != req.uri().scheme()
+                || location.authority() != req.uri().authority()
+            {
+                req.headers_mut().remove(AUTHORIZATION);
+            }
+            *req.uri_mut() = location;
         }
     }
 }
 
+fn is_redirect(status: StatusCode) -> bool {
+    matches!(
+        status,
+        StatusCode::MOVED_PERMANENTLY
+            | StatusCode::FOUND
+            | StatusCode::SEE_OTHER
+            | StatusCode::TEMPORARY_REDIRECT
+            | StatusCode::PERMANENT_REDIRECT
+    )
+}
+
+/// Resolves the (possibly relative) `Location` header value against the request uri
+/// (following RFC 3986 reference resolution)
+fn resolve_location(base: &Uri, location: &str) -> LuaResult<Uri> {
+    let base = Url::parse(&base.to_string()).into_lua_err()?;
+    let mut url = (base.join(location))
+        .map_err(|err| format!("invalid redirect location: {err}"))
+        .into_lua_err()?;
+    url.set_fragment(None);
+    Uri::try_from(url.as_str())
+        .map_err(|err| format!("invalid redirect location: {err}"))
+        .into_lua_err()
+}
+
 impl FromLua for LuaHttpClient {
     fn from_lua(value: Value, lua: &Lua) -> LuaResult<Self> {
         if value == Value::Nil {
-            return Ok(LuaHttpClient::from(Client::new()));
+            return Ok(LuaHttpClient::new(lua, Client::build(), Connector::new()));
         }
 
         let mut client_builder = Client::build();
@@ -99,21 +200,32 @@ impl FromLua for LuaHttpClient {
             connector = connector.limit(val as usize);
         }
 
-        Ok(LuaHttpClient {
-            client: client_builder.connector(connector.finish()).finish(),
-            no_decompress,
-        })
+        let mut client = LuaHttpClient::new(lua, client_builder, connector);
+        client.no_decompress = no_decompress;
+        Ok(client)
     }
 }
 
 impl UserData for LuaHttpClient {
     fn add_methods<M: UserDataMethods<Self>>(methods: &mut M) {
-        methods.add_function("new", |_lua, _params: Value| {
-            Ok(LuaHttpClient::from(Client::new()))
+        methods.add_function("new", |lua, _params: Value| {
+            Ok(LuaHttpClient::new(lua, Client::build(), Connector::new()))
         });
 
-        methods.add_async_method("request", |_, this, req| async move {
-            Ok(Ok(lua_try!(this.request(req).await)))
+        methods.add_async_method("request", |lua, this, params: Value| async move {
+            // Number of redirects to follow (none by default)
+            let follow_redirects = match &params {
+                Value::Table(params) => params.raw_get::<Option<usize>>("follow_redirects")?,
+                _ => None,
+            };
+            let req = LuaRequest::from_lua(params, &lua)?;
+            let resp = match follow_redirects {
+                Some(max_redirects) if max_redirects > 0 => {
+                    this.request_with_redirects(req, max_redirects).await
+                }
+                _ => this.request(req).await,
+            };
+            Ok(Ok(lua_try!(resp)))
         });
     }
 }
@@ -121,6 +233,7 @@ impl UserData for LuaHttpClient {
 #[cfg(test)]
 mod tests {
     use mlua::{chunk, Lua, Result};
+    use ntex::util::Bytes;
     use ntex::web::{self, test, App};
 
     use super::*;
@@ -159,4 +272,165 @@ mod tests {
         .exec_async()
         .await
     }
+
+    #[test]
+    fn test_resolve_location() {
+        let base = Uri::from_static("http://example.com/a/b?x=1");
+        for (location, expected) in [
+            ("c", "http://example.com/a/c"),
+            ("/c?y=2", "http://example.com/c?y=2"),
+            ("../c", "http://example.com/c"),
+            ("?y=2", "http://example.com/a/b?y=2"),
+            ("page?u=http://x", "http://example.com/a/page?u=http://x"),
+            ("//other.com/c", "http://other.com/c"),
+            ("https://other.com/c#frag", "https://other.com/c"),
+        ] {
+            let uri = resolve_location(&base, location).unwrap();
+            assert_eq!(uri.to_string(), expected, "location: {location}");
+        }
+    }
+
+    #[ntex::test]
+    async fn test_client_allowlist() -> Result<()> {
+        let lua = Lua::new();
+
+        lua.globals()
+            .set("Client", lua.create_proxy::<LuaHttpClient>()?)?;
+
+        let mock_server = test::server(|| {
+            App::new()
+                .service(web::resource("/").to(|| async { "hello" }))
+                .service(web::resource("/redirect").to(|| async {
+                    web::HttpResponse::Found()
+                        .header("location", "http://evil.com/")
+                        .finish()
+                }))
+        });
+        let port = mock_server.addr().port();
+
+        // Allowed host name resolving to a blocked address
+        let allowlist = ["localhost".to_string()];
+        lua.set_app_data(UpstreamAllowlist::new(&allowlist).unwrap());
+        let uri = format!("http://localhost:{port}/");
+        let uri2 = uri.clone();
+        lua.load(chunk! {
+            local resp, err = Client.new():request({ uri = $uri2 })
+            assert(resp == nil and err ~= nil, "connection must be refused")
+        })
+        .exec_async()
+        .await?;
+
+        // ...unless the address is explicitly allowed
+        let allowlist = ["localhost", "127.0.0.0/8"].map(|s| s.to_string());
+        lua.set_app_data(UpstreamAllowlist::new(&allowlist).unwrap());
+        lua.load(chunk! {
+            local resp = assert(Client.new():request({ uri = $uri }))
+            assert(resp.status == 200)
+        })
+        .exec_async()
+        .await?;
+
+        // Every redirect hop is checked
+        let allowlist = ["127.0.0.1".to_string()];
+        lua.set_app_data(UpstreamAllowlist::new(&allowlist).unwrap());
+        let uri = format!("http://127.0.0.1:{port}");
+        lua.load(chunk! {
+            local client = Client.new()
+            local resp = client:request({ uri = $uri.."/" })
+            assert(resp.status == 200 and resp.body:to_string() == "hello")
+            local resp, err = client:request({ uri = $uri.."/redirect", follow_redirects = 1 })
+            assert(resp == nil and err:find("upstream `evil.com` is not allowed"), err)
+        })
+        .exec_async()
+        .await
+    }
+
+    #[ntex::test]
+    async fn test_client_follow_redirects() -> Result<()> {
+        let lua = Lua::new();
+
+        lua.globals()
+            .set("Client", lua.create_proxy::<LuaHttpClient>()?)?;
+
+        fn redirect(status: StatusCode, location: &str) -> web::HttpResponse {
+            web::HttpResponse::build(status)
+                .header("location", location)
+                .finish()
+        }
+
+        async fn target(req: web::HttpRequest, body: Bytes) -> web::HttpResponse {
+            web::HttpResponse::Ok()
+                .header("x-method", req.method().as_str())
+                .header("x-query", req.uri().query().unwrap_or_default())
+                .if_some(req.headers().get("authorization"), |val, resp| {
+                    resp.header("x-authorization", val);
+                })
+                .body(body)
+        }
+
+        let other_server = test::server(|| App::new().service(web::resource("/target").to(target)));
+        let other_target = format!("http://{}/target", other_server.addr());
+
+        // Request body is read by the handlers to keep the connection alive
+        let mock_server = test::server(move || {
+            let other_target = other_target.clone();
+            App::new()
+                .service(
+                    web::resource("/redirect")
+                        .to(|| async { redirect(StatusCode::FOUND, "target?x=1") }),
+                )
+                .service(
+                    web::resource("/see_other")
+                        .to(|_: Bytes| async { redirect(StatusCode::SEE_OTHER, "/target") }),
+                )
+                .service(web::resource("/cross_host").to(move |_: Bytes| {
+                    let other_target = other_target.clone();
+                    async move { redirect(StatusCode::TEMPORARY_REDIRECT, &other_target) }
+                }))
+                .service(
+                    web::resource("/loop")
+                        .to(|| async { redirect(StatusCode::MOVED_PERMANENTLY, "/loop") }),
+                )
+                .service(web::resource("/target").to(target))
+        });
+        let uri = format!("http://{}", mock_server.addr());
+
+        lua.load(chunk! {
+            local client = Client.new()
+            local function request(path, params)
+                params.uri = $uri..path
+                params.headers = { authorization = "secret" }
+                return assert(client:request(params))
+            end
+
+            // Redirects are not followed by default
+            local resp = request("/redirect", {})
+            assert(resp.status == 302)
+
+            // Relative redirect
+            resp = request("/redirect", { follow_redirects = 1 })
+            assert(resp.status == 200)
+            assert(resp:header("x-method") == "GET")
+            assert(resp:header("x-query") == "x=1")
+            assert(resp:header("x-authorization") == "secret")
+
+            // `303` changes method to `GET` and drops body
+            resp = request("/see_other", { method = "PUT", body = "hello", follow_redirects = 1 })
+            assert(resp:header("x-method") == "GET")
+            assert(resp.body:to_string() == "")
+
+            // `307` keeps method and body, but `Authorization` is not sent to a different host
+            resp = request("/cross_host", { method = "POST", body = "hello", follow_redirects = 1 })
+            assert(resp.status == 200)
+            assert(resp:header("x-method") == "POST")
+            assert(resp.body:to_string() == "hello")
+            assert(resp:header("x-authorization") == nil)
+
+            // Redirect loop is capped
+            resp = request("/loop", { follow_redirects = 3 })
+            assert(resp.status == 301)
+        })
+        .exec_async()
+        .await
+    }
 }
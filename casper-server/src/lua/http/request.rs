@@ -1,23 +1,53 @@
 use std::collections::HashMap;
 use std::convert::{Infallible, TryFrom};
 use std::mem;
-use std::net::SocketAddr;
+use std::net::{IpAddr, SocketAddr};
 use std::time::Duration;
 
+use ipnet::IpNet;
 use mlua::{
     AnyUserData, ExternalError, ExternalResult, FromLua, IntoLua, Lua, LuaSerdeExt,
     Result as LuaResult, String as LuaString, Table, UserData, UserDataFields, UserDataMethods,
     Value,
 };
 use ntex::http::client::Client as HttpClient;
-use ntex::http::header::{HeaderMap, CONTENT_LENGTH, TRANSFER_ENCODING};
+use ntex::http::header::{HeaderMap, CONTENT_LENGTH, CONTENT_TYPE, TRANSFER_ENCODING};
 use ntex::http::uri::PathAndQuery;
 use ntex::http::{Method, Payload, Uri, Version};
+use ntex::io::{types::HttpProtocol, IoRef};
+use ntex::tls::Servername;
 use ntex::web::{FromRequest, HttpRequest};
 use serde_json::Value as JsonValue;
 
 use super::{EitherBody, LuaBody, LuaHttpHeaders, LuaHttpHeadersExt};
-use crate::http::proxy_to_upstream;
+use crate::config::PathRewriteConfig;
+use crate::http::multipart::parse_form_data;
+use crate::http::{proxy_to_upstream, ErrorPages, PathRewrite, UpstreamAllowlist};
+
+/// TLS info of the incoming connection
+#[derive(Clone, Debug)]
+struct TlsInfo {
+    // SNI server name sent by the client
+    sni: Option<String>,
+    // Negotiated (via ALPN) HTTP protocol
+    alpn: Option<&'static str>,
+}
+
+impl TlsInfo {
+    /// Queries TLS info from the connection filters.
+    ///
+    /// Returns `None` if the connection is not TLS.
+    fn from_io(io: &IoRef) -> Option<Self> {
+        // Only TLS filters respond to the HTTP protocol query
+        let alpn = match io.query::<HttpProtocol>().get()? {
+            HttpProtocol::Http1 => Some("http/1.1"),
+            HttpProtocol::Http2 => Some("h2"),
+            HttpProtocol::Unknown => None,
+        };
+        let sni = io.query::<Servername>().as_ref().map(|name| name.0.clone());
+        Some(TlsInfo { sni, alpn })
+    }
+}
 
 #[derive(Default)]
 pub struct LuaRequest {
@@ -33,6 +63,7 @@ pub struct LuaRequest {
 
     // Incoming Request fields
     remote_addr: Option<SocketAddr>,
+    tls: Option<TlsInfo>,
 
     // Outgoing Request fields
     timeout: Option<Duration>,
@@ -87,6 +118,14 @@ impl LuaRequest {
             .unwrap_or_default()
     }
 
+    pub fn scheme(&self) -> String {
+        self.orig_req
+            .as_ref()
+            .map(|req| req.connection_info().scheme().to_string())
+            .or_else(|| self.uri.scheme_str().map(|s| s.to_string()))
+            .unwrap_or_else(|| "http".to_string())
+    }
+
     pub fn headers(&self) -> &HeaderMap {
         &self.headers
     }
@@ -101,6 +140,12 @@ impl LuaRequest {
         self.timeout
     }
 
+    /// Sets timeout for outgoing request
+    #[inline]
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+    }
+
     #[inline]
     pub fn body_mut(&mut self) -> &mut EitherBody {
         &mut self.body
@@ -130,6 +175,39 @@ impl LuaRequest {
         Ok(())
     }
 
+    /// Returns the client IP address taking into account the `X-Forwarded-For` header.
+    ///
+    /// The forwarded chain is walked right-to-left skipping the trusted proxies, and the first
+    /// untrusted address is returned (or the leftmost one if all of them are trusted).
+    /// The header is ignored if the peer itself is not a trusted proxy (it can be spoofed then).
+    pub fn client_ip(&self, trusted_proxies: &[IpNet]) -> Option<IpAddr> {
+        let is_trusted = |ip: &IpAddr| trusted_proxies.iter().any(|net| net.contains(ip));
+        let remote_ip = self.remote_addr.map(|addr| addr.ip());
+        if remote_ip.is_some_and(|ip| !is_trusted(&ip)) {
+            return remote_ip;
+        }
+
+        let headers = self.headers.get_all("x-forwarded-for").collect::<Vec<_>>();
+        let forwarded = headers
+            .iter()
+            .rev()
+            .flat_map(|value| value.to_str().unwrap_or_default().rsplit(','))
+            .map(|ip| ip.trim())
+            .filter(|ip| !ip.is_empty());
+        let mut client_ip = remote_ip;
+        for ip in forwarded {
+            // Stop at the first malformed entry, addresses to the left of it cannot be trusted
+            let Ok(ip) = ip.parse::<IpAddr>() else {
+                break;
+            };
+            client_ip = Some(ip);
+            if !is_trusted(&ip) {
+                break;
+            }
+        }
+        client_ip
+    }
+
     /// Rewrites request's uri query (can be empty)
     fn set_uri_query(&mut self, query: &str) -> LuaResult<()> {
         let mut parts = self.uri().clone().into_parts();
@@ -159,6 +237,7 @@ impl LuaRequest {
             headers: self.headers.clone(),
             body: EitherBody::Body(body),
             remote_addr: self.remote_addr,
+            tls: self.tls.clone(),
             timeout: self.timeout,
         })
     }
@@ -196,6 +275,7 @@ impl<Err> FromRequest<Err> for LuaRequest {
             headers: request.headers().clone(),
             body: EitherBody::Body(body),
             remote_addr: request.peer_addr(),
+            tls: request.io().and_then(TlsInfo::from_io),
             timeout: None,
         })
     }
@@ -272,6 +352,27 @@ impl UserData for LuaRequest {
         fields.add_field_method_get("remote_addr", |_, this| {
             Ok(this.remote_addr.map(|s| s.to_string()))
         });
+        fields.add_field_method_set("remote_addr", |_, this, addr: Option<String>| {
+            this.remote_addr = match addr {
+                // Address can be set without port
+                Some(addr) => Some(match addr.parse::<IpAddr>() {
+                    Ok(ip) => SocketAddr::new(ip, 0),
+                    Err(_) => addr.parse::<SocketAddr>().into_lua_err()?,
+                }),
+                None => None,
+            };
+            Ok(())
+        });
+
+        fields.add_field_method_get("is_tls", |_, this| Ok(this.tls.is_some()));
+
+        fields.add_field_method_get("tls_sni", |_, this| {
+            Ok(this.tls.as_ref().and_then(|tls| tls.sni.clone()))
+        });
+
+        fields.add_field_method_get("tls_alpn", |_, this| {
+            Ok(this.tls.as_ref().and_then(|tls| tls.alpn))
+        });
 
         fields.add_field_function_get("body", |lua, this| {
             let mut this = this.borrow_mut::<Self>()?;
@@ -301,6 +402,25 @@ impl UserData for LuaRequest {
             Ok(())
         });
 
+        // Returns the client IP address considering `X-Forwarded-For` header sent by
+        // the trusted proxies (list of CIDR ranges or IP addresses)
+        methods.add_method(
+            "client_ip",
+            |_, this, trusted_proxies: Option<Vec<String>>| {
+                let trusted_proxies = trusted_proxies
+                    .unwrap_or_default()
+                    .iter()
+                    .map(|net| {
+                        net.parse::<IpNet>()
+                            .or_else(|_| net.parse::<IpAddr>().map(IpNet::from))
+                            .map_err(|_| format!("invalid trusted proxy `{net}`"))
+                    })
+                    .collect::<Result<Vec<_>, _>>()
+                    .into_lua_err()?;
+                Ok(this.client_ip(&trusted_proxies).map(|ip| ip.to_string()))
+            },
+        );
+
         methods.add_method("uri_path", |lua, this, ()| this.uri().path().into_lua(lua));
         methods.add_method_mut("set_uri_path", |_, this, path: String| {
             this.set_uri_path(&path)
@@ -382,15 +502,68 @@ impl UserData for LuaRequest {
             Ok(())
         });
 
+        // Buffers the `multipart/form-data` body and parses it
+        // Returns a list of parts `{name, filename, content_type, data}` or `nil, error`
+        methods.add_async_method_mut("multipart", |lua, mut this, ()| async move {
+            let content_type = match this.headers().get(CONTENT_TYPE) {
+                Some(value) => lua_try!(value.to_str()).to_string(),
+                None => return Ok(Err("missing content type".to_string())),
+            };
+            let body = lua_try!(this.body_mut().buffer().await).unwrap_or_default();
+            let parts = lua_try!(parse_form_data(&content_type, body).await);
+            let result = lua.create_table_with_capacity(parts.len(), 0)?;
+            for part in parts {
+                let item = lua.create_table_with_capacity(0, 4)?;
+                item.raw_set("name", part.name)?;
+                item.raw_set("filename", part.filename)?;
+                item.raw_set("content_type", part.content_type)?;
+                item.raw_set("data", lua.create_string(&part.data)?)?;
+                result.raw_push(item)?;
+            }
+            Ok(Ok(result))
+        });
+
+        // Options:
+        //  `path_rewrite`: overrides the configured upstream path rewrite (`false` disables it)
         methods.add_async_function(
             "proxy_to_upstream",
-            |lua, (this, upstream): (AnyUserData, Option<String>)| async move {
+            |lua, (this, upstream, opts): (AnyUserData, Option<String>, Option<Table>)| async move {
+                let mut rewrite = lua.app_data_ref::<PathRewrite>().map(|r| r.clone());
+                match opts
+                    .map(|t| t.raw_get::<Value>("path_rewrite"))
+                    .transpose()?
+                {
+                    None | Some(Value::Nil) => {}
+                    Some(Value::Boolean(false)) => rewrite = None,
+                    Some(value) => {
+                        let config = lua
+                            .from_value::<PathRewriteConfig>(value)
+                            .map_err(|err| format!("invalid path rewrite: {err}"))
+                            .into_lua_err()?;
+                        rewrite = Some(PathRewrite::new(&config).into_lua_err()?);
+                    }
+                }
+
                 let req = this.take::<LuaRequest>()?;
                 let client = lua
                     .app_data_ref::<HttpClient>()
                     .expect("Failed to get default http client")
                     .clone();
-                proxy_to_upstream(client, req, upstream.as_deref()).await
+                let allowlist = lua.app_data_ref::<UpstreamAllowlist>().map(|a| a.clone());
+                let upstream = upstream.as_deref();
+                let error_pages = lua.app_data_ref::<ErrorPages>().map(|p| p.clone());
+                let (allowlist, rewrite) = (allowlist.as_ref(), rewrite.as_ref());
+                let mut resp = proxy_to_upstream(
+                    client,
+                    req,
+                    upstream,
+                    allowlist,
+                    rewrite,
+                    error_pages.as_ref(),
+                )
+                .await?;
+                resp.apply_default_content_type(&lua);
+                Ok(resp)
             },
         );
     }
@@ -399,9 +572,13 @@ impl UserData for LuaRequest {
 #[cfg(test)]
 mod tests {
     use mlua::{chunk, Lua, Result};
+    use ntex::http::header::HeaderValue;
     use ntex::web::{self, test, App};
 
     use super::*;
+    use crate::lua::storage::LuaStorage;
+    use crate::storage::Backend;
+    use crate::types::DefaultContentType;
 
     #[ntex::test]
     async fn test_request() -> Result<()> {
@@ -518,6 +695,120 @@ mod tests {
         Ok(())
     }
 
+    #[ntex::test]
+    async fn test_request_multipart() -> Result<()> {
+        let lua = Lua::new();
+
+        lua.globals()
+            .set("Request", lua.create_proxy::<LuaRequest>()?)?;
+
+        let body = "--boundary\r\n\
+            Content-Disposition: form-data; name=\"field\"\r\n\r\n\
+            value\r\n\
+            --boundary\r\n\
+            Content-Disposition: form-data; name=\"upload\"; filename=\"hello.txt\"\r\n\
+            Content-Type: text/plain\r\n\r\n\
+            hello, world\r\n\
+            --boundary--\r\n";
+        let content_type = "multipart/form-data; boundary=boundary";
+        lua.load(chunk! {
+            local function make_request(content_type)
+                local sent = false
+                return Request.new({
+                    method = "POST",
+                    headers = { ["content-type"] = content_type },
+                    body = function()
+                        if not sent then
+                            sent = true
+                            return $body
+                        end
+                    end,
+                })
+            end
+
+            local parts = assert(make_request($content_type):multipart())
+            assert(#parts == 2)
+            assert(parts[1].name == "field" and parts[1].filename == nil)
+            assert(parts[1].content_type == nil and parts[1].data == "value")
+            assert(parts[2].name == "upload" and parts[2].filename == "hello.txt")
+            assert(parts[2].content_type == "text/plain" and parts[2].data == "hello, world")
+
+            // Missing boundary
+            local _, err = make_request("multipart/form-data"):multipart()
+            assert(err == "missing multipart boundary", err)
+
+            // Body size limit
+            local req = make_request($content_type)
+            req.body:set_max_size(64)
+            _, err = req:multipart()
+            assert(err == "body exceeds max size of 64 bytes", err)
+        })
+        .exec_async()
+        .await
+    }
+
+    #[test]
+    fn test_client_ip() -> Result<()> {
+        let lua = Lua::new();
+
+        lua.globals()
+            .set("Request", lua.create_proxy::<LuaRequest>()?)?;
+
+        lua.load(chunk! {
+            local trusted = {"10.0.0.0/8", "192.168.1.1", "2001:db8::/32"}
+            local req = Request.new({
+                headers = {
+                    ["x-forwarded-for"] = {"1.2.3.4, 5.6.7.8, 10.1.1.1", "2001:db8::1"},
+                },
+            })
+            req.remote_addr = "192.168.1.1:1234"
+
+            // Trusted proxies are skipped right-to-left
+            assert(req:client_ip(trusted) == "5.6.7.8")
+            assert(req:client_ip({"10.0.0.0/8", "192.168.1.1/32", "2001:db8::/32", "5.6.7.8"}) == "1.2.3.4")
+            // Untrusted peer: header can be spoofed and is ignored
+            assert(req:client_ip() == "192.168.1.1")
+            req.remote_addr = "172.16.0.1"
+            assert(req:client_ip(trusted) == "172.16.0.1")
+
+            // Malformed entry stops the walk
+            req = Request.new({ headers = { ["x-forwarded-for"] = "1.2.3.4, unknown, 10.0.0.1" } })
+            req.remote_addr = "10.0.0.2:80"
+            assert(req:client_ip(trusted) == "10.0.0.1")
+
+            // No header
+            req = Request.new()
+            assert(req:client_ip(trusted) == nil)
+            req.remote_addr = "10.0.0.2:80"
+            assert(req:client_ip(trusted) == "10.0.0.2")
+
+            local ok, err = pcall(req.client_ip, req, {"10.0.0.0/33"})
+            assert(not ok and tostring(err):find("invalid trusted proxy `10.0.0.0/33`") ~= nil)
+        })
+        .exec()
+    }
+
+    #[ntex::test]
+    async fn test_request_tls_info() {
+        let server = test::server(|| {
+            App::new().service(web::resource("/").to(|req: LuaRequest| async move {
+                let lua = Lua::new();
+                lua.load(chunk! {
+                    assert($req.is_tls == false)
+                    assert($req.tls_sni == nil)
+                    assert($req.tls_alpn == nil)
+                })
+                .exec()
+                .map(|_| "ok".to_string())
+                .unwrap_or_else(|err| err.to_string())
+            }))
+        });
+
+        let mut resp = server.get("/").send().await.unwrap();
+        assert!(resp.status().is_success());
+        assert_eq!(resp.body().await.unwrap(), "ok");
+    }
+
     #[ntex::test]
     async fn test_proxy_to_upstream() -> Result<()> {
         let lua = Lua::new();
@@ -529,17 +820,24 @@ mod tests {
         lua.set_app_data(HttpClient::new());
 
         let mock_server = test::server(|| {
-            App::new().service(web::resource("/status").to(|| async move {
-                web::HttpResponse::Ok()
-                    .header("x-test", "abc")
-                    .body("hello, world!")
-            }))
+            App::new()
+                .service(web::resource("/status").to(|| async move {
+                    web::HttpResponse::Ok()
+                        .header("x-test", "abc")
+                        .body("hello, world!")
+                }))
+                .service(web::resource("/json").to(|| async move {
+                    web::HttpResponse::Ok()
+                        .content_type("application/json")
+                        .body("{}")
+                }))
         });
         let upstream = format!("http://{}", mock_server.addr());
 
+        let upstream2 = upstream.clone();
         lua.load(chunk! {
             local req = Request.new({uri = "/status"})
-            local resp = req:proxy_to_upstream($upstream)
+            local resp = req:proxy_to_upstream($upstream2)
             assert(resp.status == 200)
             assert(resp:header("x-test") == "abc")
             assert(resp.body:to_string() == "hello, world!")
@@ -548,6 +846,356 @@ mod tests {
         .await
         .unwrap();
 
+        // Default content type is set only when missing
+        let content_type = HeaderValue::from_static("text/plain; charset=utf-8");
+        lua.set_app_data(DefaultContentType(content_type));
+        lua.load(chunk! {
+            local resp = Request.new({uri = "/status"}):proxy_to_upstream($upstream)
+            assert(resp:header("content-type") == "text/plain; charset=utf-8")
+            resp = Request.new({uri = "/json"}):proxy_to_upstream($upstream)
+            assert(resp:header("content-type") == "application/json")
+        })
+        .exec_async()
+        .await
+        .unwrap();
+
+        Ok(())
+    }
+
+    #[ntex::test]
+    async fn test_proxy_upstream_allowlist() -> Result<()> {
+        let lua = Lua::new();
+
+        lua.globals()
+            .set("Request", lua.create_proxy::<LuaRequest>()?)?;
+        lua.set_app_data(HttpClient::new());
+
+        let mock_server =
+            test::server(|| App::new().service(web::resource("/").to(|| async { "hello" })));
+        let upstream = format!("http://{}", mock_server.addr());
+
+        // Loopback address is not allowed by a wider network
+        let allowlist = ["0.0.0.0/0", "*.example.com"].map(|s| s.to_string());
+        lua.set_app_data(UpstreamAllowlist::new(&allowlist).unwrap());
+        let upstream2 = upstream.clone();
+        lua.load(chunk! {
+            local ok, err = pcall(function()
+                return Request.new({uri = "/"}):proxy_to_upstream($upstream2)
+            end)
+            assert(not ok and tostring(err):find("upstream `127.0.0.1` is not allowed") ~= nil)
+            ok, err = pcall(function()
+                return Request.new({uri = "/"}):proxy_to_upstream("http://evil.com")
+            end)
+            assert(not ok and tostring(err):find("upstream `evil.com` is not allowed") ~= nil)
+        })
+        .exec_async()
+        .await?;
+
+        // Explicitly allowed
+        let allowlist = ["127.0.0.1".to_string()];
+        lua.set_app_data(UpstreamAllowlist::new(&allowlist).unwrap());
+        lua.load(chunk! {
+            local resp = Request.new({uri = "/"}):proxy_to_upstream($upstream)
+            assert(resp.status == 200)
+            assert(resp.body:to_string() == "hello")
+        })
+        .exec_async()
+        .await
+    }
+
+    #[ntex::test]
+    async fn test_proxy_path_rewrite() -> Result<()> {
+        let lua = Lua::new();
+
+        lua.globals()
+            .set("Request", lua.create_proxy::<LuaRequest>()?)?;
+        lua.set_app_data(HttpClient::new());
+
+        // Echo the request target
+        let mock_server = test::server(|| {
+            App::new().default_service(web::to(|req: web::HttpRequest| async move {
+                req.uri().to_string()
+            }))
+        });
+        let upstream = format!("http://{}", mock_server.addr());
+
+        let config = PathRewriteConfig {
+            strip_prefix: Some("/public".into()),
+            add_prefix: Some("/origin".into()),
+            ..Default::default()
+        };
+        lua.set_app_data(PathRewrite::new(&config).unwrap());
+        lua.load(chunk! {
+            local resp = Request.new({uri = "/public/a?b=c"}):proxy_to_upstream($upstream)
+            assert(resp.body:to_string() == "/origin/a?b=c")
+
+            // Override in Lua
+            local rewrite = {regex = "^/public/(\\w+)", replacement = "/$1/index"}
+            resp = Request.new({uri = "/public/a?b=c"}):proxy_to_upstream($upstream, {path_rewrite = rewrite})
+            assert(resp.body:to_string() == "/a/index?b=c")
+            resp = Request.new({uri = "/public/a"}):proxy_to_upstream($upstream, {path_rewrite = false})
+            assert(resp.body:to_string() == "/public/a")
+
+            local ok, err = pcall(function()
+                return Request.new({uri = "/"}):proxy_to_upstream($upstream, {path_rewrite = {regex = "("}})
+            end)
+            assert(not ok and tostring(err):find("invalid regex") ~= nil)
+        })
+        .exec_async()
+        .await
+    }
+
+    #[ntex::test]
+    async fn test_proxy_error_pages() -> Result<()> {
+        use std::io::Write;
+        use std::net::TcpListener;
+
+        use crate::config::ErrorPageConfig;
+
+        let lua = Lua::new();
+
+        lua.globals()
+            .set("Request", lua.create_proxy::<LuaRequest>()?)?;
+        lua.set_app_data(HttpClient::new());
+
+        // Upstream responding with garbage
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let upstream = format!("http://{}", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            for mut stream in listener.incoming().flatten() {
+                _ = stream.write_all(b"garbage\r\n\r\n");
+            }
+        });
+
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(b"unavailable").unwrap();
+        let error_pages = HashMap::from([
+            (
+                502,
+                ErrorPageConfig {
+                    body: Some("<h1>Bad Gateway</h1><p>Request: {{ request_id }}</p>".into()),
+                    headers: HashMap::from([("x-error".into(), "1".into())]),
+                    ..Default::default()
+                },
+            ),
+            (
+                503,
+                ErrorPageConfig {
+                    file: Some(file.path().to_path_buf()),
+                    ..Default::default()
+                },
+            ),
+        ]);
+        lua.set_app_data(ErrorPages::new(&error_pages).unwrap());
+
+        lua.load(chunk! {
+            local req = Request.new({uri = "/", headers = {["x-request-id"] = "req-123"}})
+            local resp = req:proxy_to_upstream($upstream)
+            assert(resp.status == 502)
+            assert(resp.body:to_string() == "<h1>Bad Gateway</h1><p>Request: req-123</p>")
+            assert(resp:header("content-type") == "text/html")
+            assert(resp:header("x-error") == "1")
+
+            // Connection refused
+            resp = Request.new({uri = "/"}):proxy_to_upstream("http://127.0.0.1:1")
+            assert(resp.status == 503 and resp.body:to_string() == "unavailable")
+        })
+        .exec_async()
+        .await
+    }
+
+    #[ntex::test]
+    async fn test_proxy_expect_continue() -> Result<()> {
+        let lua = Lua::new();
+
+        lua.globals()
+            .set("Request", lua.create_proxy::<LuaRequest>()?)?;
+        lua.set_app_data(HttpClient::new());
+
+        // Upstream echoes the body and the `Expect` header it received
+        let mock_server = test::server(|| {
+            App::new().service(web::resource("/").to(
+                |req: web::HttpRequest, body: ntex::util::Bytes| {
+                    let expect = req.headers().contains_key("expect");
+                    async move {
+                        web::HttpResponse::Ok()
+                            .header("x-expect", expect.to_string())
+                            .body(body)
+                    }
+                },
+            ))
+        });
+        let upstream = format!("http://{}", mock_server.addr());
+
+        lua.load(chunk! {
+            local req = Request.new({
+                method = "POST",
+                uri = "/",
+                headers = { expect = "100-continue" },
+                body = "hello",
+            })
+            local resp = req:proxy_to_upstream($upstream)
+            assert(resp.status == 200)
+            assert(resp:header("x-expect") == "false")
+            assert(resp.body:to_string() == "hello")
+        })
+        .exec_async()
+        .await
+    }
+
+    #[ntex::test]
+    async fn test_proxy_ttfb_histogram() -> Result<()> {
+        use std::time::Duration;
+
+        let lua = Lua::new();
+
+        lua.globals()
+            .set("Request", lua.create_proxy::<LuaRequest>()?)?;
+        lua.set_app_data(HttpClient::new());
+
+        // Upstream sends headers immediately but delays the first body byte
+        let mock_server = test::server(|| {
+            App::new().service(web::resource("/").to(|| async {
+                let body = futures::stream::once(async {
+                    ntex::time::sleep(Duration::from_millis(200)).await;
+                    Ok::<_, web::Error>(ntex::util::Bytes::from("hello"))
+                });
+                web::HttpResponse::Ok().streaming(Box::pin(body))
+            }))
+        });
+        let upstream_addr = mock_server.addr().to_string();
+        let upstream = format!("http://{upstream_addr}");
+
+        lua.load(chunk! {
+            local resp = Request.new({ uri = "/" }):proxy_to_upstream($upstream)
+            assert(resp.status == 200)
+            assert(resp.body:to_string() == "hello")
+        })
+        .exec_async()
+        .await?;
+
+        let histogram = prometheus::default_registry()
+            .gather()
+            .into_iter()
+            .filter(|family| family.get_name() == "upstream_ttfb_seconds")
+            .flat_map(|mut family| family.take_metric())
+            .find(|metric| {
+                metric
+                    .get_label()
+                    .iter()
+                    .any(|l| l.get_name() == "upstream" && l.get_value() == upstream_addr)
+            })
+            .expect("histogram is not recorded")
+            .take_histogram();
+        assert_eq!(histogram.get_sample_count(), 1);
+        assert!(histogram.get_sample_sum() >= 0.2);
+
+        Ok(())
+    }
+
+    #[ntex::test]
+    async fn test_proxy_head_with_body() -> Result<()> {
+        use std::io::{Read, Write};
+        use std::net::TcpListener;
+
+        let lua = Lua::new();
+
+        lua.globals()
+            .set("Request", lua.create_proxy::<LuaRequest>()?)?;
+        lua.set_app_data(HttpClient::new());
+
+        // Misbehaving upstream that sends a body for `HEAD` requests
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let upstream = format!("http://{}", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut buf = [0; 1024];
+            let _ = stream.read(&mut buf).unwrap();
+            let resp = "HTTP/1.1 200 OK\r\ncontent-length: 5\r\n\r\nhello";
+            stream.write_all(resp.as_bytes()).unwrap();
+        });
+
+        let backend_config = serde_yaml::from_str("{backend: memory, max_size: 1000000}").unwrap();
+        let backend = Backend::new("test".to_string(), backend_config).unwrap();
+        let storage = LuaStorage::new(backend);
+
+        lua.load(chunk! {
+            local resp = Request.new({method = "HEAD", uri = "/"}):proxy_to_upstream($upstream)
+            assert(resp.status == 200)
+            assert(resp:header("content-length") == "5")
+            assert(resp.body:to_string() == nil, "body must be dropped")
+
+            // `HEAD` responses must not be cached
+            local res = $storage:store_response({key = "head", response = resp, ttl = 10})
+            assert(res == false, "HEAD response must not be stored")
+            assert($storage:get_response("head") == nil)
+        })
+        .exec_async()
+        .await
+    }
+
+    #[ntex::test]
+    async fn test_proxy_encode_brotli() -> Result<()> {
+        use std::io::Read;
+
+        let lua = Lua::new();
+
+        lua.globals()
+            .set("Request", lua.create_proxy::<LuaRequest>()?)?;
+        lua.set_app_data(HttpClient::new());
+
+        let mock_server = test::server(|| {
+            App::new()
+                .service(web::resource("/plain").to(|| async move {
+                    web::HttpResponse::Ok().body("hello, world! ".repeat(100))
+                }))
+                .service(web::resource("/gzip").to(|| async move {
+                    web::HttpResponse::Ok()
+                        .header("content-encoding", "gzip")
+                        .body("not really gzipped")
+                }))
+        });
+        let upstream = format!("http://{}", mock_server.addr());
+
+        let body: LuaString = lua
+            .load(chunk! {
+                local function proxy(path)
+                    return Request.new({uri = path}):proxy_to_upstream($upstream)
+                end
+
+                // Client does not accept brotli
+                local resp = proxy("/plain")
+                assert(resp:encode_brotli("gzip, br;q=0") == false)
+                assert(resp:header("content-encoding") == nil)
+
+                // Body is too small
+                resp = proxy("/plain")
+                assert(resp:encode_brotli("br", { min_size = 2000 }) == false)
+                assert(#resp.body:to_string() == 1400)
+
+                // Already encoded body is not touched
+                resp = proxy("/gzip")
+                assert(resp:encode_brotli("gzip, br") == false)
+                assert(resp:header("content-encoding") == "gzip")
+
+                resp = proxy("/plain")
+                assert(resp:encode_brotli("gzip, br", { quality = 9 }) == true)
+                assert(resp:header("content-encoding") == "br")
+                assert(resp:header("vary") == "Accept-Encoding")
+                local body = resp.body:to_string()
+                assert(resp:header("content-length") == tostring(#body))
+                assert(#body < 1400)
+                return body
+            })
+            .eval_async()
+            .await?;
+
+        let mut decoded = String::new();
+        brotli::Decompressor::new(&body.as_bytes()[..], 4096)
+            .read_to_string(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded, "hello, world! ".repeat(100));
+
         Ok(())
     }
 }
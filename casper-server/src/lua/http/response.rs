@@ -1,23 +1,43 @@
 use std::cell::{Ref, RefCell, RefMut};
 use std::collections::HashMap;
 use std::mem;
-use std::time::Duration;
+use std::time::{Duration, SystemTime};
 
 use mlua::{
-    ExternalError, ExternalResult, FromLua, IntoLua, Lua, Result as LuaResult, String as LuaString,
-    Table, UserData, UserDataFields, UserDataMethods, Value,
+    ExternalError, ExternalResult, FromLua, Function, IntoLua, Lua, LuaSerdeExt,
+    Result as LuaResult, String as LuaString, Table, UserData, UserDataFields, UserDataMethods,
+    Value,
 };
-use ntex::http::body::MessageBody;
+use ntex::http::body::{BodySize, MessageBody};
 use ntex::http::client::ClientResponse;
-use ntex::http::header::{HeaderMap, CONTENT_LENGTH};
+use ntex::http::header::{
+    HeaderMap, HeaderName, HeaderValue, CACHE_CONTROL, CONTENT_ENCODING, CONTENT_LENGTH,
+    CONTENT_RANGE, CONTENT_TYPE, ETAG, LAST_MODIFIED, VARY, WARNING,
+};
 use ntex::http::{HttpMessage, Method, Response, ResponseHead, StatusCode, Version};
 use ntex::util::{Bytes, Extensions};
 use ntex::web::{HttpRequest, Responder};
 use opentelemetry::{Key as OTKey, Value as OTValue};
 
 use super::{EitherBody, LuaBody, LuaHttpHeaders, LuaHttpHeadersExt};
+use crate::http::cache_control::CacheControl;
+use crate::http::encoding::{compress_with_brotli, ContentEncoding, BROTLI_DEFAULT_QUALITY};
+use crate::http::range::{if_range_matches, parse_byte_range, ByteRange};
 use crate::lua::json::JsonObject;
-use crate::types::EncryptedExt;
+use crate::lua::FlexBytes;
+use crate::types::{
+    CacheDebugHeaders, DefaultContentType, EncryptedExt, HeadResponseExt, NegativeTtlExt,
+    StoredMetaExt,
+};
+
+type WrapBodyArgs = (Option<FlexBytes>, Option<FlexBytes>, Option<Table>);
+
+// Bodies up to this size are transcoded in memory
+const TRANSCODE_INPLACE_SIZE: usize = 64 * 1024;
+
+const SERVER_TIMING: HeaderName = HeaderName::from_static("server-timing");
+const X_CACHE_AGE: HeaderName = HeaderName::from_static("x-cache-age");
+const X_CACHE_LIFETIME: HeaderName = HeaderName::from_static("x-cache-lifetime");
 
 #[derive(Default, Debug)]
 pub struct LuaResponse {
@@ -29,6 +49,8 @@ pub struct LuaResponse {
     labels: Option<HashMap<OTKey, OTValue>>, // For metrics
     pub is_proxied: bool,
     pub is_stored: bool,
+    // Headers must be sent without waiting for the body (the body cannot be buffered)
+    headers_flushed: bool,
 }
 
 impl LuaResponse {
@@ -80,6 +102,26 @@ impl LuaResponse {
         &mut self.body
     }
 
+    /// Returns `true` if the response is for a `HEAD` request
+    #[inline]
+    pub fn is_head(&self) -> bool {
+        self.extensions().get::<HeadResponseExt>().is_some()
+    }
+
+    /// Marks the response to send headers (and status) without waiting for the body.
+    ///
+    /// The body is streamed to the client afterwards, so it cannot be buffered anymore.
+    #[inline]
+    pub fn flush_headers(&mut self) {
+        self.headers_flushed = true;
+    }
+
+    /// Returns `true` if the headers are marked to be flushed early
+    #[inline]
+    pub fn headers_flushed(&self) -> bool {
+        self.headers_flushed
+    }
+
     pub(crate) fn set_proxied(&mut self, proxied: bool) {
         self.is_proxied = proxied;
     }
@@ -97,8 +139,386 @@ impl LuaResponse {
         self.labels.take()
     }
 
+    /// Wraps the body with prefix and suffix if the response content type is in the list
+    /// (empty list means any content type).
+    ///
+    /// Updates `Content-Length` header or removes it if the new body size is unknown.
+    pub fn wrap_body(&mut self, prefix: Bytes, suffix: Bytes, content_types: &[String]) -> bool {
+        let content_type_matches = match self.mime_type() {
+            Ok(Some(m)) => content_types.iter().any(|ct| ct == m.essence_str()),
+            _ => false,
+        };
+        // Encoded (compressed) bodies cannot be wrapped
+        let is_encoded = self
+            .headers
+            .get(CONTENT_ENCODING)
+            .is_some_and(|enc| enc != "identity");
+        if !(content_types.is_empty() || content_type_matches) || is_encoded {
+            return false;
+        }
+
+        let body = LuaBody::from(mem::take(&mut self.body)).wrap(prefix, suffix);
+        match body.size() {
+            BodySize::Sized(len) if self.headers.contains_key(CONTENT_LENGTH) => {
+                self.headers.insert(CONTENT_LENGTH, HeaderValue::from(len));
+            }
+            BodySize::Stream => {
+                self.headers.remove(CONTENT_LENGTH);
+            }
+            _ => {}
+        }
+        self.body = EitherBody::Body(body);
+        true
+    }
+
+    /// Passes every chunk of the body through the Lua function (without buffering the body).
+    ///
+    /// The function result replaces the chunk, `nil` ends the body. As chunk boundaries are
+    /// arbitrary, a substitution is applied only if the whole match is within a chunk.
+    ///
+    /// The function is called synchronously while the body is read, so it must not yield
+    /// (e.g. do async calls), otherwise reading the body fails.
+    ///
+    /// Encoded (compressed) bodies cannot be transformed.
+    /// Removes `Content-Length` header as the new body size is unknown, and `ETag` header as
+    /// the transformed body is a different representation.
+    pub fn transform_body(&mut self, lua: &Lua, func: Function) -> LuaResult<()> {
+        if self.headers_flushed {
+            return Err("cannot transform body after flushing headers".into_lua_err());
+        }
+        let is_encoded = self
+            .headers
+            .get(CONTENT_ENCODING)
+            .is_some_and(|enc| enc != "identity");
+        if is_encoded {
+            return Err("cannot transform encoded body".into_lua_err());
+        }
+        let body = LuaBody::from(mem::take(&mut self.body)).transform(lua, func);
+        self.headers.remove(CONTENT_LENGTH);
+        self.headers.remove(ETAG);
+        self.body = EitherBody::Body(body);
+        Ok(())
+    }
+
+    /// Transcodes the body to a content encoding acceptable by the client
+    /// according to the `Accept-Encoding` header value.
+    ///
+    /// Bodies up to `max_inplace_size` bytes are buffered and transcoded in memory,
+    /// bigger bodies are transcoded chunk-by-chunk without buffering.
+    ///
+    /// Returns `true` if the body was transcoded.
+    pub async fn transcode_body(
+        &mut self,
+        accept_encoding: &str,
+        max_inplace_size: usize,
+    ) -> LuaResult<bool> {
+        let current = self
+            .headers
+            .get(CONTENT_ENCODING)
+            .map(|enc| enc.to_str().unwrap_or_default());
+        let Some(current) = ContentEncoding::parse(current) else {
+            // Unknown encoding cannot be transcoded
+            return Ok(false);
+        };
+        let target = match ContentEncoding::negotiate(accept_encoding, current) {
+            Some(target) if target != current => target,
+            _ => return Ok(false),
+        };
+
+        let mut body = LuaBody::from(mem::take(&mut self.body));
+        // Body must be streamed (not buffered) after flushing headers
+        let max_inplace_size = if self.headers_flushed {
+            0
+        } else {
+            max_inplace_size
+        };
+        if matches!(body.size(), BodySize::Sized(len) if len <= max_inplace_size as u64) {
+            body.buffer().await?;
+        }
+        let body = body.transcode(current, target, max_inplace_size)?;
+
+        self.set_encoded_body(body, target);
+        Ok(true)
+    }
+
+    /// Compresses the (identity encoded) body with Brotli if the client accepts it
+    /// according to the `Accept-Encoding` header value.
+    ///
+    /// The body is buffered in memory, bodies smaller than `min_size` bytes are left untouched
+    /// as well as already encoded ones.
+    ///
+    /// Returns `true` if the body was compressed.
+    pub async fn brotli_encode_body(
+        &mut self,
+        accept_encoding: &str,
+        quality: u32,
+        min_size: usize,
+    ) -> LuaResult<bool> {
+        if self.headers_flushed {
+            return Err("cannot compress body after flushing headers".into_lua_err());
+        }
+        let current = self
+            .headers
+            .get(CONTENT_ENCODING)
+            .map(|enc| enc.to_str().unwrap_or_default());
+        if ContentEncoding::parse(current) != Some(ContentEncoding::Identity) {
+            return Ok(false);
+        }
+        let target = ContentEncoding::negotiate(accept_encoding, ContentEncoding::Brotli);
+        if target != Some(ContentEncoding::Brotli) {
+            return Ok(false);
+        }
+
+        let mut body = LuaBody::from(mem::take(&mut self.body));
+        let data = body.buffer().await;
+        let data = match data {
+            Ok(Some(data)) if data.len() >= min_size => data,
+            result => {
+                self.body = EitherBody::Body(body);
+                return result.map(|_| false);
+            }
+        };
+        let data = compress_with_brotli(data, quality).await.into_lua_err()?;
+        self.set_encoded_body(LuaBody::Bytes(data), ContentEncoding::Brotli);
+        Ok(true)
+    }
+
+    /// Serves a part of the (successful) response according to the `Range` header value.
+    ///
+    /// If `If-Range` value is provided and does not match the response `ETag` or `Last-Modified`
+    /// validators, the full response is kept. The body is buffered in memory.
+    ///
+    /// Returns `true` if the response was turned into `206 Partial Content`
+    /// or `416 Range Not Satisfiable`.
+    pub async fn apply_range(&mut self, range: &str, if_range: Option<&str>) -> LuaResult<bool> {
+        if self.headers_flushed {
+            return Err("cannot apply range after flushing headers".into_lua_err());
+        }
+        if self.status != StatusCode::OK {
+            return Ok(false);
+        }
+        if let Some(if_range) = if_range {
+            let etag = self.headers.get(ETAG).and_then(|v| v.to_str().ok());
+            let last_modified = self
+                .headers
+                .get(LAST_MODIFIED)
+                .and_then(|v| v.to_str().ok());
+            if !if_range_matches(if_range, etag, last_modified) {
+                return Ok(false);
+            }
+        }
+
+        let mut body = LuaBody::from(mem::take(&mut self.body));
+        let data = match body.buffer().await {
+            Ok(data) => data.unwrap_or_default(),
+            Err(err) => {
+                self.body = EitherBody::Body(body);
+                return Err(err);
+            }
+        };
+        let len = data.len() as u64;
+        match parse_byte_range(range, len) {
+            ByteRange::Full => {
+                self.body = EitherBody::Body(body);
+                Ok(false)
+            }
+            ByteRange::Partial(range) => {
+                let content_range = format!("bytes {}-{}/{len}", range.start, range.end - 1);
+                let data = data.slice(range.start as usize..range.end as usize);
+                self.status = StatusCode::PARTIAL_CONTENT;
+                self.headers
+                    .insert(CONTENT_RANGE, HeaderValue::try_from(content_range).unwrap());
+                self.headers
+                    .insert(CONTENT_LENGTH, HeaderValue::from(data.len()));
+                self.body = EitherBody::Body(LuaBody::Bytes(data));
+                Ok(true)
+            }
+            ByteRange::Unsatisfiable => {
+                let content_range = format!("bytes */{len}");
+                self.status = StatusCode::RANGE_NOT_SATISFIABLE;
+                self.headers
+                    .insert(CONTENT_RANGE, HeaderValue::try_from(content_range).unwrap());
+                self.headers.insert(CONTENT_LENGTH, HeaderValue::from(0));
+                self.body = EitherBody::Body(LuaBody::Bytes(Bytes::new()));
+                Ok(true)
+            }
+        }
+    }
+
+    // Sets the encoded body updating the representation headers
+    fn set_encoded_body(&mut self, body: LuaBody, encoding: ContentEncoding) {
+        match encoding {
+            ContentEncoding::Identity => self.headers.remove(CONTENT_ENCODING),
+            _ => {
+                let encoding = HeaderValue::from_static(encoding.as_str());
+                self.headers.insert(CONTENT_ENCODING, encoding)
+            }
+        };
+        match body.size() {
+            BodySize::Sized(len) => {
+                self.headers.insert(CONTENT_LENGTH, HeaderValue::from(len));
+            }
+            _ => {
+                self.headers.remove(CONTENT_LENGTH);
+            }
+        }
+        let varies = self.headers.get_all(VARY).any(|v| {
+            v.to_str()
+                .unwrap_or_default()
+                .split(',')
+                .any(|v| v.trim().eq_ignore_ascii_case("accept-encoding") || v.trim() == "*")
+        });
+        if !varies {
+            self.headers
+                .append(VARY, HeaderValue::from_static("Accept-Encoding"));
+        }
+        // Re-encoded body is no longer byte-for-byte identical, so strong validator must be weakened
+        if let Some(etag) = self.headers.get(ETAG).and_then(|v| v.to_str().ok()) {
+            if !etag.starts_with("W/") {
+                let etag = HeaderValue::try_from(format!("W/{etag}"));
+                match etag {
+                    Ok(etag) => self.headers.insert(ETAG, etag),
+                    Err(_) => self.headers.remove(ETAG),
+                };
+            }
+        }
+        self.body = EitherBody::Body(body);
+    }
+
+    /// Sets TTL to store the response with if it's an error (negative caching).
+    ///
+    /// Overrides the storage `negative_cache` rules.
+    pub fn set_negative_ttl(&mut self, ttl: Option<Duration>) {
+        match ttl {
+            Some(ttl) => self.extensions_mut().insert(NegativeTtlExt(ttl)),
+            None => self.extensions_mut().remove::<NegativeTtlExt>(),
+        };
+    }
+
+    /// Returns explicitly set TTL to store the error response with
+    pub fn negative_ttl(&self) -> Option<Duration> {
+        self.extensions().get::<NegativeTtlExt>().map(|ext| ext.0)
+    }
+
+    /// Returns metadata of the stored item if the response was fetched from a storage
+    pub fn stored_meta(&self) -> Option<StoredMetaExt> {
+        if !self.is_stored {
+            return None;
+        }
+        self.extensions().get::<StoredMetaExt>().copied()
+    }
+
+    /// Sets the `Content-Type` header if it's missing.
+    ///
+    /// Responses that cannot have a body (`1xx`, `204` and `304`) are left untouched.
+    /// Returns `true` if the header was set.
+    pub fn ensure_content_type(&mut self, content_type: HeaderValue) -> bool {
+        let status = self.status;
+        let has_body = !(status.is_informational()
+            || status == StatusCode::NO_CONTENT
+            || status == StatusCode::NOT_MODIFIED);
+        if !has_body || self.headers.contains_key(CONTENT_TYPE) {
+            return false;
+        }
+        self.headers.insert(CONTENT_TYPE, content_type);
+        true
+    }
+
+    /// Sets the configured default `Content-Type` (if any) when it's missing
+    pub(crate) fn apply_default_content_type(&mut self, lua: &Lua) {
+        if let Some(content_type) = lua.app_data_ref::<DefaultContentType>() {
+            self.ensure_content_type(content_type.0.clone());
+        }
+    }
+
+    /// Sets `X-Cache-Age` and `X-Cache-Lifetime` headers (in seconds) of the stored response
+    /// if enabled in the config
+    pub(crate) fn apply_cache_debug_headers(&mut self, lua: &Lua) {
+        if lua.app_data_ref::<CacheDebugHeaders>().is_none() {
+            return;
+        }
+        let Some(meta) = self.stored_meta() else {
+            return;
+        };
+        let age = SystemTime::now().duration_since(meta.stored_at);
+        let age = age.unwrap_or_default().as_secs();
+        self.headers.insert(X_CACHE_AGE, HeaderValue::from(age));
+        match meta.expires_at {
+            Some(expires_at) => {
+                let lifetime = expires_at.duration_since(meta.stored_at);
+                let lifetime = lifetime.unwrap_or_default().as_secs();
+                self.headers
+                    .insert(X_CACHE_LIFETIME, HeaderValue::from(lifetime));
+            }
+            None => {
+                self.headers.remove(X_CACHE_LIFETIME);
+            }
+        }
+    }
+
+    /// Appends a `Server-Timing` entry in the `name;dur=<ms>;desc="<desc>"` format.
+    ///
+    /// Each entry is added as a separate header value, so existing entries are preserved.
+    pub fn add_server_timing(
+        &mut self,
+        name: &str,
+        dur_ms: Option<f64>,
+        desc: Option<&str>,
+    ) -> Result<(), String> {
+        let is_tchar = |c: char| c.is_ascii_alphanumeric() || "!#$%&'*+-.^_`|~".contains(c);
+        if name.is_empty() || !name.chars().all(is_tchar) {
+            return Err(format!("invalid server timing name '{name}'"));
+        }
+        let mut entry = name.to_string();
+        if let Some(dur) = dur_ms {
+            if !dur.is_finite() || dur < 0.0 {
+                return Err(format!("invalid server timing duration '{dur}'"));
+            }
+            entry.push_str(&format!(";dur={dur}"));
+        }
+        if let Some(desc) = desc {
+            let desc = desc.replace('\\', "\\\\").replace('"', "\\\"");
+            entry.push_str(&format!(";desc=\"{desc}\""));
+        }
+        let value = HeaderValue::try_from(entry).map_err(|err| err.to_string())?;
+        self.headers.append(SERVER_TIMING, value);
+        Ok(())
+    }
+
+    /// Appends a `Warning` header value in the `<code> - "<text>"` format (RFC 7234).
+    pub fn add_warning(&mut self, code: u16, text: &str) -> Result<(), String> {
+        if !(100..300).contains(&code) {
+            return Err(format!("invalid warning code '{code}'"));
+        }
+        let text = text.replace('\\', "\\\\").replace('"', "\\\"");
+        let value =
+            HeaderValue::try_from(format!("{code} - \"{text}\"")).map_err(|err| err.to_string())?;
+        self.headers.append(WARNING, value);
+        Ok(())
+    }
+
+    /// Removes `Warning` headers with `1xx` codes.
+    ///
+    /// They describe freshness of the response and must be deleted after successful revalidation.
+    pub fn remove_stale_warnings(&mut self) {
+        let warnings = self.headers.get_all(WARNING).cloned().collect::<Vec<_>>();
+        if warnings.is_empty() {
+            return;
+        }
+        self.headers.remove(WARNING);
+        for value in warnings {
+            if !value.as_bytes().starts_with(b"1") {
+                self.headers.append(WARNING, value);
+            }
+        }
+    }
+
     /// Clones the response including buffering body
     async fn clone(&mut self) -> LuaResult<Self> {
+        if self.headers_flushed {
+            return Err("cannot clone response after flushing headers".into_lua_err());
+        }
+
         // Try to buffer body first
         let body = self.body_mut().buffer().await?;
         let body = body.map(LuaBody::Bytes).unwrap_or(LuaBody::None);
@@ -112,6 +532,7 @@ impl LuaResponse {
             labels: self.labels.clone(),
             is_proxied: self.is_proxied,
             is_stored: self.is_stored,
+            headers_flushed: false,
         })
     }
 }
@@ -135,6 +556,7 @@ impl From<ClientResponse> for LuaResponse {
             labels: None,
             is_proxied: true,
             is_stored: false,
+            headers_flushed: false,
         }
     }
 }
@@ -152,6 +574,7 @@ impl From<ResponseHead> for LuaResponse {
             labels: None,
             is_proxied: false,
             is_stored: false,
+            headers_flushed: false,
         }
     }
 }
@@ -163,8 +586,10 @@ where
     #[inline]
     fn from(mut response: Response<B>) -> Self {
         let extensions = mem::take(&mut *response.extensions_mut());
+        // Stored responses keep the version of the original response
+        let version = (extensions.get::<StoredMetaExt>()).and_then(|meta| meta.version);
         LuaResponse {
-            version: None,
+            version,
             status: response.status(),
             headers: mem::take(response.headers_mut()),
             extensions: RefCell::new(extensions),
@@ -172,6 +597,7 @@ where
             labels: None,
             is_proxied: false,
             is_stored: false,
+            headers_flushed: false,
         }
     }
 }
@@ -263,6 +689,24 @@ impl UserData for LuaResponse {
                     .unwrap_or_default())
         });
 
+        // Stored item metadata (`nil` if the response is not fetched from a storage)
+        fields.add_field_method_get("stored_at", |_, this| {
+            Ok(this.stored_meta().map(|meta| {
+                let stored_at = meta.stored_at.duration_since(SystemTime::UNIX_EPOCH);
+                stored_at.unwrap_or_default().as_secs_f64()
+            }))
+        });
+        fields.add_field_method_get("ttl_remaining", |_, this| {
+            Ok(this.stored_meta().and_then(|meta| {
+                let expires_at = meta.expires_at?;
+                let ttl = expires_at.duration_since(SystemTime::now());
+                Some(ttl.unwrap_or_default().as_secs_f64())
+            }))
+        });
+        fields.add_field_method_get("stored_size", |_, this| {
+            Ok(this.stored_meta().map(|meta| meta.body_size))
+        });
+
         fields.add_field_method_get("status", |_, this| Ok(this.status().as_u16()));
         fields.add_field_method_set("status", |_, this, status: u16| {
             *this.status_mut() = StatusCode::from_u16(status)
@@ -276,8 +720,14 @@ impl UserData for LuaResponse {
             None => Ok(Value::Nil),
         });
 
+        fields.add_field_method_get("headers_flushed", |_, this| Ok(this.headers_flushed));
+
         fields.add_field_function_get("body", |lua, this| {
             let mut this = this.borrow_mut::<Self>()?;
+            // Body can be buffered in Lua, which would delay the (already flushed) headers
+            if this.headers_flushed {
+                return Err("cannot access body after flushing headers".into_lua_err());
+            }
             this.body_mut().to_userdata(lua)
         });
     }
@@ -296,6 +746,11 @@ impl UserData for LuaResponse {
 
         methods.add_async_method_mut("clone", |_, mut this, ()| async move { this.clone().await });
 
+        methods.add_method_mut("flush_headers", |_, this, ()| {
+            this.flush_headers();
+            Ok(())
+        });
+
         methods.add_method("header", |lua, this, name: String| {
             LuaHttpHeadersExt::get(this.headers(), lua, &name)
         });
@@ -333,6 +788,46 @@ impl UserData for LuaResponse {
             },
         );
 
+        // Sets the `Content-Type` header if it's missing
+        // Returns `true` if the header was set
+        methods.add_method_mut("ensure_content_type", |_, this, value: LuaString| {
+            let value = HeaderValue::from_bytes(&value.as_bytes()).into_lua_err()?;
+            Ok(this.ensure_content_type(value))
+        });
+
+        methods.add_method_mut(
+            "add_server_timing",
+            |_, this, (name, dur_ms, desc): (String, Option<f64>, Option<String>)| {
+                this.add_server_timing(&name, dur_ms, desc.as_deref())
+                    .into_lua_err()
+            },
+        );
+
+        // Sets the `Cache-Control` header built from the table of directives
+        // (the header is removed if no directives are left)
+        methods.add_method_mut("set_cache_control", |lua, this, directives: Value| {
+            let cache_control = lua.from_value::<CacheControl>(directives)?.to_string();
+            match cache_control.is_empty() {
+                true => this.headers_mut().remove(CACHE_CONTROL),
+                false => {
+                    let value = HeaderValue::from_str(&cache_control).into_lua_err()?;
+                    this.headers_mut().insert(CACHE_CONTROL, value)
+                }
+            };
+            Ok(())
+        });
+
+        // Sets TTL to store the response with if it's an error (`nil` to reset)
+        methods.add_method_mut("set_negative_ttl", |_, this, ttl: Option<f64>| {
+            let ttl = ttl.map(Duration::try_from_secs_f64).transpose();
+            this.set_negative_ttl(ttl.into_lua_err()?);
+            Ok(())
+        });
+
+        methods.add_method_mut("add_warning", |_, this, (code, text): (u16, String)| {
+            this.add_warning(code, &text).into_lua_err()
+        });
+
         methods.add_method("headers", |_, this, ()| {
             Ok(LuaHttpHeaders::from(this.headers().clone()))
         });
@@ -353,6 +848,66 @@ impl UserData for LuaResponse {
             Ok(())
         });
 
+        // Wraps the body with prefix and suffix (without buffering it)
+        // By default applies only to `text/html` responses
+        // Returns `true` if the body was wrapped
+        methods.add_method_mut("wrap_body", |_, this, args: WrapBodyArgs| {
+            let (prefix, suffix, opts) = args;
+            let content_types = match opts {
+                Some(opts) => opts.raw_get::<Option<Vec<String>>>("content_types")?,
+                None => None,
+            };
+            let content_types = content_types.unwrap_or_else(|| vec!["text/html".into()]);
+            let prefix = prefix.map(|b| b.into_bytes()).unwrap_or_default();
+            let suffix = suffix.map(|b| b.into_bytes()).unwrap_or_default();
+            Ok(this.wrap_body(prefix, suffix, &content_types))
+        });
+
+        // Passes body chunks through the function lazily (without buffering the body)
+        // Returning `nil` from the function ends the body, the function must not yield
+        methods.add_method_mut("transform_body", |lua, this, func: Function| {
+            this.transform_body(lua, func)
+        });
+
+        // Transcodes the body to an encoding acceptable by the client (e.g. zstd -> gzip)
+        // Returns `true` if the body was transcoded
+        methods.add_async_method_mut(
+            "transcode",
+            |_, mut this, accept_encoding: String| async move {
+                let result = this
+                    .transcode_body(&accept_encoding, TRANSCODE_INPLACE_SIZE)
+                    .await;
+                Ok(Ok(lua_try!(result)))
+            },
+        );
+
+        // Compresses the (identity encoded) body with Brotli if the client accepts it
+        // Returns `true` if the body was compressed
+        methods.add_async_method_mut(
+            "encode_brotli",
+            |_, mut this, (accept_encoding, opts): (String, Option<Table>)| async move {
+                let (quality, min_size) = match opts {
+                    Some(opts) => (opts.raw_get("quality")?, opts.raw_get("min_size")?),
+                    None => (None, None),
+                };
+                let quality = quality.unwrap_or(BROTLI_DEFAULT_QUALITY);
+                let result = this
+                    .brotli_encode_body(&accept_encoding, quality, min_size.unwrap_or(0))
+                    .await;
+                Ok(Ok(lua_try!(result)))
+            },
+        );
+
+        // Serves a part of the response according to the `Range` and `If-Range` header values
+        // Returns `true` if the response status was changed to 206 or 416
+        methods.add_async_method_mut(
+            "apply_range",
+            |_, mut this, (range, if_range): (String, Option<String>)| async move {
+                let result = this.apply_range(&range, if_range.as_deref()).await;
+                Ok(Ok(lua_try!(result)))
+            },
+        );
+
         methods.add_async_method_mut(
             "body_json",
             |lua, mut this, timeout: Option<f64>| async move {
@@ -485,6 +1040,103 @@ mod tests {
         .exec()
     }
 
+    #[ntex::test]
+    async fn test_response_ensure_content_type() -> Result<()> {
+        let lua = Lua::new();
+
+        lua.globals()
+            .set("Response", lua.create_proxy::<LuaResponse>()?)?;
+
+        lua.load(chunk! {
+            // Missing content type
+            local resp = Response.new({ body = "{\"hello\": \"world\"}" })
+            assert(resp:body_json() == nil, "body_json must fail without content type")
+            assert(resp:ensure_content_type("application/json; charset=utf-8") == true)
+            assert(resp:header("content-type") == "application/json; charset=utf-8")
+            assert(resp:body_json().hello == "world")
+
+            // Present content type
+            resp = Response.new({ headers = { ["content-type"] = "text/html" } })
+            assert(resp:ensure_content_type("application/json") == false)
+            assert(resp:header("content-type") == "text/html")
+
+            // Response without body
+            resp = Response.new({ status = 204 })
+            assert(resp:ensure_content_type("application/json") == false)
+            assert(resp:header("content-type") == nil)
+        })
+        .exec_async()
+        .await
+    }
+
+    #[ntex::test]
+    async fn test_response_server_timing() -> Result<()> {
+        let lua = Lua::new();
+
+        lua.globals()
+            .set("Response", lua.create_proxy::<LuaResponse>()?)?;
+
+        lua.load(chunk! {
+            local resp = Response.new({
+                headers = { ["server-timing"] = "upstream;dur=100" },
+            })
+            resp:add_server_timing("cache", 1.5, "Cache lookup")
+            resp:add_server_timing("miss")
+            resp:add_server_timing("db", 53)
+            resp:add_server_timing("app", nil, "say \"hi\"")
+            local timings = resp:header_all("server-timing")
+            assert(#timings == 5, "expected 5 server timing entries")
+            assert(timings[1] == "upstream;dur=100")
+            assert(timings[2] == "cache;dur=1.5;desc=\"Cache lookup\"")
+            assert(timings[3] == "miss")
+            assert(timings[4] == "db;dur=53")
+            assert(timings[5] == "app;desc=\"say \\\"hi\\\"\"")
+
+            // Invalid entries
+            local ok, err = pcall(resp.add_server_timing, resp, "bad name", 1)
+            assert(not ok and tostring(err):find("invalid server timing name"))
+            ok, err = pcall(resp.add_server_timing, resp, "neg", -1)
+            assert(not ok and tostring(err):find("invalid server timing duration"))
+        })
+        .exec_async()
+        .await
+    }
+
+    #[ntex::test]
+    async fn test_response_warning() -> Result<()> {
+        let lua = Lua::new();
+
+        lua.globals()
+            .set("Response", lua.create_proxy::<LuaResponse>()?)?;
+
+        let resp = lua
+            .load(chunk! {
+                local resp = Response.new({
+                    headers = { warning = "214 - \"Transformation Applied\"" },
+                })
+                resp:add_warning(110, "Response is Stale")
+                resp:add_warning(111, "say \"hi\"")
+                local warnings = resp:header_all("warning")
+                assert(#warnings == 3, "expected 3 warnings")
+                assert(warnings[2] == "110 - \"Response is Stale\"")
+                assert(warnings[3] == "111 - \"say \\\"hi\\\"\"")
+
+                local ok, err = pcall(resp.add_warning, resp, 999, "bad")
+                assert(not ok and tostring(err):find("invalid warning code"))
+                return resp
+            })
+            .eval_async::<AnyUserData>()
+            .await?;
+
+        // Only freshness (1xx) warnings are removed after revalidation
+        let mut resp = resp.borrow_mut::<LuaResponse>()?;
+        resp.remove_stale_warnings();
+        let warnings = resp.headers().get_all("warning").collect::<Vec<_>>();
+        assert_eq!(warnings, ["214 - \"Transformation Applied\""]);
+
+        Ok(())
+    }
+
     #[ntex::test]
     async fn test_response_clone() -> Result<()> {
         let lua = Lua::new();
@@ -579,6 +1231,281 @@ mod tests {
         .await
     }
 
+    #[ntex::test]
+    async fn test_response_wrap_body() -> Result<()> {
+        let lua = Lua::new();
+
+        lua.globals()
+            .set("Response", lua.create_proxy::<LuaResponse>()?)?;
+
+        lua.load(chunk! {
+            // Buffered html body with content length
+            local resp = Response.new({
+                headers = { ["content-type"] = "text/html; charset=utf-8", ["content-length"] = "5" },
+                body = "hello",
+            })
+            assert(resp:wrap_body("<p>", "</p>") == true)
+            assert(resp:header("content-length") == "12")
+            assert(resp.body:to_string() == "<p>hello</p>")
+
+            // Non-html body is not wrapped by default
+            resp = Response.new({ headers = { ["content-type"] = "text/plain" }, body = "hello" })
+            assert(resp:wrap_body("<p>", "</p>") == false)
+            assert(resp.body:to_string() == "hello")
+            // Unless content type is allowed
+            assert(resp:wrap_body(nil, "!", { content_types = {"text/plain"} }) == true)
+            assert(resp.body:to_string() == "hello!")
+
+            // Compressed body cannot be wrapped
+            resp = Response.new({
+                headers = { ["content-type"] = "text/html", ["content-encoding"] = "gzip" },
+                body = "hello",
+            })
+            assert(resp:wrap_body("<p>", "</p>") == false)
+
+            // Streaming body with unknown size switches to chunked encoding
+            local i = 0
+            resp = Response.new({
+                headers = { ["content-type"] = "text/html", ["content-length"] = "10" },
+                body = function()
+                    i += 1
+                    if i <= 2 then
+                        return "hello"
+                    end
+                end,
+            })
+            assert(resp:wrap_body("<p>", "</p>") == true)
+            assert(resp:header("content-length") == nil)
+            assert(resp.body:to_string() == "<p>hellohello</p>")
+        })
+        .exec_async()
+        .await
+    }
+
+    #[ntex::test]
+    async fn test_response_transform_body() -> Result<()> {
+        let lua = Lua::new();
+
+        lua.globals()
+            .set("Response", lua.create_proxy::<LuaResponse>()?)?;
+
+        lua.load(chunk! {
+            local function make_body(chunks)
+                local i = 0
+                return function()
+                    i += 1
+                    return chunks[i]
+                end
+            end
+            local function replace(chunk)
+                return (chunk:gsub("world", "lua"))
+            end
+
+            // Chunks are transformed lazily
+            local calls = 0
+            local resp = Response.new({
+                headers = { ["content-length"] = "11", etag = "\"abc\"" },
+                body = "hello world",
+            })
+            resp:transform_body(function(chunk)
+                calls += 1
+                return replace(chunk)
+            end)
+            assert(calls == 0)
+            assert(resp:header("content-length") == nil)
+            assert(resp:header("etag") == nil)
+            assert(resp.body:to_string() == "hello lua")
+            assert(calls == 1)
+
+            // Substitution works only within a chunk (matches across chunk boundaries are kept)
+            resp = Response.new({ body = make_body({ "hello wo", "rld, hello world", "" }) })
+            resp:transform_body(replace)
+            assert(resp.body:to_string() == "hello world, hello lua")
+
+            // Returning `nil` ends the body
+            resp = Response.new({ body = make_body({ "a", "b", "STOP", "c" }) })
+            resp:transform_body(function(chunk)
+                if chunk ~= "STOP" then
+                    return chunk:upper()
+                end
+            end)
+            assert(resp.body:to_string() == "AB")
+
+            // Errors are propagated to the reader
+            resp = Response.new({ body = "hello" })
+            resp:transform_body(function() error("boom") end)
+            local data, err = resp.body:to_string()
+            assert(data == nil and err:find("boom") ~= nil)
+
+            // Yielding functions are not supported
+            resp = Response.new({ body = "hello" })
+            resp:transform_body(function(chunk)
+                coroutine.yield()
+                return chunk
+            end)
+            data, err = resp.body:to_string()
+            assert(data == nil and err ~= nil)
+
+            // Encoded bodies cannot be transformed
+            resp = Response.new({ headers = { ["content-encoding"] = "gzip" }, body = "hello" })
+            local ok, err = pcall(resp.transform_body, resp, replace)
+            assert(not ok and tostring(err):find("cannot transform encoded body") ~= nil)
+        })
+        .exec_async()
+        .await
+    }
+
+    #[ntex::test]
+    async fn test_response_transcode() -> Result<()> {
+        use std::io::Read;
+
+        use futures::stream::{self, StreamExt};
+        use ntex::http::body::BoxedBodyStream;
+        use rand::distributions::{Alphanumeric, DistString};
+
+        let lua = Lua::new();
+
+        lua.globals()
+            .set("Response", lua.create_proxy::<LuaResponse>()?)?;
+
+        let gunzip = |data: &[u8]| {
+            let mut output = Vec::new();
+            flate2::read::GzDecoder::new(data)
+                .read_to_end(&mut output)
+                .map(|_| output)
+        };
+
+        // Small zstd body is transcoded in memory
+        let zstd_data = lua.create_string(zstd::encode_all(&b"hello, world"[..], 0).unwrap())?;
+        let resp: AnyUserData = lua
+            .load(chunk! {
+                local resp = Response.new({
+                    headers = { ["content-encoding"] = "zstd", vary = "Origin", etag = "\"abc\"" },
+                    body = $zstd_data,
+                })
+                // Client accepts zstd
+                assert(resp:transcode("gzip, zstd") == false)
+                // Client does not support any compression
+                assert(resp:transcode("deflate;q=1, identity;q=0") == false)
+                assert(resp:transcode("gzip") == true)
+                assert(resp:header("content-encoding") == "gzip")
+                assert(resp:header("content-length") == tostring(#resp.body:to_string()))
+                assert(resp:header_cnt("vary") == 2)
+                assert(resp:header("etag") == "W/\"abc\"")
+                return resp
+            })
+            .eval_async()
+            .await?;
+        let mut resp = resp.take::<LuaResponse>()?;
+        let body = resp.body_mut().buffer().await?.unwrap();
+        assert_eq!(gunzip(&body).unwrap(), b"hello, world");
+
+        // Big zstd body that never ends must be transcoded without buffering
+        let data = Alphanumeric.sample_string(&mut rand::thread_rng(), 1024 * 1024);
+        let data = data.as_bytes();
+        let compressed = Bytes::from(zstd::encode_all(data, 0).unwrap());
+        let chunks = compressed
+            .chunks(8 * 1024)
+            .map(|chunk| Ok(Bytes::copy_from_slice(chunk)))
+            .collect::<Vec<_>>();
+        let stream = stream::iter(chunks).chain(stream::pending());
+        let mut resp = LuaResponse::new(LuaBody::from(BoxedBodyStream::new(stream)));
+        resp.headers_mut()
+            .insert(CONTENT_ENCODING, HeaderValue::from_static("zstd"));
+        resp.headers_mut()
+            .insert(CONTENT_LENGTH, HeaderValue::from(compressed.len()));
+        let resp = lua.create_userdata(resp)?;
+        let lua_resp = resp.clone();
+        lua.load(chunk! {
+            local resp = $lua_resp
+            assert(resp:transcode("gzip;q=0.8, deflate") == true)
+            assert(resp:header("content-encoding") == "gzip")
+            assert(resp:header("content-length") == nil)
+            assert(resp:header("vary") == "Accept-Encoding")
+        })
+        .exec_async()
+        .await?;
+        let mut body = LuaBody::from(resp.take::<LuaResponse>()?.body);
+        let mut output = Vec::new();
+        while output.len() < 1024 {
+            let chunk = tokio::time::timeout(
+                Duration::from_secs(5),
+                futures::future::poll_fn(|cx| body.poll_next_chunk(cx)),
+            )
+            .await
+            .expect("body must be transcoded without buffering");
+            output.extend_from_slice(&chunk.unwrap().unwrap());
+        }
+        // Decode available (partial) gzip stream
+        let mut decoded = Vec::new();
+        let _ = flate2::read::GzDecoder::new(&output[..]).read_to_end(&mut decoded);
+        assert!(!decoded.is_empty() && data.starts_with(&decoded));
+
+        Ok(())
+    }
+
+    #[ntex::test]
+    async fn test_response_apply_range() -> Result<()> {
+        let lua = Lua::new();
+
+        lua.globals()
+            .set("Response", lua.create_proxy::<LuaResponse>()?)?;
+
+        lua.load(chunk! {
+            local function cached()
+                return Response.new({
+                    headers = {
+                        etag = "\"abc\"",
+                        ["last-modified"] = "Wed, 21 Oct 2015 07:28:00 GMT",
+                    },
+                    body = "hello, world",
+                })
+            end
+
+            // No validator
+            local resp = cached()
+            assert(resp:apply_range("bytes=0-4") == true)
+            assert(resp.status == 206)
+            assert(resp:header("content-range") == "bytes 0-4/12")
+            assert(resp:header("content-length") == "5")
+            assert(resp.body:to_string() == "hello")
+
+            // Matching validators
+            resp = cached()
+            assert(resp:apply_range("bytes=7-", "\"abc\"") == true)
+            assert(resp.status == 206)
+            assert(resp.body:to_string() == "world")
+            resp = cached()
+            assert(resp:apply_range("bytes=-5", "Wed, 21 Oct 2015 07:28:00 GMT") == true)
+            assert(resp.status == 206)
+            assert(resp.body:to_string() == "world")
+
+            // Non-matching validators
+            resp = cached()
+            assert(resp:apply_range("bytes=0-4", "\"xyz\"") == false)
+            assert(resp.status == 200)
+            assert(resp:header("content-range") == nil)
+            assert(resp.body:to_string() == "hello, world")
+            resp = cached()
+            assert(resp:apply_range("bytes=0-4", "Thu, 22 Oct 2015 07:28:00 GMT") == false)
+            assert(resp.status == 200)
+            assert(resp.body:to_string() == "hello, world")
+
+            // Unsatisfiable range
+            resp = cached()
+            assert(resp:apply_range("bytes=100-") == true)
+            assert(resp.status == 416)
+            assert(resp:header("content-range") == "bytes */12")
+
+            // Non-successful response is untouched
+            resp = Response.new(404, "not found")
+            assert(resp:apply_range("bytes=0-2") == false)
+            assert(resp.status == 404)
+        })
+        .exec_async()
+        .await
+    }
+
     #[ntex::test]
     async fn test_response_labels() -> Result<()> {
         let lua = Lua::new();
@@ -599,4 +1526,59 @@ mod tests {
 
         Ok(())
     }
+
+    #[ntex::test]
+    async fn test_response_flush_headers() -> Result<()> {
+        use std::error::Error as StdError;
+        use std::time::{Duration, Instant};
+
+        use futures::stream::{self, StreamExt};
+        use ntex::http::body::BoxedBodyStream;
+        use ntex::web::{self, test, App};
+
+        let lua = Lua::new();
+
+        lua.globals()
+            .set("Response", lua.create_proxy::<LuaResponse>()?)?;
+
+        // Buffering the body is not allowed after flushing headers
+        lua.load(chunk! {
+            local resp = Response.new(200, "hello")
+            assert(resp.headers_flushed == false)
+            resp:flush_headers()
+            assert(resp.headers_flushed == true)
+            local ok, err = pcall(function() return resp:clone() end)
+            assert(not ok and tostring(err):find("cannot clone response after flushing headers"))
+            ok, err = pcall(function() return resp.body end)
+            assert(not ok and tostring(err):find("cannot access body after flushing headers"))
+        })
+        .exec_async()
+        .await?;
+
+        // Headers must arrive before the (delayed) body
+        let srv = test::server(|| {
+            App::new().service(web::resource("/").to(|| async {
+                let delayed = stream::once(async {
+                    ntex::time::sleep(Duration::from_millis(500)).await;
+                    Ok::<_, Box<dyn StdError>>(Bytes::from("hello, world!"))
+                });
+                let body = LuaBody::from(BoxedBodyStream::new(delayed.boxed_local()));
+                let mut resp = LuaResponse::new(body);
+                resp.headers_mut()
+                    .insert(HeaderName::from_static("x-test"), "abc".parse().unwrap());
+                resp.flush_headers();
+                resp
+            }))
+        });
+
+        let start = Instant::now();
+        let mut resp = srv.get("/").send().await.unwrap();
+        assert!(start.elapsed() < Duration::from_millis(400));
+        assert_eq!(resp.headers().get("x-test").unwrap(), "abc");
+        let body = resp.body().await.unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(500));
+        assert_eq!(body, "hello, world!");
+
+        Ok(())
+    }
 }
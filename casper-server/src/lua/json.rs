@@ -1,3 +1,4 @@
+use std::borrow::Cow;
 use std::rc::Rc;
 use std::result::Result as StdResult;
 
@@ -76,10 +77,16 @@ impl JsonObject {
     }
 
     /// Returns a new `JsonObject` by following the given JSON Pointer path.
+    ///
+    /// The leading slash is optional (e.g. `a/b/0/c` is the same as `/a/b/0/c`).
     fn pointer(&self, path: &str) -> Option<JsonObject> {
+        let path = match path.is_empty() || path.starts_with('/') {
+            true => Cow::Borrowed(path),
+            false => Cow::Owned(format!("/{path}")),
+        };
         Some(JsonObject {
             root: self.root.clone(),
-            current: self.current().pointer(path)?,
+            current: self.current().pointer(&path)?,
         })
     }
 
@@ -125,6 +132,8 @@ impl UserData for JsonObject {
         /*
         --- @within JsonObject
         --- Follows the given JSON Pointer and returns the value.
+        --- The leading slash is optional (e.g. `a/b/0/c`).
+        --- Returns `nil` if the path does not exist.
         ---
         --- @param path json pointer
         function jsonObject:pointer(path: string): ValueOrJsonObject
@@ -326,6 +335,19 @@ mod tests {
             assert(native_value:pointer("/a") == 1)
             assert(native_value:pointer("/c/0") == 3)
 
+            local nested = $json.decode_native("{\"a\":{\"b\":[{\"c\":1},{\"c\":\"x\",\"d/e\":true}]}}")
+            assert(nested:pointer("a/b/0/c") == 1)
+            assert(nested:pointer("/a/b/1/c") == "x")
+            assert(nested:pointer("a/b/1/d~1e") == true)
+            assert(nested:pointer("a/b"):pointer("1/c") == "x")
+            assert(type(nested:pointer("a/b")) == "userdata")
+            assert(nested:pointer(""):pointer("a/b/0/c") == 1)
+            assert(nested:pointer("a/x/0") == nil)
+            assert(nested:pointer("a/b/2/c") == nil)
+            assert(nested:pointer("a/b/-1") == nil)
+            assert(nested:pointer("a/b/c") == nil)
+            assert(nested:pointer("a/b/0/c/d") == nil)
+
             // Test preserving data types
             local float_data = "{\"f\":[[],{},0.0,1.0,3]}"
             assert($json.encode($json.decode_native(float_data)) == float_data)
@@ -1,16 +1,34 @@
-use mlua::{Lua, Result, Table, UserData, UserDataMethods, Value};
+use mlua::{Error as LuaError, Lua, Result, Table, UserData, UserDataMethods, Value};
 
-use opentelemetry::metrics::Counter;
+use std::collections::HashMap;
+
+use opentelemetry::metrics::Histogram;
 use opentelemetry::KeyValue;
 
-struct U64Counter(Counter<u64>);
+use crate::metrics::{ActiveCounter, UserCounter};
+
+struct U64Counter(UserCounter);
 
 impl UserData for U64Counter {
     fn add_methods<M: UserDataMethods<Self>>(methods: &mut M) {
         methods.add_method(
             "add",
             |_, this, (value, attributes): (u64, Option<Table>)| {
-                this.0.add(value, &from_lua_attributes(attributes)?);
+                this.0.add(value, from_lua_attributes(attributes)?);
+                Ok(())
+            },
+        );
+    }
+}
+
+struct F64Histogram(Histogram<f64>);
+
+impl UserData for F64Histogram {
+    fn add_methods<M: UserDataMethods<Self>>(methods: &mut M) {
+        methods.add_method(
+            "record",
+            |_, this, (value, attributes): (f64, Option<Table>)| {
+                this.0.record(value, &from_lua_attributes(attributes)?);
                 Ok(())
             },
         );
@@ -43,11 +61,209 @@ fn from_lua_attributes(attributes: Option<Table>) -> Result<Vec<KeyValue>> {
 }
 
 pub fn create_module(lua: &Lua) -> Result<Table> {
+    let metrics = crate::metrics::global();
+    create_module_with(lua, &metrics.counters, &metrics.histograms, &metrics.gauges)
+}
+
+fn create_module_with(
+    lua: &Lua,
+    counters: &HashMap<String, UserCounter>,
+    histograms: &HashMap<String, Histogram<f64>>,
+    gauges: &HashMap<String, ActiveCounter>,
+) -> Result<Table> {
     let metrics = lua.create_table()?;
 
-    for (name, counter) in &crate::metrics::global().counters {
+    let gauges2 = gauges.clone();
+    let get_gauge = move |name: &str| {
+        gauges2
+            .get(name)
+            .cloned()
+            .ok_or_else(|| LuaError::runtime(format!("gauge `{name}` is not defined")))
+    };
+    let get_gauge2 = get_gauge.clone();
+    metrics.raw_set(
+        "gauge_set",
+        lua.create_function(move |_, (name, value): (String, u64)| {
+            get_gauge(&name)?.set(value);
+            Ok(())
+        })?,
+    )?;
+    metrics.raw_set(
+        "gauge_add",
+        lua.create_function(move |_, (name, delta): (String, i64)| {
+            get_gauge2(&name)?.add(delta);
+            Ok(())
+        })?,
+    )?;
+
+    for (name, counter) in counters {
         metrics.raw_set(name.as_str(), U64Counter(counter.clone()))?;
     }
+    for (name, histogram) in histograms {
+        metrics.raw_set(name.as_str(), F64Histogram(histogram.clone()))?;
+    }
 
     Ok(metrics)
 }
+
+#[cfg(test)]
+mod tests {
+    use mlua::{chunk, Lua, Result};
+    use opentelemetry::metrics::MeterProvider as _;
+    use opentelemetry_sdk::metrics::SdkMeterProvider;
+    use prometheus::proto::MetricFamily;
+
+    use crate::config::Config;
+
+    fn find_family(registry: &prometheus::Registry, name: &str) -> MetricFamily {
+        (registry.gather().into_iter())
+            .find(|family| family.get_name() == name)
+            .unwrap_or_else(|| panic!("metric `{name}` is not registered"))
+    }
+
+    #[test]
+    fn test_histogram() -> Result<()> {
+        let config: Config = serde_yaml::from_str(
+            r#"
+            metrics:
+              histograms:
+                lua_latency:
+                  name: lua_logic_duration_seconds
+                  description: "Lua logic latency"
+                  buckets: [0.1, 1.0]
+        "#,
+        )
+        .unwrap();
+
+        let (provider, registry) = crate::metrics::test_meter_provider();
+        let meter = provider.meter("test");
+        let histograms = crate::metrics::user_histograms(&meter, &config);
+
+        let lua = Lua::new();
+        let metrics =
+            super::create_module_with(&lua, &Default::default(), &histograms, &Default::default())?;
+        lua.load(chunk! {
+            $metrics.lua_latency:record(0.05, { route = "a" })
+            $metrics.lua_latency:record(0.5, { route = "a" })
+        })
+        .exec()?;
+
+        let family = find_family(&registry, "lua_logic_duration_seconds");
+        assert_eq!(family.get_help(), "Lua logic latency");
+        let metric = &family.get_metric()[0];
+        assert_eq!(metric.get_label()[0].get_value(), "a");
+        let histogram = metric.get_histogram();
+        assert_eq!(histogram.get_sample_count(), 2);
+        let buckets = histogram
+            .get_bucket()
+            .iter()
+            .map(|b| (b.get_upper_bound(), b.get_cumulative_count()))
+            .collect::<Vec<_>>();
+        assert_eq!(buckets, [(0.1, 1), (1.0, 2)]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_gauge() -> Result<()> {
+        let config: Config = serde_yaml::from_str(
+            r#"
+            metrics:
+              gauges:
+                queue_depth:
+                  description: "Lua queue depth"
+        "#,
+        )
+        .unwrap();
+
+        let provider = SdkMeterProvider::builder().build();
+        let meter = provider.meter("test");
+        let gauges = crate::metrics::user_gauges(&meter, &config);
+        let queue_depth = gauges["queue_depth"].clone();
+
+        let lua = Lua::new();
+        let metrics =
+            super::create_module_with(&lua, &Default::default(), &Default::default(), &gauges)?;
+        lua.globals().set("metrics", metrics)?;
+        lua.load(chunk! {
+            metrics.gauge_set("queue_depth", 10)
+        })
+        .exec()?;
+        assert_eq!(queue_depth.get(), 10);
+
+        lua.load(chunk! {
+            metrics.gauge_add("queue_depth", 5)
+            metrics.gauge_add("queue_depth", -3)
+        })
+        .exec()?;
+        assert_eq!(queue_depth.get(), 12);
+
+        // Values never go below zero
+        lua.load(chunk! {
+            metrics.gauge_add("queue_depth", -100)
+        })
+        .exec()?;
+        assert_eq!(queue_depth.get(), 0);
+
+        // Unknown gauge
+        let err = lua
+            .load(chunk! {
+                metrics.gauge_set("unknown", 1)
+            })
+            .exec()
+            .unwrap_err();
+        assert!(err.to_string().contains("gauge `unknown` is not defined"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_counter_labels_limit() -> Result<()> {
+        let config: Config = serde_yaml::from_str(
+            r#"
+            metrics:
+              max_label_values: 2
+              counters:
+                lua_requests:
+                  name: lua_requests_total
+        "#,
+        )
+        .unwrap();
+
+        let (provider, registry) = crate::metrics::test_meter_provider();
+        let meter = provider.meter("test");
+        let counters = crate::metrics::user_counters(&meter, &config);
+
+        let lua = Lua::new();
+        let metrics =
+            super::create_module_with(&lua, &counters, &Default::default(), &Default::default())?;
+        lua.load(chunk! {
+            for i = 1, 5 do
+                $metrics.lua_requests:add(1, { user = "user" .. i })
+            end
+            $metrics.lua_requests:add(1, { user = "user1" })
+        })
+        .exec()?;
+
+        let family = find_family(&registry, "lua_requests_total");
+        let mut values = family
+            .get_metric()
+            .iter()
+            .map(|m| {
+                let label = m.get_label()[0].get_value().to_string();
+                (label, m.get_counter().get_value())
+            })
+            .collect::<Vec<_>>();
+        values.sort_by(|a, b| a.0.cmp(&b.0));
+        assert_eq!(
+            values,
+            [
+                ("__overflow__".to_string(), 3.0),
+                ("user1".to_string(), 2.0),
+                ("user2".to_string(), 1.0),
+            ]
+        );
+
+        Ok(())
+    }
+}
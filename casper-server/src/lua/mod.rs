@@ -1,7 +1,8 @@
 pub use {
     self::http::{LuaBody, LuaRequest, LuaResponse},
     self::regex::Regex,
-    storage::LuaStorage,
+    routes::LuaRoutes,
+    storage::{LuaStorage, LuaStorageChain},
 };
 
 pub(crate) use types::FlexBytes;
@@ -11,6 +12,7 @@ mod macros;
 
 mod bytes;
 pub mod core;
+pub mod counters;
 pub mod crypto;
 pub mod csv;
 pub mod datetime;
@@ -19,9 +21,13 @@ pub mod http;
 pub mod json;
 pub mod log;
 pub mod metrics;
+pub mod ratelimit;
 pub mod regex;
+pub mod routes;
 pub mod storage;
 pub mod tasks;
+pub mod template;
+pub mod time;
 pub mod trace;
 mod types;
 pub mod udp;
@@ -0,0 +1,80 @@
+use std::time::Duration;
+
+use mlua::{ExternalResult, Lua, Result, Table, UserDataRef, Value};
+
+use super::LuaStorage;
+use crate::storage::Backend;
+
+/// Counts a hit of the key in a fixed window of `window` seconds and checks it against the `limit`.
+///
+/// The window starts with the first hit (when the counter is created in the storage).
+/// Returns whether the hit is allowed and the number of remaining hits in the window.
+/// In case of error returns `nil` and a string with error message.
+async fn check(
+    lua: Lua,
+    (storage, key, limit, window): (UserDataRef<LuaStorage<Backend>>, Value, u64, f64),
+) -> Result<(Option<bool>, Value)> {
+    let window = Duration::try_from_secs_f64(window).into_lua_err()?;
+    match storage.incr_counter(&lua, key, window).await? {
+        Ok(count) => {
+            let remaining = limit.saturating_sub(count);
+            Ok((Some(count <= limit), Value::Integer(remaining as _)))
+        }
+        Err(err) => Ok((None, Value::String(lua.create_string(err)?))),
+    }
+}
+
+pub fn create_module(lua: &Lua) -> Result<Table> {
+    lua.create_table_from([("check", lua.create_async_function(check)?)])
+}
+
+#[cfg(test)]
+mod tests {
+    use mlua::{chunk, Lua, Result};
+
+    use super::*;
+
+    #[ntex::test]
+    async fn test_ratelimit() -> Result<()> {
+        let lua = Lua::new();
+
+        let backend_config = serde_yaml::from_str(
+            r#"
+            backend: memory
+            max_size: 1000000
+        "#,
+        )
+        .unwrap();
+        let backend = Backend::new("test".to_string(), backend_config).unwrap();
+        let storage = LuaStorage::new(backend);
+
+        let ratelimit = super::create_module(&lua)?;
+        lua.globals().set(
+            "sleep",
+            lua.create_async_function(|_, secs: f64| async move {
+                tokio::time::sleep(Duration::from_secs_f64(secs)).await;
+                Ok(())
+            })?,
+        )?;
+        lua.load(chunk! {
+            local storage, ratelimit = $storage, $ratelimit
+            for i = 1, 3 do
+                local allowed, remaining = ratelimit.check(storage, "client1", 3, 0.2)
+                assert(allowed == true, "hit " .. i .. " must be allowed")
+                assert(remaining == 3 - i)
+            end
+            local allowed, remaining = ratelimit.check(storage, "client1", 3, 0.2)
+            assert(allowed == false and remaining == 0, "hit over the limit must be blocked")
+
+            // Other keys have their own quota
+            assert(ratelimit.check(storage, {"client", "2"}, 3, 0.2) == true)
+
+            // Counter is restarted in the next window
+            sleep(0.25)
+            allowed, remaining = ratelimit.check(storage, "client1", 3, 0.2)
+            assert(allowed == true and remaining == 2)
+        })
+        .exec_async()
+        .await
+    }
+}
@@ -0,0 +1,68 @@
+use std::collections::HashMap;
+
+use mlua::{ExternalResult, UserData, UserDataMethods};
+
+use crate::routes::RoutingTable;
+
+/// Lua interface to the (shared between workers) routing table
+pub struct LuaRoutes(RoutingTable);
+
+impl LuaRoutes {
+    pub fn new(table: RoutingTable) -> Self {
+        LuaRoutes(table)
+    }
+}
+
+impl UserData for LuaRoutes {
+    fn add_methods<M: UserDataMethods<Self>>(methods: &mut M) {
+        // Returns target and the matched prefix of the route for the path
+        methods.add_method("lookup", |_, this, path: String| {
+            let routes = this.0.snapshot();
+            Ok(routes
+                .lookup(&path)
+                .map(|(prefix, target)| (target.to_string(), prefix.to_string()))
+                .unzip())
+        });
+
+        // Atomically replaces all the routes
+        methods.add_method("replace", |_, this, routes: HashMap<String, String>| {
+            this.0.replace(routes).into_lua_err()
+        });
+
+        methods.add_method("all", |lua, this, ()| {
+            let routes = this.0.snapshot();
+            lua.create_table_from(routes.iter())
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mlua::{chunk, Lua, Result};
+
+    use super::*;
+
+    #[test]
+    fn test_routes() -> Result<()> {
+        let lua = Lua::new();
+
+        let table = RoutingTable::default();
+        let routes = LuaRoutes::new(table.clone());
+        lua.load(chunk! {
+            assert($routes:lookup("/api") == nil)
+            $routes:replace({ ["/api"] = "http://api:8080", ["/api/v2"] = "http://api2:8080" })
+            local target, prefix = $routes:lookup("/api/v2/users")
+            assert(target == "http://api2:8080" and prefix == "/api/v2")
+            assert($routes:all()["/api"] == "http://api:8080")
+
+            local ok, err = pcall($routes.replace, $routes, { api = "http://api" })
+            assert(not ok and tostring(err):find("route path `api` must start with `/`") ~= nil)
+        })
+        .exec()?;
+
+        // Routes are shared
+        let snapshot = table.snapshot();
+        assert_eq!(snapshot.lookup("/api"), Some(("/api", "http://api:8080")));
+        Ok(())
+    }
+}
@@ -1,34 +1,97 @@
 use std::borrow::Cow;
 use std::cell::RefCell;
 use std::error::Error as StdError;
+use std::future::Future;
 use std::iter::IntoIterator;
+use std::mem;
 use std::time::{Duration, Instant};
 
 use mlua::{
-    ErrorContext, FromLua, Lua, Result as LuaResult, String as LuaString, Table, UserData,
-    UserDataMethods, UserDataRefMut, Value,
+    ErrorContext, ExternalError, FromLua, IntoLua, IntoLuaMulti, Lua, MultiValue,
+    Result as LuaResult, String as LuaString, Table, UserData, UserDataMethods, UserDataRef,
+    UserDataRefMut, Value,
 };
-use tracing::instrument;
+use ntex::http::body::BodySize;
+use tracing::{instrument, warn};
 
-use super::http::LuaResponse;
+use super::http::{LuaBody, LuaRequest, LuaResponse};
 use crate::http::filter_hop_headers;
-use crate::storage::{Body, Item, ItemKey, Key, Storage};
+use crate::storage::{
+    Body, Item, ItemKey, Key, LatencyStats, SkipReason, Storage, StorePolicy, StoredItem,
+};
+use crate::types::SurrogateKeysExt;
+
+tokio::task_local! {
+    // Set while handling a request that must bypass the cache
+    static CACHE_BYPASS: ();
+}
+
+/// Runs the future with the cache bypassed: storage lookups report a miss without
+/// querying the backends.
+///
+/// Writes are not affected, so a fresh response fetched from upstream refreshes the cache.
+pub(crate) async fn with_cache_bypass<F: Future>(fut: F) -> F::Output {
+    CACHE_BYPASS.scope((), fut).await
+}
+
+fn is_cache_bypassed() -> bool {
+    CACHE_BYPASS.try_with(|_| ()).is_ok()
+}
 
-pub struct LuaStorage<T: Storage>(T);
+#[derive(Clone)]
+pub struct LuaStorage<T: Storage>(T, StorePolicy);
 
 impl<T: Storage> LuaStorage<T> {
-    pub const fn new(storage: T) -> Self {
-        LuaStorage(storage)
+    pub fn new(storage: T) -> Self {
+        LuaStorage(storage, StorePolicy::default())
+    }
+
+    /// Sets the policy to decide which responses can be stored
+    pub fn with_store_policy(mut self, policy: StorePolicy) -> Self {
+        self.1 = policy;
+        self
     }
 }
 
 type LuaDoubleResult<T> = LuaResult<Result<T, String>>;
 
+// Error returned when the backend refuses to store a response because of its body size
+const TOO_LARGE_ERROR: &str = "body is too large to cache";
+
+// Common fields of a response to store read from a Lua table
+struct StoreParams {
+    key: Key,
+    resp: UserDataRefMut<LuaResponse>,
+    surrogate_keys: Vec<Key>,
+    ttl: Duration,
+    encrypt: bool,
+    compress: Option<bool>,
+}
+
+// Item ready to be stored, with the response body to stream (if any)
+struct PreparedItem<'a> {
+    item: Item<'a>,
+    streamed_body: Option<LuaBody>,
+    body_size: Option<usize>,
+}
+
 impl<T> LuaStorage<T>
 where
     T: Storage<Body = Body> + 'static,
     <T as Storage>::Error: Into<Box<dyn StdError + Send + Sync>>,
 {
+    /// Returns explicitly set TTL or picks it from the store policy using the request path
+    fn get_ttl(&self, ttl: Option<f32>, path: Option<&str>) -> LuaResult<Duration> {
+        match ttl {
+            Some(ttl) if ttl >= 0.0 && ttl.is_finite() => Ok(Duration::from_secs_f32(ttl)),
+            Some(ttl) => Err(format!("`{ttl}` must be a non-negative number").into_lua_err()),
+            None => self
+                .1
+                .ttl_for_path(path)
+                .ok_or_else(|| "ttl is not set and no ttl rule matches".into_lua_err()),
+        }
+    }
+
     /// Fetches a response from the storage
     ///
     /// Returns `nil` if response is not found.
@@ -38,19 +101,44 @@ where
         let start = Instant::now();
 
         let key = calculate_primary_key(lua, key).context("failed to calculate primary key")?;
+        if is_cache_bypassed() {
+            storage_counter_add!(1, "name" => self.0.name(), "operation" => "get");
+            storage_results_counter_add!(1, "name" => self.0.name(), "operation" => "get", "status" => "bypass");
+            return Ok(Ok(None));
+        }
         let resp = self.0.get_response(key).await.map_err(Into::into);
 
-        storage_counter_add!(1, "name" => self.0.name(), "operation" => "get");
+        add_fetch_counters(&self.0.name(), std::slice::from_ref(&resp));
         storage_histogram_rec!(start, "name" => self.0.name(), "operation" => "get");
 
         let resp = lua_try!(resp);
         Ok(Ok(resp.map(|resp| {
             let mut resp = LuaResponse::from(resp);
             resp.is_stored = true;
+            resp.apply_cache_debug_headers(lua);
             resp
         })))
     }
 
+    /// Checks that a response exists in the storage (without fetching it)
+    ///
+    /// In case of error returns `nil` and a string with error message.
+    #[instrument(skip_all, fields(name = self.0.name(), backend = self.0.backend_type()))]
+    async fn has_response(&self, lua: &Lua, key: Value) -> LuaDoubleResult<bool> {
+        let start = Instant::now();
+
+        let key = calculate_primary_key(lua, key).context("failed to calculate primary key")?;
+        if is_cache_bypassed() {
+            return Ok(Ok(false));
+        }
+        let result = self.0.has_response(key).await.map_err(Into::into);
+
+        add_storage_counters(&self.0.name(), "has", std::slice::from_ref(&result));
+        storage_histogram_rec!(start, "name" => self.0.name(), "operation" => "has");
+
+        Ok(Ok(lua_try!(result)))
+    }
+
     /// Fetches responses from the storage
     ///
     /// Returns a table of: { Response | string | false }
@@ -65,10 +153,15 @@ where
             .map(|key| key.and_then(|k| calculate_primary_key(lua, k)))
             .collect::<LuaResult<Vec<_>>>()
             .context("failed to calculate primary keys")?;
-        let items_count = keys.len() as u64;
+        if is_cache_bypassed() {
+            let count = keys.len() as u64;
+            storage_counter_add!(count, "name" => self.0.name(), "operation" => "get");
+            storage_results_counter_add!(count, "name" => self.0.name(), "operation" => "get", "status" => "bypass");
+            return Ok(vec![Value::Boolean(false); keys.len()]);
+        }
         let results = self.0.get_responses(keys).await;
 
-        storage_counter_add!(items_count, "name" => self.0.name(), "operation" => "get");
+        add_fetch_counters(&self.0.name(), &results);
         storage_histogram_rec!(start, "name" => self.0.name(), "operation" => "get");
 
         // Convert results to a table of: { Response | string | false }
@@ -80,6 +173,7 @@ where
                 Ok(Some(resp)) => {
                     let mut resp = LuaResponse::from(resp);
                     resp.is_stored = true;
+                    resp.apply_cache_debug_headers(lua);
                     Ok(Value::UserData(lua.create_userdata(resp)?))
                 }
                 Ok(None) => Ok(Value::Boolean(false)),
@@ -127,10 +221,9 @@ where
             );
         }
 
-        let items_count: u64 = item_keys.len() as u64;
         let results = self.0.delete_responses_multi(item_keys).await;
 
-        storage_counter_add!(items_count, "name" => self.0.name(), "operation" => "delete");
+        add_storage_counters(&self.0.name(), "delete", &results);
         storage_histogram_rec!(start, "name" => self.0.name(), "operation" => "delete");
 
         if results.iter().all(|r| r.is_ok()) {
@@ -147,28 +240,211 @@ where
         Ok((false, Some(results)))
     }
 
+    /// Sets a new TTL of the response without re-storing it
+    ///
+    /// The store policy `min_ttl` is applied to the new TTL.
+    ///
+    /// Returns `true` if the response exists (and its TTL was updated).
+    /// Returns `false` and `ttl_too_short` if the TTL is below `min_ttl` (and the action is `skip`).
+    /// In case of error returns `nil` and a string with error message.
+    #[instrument(skip_all, fields(name = self.0.name(), backend = self.0.backend_type()))]
+    async fn touch(
+        &self,
+        lua: &Lua,
+        (key, ttl): (Value, Option<f32>),
+    ) -> LuaDoubleResult<Result<bool, SkipReason>> {
+        let start = Instant::now();
+
+        let key = calculate_primary_key(lua, key).context("failed to calculate primary key")?;
+        let ttl = self.get_ttl(ttl, None).context("invalid `ttl`")?;
+        let ttl = match self.1.apply_min_ttl(ttl) {
+            Ok(ttl) => ttl,
+            Err(reason) => return Ok(Ok(Err(reason))),
+        };
+        let result = self.0.touch(key, ttl).await.map_err(Into::into);
+
+        add_storage_counters(&self.0.name(), "touch", std::slice::from_ref(&result));
+        storage_histogram_rec!(start, "name" => self.0.name(), "operation" => "touch");
+
+        Ok(Ok(Ok(lua_try!(result))))
+    }
+
+    /// Increments the counter under the key, starting a new one with the given TTL if missing
+    ///
+    /// Returns the new counter value.
+    /// In case of error returns `nil` and a string with error message.
+    #[instrument(skip_all, fields(name = self.0.name(), backend = self.0.backend_type()))]
+    pub(crate) async fn incr_counter(
+        &self,
+        lua: &Lua,
+        key: Value,
+        ttl: Duration,
+    ) -> LuaDoubleResult<u64> {
+        let start = Instant::now();
+
+        let key = calculate_primary_key(lua, key).context("failed to calculate primary key")?;
+        let result = self.0.incr_counter(key, ttl).await.map_err(Into::into);
+
+        add_storage_counters(&self.0.name(), "incr", std::slice::from_ref(&result));
+        storage_histogram_rec!(start, "name" => self.0.name(), "operation" => "incr");
+
+        Ok(Ok(lua_try!(result)))
+    }
+
+    /// Returns latency statistics of the storage (in seconds)
+    ///
+    /// The table has `fetch` and `store` fields (with `p50`, `p99` and `samples` of all
+    /// operations) and `network` (with `avg` and `max` of all commands).
+    /// Returns an empty table if the storage does not track latencies.
+    fn stats(&self, lua: &Lua) -> LuaResult<Table> {
+        let Some(stats) = self.0.stats() else {
+            return lua.create_table();
+        };
+        let latency_table = |latency: LatencyStats| {
+            let table = lua.create_table_with_capacity(0, 3)?;
+            table.raw_set("p50", latency.p50.as_secs_f64())?;
+            table.raw_set("p99", latency.p99.as_secs_f64())?;
+            table.raw_set("samples", latency.samples)?;
+            Ok::<_, mlua::Error>(table)
+        };
+        let network = lua.create_table_with_capacity(0, 2)?;
+        network.raw_set("avg", stats.network_avg.as_secs_f64())?;
+        network.raw_set("max", stats.network_max.as_secs_f64())?;
+
+        let table = lua.create_table_with_capacity(0, 3)?;
+        table.raw_set("fetch", latency_table(stats.fetch)?)?;
+        table.raw_set("store", latency_table(stats.store)?)?;
+        table.raw_set("network", network)?;
+        Ok(table)
+    }
+
+    /// Removes all responses from the storage
+    ///
+    /// Returns `true` on success.
+    /// In case of error returns `nil` and a string with error message.
+    #[instrument(skip_all, fields(name = self.0.name(), backend = self.0.backend_type()))]
+    async fn clear(&self) -> LuaDoubleResult<bool> {
+        let start = Instant::now();
+
+        let result = self.0.clear().await.map_err(Into::into);
+
+        add_storage_counters(&self.0.name(), "clear", std::slice::from_ref(&result));
+        storage_histogram_rec!(start, "name" => self.0.name(), "operation" => "clear");
+
+        lua_try!(result);
+        Ok(Ok(true))
+    }
+
     /// Stores a response in the storage.
     ///
-    /// Returns number of written bytes to the cache if the response was stored.
+    /// If `stream` is set, the response body is written to the storage chunk by chunk
+    /// as it's read (unless the store policy needs to know the body size and it's unknown).
+    /// The read chunks are kept in the response body, so it can still be sent to the client.
+    ///
+    /// Returns a table with `size` (number of written bytes to the cache) and `num_chunks`
+    /// (number of chunks the body was split into) fields if the response was stored.
+    /// Returns `false` if the response does not qualify the store policy
+    /// (and `ttl_too_short` as a second value if the TTL is below `min_ttl`).
+    /// In case of errors returns `nil` and a string with error message
+    /// (`body is too large to cache` if the backend refused to store the body because of its size).
+    #[instrument(skip_all, fields(name = self.0.name(), backend = self.0.backend_type()))]
+    async fn store_response(
+        &self,
+        lua: &Lua,
+        item: Table,
+    ) -> LuaDoubleResult<Result<Table, SkipReason>> {
+        let stream: Option<bool> = item.raw_get("stream").context("invalid `stream`")?;
+        let mut params = self.read_store_params(lua, &item)?;
+
+        let result = self
+            .store_lua_response(
+                params.key,
+                &mut params.resp,
+                params.surrogate_keys,
+                params.ttl,
+                params.encrypt,
+                params.compress,
+                stream.unwrap_or_default(),
+            )
+            .await?;
+
+        match lua_try!(result) {
+            Ok(stored) => {
+                let result = lua.create_table_with_capacity(0, 2)?;
+                result.raw_set("size", stored.size)?;
+                result.raw_set("num_chunks", stored.num_chunks)?;
+                Ok(Ok(Ok(result)))
+            }
+            Err(reason) => Ok(Ok(Err(reason))),
+        }
+    }
+
+    /// Stores a response in the storage only if no response exists under the key
+    /// (e.g. to not clobber a concurrent cache fill).
+    ///
+    /// Accepts the same fields as `store_response` (except `stream`).
+    /// Returns `true` if the response was stored, `false` if a response already exists
+    /// or the response does not qualify the store policy (same as `store_response`).
     /// In case of errors returns `nil` and a string with error message.
     #[instrument(skip_all, fields(name = self.0.name(), backend = self.0.backend_type()))]
-    async fn store_response(&self, lua: &Lua, item: Table) -> LuaDoubleResult<usize> {
+    async fn store_response_if_absent(
+        &self,
+        lua: &Lua,
+        item: Table,
+    ) -> LuaDoubleResult<Result<bool, SkipReason>> {
         let start = Instant::now();
 
+        let mut params = self.read_store_params(lua, &item)?;
+        let prepared = self
+            .prepare_item(
+                params.key,
+                &mut params.resp,
+                params.surrogate_keys,
+                params.ttl,
+                params.encrypt,
+                params.compress,
+                false,
+            )
+            .await?;
+        let PreparedItem {
+            item, body_size, ..
+        } = match lua_try!(prepared) {
+            Ok(prepared) => prepared,
+            Err(reason) => return Ok(Ok(Err(reason))),
+        };
+
+        let result = self.0.store_response_nx(item).await;
+
+        add_storage_counters(&self.0.name(), "store", std::slice::from_ref(&result));
+        storage_histogram_rec!(start, "name" => self.0.name(), "operation" => "store");
+        if let (Ok(true), Some(body_size)) = (&result, body_size) {
+            storage_body_size_rec!(body_size, "name" => self.0.name(), "operation" => "store");
+        }
+
+        Ok(Ok(Ok(lua_try!(
+            result.map_err(|err| err.into().to_string())
+        ))))
+    }
+
+    /// Reads the common fields of a response to store from a Lua table
+    fn read_store_params(&self, lua: &Lua, item: &Table) -> LuaResult<StoreParams> {
         let key: Value = item.raw_get("key").context("invalid `key`")?;
         let mut resp: UserDataRefMut<LuaResponse> =
             item.raw_get("response").context("invalid `response`")?;
         let surrogate_keys: Option<Vec<LuaString>> = item
             .raw_get("surrogate_keys")
             .context("invalid `surrogate_keys`")?;
-        let ttl: f32 = item.raw_get("ttl").context("invalid `ttl`")?;
+        let ttl: Option<f32> = item.raw_get("ttl").context("invalid `ttl`")?;
+        let req: Option<UserDataRef<LuaRequest>> =
+            item.raw_get("request").context("invalid `request`")?;
+        let ttl = self
+            .get_ttl(ttl, req.as_ref().map(|req| req.uri().path()))
+            .context("invalid `ttl`")?;
         let encrypt: Option<bool> = item.raw_get("encrypt").unwrap_or_default();
+        let compress: Option<bool> = item.raw_get("compress").context("invalid `compress`")?;
 
-        // Read Response body (it's consumed and saved)
-        let body = lua_try!(resp.body_mut().buffer().await).unwrap_or_default();
-
-        // Remove hop by hop headers
-        filter_hop_headers(resp.headers_mut());
+        let key = calculate_primary_key(lua, key).context("failed to calculate primary key")?;
+        resp.apply_default_content_type(lua);
 
         // Convert surrogate keys
         let surrogate_keys = surrogate_keys
@@ -177,31 +453,160 @@ where
             .map(|s| Key::copy_from_slice(&s.as_bytes()))
             .collect();
 
-        let result = self
-            .0
-            .store_response(Item {
-                key: calculate_primary_key(lua, key).context("failed to calculate primary key")?,
-                status: resp.status(),
-                headers: Cow::Borrowed(resp.headers()),
-                body,
-                surrogate_keys,
-                ttl: Duration::from_secs_f32(ttl),
-                encrypt: encrypt.unwrap_or_default(),
-            })
-            .await;
+        Ok(StoreParams {
+            key,
+            resp,
+            surrogate_keys,
+            ttl,
+            encrypt: encrypt.unwrap_or_default(),
+            compress,
+        })
+    }
+
+    /// Checks a Lua response against the store policy and builds an item to store.
+    ///
+    /// The response body is buffered unless `stream` is set (and the store policy does not
+    /// need to know the unknown body size), then it's taken out of the response to be read
+    /// while storing.
+    #[allow(clippy::too_many_arguments)]
+    async fn prepare_item<'a>(
+        &self,
+        key: Key,
+        resp: &'a mut LuaResponse,
+        surrogate_keys: Vec<Key>,
+        ttl: Duration,
+        encrypt: bool,
+        compress: Option<bool>,
+        stream: bool,
+    ) -> LuaDoubleResult<Result<PreparedItem<'a>, SkipReason>> {
+        if resp.headers_flushed() {
+            return Ok(Err(
+                "cannot store response after flushing headers".to_string()
+            ));
+        }
+
+        // Streamed body size must be known upfront if the store policy restricts it
+        let body_size = match resp.body_mut().size() {
+            BodySize::None | BodySize::Empty => Some(0),
+            BodySize::Sized(size) => Some(size as usize),
+            BodySize::Stream => None,
+        };
+        let stream = stream && (body_size.is_some() || !self.1.has_body_size_limits());
+
+        // Read Response body (it's consumed and saved)
+        let body = match stream {
+            true => None,
+            false => Some(lua_try!(resp.body_mut().buffer().await).unwrap_or_default()),
+        };
+        let body_size = body.as_ref().map(|b| b.len()).or(body_size);
+
+        // Check that the response can be stored (`HEAD` responses have no body)
+        if resp.is_head() {
+            return Ok(Ok(Err(SkipReason::NotCacheable)));
+        }
+        let negative_ttl = resp.negative_ttl();
+        let size = body_size.unwrap_or_default();
+        let ttl = match (self.1).store_ttl(resp.status(), size, ttl, negative_ttl) {
+            Ok(ttl) => ttl,
+            Err(reason) => return Ok(Ok(Err(reason))),
+        };
+
+        // Remove hop by hop headers and freshness warnings (the response is revalidated)
+        filter_hop_headers(resp.headers_mut());
+        resp.remove_stale_warnings();
+
+        let streamed_body = body
+            .is_none()
+            .then(|| LuaBody::from(mem::take(resp.body_mut())));
+        let item = Item {
+            key,
+            status: resp.status(),
+            version: resp.version(),
+            headers: Cow::Borrowed(resp.headers()),
+            body: body.unwrap_or_default(),
+            surrogate_keys,
+            ttl,
+            encrypt,
+            compress,
+        };
+        Ok(Ok(Ok(PreparedItem {
+            item,
+            streamed_body,
+            body_size,
+        })))
+    }
+
+    /// Stores a Lua response in the storage (buffering its body unless `stream` is set).
+    ///
+    /// Returns the skip reason if the response does not qualify the store policy.
+    #[allow(clippy::too_many_arguments)]
+    async fn store_lua_response(
+        &self,
+        key: Key,
+        resp: &mut LuaResponse,
+        surrogate_keys: Vec<Key>,
+        ttl: Duration,
+        encrypt: bool,
+        compress: Option<bool>,
+        stream: bool,
+    ) -> LuaDoubleResult<Result<StoredItem, SkipReason>> {
+        let start = Instant::now();
+
+        let prepared =
+            (self.prepare_item(key, resp, surrogate_keys, ttl, encrypt, compress, stream)).await?;
+        let PreparedItem {
+            item,
+            mut streamed_body,
+            body_size,
+        } = match lua_try!(prepared) {
+            Ok(prepared) => prepared,
+            Err(reason) => return Ok(Ok(Err(reason))),
+        };
+        let result = match &mut streamed_body {
+            Some(body) => (body.tee(|body| self.0.store_response_stream(item, body))).await,
+            None => self.0.store_item(item).await,
+        };
+        // Read chunks of the streamed body are kept to be sent to the client
+        if let Some(body) = streamed_body {
+            *resp.body_mut() = body.into();
+        }
 
-        storage_counter_add!(1, "name" => self.0.name(), "operation" => "store");
+        // Responses too large to store are not counted as stored
+        add_storage_counters_with(
+            &self.0.name(),
+            "store",
+            std::slice::from_ref(&result),
+            |stored| if stored.too_large { "too_large" } else { "ok" },
+        );
         storage_histogram_rec!(start, "name" => self.0.name(), "operation" => "store");
+        if let Ok(
+            stored @ StoredItem {
+                too_large: false, ..
+            },
+        ) = &result
+        {
+            storage_stored_size_rec!(stored.size, "name" => self.0.name());
+            // Size of streamed bodies can be unknown
+            if let Some(body_size) = body_size {
+                storage_body_size_rec!(body_size, "name" => self.0.name(), "operation" => "store");
+            }
+        }
 
-        Ok(result.map_err(|err| err.into().to_string()))
+        let stored = lua_try!(result.map_err(|err| err.into().to_string()));
+        if stored.too_large {
+            return Ok(Err(TOO_LARGE_ERROR.to_string()));
+        }
+        Ok(Ok(Ok(stored)))
     }
 
     /// Stores responses in the storage.
     ///
-    /// Returns total number of written bytes to the cache if all the responses were stored.
-    /// In case of errors returns `nil` and a table of: { string | number }
+    /// Returns total number of written bytes to the cache if all the responses were stored
+    /// (responses that do not qualify the store policy are skipped).
+    /// In case of errors returns `nil` and a table of: { string | number | false }
     ///   string - error message
     ///   number - number of bytes written to the cache
+    ///   `false` - if response was skipped by the store policy
     #[instrument(skip_all, fields(name = self.0.name(), backend = self.0.backend_type()))]
     async fn store_responses(
         &self,
@@ -212,6 +617,7 @@ where
 
         // Read rest of the fields
         let mut items = Vec::with_capacity(lua_items.raw_len());
+        let mut skipped = 0;
         for (i, item) in lua_items.sequence_values::<Table>().enumerate() {
             let item = item?;
             let key: Value = item
@@ -223,16 +629,46 @@ where
             let surrogate_keys: Option<Vec<LuaString>> = item
                 .raw_get("surrogate_keys")
                 .with_context(|_| format!("invalid `surrogate_keys` #{}", i + 1))?;
-            let ttl: f32 = item
+            let ttl: Option<f32> = item
                 .raw_get("ttl")
                 .with_context(|_| format!("invalid `ttl` #{}", i + 1))?;
+            let req: Option<UserDataRef<LuaRequest>> = item
+                .raw_get("request")
+                .with_context(|_| format!("invalid `request` #{}", i + 1))?;
+            let ttl = self
+                .get_ttl(ttl, req.as_ref().map(|req| req.uri().path()))
+                .with_context(|_| format!("invalid `ttl` #{}", i + 1))?;
             let encrypt: Option<bool> = item.raw_get("encrypt").unwrap_or_default();
+            let compress: Option<bool> = item
+                .raw_get("compress")
+                .with_context(|_| format!("invalid `compress` #{}", i + 1))?;
+
+            if resp.headers_flushed() {
+                let err = "cannot store response after flushing headers".into_lua_err();
+                return Err(err).with_context(|_| format!("invalid `response` #{}", i + 1));
+            }
 
             // Read Response body (it's consumed and saved)
             let body = resp.body_mut().buffer().await?.unwrap_or_default();
 
-            // Remove hop by hop headers
+            // Skip responses that cannot be stored (`HEAD` responses have no body)
+            let negative_ttl = resp.negative_ttl();
+            let ttl = match resp.is_head() {
+                true => None,
+                false => self
+                    .1
+                    .store_ttl(resp.status(), body.len(), ttl, negative_ttl)
+                    .ok(),
+            };
+            let Some(ttl) = ttl else {
+                skipped += 1;
+                continue;
+            };
+
+            // Remove hop by hop headers and freshness warnings (the response is revalidated)
             filter_hop_headers(resp.headers_mut());
+            resp.remove_stale_warnings();
+            resp.apply_default_content_type(lua);
 
             // Calculate primary key
             let key = calculate_primary_key(lua, key)
@@ -245,46 +681,56 @@ where
                 .map(|s| Key::copy_from_slice(&s.as_bytes()))
                 .collect::<Vec<_>>();
 
-            items.push((i, key, resp, body, surrogate_keys, ttl, encrypt));
+            items.push((i, key, resp, body, surrogate_keys, ttl, encrypt, compress));
         }
 
         // Transform items elements from tuple to Item struct
-        let items = items
+        let store_items = items
             .iter()
-            .map(|(_, key, resp, body, surrogate_keys, ttl, encrypt)| Item {
-                key: key.clone(),
-                status: resp.status(),
-                headers: Cow::Borrowed(resp.headers()),
-                body: body.clone(),
-                surrogate_keys: surrogate_keys.clone(),
-                ttl: Duration::from_secs_f32(*ttl),
-                encrypt: encrypt.unwrap_or_default(),
-            })
+            .map(
+                |(_, key, resp, body, surrogate_keys, ttl, encrypt, compress)| Item {
+                    key: key.clone(),
+                    status: resp.status(),
+                    version: resp.version(),
+                    headers: Cow::Borrowed(resp.headers()),
+                    body: body.clone(),
+                    surrogate_keys: surrogate_keys.clone(),
+                    ttl: *ttl,
+                    encrypt: encrypt.unwrap_or_default(),
+                    compress: *compress,
+                },
+            )
             .collect::<Vec<_>>();
 
-        let items_len = items.len();
-        let results = self.0.store_responses(items).await;
+        let results = self.0.store_responses(store_items).await;
 
-        storage_counter_add!(items_len as u64, "name" => self.0.name(), "operation" => "store");
+        add_storage_counters(&self.0.name(), "store", &results);
         storage_histogram_rec!(start, "name" => self.0.name(), "operation" => "store");
+        for ((_, _, _, body, ..), result) in items.iter().zip(&results) {
+            if let Ok(size) = result {
+                storage_stored_size_rec!(*size, "name" => self.0.name());
+                storage_body_size_rec!(body.len(), "name" => self.0.name(), "operation" => "store");
+            }
+        }
 
         // If all responses were stored then return `true`
         let mut total_size = 0;
         if results.iter().all(|r| {
-            total_size += r.as_ref().cloned().unwrap_or_default();
+            total_size += r.as_ref().copied().unwrap_or_default();
             r.is_ok()
         }) {
             return Ok((Some(total_size), None));
         }
 
-        let results = results
-            .into_iter()
-            .map(|res| match res {
-                Ok(size) => Ok(Value::Integer(size as _)),
-                Err(err) => Ok(Value::String(lua.create_string(err.into().to_string())?)),
-            })
-            .collect::<LuaResult<Vec<_>>>()?;
-        Ok((None, Some(results)))
+        // Put skipped items back to their original positions
+        let mut output = vec![Value::Boolean(false); items.len() + skipped];
+        for ((i, ..), res) in items.iter().zip(results) {
+            output[*i] = match res {
+                Ok(size) => Value::Integer(size as _),
+                Err(err) => Value::String(lua.create_string(err.into().to_string())?),
+            };
+        }
+        Ok((None, Some(output)))
     }
 }
 
@@ -298,6 +744,10 @@ where
             this.get_response(&lua, args).await
         });
 
+        methods.add_async_method("has_response", |lua, this, args| async move {
+            this.has_response(&lua, args).await
+        });
+
         methods.add_async_method("get_responses", |lua, this, args| async move {
             this.get_responses(&lua, args).await
         });
@@ -306,8 +756,20 @@ where
             this.delete_responses(&lua, args).await
         });
 
+        methods.add_async_method("touch", |lua, this, args| async move {
+            into_store_result(&lua, this.touch(&lua, args).await?)
+        });
+
+        methods.add_async_method("clear", |_, this, ()| async move { this.clear().await });
+
+        methods.add_method("stats", |lua, this, ()| this.stats(lua));
+
         methods.add_async_method("store_response", |lua, this, args| async move {
-            this.store_response(&lua, args).await
+            into_store_result(&lua, this.store_response(&lua, args).await?)
+        });
+
+        methods.add_async_method("store_response_if_absent", |lua, this, args| async move {
+            into_store_result(&lua, this.store_response_if_absent(&lua, args).await?)
         });
 
         methods.add_async_method("store_responses", |lua, this, args| async move {
@@ -316,6 +778,193 @@ where
     }
 }
 
+/// Ordered chain of storages (e.g. a fast local tier followed by a shared one)
+///
+/// Reads query the storages in order and return the first hit.
+/// If `promote_ttl` is set, the hit is also stored (with this TTL) into all preceding
+/// (faster) storages. Promoted copies keep surrogate keys of the original item,
+/// so they can be invalidated the same way.
+///
+/// Writes go to all storages.
+pub struct LuaStorageChain<T: Storage> {
+    storages: Vec<LuaStorage<T>>,
+    promote_ttl: Option<Duration>,
+}
+
+impl<T> LuaStorageChain<T>
+where
+    T: Storage<Body = Body> + Clone + 'static,
+    <T as Storage>::Error: Into<Box<dyn StdError + Send + Sync>>,
+{
+    /// Fetches a response from the first storage that has it
+    ///
+    /// Returns `nil` if response is not found in any storage.
+    /// If no storage has the response and some of them failed, returns a second value
+    /// with the last error message.
+    async fn get_response(&self, lua: &Lua, key: Value) -> LuaDoubleResult<Option<LuaResponse>> {
+        let mut last_error = None;
+        for (i, storage) in self.storages.iter().enumerate() {
+            let mut resp = match storage.get_response(lua, key.clone()).await? {
+                Ok(Some(resp)) => resp,
+                Ok(None) => continue,
+                Err(err) => {
+                    last_error = Some(err);
+                    continue;
+                }
+            };
+
+            if let Some(ttl) = self.promote_ttl.filter(|_| i > 0) {
+                let key =
+                    calculate_primary_key(lua, key).context("failed to calculate primary key")?;
+                let surrogate_keys = (resp.extensions().get::<SurrogateKeysExt>())
+                    .map(|ext| ext.0.clone())
+                    .unwrap_or_default();
+                for faster in &self.storages[..i] {
+                    let result = faster
+                        .store_lua_response(
+                            key.clone(),
+                            &mut resp,
+                            surrogate_keys.clone(),
+                            ttl,
+                            false,
+                            None,
+                            false,
+                        )
+                        .await?;
+                    // Promotion errors are not critical
+                    if let Err(err) = result {
+                        warn!(name = faster.0.name(), "failed to promote response: {err}");
+                    }
+                }
+            }
+            return Ok(Ok(Some(resp)));
+        }
+
+        match last_error {
+            Some(err) => Ok(Err(err)),
+            None => Ok(Ok(None)),
+        }
+    }
+
+    /// Stores a response in all storages
+    ///
+    /// Returns `true` if there were no errors (responses skipped by store policy are not errors).
+    /// In case of errors returns `nil` and a string with error messages.
+    async fn store_response(&self, lua: &Lua, item: Table) -> LuaDoubleResult<bool> {
+        let mut errors = Vec::new();
+        for storage in &self.storages {
+            if let Err(err) = storage.store_response(lua, item.clone()).await? {
+                errors.push(format!("{}: {err}", storage.0.name()));
+            }
+        }
+        if !errors.is_empty() {
+            return Ok(Err(errors.join("; ")));
+        }
+        Ok(Ok(true))
+    }
+}
+
+impl<T> UserData for LuaStorageChain<T>
+where
+    T: Storage<Body = Body> + Clone + 'static,
+    <T as Storage>::Error: Into<Box<dyn StdError + Send + Sync>>,
+{
+    fn add_methods<M: UserDataMethods<Self>>(methods: &mut M) {
+        methods.add_function(
+            "new",
+            |_, (storages, options): (Vec<UserDataRef<LuaStorage<T>>>, Option<Table>)| {
+                let promote_ttl = match options {
+                    Some(options) => options
+                        .raw_get::<Option<f64>>("promote_ttl")
+                        .context("invalid `promote_ttl`")?,
+                    None => None,
+                };
+                let promote_ttl = match promote_ttl {
+                    Some(ttl) if ttl >= 0.0 && ttl.is_finite() => {
+                        Some(Duration::from_secs_f64(ttl))
+                    }
+                    Some(ttl) => {
+                        let err = format!("`{ttl}` must be a non-negative number").into_lua_err();
+                        return Err(err.context("invalid `promote_ttl`"));
+                    }
+                    None => None,
+                };
+                Ok(LuaStorageChain {
+                    storages: storages.iter().map(|s| (*s).clone()).collect(),
+                    promote_ttl,
+                })
+            },
+        );
+
+        methods.add_async_method("get_response", |lua, this, args| async move {
+            this.get_response(&lua, args).await
+        });
+
+        methods.add_async_method("store_response", |lua, this, args| async move {
+            this.store_response(&lua, args).await
+        });
+    }
+}
+
+/// Converts a result of a store operation to Lua values
+///
+/// Responses skipped by the store policy are returned as `false`, with `ttl_too_short`
+/// as a second value if the TTL is below `min_ttl`.
+fn into_store_result(
+    lua: &Lua,
+    result: Result<Result<impl IntoLua, SkipReason>, String>,
+) -> LuaResult<MultiValue> {
+    match result {
+        Ok(Ok(value)) => value.into_lua_multi(lua),
+        Ok(Err(SkipReason::NotCacheable)) => false.into_lua_multi(lua),
+        Ok(Err(SkipReason::TtlTooShort)) => (false, "ttl_too_short").into_lua_multi(lua),
+        Err(err) => (Value::Nil, err).into_lua_multi(lua),
+    }
+}
+
+/// Updates storage counters of fetch results splitting them by status (`hit`, `miss` or `error`)
+fn add_fetch_counters<T, E>(name: &str, results: &[Result<Option<T>, E>]) {
+    storage_counter_add!(results.len() as u64, "name" => name.to_string(), "operation" => "get");
+    for status in ["hit", "miss", "error"] {
+        let count = (results.iter())
+            .filter(|r| match r {
+                Ok(Some(_)) => status == "hit",
+                Ok(None) => status == "miss",
+                Err(_) => status == "error",
+            })
+            .count() as u64;
+        if count > 0 {
+            storage_results_counter_add!(count, "name" => name.to_string(), "operation" => "get", "status" => status);
+        }
+    }
+}
+
+/// Updates storage counters splitting results by status (`ok` or `error`)
+fn add_storage_counters<T, E>(name: &str, operation: &'static str, results: &[Result<T, E>]) {
+    add_storage_counters_with(name, operation, results, |_| "ok");
+}
+
+/// Adds storage counters using the `status` function to get the status of successful results
+fn add_storage_counters_with<T, E>(
+    name: &str,
+    operation: &'static str,
+    results: &[Result<T, E>],
+    status: impl Fn(&T) -> &'static str,
+) {
+    storage_counter_add!(results.len() as u64, "name" => name.to_string(), "operation" => operation);
+    let mut statuses: Vec<(&'static str, u64)> = Vec::new();
+    for result in results {
+        let status = result.as_ref().map(&status).unwrap_or("error");
+        match statuses.iter_mut().find(|(s, _)| *s == status) {
+            Some((_, count)) => *count += 1,
+            None => statuses.push((status, 1)),
+        }
+    }
+    for (status, count) in statuses {
+        storage_results_counter_add!(count, "name" => name.to_string(), "operation" => operation, "status" => status);
+    }
+}
+
 /// Calculates primary key from Lua Value
 /// The Value can be a string or a list of strings
 fn calculate_primary_key(lua: &Lua, key: Value) -> LuaResult<Key> {
@@ -378,27 +1027,264 @@ mod tests {
                 headers = { hello = "world" },
                 body = "test response 1",
             })
-            local size, err = $storage:store_response({
+            local res, err = $storage:store_response({
                 key = {"a", "bc"}, // key parts should be concatenated
                 response = resp,
                 surrogate_keys = {"skey1", "skey2"},
                 ttl = 10,
             })
-            assert(size > 0 and err == nil)
+            assert(res.size > 0 and err == nil)
+            assert(res.num_chunks == 1)
+            assert($storage:has_response({"ab", "c"}) == true)
+            assert($storage:has_response("xyz") == false)
+            assert($storage:touch("abc", 10) == true)
+            assert($storage:touch("xyz", 10) == false)
             resp = $storage:get_response("abc")
             assert(resp.status == 201)
             assert(resp:header("hello") == "world")
             assert(resp.body:to_string() == "test response 1")
 
+            // Stored item metadata
+            assert(math.abs(resp.stored_at - os.time()) <= 2, "stored_at must be close to now")
+            assert(resp.ttl_remaining > 8 and resp.ttl_remaining <= 10)
+            assert(resp.stored_size == 15)
+            assert(Response.new({}).stored_at == nil)
+
+            // Store response streaming its body
+            local parts = { "streamed ", "response" }
+            resp = Response.new({ body = function() return table.remove(parts, 1) end })
+            res = $storage:store_response({ key = "stream", response = resp, ttl = 10, stream = true })
+            assert(res.size > 0)
+            assert($storage:get_response("stream").body:to_string() == "streamed response")
+            assert(resp.body:to_string() == "streamed response", "streamed body must be kept")
+
             // Delete response
             $storage:delete_responses({surrogate_keys = {"skey2"}})
             resp, err = $storage:get_response({"abc"})
             assert(resp == nil and err == nil)
+
+            // Store only if absent
+            resp = Response.new({ body = "first" })
+            assert($storage:store_response_if_absent({ key = "nx", response = resp, ttl = 10 }) == true)
+            resp = Response.new({ body = "second" })
+            assert($storage:store_response_if_absent({ key = "nx", response = resp, ttl = 10 }) == false)
+            assert($storage:get_response("nx").body:to_string() == "first")
+
+            // Memory storage does not track latencies
+            assert(next($storage:stats()) == nil)
+
+            // Clear storage
+            $storage:store_response({ key = "abc", response = Response.new({ body = "test" }), ttl = 10 })
+            assert($storage:clear() == true)
+            assert($storage:has_response("abc") == false)
+
+            // Server timing entries are preserved
+            resp = Response.new({ body = "timing" })
+            resp:add_server_timing("upstream", 12.5)
+            resp:add_server_timing("app", 3, "render")
+            $storage:store_response({ key = "timing", response = resp, ttl = 10 })
+            local timings = $storage:get_response("timing"):header_all("server-timing")
+            assert(#timings == 2 and timings[1] == "upstream;dur=12.5")
+            assert(timings[2] == "app;dur=3;desc=\"render\"")
+        })
+        .exec_async()
+        .await
+    }
+
+    #[ntex::test]
+    async fn test_storage_stats() -> Result<()> {
+        let lua = Lua::new();
+
+        // Stats are read locally, no connection is required
+        let backend_config = serde_yaml::from_str("backend: redis").unwrap();
+        let backend = Backend::new("test".to_string(), backend_config).unwrap();
+        let storage = LuaStorage::new(backend);
+
+        lua.load(chunk! {
+            local stats = $storage:stats()
+            for _, op in {"fetch", "store"} do
+                assert(type(stats[op]) == "table", op .. " stats are missing")
+                assert(stats[op].p50 == 0 and stats[op].p99 == 0 and stats[op].samples == 0)
+            end
+            assert(stats.network.avg == 0 and stats.network.max == 0)
+        })
+        .exec_async()
+        .await
+    }
+
+    #[ntex::test]
+    async fn test_store_too_large() -> Result<()> {
+        let lua = Lua::new();
+
+        // Bodies above the limit are rejected before reaching Redis
+        let backend_config = serde_yaml::from_str(
+            r#"
+            backend: redis
+            max_cacheable_body_size: 4
+        "#,
+        )
+        .unwrap();
+        let backend = Backend::new("test".to_string(), backend_config).unwrap();
+        let storage = LuaStorage::new(backend);
+
+        lua.globals()
+            .set("Response", lua.create_proxy::<LuaResponse>()?)?;
+
+        lua.load(chunk! {
+            local resp = Response.new({ body = "hello" })
+            local res, err = $storage:store_response({ key = "abc", response = resp, ttl = 10 })
+            assert(res == nil and err == "body is too large to cache")
+
+            resp = Response.new({ body = "hello" })
+            res, err = $storage:store_response({ key = "abc", response = resp, ttl = 10, stream = true })
+            assert(res == nil and err == "body is too large to cache")
         })
         .exec_async()
         .await
     }
 
+    #[ntex::test]
+    async fn test_cache_debug_headers() -> Result<()> {
+        use crate::types::CacheDebugHeaders;
+
+        let lua = Lua::new();
+
+        let backend_config = serde_json::json!({"backend": "memory", "max_size": 1000000});
+        let backend = Backend::new("test".to_string(), backend_config).unwrap();
+        let storage = LuaStorage::new(backend);
+
+        lua.globals().set("storage", storage)?;
+        lua.globals()
+            .set("Response", lua.create_proxy::<LuaResponse>()?)?;
+
+        lua.load(chunk! {
+            storage:store_response({ key = "abc", response = Response.new({ body = "a" }), ttl = 60 })
+
+            // Disabled by default
+            local resp = storage:get_response("abc")
+            assert(resp:header("x-cache-age") == nil)
+            assert(resp:header("x-cache-lifetime") == nil)
+        })
+        .exec_async()
+        .await?;
+
+        lua.set_app_data(CacheDebugHeaders);
+        lua.load(chunk! {
+            local resp = storage:get_response("abc")
+            assert(tonumber(resp:header("x-cache-age")) <= 1)
+            assert(resp:header("x-cache-lifetime") == "60")
+
+            local resps = storage:get_responses({"abc", "xyz"})
+            assert(resps[1]:header("x-cache-lifetime") == "60")
+            assert(resps[2] == false)
+
+            // Not stored responses are untouched
+            assert(Response.new({}):header("x-cache-age") == nil)
+        })
+        .exec_async()
+        .await
+    }
+
+    #[ntex::test]
+    async fn test_body_size_histogram() -> Result<()> {
+        let lua = Lua::new();
+
+        let backend_config = serde_json::json!({"backend": "memory", "max_size": 1000000});
+        let backend = Backend::new("body_size_test".to_string(), backend_config).unwrap();
+        let storage = LuaStorage::new(backend);
+
+        lua.globals()
+            .set("Response", lua.create_proxy::<LuaResponse>()?)?;
+
+        lua.load(chunk! {
+            local function item(key, size)
+                return { key = key, response = Response.new({ body = string.rep("x", size) }), ttl = 10 }
+            end
+            assert($storage:store_response(item("a", 100)))
+            assert($storage:store_response(item("b", 2000)))
+            assert($storage:store_responses({ item("c", 50000) }))
+        })
+        .exec_async()
+        .await?;
+
+        let histogram = prometheus::default_registry()
+            .gather()
+            .into_iter()
+            .filter(|family| family.get_name() == "storage_item_body_bytes")
+            .flat_map(|mut family| family.take_metric())
+            .find(|metric| {
+                metric
+                    .get_label()
+                    .iter()
+                    .any(|l| l.get_name() == "name" && l.get_value() == "body_size_test")
+            })
+            .expect("histogram is not recorded")
+            .take_histogram();
+        assert_eq!(histogram.get_sample_count(), 3);
+        assert_eq!(histogram.get_sample_sum(), 52100.0);
+        let buckets = histogram
+            .get_bucket()
+            .iter()
+            .map(|b| (b.get_upper_bound(), b.get_cumulative_count()))
+            .collect::<Vec<_>>();
+        assert_eq!(buckets[..3], [(256.0, 1), (1024.0, 1), (10240.0, 2)]);
+
+        Ok(())
+    }
+
+    #[ntex::test]
+    async fn test_stored_size_histogram() -> Result<()> {
+        let lua = Lua::new();
+
+        let backend_config = serde_json::json!({
+            "backend": "memory",
+            "max_size": 1000000,
+            "store_policy": { "cacheable_statuses": [200] },
+        });
+        let store_policy = StorePolicy::from_config(&backend_config).unwrap();
+        let backend = Backend::new("stored_size_test".to_string(), backend_config).unwrap();
+        let storage = LuaStorage::new(backend).with_store_policy(store_policy);
+
+        lua.globals()
+            .set("Response", lua.create_proxy::<LuaResponse>()?)?;
+
+        let stored_samples = || {
+            prometheus::default_registry()
+                .gather()
+                .into_iter()
+                .filter(|family| family.get_name() == "storage_stored_body_bytes")
+                .flat_map(|mut family| family.take_metric())
+                .find(|metric| {
+                    metric
+                        .get_label()
+                        .iter()
+                        .any(|l| l.get_name() == "name" && l.get_value() == "stored_size_test")
+                })
+                .map(|metric| metric.get_histogram().get_sample_count())
+                .unwrap_or_default()
+        };
+
+        // Skipped store does not record a sample
+        let storage2 = storage.clone();
+        lua.load(chunk! {
+            local resp = Response.new({ status = 500, body = "error" })
+            assert($storage2:store_response({ key = "a", response = resp, ttl = 10 }) == false)
+        })
+        .exec_async()
+        .await?;
+        assert_eq!(stored_samples(), 0);
+
+        lua.load(chunk! {
+            local resp = Response.new({ body = "hello" })
+            assert($storage:store_response({ key = "b", response = resp, ttl = 10 }).size > 0)
+        })
+        .exec_async()
+        .await?;
+        assert_eq!(stored_samples(), 1);
+
+        Ok(())
+    }
+
     #[ntex::test]
     async fn test_storage_multi() -> Result<()> {
         let lua = Lua::new();
@@ -470,5 +1356,390 @@ mod tests {
         .await
     }
 
+    #[ntex::test]
+    async fn test_store_policy() -> Result<()> {
+        let lua = Lua::new();
+
+        let backend_config: serde_json::Value = serde_yaml::from_str(
+            r#"
+            backend: memory
+            max_size: 1000000
+            store_policy:
+              cacheable_statuses: [200, 301]
+              min_body_size: 2
+              max_body_size: 5
+        "#,
+        )
+        .unwrap();
+        let store_policy = StorePolicy::from_config(&backend_config).unwrap();
+        let backend = Backend::new("test".to_string(), backend_config).unwrap();
+        let storage = LuaStorage::new(backend).with_store_policy(store_policy);
+
+        lua.globals()
+            .set("Response", lua.create_proxy::<LuaResponse>()?)?;
+
+        lua.load(chunk! {
+            local function store(key, status, body)
+                return $storage:store_response({
+                    key = key,
+                    response = Response.new(status, body),
+                    ttl = 10,
+                })
+            end
+
+            // Status boundaries
+            assert(store("s1", 200, "abc").size > 0, "status 200 should be stored")
+            assert(store("s2", 301, "abc").size > 0, "status 301 should be stored")
+            local res, err = store("s3", 404, "abc")
+            assert(res == false and err == nil, "status 404 should be skipped")
+            assert($storage:get_response("s3") == nil)
+
+            // Size boundaries
+            assert(store("b1", 200, "a") == false, "body below min size should be skipped")
+            assert(store("b2", 200, "ab").size > 0, "body of min size should be stored")
+            assert(store("b3", 200, "abcde").size > 0, "body of max size should be stored")
+            assert(store("b4", 200, "abcdef") == false, "body above max size should be skipped")
+            assert($storage:get_response("b3") ~= nil)
+            assert($storage:get_response("b4") == nil)
+
+            // Multiple responses
+            local size, err = $storage:store_responses({
+                { key = "m1", response = Response.new(200, "abc"), ttl = 10 },
+                { key = "m2", response = Response.new(500, "abc"), ttl = 10 },
+            })
+            assert(size > 0 and err == nil, "skipped responses are not errors")
+            assert($storage:get_response("m1") ~= nil)
+            assert($storage:get_response("m2") == nil)
+        })
+        .exec_async()
+        .await
+    }
+
+    #[ntex::test]
+    async fn test_min_ttl() -> Result<()> {
+        let lua = Lua::new();
+
+        lua.globals()
+            .set("Response", lua.create_proxy::<LuaResponse>()?)?;
+
+        for action in ["skip", "clamp"] {
+            let backend_config: serde_json::Value = serde_yaml::from_str(&format!(
+                r#"
+                backend: memory
+                max_size: 1000000
+                store_policy:
+                  min_ttl: 5
+                  min_ttl_action: {action}
+            "#
+            ))
+            .unwrap();
+            let store_policy = StorePolicy::from_config(&backend_config).unwrap();
+            let backend = Backend::new("test".to_string(), backend_config).unwrap();
+            let storage = LuaStorage::new(backend).with_store_policy(store_policy);
+
+            lua.load(chunk! {
+                local function store(key, ttl)
+                    return $storage:store_response({
+                        key = key,
+                        response = Response.new(200, "abc"),
+                        ttl = ttl,
+                    })
+                end
+
+                assert(store("long", 10).size > 0, "ttl above min ttl should be stored")
+                assert(store("min", 5).size > 0, "ttl equal to min ttl should be stored")
+                local res, err = store("short", 1)
+                if $action == "skip" then
+                    assert(res == false and err == "ttl_too_short", "ttl below min ttl should be skipped")
+                    assert($storage:get_response("short") == nil)
+                else
+                    assert(res.size > 0, "ttl below min ttl should be clamped")
+                    local resp = $storage:get_response("short")
+                    assert(resp.ttl_remaining > 4 and resp.ttl_remaining <= 5)
+                end
+
+                // Touch
+                local ok, err = $storage:touch("long", 1)
+                if $action == "skip" then
+                    assert(ok == false and err == "ttl_too_short", "touch below min ttl should be skipped")
+                    assert($storage:get_response("long").ttl_remaining > 5)
+                else
+                    assert(ok == true and err == nil, "touch below min ttl should be clamped")
+                    local resp = $storage:get_response("long")
+                    assert(resp.ttl_remaining > 4 and resp.ttl_remaining <= 5)
+                end
+
+                // Negative TTL is rejected
+                local ok, err = pcall($storage.touch, $storage, "min", -1)
+                assert(not ok and tostring(err):find("invalid `ttl`"), "negative ttl should be rejected")
+                local ok, err = pcall(store, "negative", -1)
+                assert(not ok and tostring(err):find("invalid `ttl`"), "negative ttl should be rejected")
+
+                // Multiple responses
+                local size, err = $storage:store_responses({
+                    { key = "m1", response = Response.new(200, "abc"), ttl = 10 },
+                    { key = "m2", response = Response.new(200, "abc"), ttl = 1 },
+                })
+                assert(size > 0 and err == nil)
+                assert(($storage:get_response("m2") ~= nil) == ($action == "clamp"))
+            })
+            .exec_async()
+            .await?;
+        }
+
+        // Negative TTLs in the store policy are rejected
+        let backend_config = serde_json::json!({"store_policy": {"min_ttl": -1}});
+        assert!(StorePolicy::from_config(&backend_config).is_err());
+
+        Ok(())
+    }
+
+    #[ntex::test]
+    async fn test_negative_cache() -> Result<()> {
+        let lua = Lua::new();
+
+        let backend_config: serde_json::Value = serde_yaml::from_str(
+            r#"
+            backend: memory
+            max_size: 1000000
+            store_policy:
+              cacheable_statuses: [200]
+              min_ttl: 5
+              negative_cache:
+                - status: "500-599"
+                  ttl: 1
+        "#,
+        )
+        .unwrap();
+        let store_policy = StorePolicy::from_config(&backend_config).unwrap();
+        let backend = Backend::new("test".to_string(), backend_config).unwrap();
+        let storage = LuaStorage::new(backend).with_store_policy(store_policy);
+
+        lua.globals()
+            .set("Response", lua.create_proxy::<LuaResponse>()?)?;
+        lua.globals().set("storage", storage)?;
+
+        lua.load(chunk! {
+            local function store(key, resp)
+                return storage:store_response({ key = key, response = resp, ttl = 60 })
+            end
+
+            // Error responses matching a rule are stored with the negative TTL
+            assert(store("e503", Response.new(503, "unavailable")).size > 0)
+            local resp = storage:get_response("e503")
+            assert(resp.status == 503 and resp.body:to_string() == "unavailable")
+            assert(resp.ttl_remaining <= 1)
+            assert(store("e404", Response.new(404, "not found")) == false)
+
+            // Explicitly set negative TTL
+            resp = Response.new(404, "not found")
+            resp:set_negative_ttl(1)
+            assert(store("e404", resp).size > 0)
+            assert(storage:get_response("e404").ttl_remaining <= 1)
+
+            // Negative TTL does not apply to successful responses
+            resp = Response.new(200, "ok")
+            resp:set_negative_ttl(1)
+            assert(store("ok", resp).size > 0)
+            assert(storage:get_response("ok").ttl_remaining > 50)
+        })
+        .exec_async()
+        .await?;
+
+        // Error responses expire quickly
+        tokio::time::sleep(Duration::from_millis(1100)).await;
+        lua.load(chunk! {
+            assert(storage:get_response("e503") == nil)
+            assert(storage:get_response("e404") == nil)
+            assert(storage:get_response("ok") ~= nil)
+        })
+        .exec_async()
+        .await?;
+
+        // Invalid status range
+        let config = serde_json::json!({"store_policy": {"negative_cache": [{"status": "200-299", "ttl": 1}]}});
+        assert!(StorePolicy::from_config(&config).is_err());
+
+        Ok(())
+    }
+
+    #[ntex::test]
+    async fn test_ttl_rules() -> Result<()> {
+        let lua = Lua::new();
+
+        let backend_config = serde_yaml::from_str(
+            r#"
+            backend: memory
+            max_size: 1000000
+            store_policy:
+              ttl_rules:
+                - path: "^/static/"
+                  ttl: 10
+                - path: "^/api/"
+                  ttl: 100
+              default_ttl: 1000
+        "#,
+        )
+        .unwrap();
+        let store_policy = StorePolicy::from_config(&backend_config).unwrap();
+        let backend = Backend::new("test".to_string(), backend_config).unwrap();
+        let storage = LuaStorage::new(backend).with_store_policy(store_policy);
+
+        let backend_config = serde_json::json!({"backend": "memory", "max_size": 1000});
+        let backend = Backend::new("test2".to_string(), backend_config).unwrap();
+        let storage_no_rules = LuaStorage::new(backend);
+
+        lua.globals()
+            .set("Request", lua.create_proxy::<LuaRequest>()?)?;
+        lua.globals()
+            .set("Response", lua.create_proxy::<LuaResponse>()?)?;
+        lua.globals().set("storage", storage)?;
+
+        lua.load(chunk! {
+            // Stores a response for the request and returns its remaining ttl
+            local function store(uri, ttl)
+                local req = Request.new({ uri = uri })
+                assert(storage:store_response({
+                    key = uri,
+                    response = Response.new(200, "abc"),
+                    request = req,
+                    ttl = ttl,
+                }).size > 0)
+                return storage:get_response(uri).ttl_remaining
+            end
+            local function near(value, expected)
+                return value > expected - 5 and value <= expected
+            end
+
+            assert(near(store("/static/app.js?v=1"), 10), "matching rule ttl")
+            assert(near(store("/api/v1/users", 20), 20), "explicit ttl has priority")
+            assert(near(store("/api/v1/items"), 100), "matching rule ttl")
+            assert(near(store("/other/static/"), 1000), "default ttl")
+
+            // Without a request only the default ttl can be used
+            assert(storage:store_response({ key = "abc", response = Response.new(200, "abc") }).size > 0)
+            assert(near(storage:get_response("abc").ttl_remaining, 1000))
+
+            // No ttl, no rules
+            local ok, err = pcall(function()
+                return $storage_no_rules:store_response({ key = "abc", response = Response.new(200, "abc") })
+            end)
+            assert(not ok and tostring(err):find("no ttl rule matches") ~= nil)
+        })
+        .exec_async()
+        .await
+    }
+
+    #[ntex::test]
+    async fn test_storage_chain() -> Result<()> {
+        let lua = Lua::new();
+
+        let new_storage = |name: &str| {
+            let backend_config = serde_yaml::from_str(
+                r#"
+                backend: memory
+                max_size: 1000000
+            "#,
+            )
+            .unwrap();
+            LuaStorage::new(Backend::new(name.to_string(), backend_config).unwrap())
+        };
+        let (fast, slow) = (new_storage("fast"), new_storage("slow"));
+
+        lua.globals()
+            .set("Response", lua.create_proxy::<LuaResponse>()?)?;
+        lua.globals().set(
+            "StorageChain",
+            lua.create_proxy::<LuaStorageChain<Backend>>()?,
+        )?;
+
+        lua.load(chunk! {
+            local chain = StorageChain.new({$fast, $slow})
+            local promote_chain = StorageChain.new({$fast, $slow}, { promote_ttl = 10 })
+
+            // Only the second storage has the response
+            $slow:store_response({
+                key = "abc",
+                response = Response.new({ body = "shared" }),
+                surrogate_keys = {"skey"},
+                ttl = 10,
+            })
+            assert(chain:get_response("xyz") == nil)
+            local resp = chain:get_response("abc")
+            assert(resp.body:to_string() == "shared")
+            assert($fast:has_response("abc") == false, "response should not be promoted")
+
+            // Promote on hit
+            resp = promote_chain:get_response("abc")
+            assert(resp.body:to_string() == "shared")
+            assert($fast:has_response("abc") == true, "response should be promoted")
+            assert($fast:get_response("abc").body:to_string() == "shared")
+
+            // Promoted copy keeps surrogate keys
+            $fast:delete_responses({ surrogate_keys = {"skey"} })
+            assert($fast:has_response("abc") == false, "promoted response should be invalidated")
+
+            // Invalid promote TTL is rejected
+            for _, ttl in {-1, 0/0, math.huge} do
+                local ok, err = pcall(StorageChain.new, {$fast, $slow}, { promote_ttl = ttl })
+                assert(not ok and tostring(err):find("invalid `promote_ttl`") ~= nil)
+            end
+
+            // Store to all storages
+            local ok, err = chain:store_response({ key = "def", response = Response.new({ body = "all" }), ttl = 10 })
+            assert(ok == true and err == nil)
+            assert($fast:has_response("def") == true)
+            assert($slow:has_response("def") == true)
+        })
+        .exec_async()
+        .await
+    }
+
+    #[ntex::test]
+    async fn test_storage_chain_fail_open() -> Result<()> {
+        let lua = Lua::new();
+
+        let new_storage = |name: &str, config: &str| {
+            let backend_config = serde_yaml::from_str(config).unwrap();
+            LuaStorage::new(Backend::new(name.to_string(), backend_config).unwrap())
+        };
+        let healthy = new_storage("healthy", "{backend: memory, max_size: 1000000}");
+        let faulty = new_storage(
+            "faulty",
+            r#"
+            backend: memory
+            max_size: 1000000
+            fault_injection:
+              enabled: true
+              latency: 10
+              timeout: 0.1
+              operations: [get]
+            "#,
+        );
+
+        lua.globals()
+            .set("Response", lua.create_proxy::<LuaResponse>()?)?;
+        lua.globals().set(
+            "StorageChain",
+            lua.create_proxy::<LuaStorageChain<Backend>>()?,
+        )?;
+
+        lua.load(chunk! {
+            local chain = StorageChain.new({$faulty, $healthy})
+            local ok, err = chain:store_response({ key = "abc", response = Response.new({ body = "hello" }), ttl = 10 })
+            assert(ok == true and err == nil)
+
+            // Faulty storage times out
+            local resp, err = $faulty:get_response("abc")
+            assert(resp == nil and err == "get operation timed out (injected fault)")
+
+            // Chain falls back to the healthy storage
+            resp = chain:get_response("abc")
+            assert(resp.body:to_string() == "hello")
+        })
+        .exec_async()
+        .await
+    }
+
     // TODO: test wrong arguments (panic)
 }
@@ -132,6 +132,41 @@ fn spawn_task(lua: &Lua, arg: Value) -> Result<StdResult<TaskHandle, String>> {
     }))
 }
 
+/// Spawns background tasks without holding a reference to the Lua instance
+#[derive(Clone)]
+pub(crate) struct TaskSpawner(UnboundedSender<Task>);
+
+impl TaskSpawner {
+    pub(crate) fn new(lua: &Lua) -> Result<Self> {
+        let task_tx = lua
+            .app_data_ref::<UnboundedSender<Task>>()
+            .ok_or_else(|| "task scheduler is stopped".into_lua_err())?;
+        Ok(TaskSpawner(task_tx.clone()))
+    }
+
+    /// Returns `true` if the task scheduler is stopped
+    pub(crate) fn is_closed(&self) -> bool {
+        self.0.is_closed()
+    }
+
+    /// Schedules the handler to run as a named background task without returning a handle to it.
+    ///
+    /// Fails if the task scheduler is stopped.
+    pub(crate) fn spawn_handler(&self, name: &str, handler: Function) -> Result<()> {
+        let (join_handle_tx, _) = oneshot::channel();
+        self.0
+            .send(Task {
+                id: NEXT_TASK_ID.fetch_add(1, Ordering::Relaxed),
+                name: Some(name.to_string()),
+                timeout: None,
+                handler,
+                join_handle_tx,
+            })
+            .map_err(|err| format!("cannot spawn task: {err}"))
+            .into_lua_err()
+    }
+}
+
 pub fn start_task_scheduler(lua: &Lua, max_background_tasks: Option<u64>) {
     let lua = Rc::new(lua.clone());
     let mut task_rx = lua
@@ -0,0 +1,283 @@
+use std::result::Result as StdResult;
+
+use mlua::{Lua, Result, String as LuaString, Table, Value};
+
+/// Template node
+#[derive(Debug)]
+enum Node {
+    Text(String),
+    Var {
+        path: String,
+        escape: bool,
+    },
+    Section {
+        path: String,
+        inverted: bool,
+        children: Vec<Node>,
+    },
+}
+
+/// Parses a (Mustache-like) template.
+///
+/// Supported tags:
+///     - `{{ name }}` - variable substitution (HTML-escaped), `.` refers to the current item
+///     - `{{{ name }}}` or `{{& name }}` - raw variable substitution (not escaped)
+///     - `{{# name }}...{{/ name }}` - section, rendered for each item of a non-empty array,
+///       or once if the value is truthy
+///     - `{{^ name }}...{{/ name }}` - inverted section, rendered if the value is falsy
+///       (`nil`, `false` or an empty table)
+///     - `{{! comment }}` - comment
+///
+/// Nested values can be accessed using dot notation, e.g. `{{ user.name }}`.
+fn parse(template: &str) -> StdResult<Vec<Node>, String> {
+    // Stack of the open sections: (path, inverted, children)
+    let mut stack: Vec<(String, bool, Vec<Node>)> = vec![(String::new(), false, Vec::new())];
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        let nodes = &mut stack.last_mut().unwrap().2;
+        if start > 0 {
+            nodes.push(Node::Text(rest[..start].to_string()));
+        }
+        rest = &rest[start + 2..];
+
+        // Raw variable in triple braces
+        if let Some(tag) = rest.strip_prefix('{') {
+            let end = tag.find("}}}").ok_or("unclosed tag `{{{`")?;
+            let path = tag[..end].trim().to_string();
+            nodes.push(Node::Var {
+                path,
+                escape: false,
+            });
+            rest = &tag[end + 3..];
+            continue;
+        }
+
+        let end = rest.find("}}").ok_or("unclosed tag `{{`")?;
+        let tag = rest[..end].trim();
+        rest = &rest[end + 2..];
+        let (kind, name) = match tag.chars().next() {
+            Some(c @ ('#' | '^' | '/' | '!' | '&')) => (Some(c), tag[1..].trim()),
+            _ => (None, tag),
+        };
+        if name.is_empty() && kind != Some('!') {
+            return Err("empty tag".to_string());
+        }
+        match kind {
+            Some('#' | '^') => stack.push((name.to_string(), kind == Some('^'), Vec::new())),
+            Some('/') => {
+                let (path, inverted, children) = stack.pop().unwrap();
+                if stack.is_empty() || path != name {
+                    return Err(format!("unexpected closing tag `{name}`"));
+                }
+                let section = Node::Section {
+                    path,
+                    inverted,
+                    children,
+                };
+                stack.last_mut().unwrap().2.push(section);
+            }
+            Some('!') => {}
+            _ => nodes.push(Node::Var {
+                path: name.to_string(),
+                escape: kind != Some('&'),
+            }),
+        }
+    }
+
+    let (path, _, mut nodes) = stack.pop().unwrap();
+    if !stack.is_empty() {
+        return Err(format!("unclosed section `{path}`"));
+    }
+    if !rest.is_empty() {
+        nodes.push(Node::Text(rest.to_string()));
+    }
+    Ok(nodes)
+}
+
+/// Looks up a value by (dotted) path in the context stack, starting from the innermost one
+fn lookup(stack: &[Value], path: &str) -> Result<Value> {
+    if path == "." {
+        return Ok(stack.last().cloned().unwrap_or(Value::Nil));
+    }
+
+    let mut parts = path.split('.');
+    let first = parts.next().unwrap_or_default();
+    for frame in stack.iter().rev() {
+        let Value::Table(table) = frame else {
+            continue;
+        };
+        let mut value = table.get::<Value>(first)?;
+        if value.is_nil() {
+            continue;
+        }
+        for part in parts {
+            value = match value {
+                Value::Table(t) => match part.parse::<i64>() {
+                    Ok(i) => t.get(i)?,
+                    Err(_) => t.get(part)?,
+                },
+                _ => Value::Nil,
+            };
+        }
+        return Ok(value);
+    }
+    Ok(Value::Nil)
+}
+
+fn is_falsy(value: &Value) -> bool {
+    match value {
+        Value::Nil | Value::Boolean(false) => true,
+        Value::Table(t) => t.is_empty(),
+        _ => false,
+    }
+}
+
+fn escape_html(s: &str, output: &mut String) {
+    for c in s.chars() {
+        match c {
+            '&' => output.push_str("&amp;"),
+            '<' => output.push_str("&lt;"),
+            '>' => output.push_str("&gt;"),
+            '"' => output.push_str("&quot;"),
+            '\'' => output.push_str("&#39;"),
+            c => output.push(c),
+        }
+    }
+}
+
+fn render_nodes(nodes: &[Node], stack: &mut Vec<Value>, output: &mut String) -> Result<()> {
+    for node in nodes {
+        match node {
+            Node::Text(text) => output.push_str(text),
+            Node::Var { path, escape } => {
+                let value = match lookup(stack, path)? {
+                    Value::Nil => continue,
+                    value => value.to_string()?,
+                };
+                match escape {
+                    true => escape_html(&value, output),
+                    false => output.push_str(&value),
+                }
+            }
+            Node::Section {
+                path,
+                inverted,
+                children,
+            } => {
+                let value = lookup(stack, path)?;
+                if *inverted {
+                    if is_falsy(&value) {
+                        render_nodes(children, stack, output)?;
+                    }
+                    continue;
+                }
+                match value {
+                    _ if is_falsy(&value) => {}
+                    Value::Table(t) if t.raw_len() > 0 => {
+                        for item in t.sequence_values::<Value>() {
+                            stack.push(item?);
+                            render_nodes(children, stack, output)?;
+                            stack.pop();
+                        }
+                    }
+                    value => {
+                        stack.push(value);
+                        render_nodes(children, stack, output)?;
+                        stack.pop();
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Renders a template using the provided variables.
+///
+/// See `parse` for the supported syntax.
+/// In case of template syntax error, returns nil and a string containing the error message.
+fn render(
+    lua: &Lua,
+    (template, vars): (LuaString, Option<Table>),
+) -> Result<StdResult<LuaString, String>> {
+    let nodes = lua_try!(parse(&template.to_str()?));
+    let mut stack = vec![Value::Table(match vars {
+        Some(vars) => vars,
+        None => lua.create_table()?,
+    })];
+    let mut output = String::new();
+    render_nodes(&nodes, &mut stack, &mut output)?;
+    Ok(Ok(lua.create_string(output)?))
+}
+
+pub fn create_module(lua: &Lua) -> Result<Table> {
+    lua.create_table_from([("render", lua.create_function(render)?)])
+}
+
+#[cfg(test)]
+mod tests {
+    use mlua::{chunk, Lua, Result};
+
+    #[test]
+    fn test_substitution() -> Result<()> {
+        let lua = Lua::new();
+
+        let template = super::create_module(&lua)?;
+        lua.load(chunk! {
+            local render = $template.render
+            assert(render("Hello, {{ name }}!", { name = "world" }) == "Hello, world!")
+            assert(render("{{user.name}} is {{ user.age }}", { user = { name = "Bob", age = 42 } }) == "Bob is 42")
+            assert(render("[{{ missing }}]", {}) == "[]")
+            assert(render("{{ items.2 }}", { items = {"a", "b"} }) == "b")
+            assert(render("{{! comment }}no vars") == "no vars")
+
+            // Syntax errors
+            local ok, err = render("{{ name", {})
+            assert(ok == nil and err == "unclosed tag `{{`")
+            ok, err = render("{{# items }}", {})
+            assert(ok == nil and err == "unclosed section `items`")
+            ok, err = render("{{/ items }}", {})
+            assert(ok == nil and err == "unexpected closing tag `items`")
+        })
+        .exec()
+    }
+
+    #[test]
+    fn test_sections() -> Result<()> {
+        let lua = Lua::new();
+
+        let template = super::create_module(&lua)?;
+        lua.load(chunk! {
+            local render = $template.render
+
+            // Loops
+            local tpl = "{{# users }}<li>{{ name }} ({{ role }})</li>{{/ users }}"
+            local vars = { role = "user", users = { { name = "a" }, { name = "b", role = "admin" } } }
+            assert(render(tpl, vars) == "<li>a (user)</li><li>b (admin)</li>")
+            assert(render("{{#items}}{{.}},{{/items}}", { items = {1, 2, 3} }) == "1,2,3,")
+
+            // Conditionals
+            tpl = "{{# logged_in }}Welcome back{{/ logged_in }}{{^ logged_in }}Please log in{{/ logged_in }}"
+            assert(render(tpl, { logged_in = true }) == "Welcome back")
+            assert(render(tpl, { logged_in = false }) == "Please log in")
+            assert(render("{{^ items }}empty{{/ items }}", { items = {} }) == "empty")
+            assert(render("{{# user }}{{ name }}{{/ user }}", { user = { name = "Bob" } }) == "Bob")
+        })
+        .exec()
+    }
+
+    #[test]
+    fn test_escaping() -> Result<()> {
+        let lua = Lua::new();
+
+        let template = super::create_module(&lua)?;
+        lua.load(chunk! {
+            local render = $template.render
+            local vars = { html = "<b>\"Tom\" & 'Jerry'</b>" }
+            assert(render("{{ html }}", vars) == "&lt;b&gt;&quot;Tom&quot; &amp; &#39;Jerry&#39;&lt;/b&gt;")
+            assert(render("{{{ html }}}", vars) == vars.html)
+            assert(render("{{& html }}", vars) == vars.html)
+        })
+        .exec()
+    }
+}
@@ -0,0 +1,122 @@
+use std::time::{Duration, Instant};
+
+use mlua::{ExternalError, Lua, Result, Table, UserData, UserDataMethods};
+
+fn to_duration(secs: f64) -> Result<Duration> {
+    Duration::try_from_secs_f64(secs).map_err(|err| err.into_lua_err())
+}
+
+/// Suspends the current coroutine for the given number of seconds (without blocking the thread)
+pub async fn sleep(_: Lua, secs: f64) -> Result<()> {
+    tokio::time::sleep(to_duration(secs)?).await;
+    Ok(())
+}
+
+/// Point in time (monotonic) to stop an operation at, e.g. a retry loop
+#[derive(Clone, Copy, Debug)]
+struct Deadline {
+    start: Instant,
+    deadline: Instant,
+}
+
+impl Deadline {
+    fn new(_: &Lua, secs: f64) -> Result<Self> {
+        let start = Instant::now();
+        Ok(Deadline {
+            start,
+            deadline: start + to_duration(secs)?,
+        })
+    }
+
+    fn remaining(&self) -> Duration {
+        self.deadline.saturating_duration_since(Instant::now())
+    }
+}
+
+impl UserData for Deadline {
+    fn add_methods<M: UserDataMethods<Self>>(methods: &mut M) {
+        methods.add_method("remaining", |_, this, ()| {
+            Ok(this.remaining().as_secs_f64())
+        });
+
+        methods.add_method("elapsed", |_, this, ()| {
+            Ok(this.start.elapsed().as_secs_f64())
+        });
+
+        methods.add_method("expired", |_, this, ()| Ok(this.remaining().is_zero()));
+
+        // Sleeps for the given number of seconds, but not longer than the deadline.
+        // Returns `false` if the deadline is reached.
+        methods.add_async_method("sleep", |_, this, secs: f64| async move {
+            let duration = to_duration(secs)?.min(this.remaining());
+            tokio::time::sleep(duration).await;
+            Ok(!this.remaining().is_zero())
+        });
+    }
+}
+
+pub fn create_module(lua: &Lua) -> Result<Table> {
+    lua.create_table_from([
+        ("sleep", lua.create_async_function(sleep)?),
+        ("deadline", lua.create_function(Deadline::new)?),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use mlua::{chunk, Lua, Result};
+
+    #[ntex::test]
+    async fn test_sleep() -> Result<()> {
+        let lua = Lua::new();
+
+        let time = super::create_module(&lua)?;
+        let time2 = time.clone();
+        let start = Instant::now();
+        lua.load(chunk! {
+            $time2.sleep(0.05)
+        })
+        .exec_async()
+        .await?;
+        let elapsed = start.elapsed();
+        assert!(elapsed >= Duration::from_millis(50));
+        assert!(elapsed < Duration::from_millis(500));
+
+        lua.load(chunk! {
+            local ok, err = pcall($time.sleep, -1)
+            assert(not ok and tostring(err):find("negative") ~= nil)
+        })
+        .exec_async()
+        .await
+    }
+
+    #[ntex::test]
+    async fn test_deadline() -> Result<()> {
+        let lua = Lua::new();
+
+        let time = super::create_module(&lua)?;
+        lua.load(chunk! {
+            local deadline = $time.deadline(0.2)
+            assert(not deadline:expired())
+            assert(deadline:remaining() > 0.1 and deadline:remaining() <= 0.2)
+
+            // Retry loop with backoff
+            local attempts = 0
+            repeat
+                attempts += 1
+            until not deadline:sleep(0.05)
+            assert(attempts >= 3 and attempts <= 5, "attempts: " .. attempts)
+            assert(deadline:expired())
+            assert(deadline:remaining() == 0)
+            assert(deadline:elapsed() >= 0.2)
+
+            // Sleep is capped by the deadline
+            deadline = $time.deadline(0.05)
+            assert(deadline:sleep(10) == false)
+        })
+        .exec_async()
+        .await
+    }
+}
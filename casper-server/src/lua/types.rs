@@ -22,6 +22,16 @@ impl FlexBytes {
             FlexBytes::Bytes(b) => f(b.as_ref()),
         }
     }
+
+    /// Converts `data` to a Lua value of the same kind as this one (string or `Bytes`).
+    pub fn create_like(&self, lua: &Lua, data: Vec<u8>) -> LuaResult<Value> {
+        match self {
+            FlexBytes::String(_) => lua.create_string(data).map(Value::String),
+            FlexBytes::Bytes(_) => lua
+                .create_any_userdata(Bytes::from(data))
+                .map(Value::UserData),
+        }
+    }
 }
 
 impl FromLua for FlexBytes {
@@ -1,11 +1,12 @@
 use std::result::Result as StdResult;
 
 use base64::Engine as _;
-use bstr::BString;
-use mlua::{Lua, Result, String as LuaString, Table};
+use mlua::{Lua, Result, Table, Value};
 use rand::distributions::Standard;
 use rand::{thread_rng, Rng as _};
 
+use super::FlexBytes;
+
 /*
 --- @class utils
 --- @tag module
@@ -64,93 +65,150 @@ fn random_string(_: &Lua, (len, charset): (usize, Option<String>)) -> Result<Str
 --- @within utils
 --- Encodes a string to base64 using the standard alphabet (with `+` and `/`).
 ---
---- @param data Input string.
+--- @param data Input string or `Bytes` (the result is of the same type).
 --- @param padding Optional flag to enable padding. Default is `false`.
-function utils.base64_encode(data: string, padding: boolean?): string
+function utils.base64_encode(data: Bytes | string, padding: boolean?): Bytes | string
     return nil :: any
 end
 */
-fn base64_encode(_: &Lua, (data, padding): (LuaString, Option<bool>)) -> Result<String> {
-    if padding.unwrap_or_default() {
-        Ok(base64::engine::general_purpose::STANDARD.encode(data.as_bytes()))
-    } else {
-        Ok(base64::engine::general_purpose::STANDARD_NO_PAD.encode(data.as_bytes()))
-    }
+fn base64_encode(lua: &Lua, (data, padding): (FlexBytes, Option<bool>)) -> Result<Value> {
+    let encoded = data.borrow_bytes(|bytes| {
+        if padding.unwrap_or_default() {
+            base64::engine::general_purpose::STANDARD.encode(bytes)
+        } else {
+            base64::engine::general_purpose::STANDARD_NO_PAD.encode(bytes)
+        }
+    });
+    data.create_like(lua, encoded.into_bytes())
 }
 
 /*
 --- @within utils
 --- Decodes a base64 string using the standard alphabet (with `+` and `/`).
 ---
---- @param data Input string.
+--- @param data Input string or `Bytes` (the result is of the same type).
 --- @param padding Optional flag to enable padding. Default is `false`.
-function utils.base64_decode(data: string, padding: boolean?): string
+function utils.base64_decode(data: Bytes | string, padding: boolean?): Bytes | string
     return nil :: any
 end
 */
 fn base64_decode(
     lua: &Lua,
-    (data, padding): (LuaString, Option<bool>),
-) -> Result<StdResult<LuaString, String>> {
-    let data = if padding.unwrap_or_default() {
-        lua_try!(base64::engine::general_purpose::STANDARD.decode(data.as_bytes()))
-    } else {
-        lua_try!(base64::engine::general_purpose::STANDARD_NO_PAD.decode(data.as_bytes()))
-    };
-    Ok(Ok(lua.create_string(&data)?))
+    (data, padding): (FlexBytes, Option<bool>),
+) -> Result<StdResult<Value, String>> {
+    let decoded = lua_try!(data.borrow_bytes(|bytes| {
+        if padding.unwrap_or_default() {
+            base64::engine::general_purpose::STANDARD.decode(bytes)
+        } else {
+            base64::engine::general_purpose::STANDARD_NO_PAD.decode(bytes)
+        }
+    }));
+    Ok(Ok(data.create_like(lua, decoded)?))
 }
 
 /*
 --- @within utils
 --- Encodes a string to base64 using the URL-safe alphabet (with `-` and `_`).
 ---
---- @param data Input string.
+--- @param data Input string or `Bytes` (the result is of the same type).
 --- @param padding Optional flag to enable padding. Default is `false`.
-function utils.base64url_encode(data: string, padding: boolean?): string
+function utils.base64url_encode(data: Bytes | string, padding: boolean?): Bytes | string
     return nil :: any
 end
 */
-fn base64url_encode(_: &Lua, (data, padding): (LuaString, Option<bool>)) -> Result<String> {
-    if padding.unwrap_or_default() {
-        Ok(base64::engine::general_purpose::URL_SAFE.encode(data.as_bytes()))
-    } else {
-        Ok(base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(data.as_bytes()))
-    }
+fn base64url_encode(lua: &Lua, (data, padding): (FlexBytes, Option<bool>)) -> Result<Value> {
+    let encoded = data.borrow_bytes(|bytes| {
+        if padding.unwrap_or_default() {
+            base64::engine::general_purpose::URL_SAFE.encode(bytes)
+        } else {
+            base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(bytes)
+        }
+    });
+    data.create_like(lua, encoded.into_bytes())
 }
 
 /*
 --- @within utils
 --- Decodes a base64 string using the URL-safe alphabet (with `-` and `_`).
 ---
---- @param data Input string.
+--- @param data Input string or `Bytes` (the result is of the same type).
 --- @param padding Optional flag to enable padding. Default is `false`.
-function utils.base64url_decode(data: string, padding: boolean?): string
+function utils.base64url_decode(data: Bytes | string, padding: boolean?): Bytes | string
     return nil :: any
 end
 */
 fn base64url_decode(
     lua: &Lua,
-    (data, padding): (LuaString, Option<bool>),
-) -> Result<StdResult<LuaString, String>> {
-    let data = if padding.unwrap_or_default() {
-        lua_try!(base64::engine::general_purpose::URL_SAFE.decode(data.as_bytes()))
-    } else {
-        lua_try!(base64::engine::general_purpose::URL_SAFE_NO_PAD.decode(data.as_bytes()))
-    };
-    Ok(Ok(lua.create_string(&data)?))
+    (data, padding): (FlexBytes, Option<bool>),
+) -> Result<StdResult<Value, String>> {
+    let decoded = lua_try!(data.borrow_bytes(|bytes| {
+        if padding.unwrap_or_default() {
+            base64::engine::general_purpose::URL_SAFE.decode(bytes)
+        } else {
+            base64::engine::general_purpose::URL_SAFE_NO_PAD.decode(bytes)
+        }
+    }));
+    Ok(Ok(data.create_like(lua, decoded)?))
+}
+
+/*
+--- @within utils
+--- Encodes a string to base32 using the RFC 4648 alphabet (`A-Z` and `2-7`).
+---
+--- @param data Input string or `Bytes` (the result is of the same type).
+--- @param padding Optional flag to enable padding. Default is `false`.
+function utils.base32_encode(data: Bytes | string, padding: boolean?): Bytes | string
+    return nil :: any
+end
+*/
+fn base32_encode(lua: &Lua, (data, padding): (FlexBytes, Option<bool>)) -> Result<Value> {
+    let encoded = data.borrow_bytes(|bytes| {
+        if padding.unwrap_or_default() {
+            data_encoding::BASE32.encode(bytes)
+        } else {
+            data_encoding::BASE32_NOPAD.encode(bytes)
+        }
+    });
+    data.create_like(lua, encoded.into_bytes())
+}
+
+/*
+--- @within utils
+--- Decodes a base32 string using the RFC 4648 alphabet (`A-Z` and `2-7`).
+--- Returns `nil` and an error message if the input is invalid.
+---
+--- @param data Input string or `Bytes` (the result is of the same type).
+--- @param padding Optional flag to enable padding. Default is `false`.
+function utils.base32_decode(data: Bytes | string, padding: boolean?): ((Bytes | string)?, string?)
+    return nil :: any
+end
+*/
+fn base32_decode(
+    lua: &Lua,
+    (data, padding): (FlexBytes, Option<bool>),
+) -> Result<StdResult<Value, String>> {
+    let decoded = lua_try!(data.borrow_bytes(|bytes| {
+        if padding.unwrap_or_default() {
+            data_encoding::BASE32.decode(bytes)
+        } else {
+            data_encoding::BASE32_NOPAD.decode(bytes)
+        }
+    }));
+    Ok(Ok(data.create_like(lua, decoded)?))
 }
 
 /*
 --- @within utils
 --- Encodes a string as hex string using lowercase characters.
 ---
---- @param data Input string.
-function utils.hex_encode(data: string): string
+--- @param data Input string or `Bytes` (the result is of the same type).
+function utils.hex_encode(data: Bytes | string): Bytes | string
     return nil :: any
 end
 */
-fn hex_encode(_: &Lua, data: LuaString) -> Result<String> {
-    Ok(hex::encode(data.as_bytes()))
+fn hex_encode(lua: &Lua, data: FlexBytes) -> Result<Value> {
+    let encoded = data.borrow_bytes(|bytes| hex::encode(bytes));
+    data.create_like(lua, encoded.into_bytes())
 }
 
 /*
@@ -158,13 +216,14 @@ fn hex_encode(_: &Lua, data: LuaString) -> Result<String> {
 --- Decodes a hex string to a byte string.
 --- Returns `nil` and an error message if the input is invalid.
 ---
---- @param data Input string.
-function utils.hex_decode(data: string): (string?, string?)
+--- @param data Input string or `Bytes` (the result is of the same type).
+function utils.hex_decode(data: Bytes | string): ((Bytes | string)?, string?)
     return nil :: any
 end
 */
-fn hex_decode(_: &Lua, data: LuaString) -> Result<StdResult<BString, String>> {
-    Ok(Ok(BString::new(lua_try!(hex::decode(data.as_bytes())))))
+fn hex_decode(lua: &Lua, data: FlexBytes) -> Result<StdResult<Value, String>> {
+    let decoded = lua_try!(data.borrow_bytes(|bytes| hex::decode(bytes)));
+    Ok(Ok(data.create_like(lua, decoded)?))
 }
 
 pub fn create_module(lua: &Lua) -> Result<Table> {
@@ -176,6 +235,8 @@ pub fn create_module(lua: &Lua) -> Result<Table> {
         ("base64_decode", lua.create_function(base64_decode)?),
         ("base64url_encode", lua.create_function(base64url_encode)?),
         ("base64url_decode", lua.create_function(base64url_decode)?),
+        ("base32_encode", lua.create_function(base32_encode)?),
+        ("base32_decode", lua.create_function(base32_decode)?),
         ("hex_encode", lua.create_function(hex_encode)?),
         ("hex_decode", lua.create_function(hex_decode)?),
     ])
@@ -188,6 +249,7 @@ return utils
 #[cfg(test)]
 mod tests {
     use mlua::{chunk, Lua, Result};
+    use ntex::util::Bytes;
 
     #[test]
     fn test_random() -> Result<()> {
@@ -246,6 +308,31 @@ mod tests {
             // Invalid input
             local r, err = $utils.base64_decode("wrong base64")
             assert(r == nil and err ~= nil, "invalid base64 decoding result")
+            r, err = $utils.base64url_decode("wrong+base64")
+            assert(r == nil and err ~= nil, "invalid URL-safe base64 decoding result")
+        })
+        .exec()
+    }
+
+    #[test]
+    fn test_base32() -> Result<()> {
+        let lua = Lua::new();
+
+        let utils = super::create_module(&lua)?;
+        lua.load(chunk! {
+            local s = "hello internet~!"
+            local b32 = $utils.base32_encode(s)
+            assert(b32 == "NBSWY3DPEBUW45DFOJXGK5D6EE", "invalid base32 encoding")
+            local b32pad = $utils.base32_encode(s, true)
+            assert(b32pad == "NBSWY3DPEBUW45DFOJXGK5D6EE======", "invalid base32 encoding with padding")
+
+            local s2 = $utils.base32_decode(b32)
+            assert(s2 == s, "invalid base32 decoding")
+            local s3 = $utils.base32_decode(b32pad, true)
+            assert(s3 == s, "invalid base32 decoding with padding")
+
+            local r, err = $utils.base32_decode("invalid base32")
+            assert(r == nil and err ~= nil, "invalid base32 decoding result")
         })
         .exec()
     }
@@ -268,4 +355,40 @@ mod tests {
         })
         .exec()
     }
+
+    #[test]
+    fn test_bytes_input() -> Result<()> {
+        let lua = Lua::new();
+        super::super::bytes::register_types(&lua)?;
+
+        let utils = super::create_module(&lua)?;
+        let data = lua.create_any_userdata(Bytes::from_static(b"hello internet~!\xff"))?;
+        lua.load(chunk! {
+            local data = $data
+            local codecs = {
+                { $utils.base64_encode, $utils.base64_decode, "aGVsbG8gaW50ZXJuZXR+If8" },
+                { $utils.base64url_encode, $utils.base64url_decode, "aGVsbG8gaW50ZXJuZXR-If8" },
+                { $utils.base32_encode, $utils.base32_decode, "NBSWY3DPEBUW45DFOJXGK5D6EH7Q" },
+                { $utils.hex_encode, $utils.hex_decode, "68656c6c6f20696e7465726e65747e21ff" },
+            }
+            for _, codec in codecs do
+                local encode, decode, expected = codec[1], codec[2], codec[3]
+                // `Bytes` input produces `Bytes` output
+                local encoded = encode(data)
+                assert(typeof(encoded) == "Bytes", "encoded value must be Bytes")
+                assert(encoded:to_string() == expected, "invalid encoding of Bytes")
+                local decoded = decode(encoded)
+                assert(typeof(decoded) == "Bytes", "decoded value must be Bytes")
+                assert(decoded:to_string() == data:to_string(), "invalid round-trip of Bytes")
+
+                // String input produces string output
+                assert(encode(data:to_string()) == expected, "invalid encoding of string")
+                assert(decode(expected) == data:to_string(), "invalid decoding of string")
+            end
+
+            local r, err = $utils.hex_decode($utils.base64_encode(data))
+            assert(r == nil and err ~= nil, "invalid hex decoding result")
+        })
+        .exec()
+    }
 }
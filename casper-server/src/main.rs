@@ -1,7 +1,11 @@
+// Layout of the connection future wrapping the nested middleware stack exceeds the default limit
+#![recursion_limit = "256"]
+
 use std::env;
 use std::sync::Arc;
 use std::time::Duration;
 
+use anyhow::bail;
 use clap::Parser;
 use ntex::http::client::{Client as HttpClient, Connector as HttpConnector};
 use ntex::http::HttpService;
@@ -12,9 +16,12 @@ use ntex::service::apply_fn_factory;
 use ntex::time::Seconds;
 use ntex::util::PoolId;
 use ntex::web::{self, App};
+use tokio::time;
 use tracing::error;
 
+use crate::config::Config;
 use crate::context::AppContext;
+use crate::http::UpstreamAllowlist;
 use crate::storage::Storage;
 
 #[macro_use]
@@ -26,6 +33,39 @@ mod trace;
 #[global_allocator]
 static GLOBAL: tikv_jemallocator::Jemalloc = tikv_jemallocator::Jemalloc;
 
+/// Constructs storage backends defined in the config and connects to them.
+///
+/// Connection errors are not critical unless `require_storage_on_start` is set.
+async fn connect_storage_backends(config: &Config) -> anyhow::Result<Vec<storage::Backend>> {
+    let mut storage_backends = Vec::new();
+    for (name, conf) in config.storage.clone() {
+        let backend = storage::Backend::new(name.clone(), conf)?;
+        if config.main.require_storage_on_start {
+            // Force connection (even in lazy mode) and wait until the storage is ready
+            let connect = async {
+                backend.reconnect().await?;
+                while !backend.is_connected() {
+                    time::sleep(Duration::from_millis(50)).await;
+                }
+                anyhow::Ok(())
+            };
+            let connect_timeout = Duration::from_secs_f64(config.main.storage_connect_timeout);
+            match time::timeout(connect_timeout, connect).await {
+                Ok(Ok(())) => {}
+                Ok(Err(err)) => {
+                    return Err(err.context(format!("failed to connect to storage `{name}`")))
+                }
+                Err(_) => bail!("timed out connecting to storage `{name}`"),
+            }
+        } else if let Err(err) = backend.connect().await {
+            // Not critical error
+            error!("Failed to establish connection with storage '{name}': {err:?}");
+        }
+        storage_backends.push(backend);
+    }
+    Ok(storage_backends)
+}
+
 #[derive(Parser, Debug)]
 #[clap(version, about, long_about = None)]
 struct Args {
@@ -36,6 +76,7 @@ struct Args {
 async fn main_inner(args: Args) -> anyhow::Result<()> {
     // Read application configuration
     let config = Arc::new(config::read_config(&args.config)?);
+    admin::validate_config(&config)?;
 
     // Propagate service name to the otel sdk
     if let Some(service_name) = &config.main.service_name {
@@ -51,29 +92,35 @@ async fn main_inner(args: Args) -> anyhow::Result<()> {
     // Init metrics subsystem
     crate::metrics::init(&config);
 
+    // Start periodic stats reporter
+    crate::stats::init(&config);
+
     // Construct storage backends defined in the config
-    let mut storage_backends = Vec::new();
-    for (name, conf) in config.storage.clone() {
-        let backend = storage::Backend::new(name.clone(), conf)?;
-        if let Err(err) = backend.connect().await {
-            // Not critical error
-            error!("Failed to establish connection with storage '{name}': {err:?}");
-        }
-        storage_backends.push(backend);
-    }
+    let storage_backends = connect_storage_backends(&config).await?;
+
+    // Routing table is shared between workers
+    let routes = routes::RoutingTable::new(config.routes.clone())?;
 
     // Try to initialize application context on the listening thread to check for errors
     let context = AppContext::builder()
         .with_config(config.clone())
         .with_storage_backends(storage_backends.clone())
+        .with_routes(routes.clone())
         .build()?;
     // Drop it
     drop(context);
 
+    // Upstreams to warm up connections to (in every worker)
+    let warmup_targets = match &config.upstreams {
+        Some(upstreams) => http::warmup::warmup_targets(upstreams)?,
+        None => Vec::new(),
+    };
+
     let addr = config.main.listen.clone();
     let workers = config.main.workers;
+    let shutdown_timeout = Duration::from_secs_f64(config.main.shutdown_timeout);
 
-    Server::build()
+    let server = Server::build()
         .bind("casper", &addr, move |conf| {
             conf.memory_pool(PoolId::P0);
 
@@ -81,18 +128,43 @@ async fn main_inner(args: Args) -> anyhow::Result<()> {
             let context = AppContext::builder()
                 .with_config(config.clone())
                 .with_storage_backends(storage_backends.clone())
+                .with_routes(routes.clone())
                 .build()
                 .unwrap();
             let id = context.id;
 
             // Construct default HTTP client and attach it to Lua
-            let connector = HttpConnector::new().limit(2000);
+            let mut connector = HttpConnector::new().limit(2000);
+            if let Some(allowlist) = context.lua.app_data_ref::<UpstreamAllowlist>() {
+                connector = allowlist.guard_connector(connector);
+            }
             let http_client = HttpClient::build()
                 .connector(connector.finish())
                 .disable_redirects()
                 .disable_timeout()
                 .finish();
-            context.lua.set_app_data(http_client);
+            context.lua.set_app_data(http_client.clone());
+
+            // Pre-establish connections to upstreams
+            if let Some(upstreams) = config
+                .upstreams
+                .as_ref()
+                .filter(|u| u.warmup_connections > 0)
+            {
+                let connections = upstreams.warmup_connections;
+                let timeout = Duration::from_secs_f64(upstreams.warmup_timeout);
+                let interval = Some(Duration::from_secs_f64(upstreams.warmup_interval))
+                    .filter(|interval| !interval.is_zero());
+                for upstream in warmup_targets.clone() {
+                    tokio::task::spawn_local(http::warmup::keep_warm(
+                        http_client.clone(),
+                        upstream,
+                        connections,
+                        timeout,
+                        interval,
+                    ));
+                }
+            }
 
             // Track Lua used memory every 10 seconds
             let lua = context.lua.clone();
@@ -107,9 +179,21 @@ async fn main_inner(args: Args) -> anyhow::Result<()> {
             let app = App::new()
                 .state(context)
                 .wrap(middleware::Metrics::new("/metrics".to_string()))
+                .wrap(middleware::Auth::new(config.auth.clone()))
                 .wrap(middleware::RequestTracing::new(config.tracing.clone()))
-                .wrap(middleware::Logger::new())
+                .wrap(middleware::Logger::new(config.http.access_log_format))
+                .wrap(middleware::ResponseHeaderDenylist::new(
+                    &config.http.response_header_denylist,
+                ))
                 // .wrap(ntex::web::middleware::Logger::default())
+                .configure(|cfg| {
+                    if let Some(health) = &config.health {
+                        health::configure(&health.path, cfg);
+                    }
+                    if let Some(admin) = &config.admin {
+                        admin::configure(&admin.path, cfg);
+                    }
+                })
                 .default_service(web::to(handler::handler));
 
             // TODO: AppConfig
@@ -118,6 +202,7 @@ async fn main_inner(args: Args) -> anyhow::Result<()> {
                 .keep_alive(30)
                 .client_timeout(Seconds::new(5))
                 .disconnect_timeout(Seconds::new(5))
+                .h1_control(http::control::h1_control(config.http.reject_expect))
                 .finish(app);
 
             apply_fn_factory(service, |io: Io, handler| async move {
@@ -128,8 +213,12 @@ async fn main_inner(args: Args) -> anyhow::Result<()> {
         })?
         .backlog(2048)
         .workers(workers)
-        .run()
-        .await?;
+        .disable_signals()
+        .run();
+
+    // Drain in-flight requests before stopping the server
+    ntex::rt::spawn(shutdown::stop_on_signal(server.clone(), shutdown_timeout));
+    server.await?;
 
     opentelemetry::global::shutdown_tracer_provider(); // sending remaining spans
 
@@ -146,13 +235,90 @@ fn main() {
     }
 }
 
+mod admin;
 mod config;
 mod context;
 mod handler;
+mod health;
 mod http;
 mod logs;
 mod lua;
 mod middleware;
+mod routes;
+mod shutdown;
+mod stats;
 mod storage;
 mod types;
 mod utils;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[ntex::test]
+    async fn test_require_storage_on_start() {
+        let config: Config = serde_yaml::from_str(
+            r#"
+            main:
+              require_storage_on_start: true
+              storage_connect_timeout: 0.5
+            storage:
+              cache:
+                backend: memory
+                max_size: 1024
+        "#,
+        )
+        .unwrap();
+        let backends = connect_storage_backends(&config).await.unwrap();
+        assert_eq!(backends.len(), 1);
+
+        // Unreachable backend aborts startup in the strict mode
+        let mut config: Config = serde_yaml::from_str(
+            r#"
+            main:
+              require_storage_on_start: true
+              storage_connect_timeout: 0.5
+            storage:
+              cache:
+                backend: redis
+                server:
+                  centralized:
+                    endpoint: 127.0.0.1:1
+                lazy: true
+        "#,
+        )
+        .unwrap();
+        let err = match connect_storage_backends(&config).await {
+            Ok(_) => panic!("expected connection error"),
+            Err(err) => err,
+        };
+        assert_eq!(err.to_string(), "timed out connecting to storage `cache`");
+
+        // ...but not by default
+        config.main.require_storage_on_start = false;
+        let backends = connect_storage_backends(&config).await.unwrap();
+        assert_eq!(backends.len(), 1);
+    }
+
+    #[test]
+    fn test_invalid_timeouts() {
+        for main in [
+            "storage_connect_timeout: -1",
+            "storage_connect_timeout: .nan",
+            "shutdown_timeout: -0.5",
+            "shutdown_timeout: .inf",
+        ] {
+            let config = format!("main: {{ {main} }}");
+            assert!(serde_yaml::from_str::<Config>(&config).is_err(), "{main}");
+        }
+        let config: Config = serde_yaml::from_str("main: { shutdown_timeout: 0 }").unwrap();
+        assert_eq!(config.main.shutdown_timeout, 0.0);
+
+        let config =
+            serde_json::json!({ "backend": "redis", "lazy": true, "retry_backoff": -0.01 });
+        let err = storage::Backend::new("cache".to_string(), config)
+            .err()
+            .unwrap();
+        assert!(format!("{err:#}").contains("invalid duration `-0.01`"));
+    }
+}
@@ -1,11 +1,13 @@
-use std::collections::HashMap;
-use std::sync::atomic::{AtomicU64, Ordering};
+use std::collections::{HashMap, HashSet};
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
 use std::sync::{Arc, OnceLock};
 
-use opentelemetry::global;
-use opentelemetry::metrics::{Counter, Histogram, MeterProvider as _};
+use opentelemetry::metrics::{Counter, Histogram, Meter, MeterProvider as _};
+use opentelemetry::{global, KeyValue};
 use opentelemetry_sdk::metrics::SdkMeterProvider;
 use tokio::sync::RwLock;
+use tracing::warn;
 
 use crate::config::Config;
 
@@ -16,12 +18,105 @@ static BOUNDARIES: &[f64] = &[
     0.001, 0.003, 0.005, 0.01, 0.025, 0.05, 0.075, 0.1, 0.25, 0.5, 0.75, 1.0, 2.5, 5.0, 7.5, 10.0,
 ];
 
+// Histogram boundaries for body sizes (in bytes)
+static BODY_SIZE_BOUNDARIES: &[f64] = &[256.0, 1024.0, 10240.0, 102400.0, 1048576.0, 10485760.0];
+
 pub fn init(config: &Config) {
     METRICS
         .set(OpenTelemetryMetrics::new(config))
         .expect("failed to init metrics");
 }
 
+/// Label value used for label sets above the limit
+pub const OVERFLOW_LABEL_VALUE: &str = "__overflow__";
+
+/// Creates user-defined counters
+pub(crate) fn user_counters(meter: &Meter, config: &Config) -> HashMap<String, UserCounter> {
+    let metrics_conf = config.metrics.as_ref();
+    let counters_conf = metrics_conf.and_then(|conf| conf.counters.as_ref());
+    let max_label_values = metrics_conf.and_then(|conf| conf.max_label_values);
+    let mut counters = HashMap::new();
+    for (key, conf) in counters_conf.into_iter().flatten() {
+        // If name already ends with `_total`, strip it out as the suffix is added automatically
+        let mut name = conf.name.clone().unwrap_or_else(|| key.clone());
+        if name.ends_with("_total") {
+            name = name[..name.len() - 6].to_string();
+        }
+        let labels_limit = max_label_values.map(|max| Arc::new(LabelsLimit::new(&name, max)));
+        let mut counter = meter.u64_counter(name);
+        if let Some(description) = conf.description.clone() {
+            counter = counter.with_description(description);
+        }
+        let counter = counter.build();
+        counters.insert(
+            key.clone(),
+            UserCounter {
+                counter,
+                labels_limit,
+            },
+        );
+    }
+    counters
+}
+
+/// Creates user-defined histograms (latency buckets are used by default)
+pub(crate) fn user_histograms(meter: &Meter, config: &Config) -> HashMap<String, Histogram<f64>> {
+    let histograms_conf = config
+        .metrics
+        .as_ref()
+        .and_then(|conf| conf.histograms.as_ref());
+    let mut histograms = HashMap::new();
+    for (key, conf) in histograms_conf.into_iter().flatten() {
+        let name = conf.name.clone().unwrap_or_else(|| key.clone());
+        let buckets = conf.buckets.clone().unwrap_or_else(|| BOUNDARIES.to_vec());
+        let mut histogram = meter.f64_histogram(name).with_boundaries(buckets);
+        if let Some(description) = conf.description.clone() {
+            histogram = histogram.with_description(description);
+        }
+        histograms.insert(key.clone(), histogram.build());
+    }
+    histograms
+}
+
+/// Creates user-defined gauges backed by atomic values (read by the observer callbacks)
+pub(crate) fn user_gauges(meter: &Meter, config: &Config) -> HashMap<String, ActiveCounter> {
+    let gauges_conf = config
+        .metrics
+        .as_ref()
+        .and_then(|conf| conf.gauges.as_ref());
+    let mut gauges = HashMap::new();
+    for (key, conf) in gauges_conf.into_iter().flatten() {
+        let name = conf.name.clone().unwrap_or_else(|| key.clone());
+        let value = ActiveCounter::new(0);
+        let value2 = value.clone();
+        let mut gauge = meter
+            .u64_observable_gauge(name)
+            .with_callback(move |instr| {
+                instr.observe(value2.get(), &[]);
+            });
+        if let Some(description) = conf.description.clone() {
+            gauge = gauge.with_description(description);
+        }
+        gauge.build();
+        gauges.insert(key.clone(), value);
+    }
+    gauges
+}
+
+/// Creates a meter provider exporting metrics to a new (not shared) prometheus registry
+#[cfg(test)]
+pub(crate) fn test_meter_provider() -> (SdkMeterProvider, prometheus::Registry) {
+    let registry = prometheus::Registry::new();
+    let exporter = opentelemetry_prometheus::exporter()
+        .with_registry(registry.clone())
+        .without_target_info()
+        .without_scope_info()
+        .build()
+        .expect("failed to create prometheus exporter");
+    let provider = SdkMeterProvider::builder().with_reader(exporter).build();
+    (provider, registry)
+}
+
 #[inline]
 pub fn global() -> &'static OpenTelemetryMetrics {
     if cfg!(test) {
@@ -38,14 +133,26 @@ pub struct OpenTelemetryMetrics {
     pub requests_counter: Counter<u64>,
     pub requests_histogram: Histogram<f64>,
     pub active_requests_counter: ActiveCounter,
+    pub rejected_requests_counter: Counter<u64>,
 
     pub storage_counter: Counter<u64>,
+    pub storage_results_counter: Counter<u64>,
     pub storage_histogram: Histogram<f64>,
+    pub storage_reconnect_counter: Counter<u64>,
+    pub storage_body_size_histogram: Histogram<u64>,
+    pub storage_stored_size_histogram: Histogram<u64>,
 
     pub filter_histogram: Histogram<f64>,
     pub filter_error_counter: Counter<u64>,
 
     pub handler_error_counter: Counter<u64>,
+    pub lua_handler_cpu_histogram: Histogram<f64>,
+
+    pub auth_failure_counter: Counter<u64>,
+
+    pub rejected_upstreams_counter: Counter<u64>,
+    pub upstream_ttfb_histogram: Histogram<f64>,
+    pub upstream_warm_connections_counter: Counter<u64>,
 
     pub active_tasks_counter: ActiveCounter,
     pub task_histogram: Histogram<f64>,
@@ -57,7 +164,9 @@ pub struct OpenTelemetryMetrics {
     pub extra_labels: HashMap<String, String>,
 
     // User-defined metrics
-    pub counters: HashMap<String, Counter<u64>>,
+    pub counters: HashMap<String, UserCounter>,
+    pub histograms: HashMap<String, Histogram<f64>>,
+    pub gauges: HashMap<String, ActiveCounter>,
 }
 
 impl OpenTelemetryMetrics {
@@ -156,25 +265,9 @@ impl OpenTelemetryMetrics {
         }
 
         // Init user-defined metrics
-        let mut counters = HashMap::new();
-        let counters_conf = config
-            .metrics
-            .as_ref()
-            .and_then(|conf| conf.counters.as_ref());
-        if let Some(counters_conf) = counters_conf {
-            for (key, conf) in counters_conf.clone() {
-                // If name already ends with `_total`, strip it out as the suffix is added automatically
-                let mut name = conf.name.unwrap_or_else(|| key.clone());
-                if name.ends_with("_total") {
-                    name = name[..name.len() - 6].to_string();
-                }
-                let mut counter = meter.u64_counter(name);
-                if let Some(description) = conf.description {
-                    counter = counter.with_description(description);
-                }
-                counters.insert(key, counter.build());
-            }
-        }
+        let counters = user_counters(&meter, config);
+        let histograms = user_histograms(&meter, config);
+        let gauges = user_gauges(&meter, config);
 
         OpenTelemetryMetrics {
             connections_counter: meter
@@ -193,6 +286,10 @@ impl OpenTelemetryMetrics {
                 .with_boundaries(BOUNDARIES.to_vec())
                 .build(),
             active_requests_counter,
+            rejected_requests_counter: meter
+                .u64_counter("http_rejected_requests")
+                .with_description("Total number of HTTP requests rejected before processing.")
+                .build(),
 
             storage_counter: meter
                 .u64_counter("storage_requests")
@@ -200,11 +297,29 @@ impl OpenTelemetryMetrics {
                     "Total number of requests being processed by the storage backend.",
                 )
                 .build(),
+            storage_results_counter: meter
+                .u64_counter("storage_results")
+                .with_description("Total number of storage backend results by status.")
+                .build(),
             storage_histogram: meter
                 .f64_histogram("storage_request_duration_seconds")
                 .with_description("The storage backend request latency in seconds.")
                 .with_boundaries(BOUNDARIES.to_vec())
                 .build(),
+            storage_reconnect_counter: meter
+                .u64_counter("storage_reconnects")
+                .with_description("Total number of manual storage backend reconnects.")
+                .build(),
+            storage_body_size_histogram: meter
+                .u64_histogram("storage_item_body_bytes")
+                .with_description("Size of the stored (cached) response bodies in bytes.")
+                .with_boundaries(BODY_SIZE_BOUNDARIES.to_vec())
+                .build(),
+            storage_stored_size_histogram: meter
+                .u64_histogram("storage_stored_body_bytes")
+                .with_description("Number of bytes written to the storage backend per item.")
+                .with_boundaries(BODY_SIZE_BOUNDARIES.to_vec())
+                .build(),
 
             filter_histogram: meter
                 .f64_histogram("filter_request_duration_seconds")
@@ -220,6 +335,34 @@ impl OpenTelemetryMetrics {
                 .u64_counter("handler_errors")
                 .with_description("Total number of errors thrown by handler.")
                 .build(),
+            lua_handler_cpu_histogram: meter
+                .f64_histogram("lua_handler_cpu_seconds")
+                .with_description(
+                    "Time spent executing Lua filters and handler (excluding awaiting I/O) per request in seconds.",
+                )
+                .with_boundaries(BOUNDARIES.to_vec())
+                .build(),
+
+            auth_failure_counter: meter
+                .u64_counter("auth_failures")
+                .with_description("Total number of requests rejected by authentication.")
+                .build(),
+
+            rejected_upstreams_counter: meter
+                .u64_counter("rejected_upstreams")
+                .with_description("Total number of proxy requests to disallowed upstreams.")
+                .build(),
+            upstream_ttfb_histogram: meter
+                .f64_histogram("upstream_ttfb_seconds")
+                .with_description(
+                    "Time from sending a proxied request to receiving the first response body byte in seconds.",
+                )
+                .with_boundaries(BOUNDARIES.to_vec())
+                .build(),
+            upstream_warm_connections_counter: meter
+                .u64_counter("upstream_warm_connections")
+                .with_description("Total number of upstream connections opened in advance by warm-up.")
+                .build(),
 
             active_tasks_counter,
             task_histogram: meter
@@ -238,6 +381,8 @@ impl OpenTelemetryMetrics {
             extra_labels,
 
             counters,
+            histograms,
+            gauges,
         }
     }
 }
@@ -277,6 +422,17 @@ macro_rules! requests_histogram_rec {
     }};
 }
 
+macro_rules! rejected_requests_counter_add {
+    ($increment:expr, $($key:expr => $val:expr),*) => {{
+        crate::metrics::global().rejected_requests_counter.add(
+            $increment,
+            &[
+                $(::opentelemetry::KeyValue::new($key, $val),)*
+            ],
+        )
+    }};
+}
+
 macro_rules! storage_counter_add {
     ($increment:expr, $($key:expr => $val:expr),*) => {{
         crate::metrics::global().storage_counter.add(
@@ -288,6 +444,17 @@ macro_rules! storage_counter_add {
     }};
 }
 
+macro_rules! storage_results_counter_add {
+    ($increment:expr, $($key:expr => $val:expr),*) => {{
+        crate::metrics::global().storage_results_counter.add(
+            $increment,
+            &[
+                $(::opentelemetry::KeyValue::new($key, $val),)*
+            ],
+        )
+    }};
+}
+
 macro_rules! storage_histogram_rec {
     ($start:expr, $($key:expr => $val:expr),*) => {{
         crate::metrics::global().storage_histogram.record(
@@ -299,6 +466,28 @@ macro_rules! storage_histogram_rec {
     }};
 }
 
+macro_rules! storage_body_size_rec {
+    ($size:expr, $($key:expr => $val:expr),*) => {{
+        crate::metrics::global().storage_body_size_histogram.record(
+            $size as u64,
+            &[
+                $(::opentelemetry::KeyValue::new($key, $val),)*
+            ],
+        )
+    }};
+}
+
+macro_rules! storage_stored_size_rec {
+    ($size:expr, $($key:expr => $val:expr),*) => {{
+        crate::metrics::global().storage_stored_size_histogram.record(
+            $size as u64,
+            &[
+                $(::opentelemetry::KeyValue::new($key, $val),)*
+            ],
+        )
+    }};
+}
+
 macro_rules! filter_histogram_rec {
     ($start:expr, $($key:expr => $val:expr),*) => {{
         crate::metrics::global().filter_histogram.record(
@@ -310,6 +499,18 @@ macro_rules! filter_histogram_rec {
     }};
 }
 
+macro_rules! lua_handler_cpu_histogram_rec {
+    ($duration:expr, $attrs_map:expr) => {{
+        let attrs = $attrs_map
+            .iter()
+            .map(|(key, value)| ::opentelemetry::KeyValue::new(key.clone(), value.clone()))
+            .collect::<Vec<_>>();
+        crate::metrics::global()
+            .lua_handler_cpu_histogram
+            .record($duration.as_secs_f64(), &attrs);
+    }};
+}
+
 macro_rules! filter_error_counter_add {
     ($increment:expr, $($key:expr => $val:expr),*) => {{
         crate::metrics::global().filter_error_counter.add(
@@ -335,6 +536,64 @@ macro_rules! handler_error_counter_add {
     }};
 }
 
+macro_rules! storage_reconnect_counter_add {
+    ($increment:expr, $($key:expr => $val:expr),*) => {{
+        crate::metrics::global().storage_reconnect_counter.add(
+            $increment,
+            &[
+                $(::opentelemetry::KeyValue::new($key, $val),)*
+            ],
+        )
+    }};
+}
+
+macro_rules! auth_failure_counter_add {
+    ($increment:expr, $($key:expr => $val:expr),*) => {{
+        crate::metrics::global().auth_failure_counter.add(
+            $increment,
+            &[
+                $(::opentelemetry::KeyValue::new($key, $val),)*
+            ],
+        )
+    }};
+}
+
+macro_rules! rejected_upstreams_counter_add {
+    ($increment:expr) => {
+        rejected_upstreams_counter_add!($increment,)
+    };
+    ($increment:expr, $($key:expr => $val:expr),*) => {{
+        crate::metrics::global().rejected_upstreams_counter.add(
+            $increment,
+            &[
+                $(::opentelemetry::KeyValue::new($key, $val),)*
+            ],
+        )
+    }};
+}
+
+macro_rules! upstream_ttfb_histogram_rec {
+    ($start:expr, $($key:expr => $val:expr),*) => {{
+        crate::metrics::global().upstream_ttfb_histogram.record(
+            $start.elapsed().as_secs_f64(),
+            &[
+                $(::opentelemetry::KeyValue::new($key, $val),)*
+            ],
+        )
+    }};
+}
+
+macro_rules! upstream_warm_connections_add {
+    ($increment:expr, $($key:expr => $val:expr),*) => {{
+        crate::metrics::global().upstream_warm_connections_counter.add(
+            $increment,
+            &[
+                $(::opentelemetry::KeyValue::new($key, $val),)*
+            ],
+        )
+    }};
+}
+
 macro_rules! tasks_counter_inc {
     () => {
         crate::metrics::global().active_tasks_counter.inc()
@@ -392,6 +651,95 @@ macro_rules! lua_used_memory_update {
     }};
 }
 
+/// User-defined counter with an optional limit on the number of label sets
+#[derive(Clone, Debug)]
+pub struct UserCounter {
+    counter: Counter<u64>,
+    labels_limit: Option<Arc<LabelsLimit>>,
+}
+
+impl UserCounter {
+    pub fn add(&self, value: u64, attributes: Vec<KeyValue>) {
+        match &self.labels_limit {
+            Some(limit) => self.counter.add(value, &limit.apply(attributes)),
+            None => self.counter.add(value, &attributes),
+        }
+    }
+}
+
+/// Tracks distinct label sets of a metric and collapses ones above the limit
+/// into a single overflow label set (with all values replaced by `__overflow__`).
+///
+/// Label sets are tracked by hash in sharded sets, so already seen label sets
+/// only take a shared lock of one shard.
+#[derive(Debug)]
+pub struct LabelsLimit {
+    name: String,
+    max: usize,
+    count: AtomicUsize,
+    shards: [parking_lot::RwLock<HashSet<u64>>; LABELS_LIMIT_SHARDS],
+    warned: AtomicBool,
+}
+
+const LABELS_LIMIT_SHARDS: usize = 16;
+
+impl LabelsLimit {
+    pub fn new(name: &str, max: usize) -> Self {
+        LabelsLimit {
+            name: name.to_string(),
+            max,
+            count: AtomicUsize::new(0),
+            shards: Default::default(),
+            warned: AtomicBool::new(false),
+        }
+    }
+
+    pub fn apply(&self, attributes: Vec<KeyValue>) -> Vec<KeyValue> {
+        let hash = Self::labels_hash(&attributes);
+        let shard = &self.shards[hash as usize % LABELS_LIMIT_SHARDS];
+        if shard.read().contains(&hash) {
+            return attributes;
+        }
+
+        let mut seen = shard.write();
+        if seen.contains(&hash) || self.reserve() {
+            seen.insert(hash);
+            return attributes;
+        }
+        drop(seen);
+
+        if !self.warned.swap(true, Ordering::Relaxed) {
+            warn!(
+                "metric `{}` exceeded the limit of {} distinct label sets, extra ones are reported as `{OVERFLOW_LABEL_VALUE}`",
+                self.name, self.max
+            );
+        }
+        attributes
+            .into_iter()
+            .map(|kv| KeyValue::new(kv.key, OVERFLOW_LABEL_VALUE))
+            .collect()
+    }
+
+    /// Takes a slot for a new label set if the limit is not reached yet
+    fn reserve(&self) -> bool {
+        (self.count)
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| {
+                (n < self.max).then_some(n + 1)
+            })
+            .is_ok()
+    }
+
+    /// Order-independent hash of the label set
+    fn labels_hash(attributes: &[KeyValue]) -> u64 {
+        attributes.iter().fold(0u64, |acc, kv| {
+            let mut hasher = DefaultHasher::new();
+            kv.key.as_str().hash(&mut hasher);
+            kv.value.as_str().hash(&mut hasher);
+            acc.wrapping_add(hasher.finish())
+        })
+    }
+}
+
 #[derive(Debug, Default, Clone)]
 pub struct ActiveCounter(Arc<AtomicU64>);
 
@@ -407,6 +755,19 @@ impl ActiveCounter {
         self.0.load(Ordering::Relaxed)
     }
 
+    pub fn set(&self, v: u64) {
+        self.0.store(v, Ordering::Relaxed);
+    }
+
+    /// Adds (possibly negative) delta to the value, saturating at the bounds
+    pub fn add(&self, delta: i64) {
+        let _ = self
+            .0
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |v| {
+                Some(v.saturating_add_signed(delta))
+            });
+    }
+
     pub fn inc(&self) -> ActiveCounterGuard {
         self.0.fetch_add(1, Ordering::Relaxed);
         ActiveCounterGuard(Arc::clone(&self.0), 1)
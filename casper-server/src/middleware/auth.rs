@@ -0,0 +1,361 @@
+use std::rc::Rc;
+use std::time::SystemTime;
+
+use percent_encoding::percent_decode_str;
+
+use ntex::http::{RequestHead, Response, StatusCode};
+use ntex::service::{forward_ready, forward_shutdown, Middleware, Service, ServiceCtx};
+use ntex::web::{ErrorRenderer, WebRequest, WebResponse};
+
+use crate::config::{
+    ApiKeyAuthConfig, AuthConfig, AuthRouteConfig, CacheBypassConfig, HmacAuthConfig,
+};
+use crate::types::CacheBypassExt;
+use crate::utils::crypto::{constant_time_eq, hmac_sha256};
+
+/// `Auth` is a middleware to protect routes using API keys or HMAC-signed requests.
+///
+/// Requests without credentials are rejected with `401`, requests with invalid credentials with `403`.
+///
+/// Additionally, authenticated requests with the cache bypass header (if configured) are marked
+/// to bypass the cache. Unauthenticated ones are served as usual (the header is ignored).
+#[derive(Default, Debug)]
+pub struct Auth {
+    routes: Rc<Vec<AuthRouteConfig>>,
+    cache_bypass: Option<Rc<CacheBypassConfig>>,
+}
+
+impl Auth {
+    pub fn new(config: Option<AuthConfig>) -> Self {
+        let config = config.unwrap_or_default();
+        Auth {
+            routes: Rc::new(config.routes),
+            cache_bypass: config.cache_bypass.map(Rc::new),
+        }
+    }
+}
+
+impl<S> Middleware<S> for Auth {
+    type Service = AuthService<S>;
+
+    fn create(&self, service: S) -> Self::Service {
+        AuthService {
+            routes: self.routes.clone(),
+            cache_bypass: self.cache_bypass.clone(),
+            service,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct AuthService<S> {
+    routes: Rc<Vec<AuthRouteConfig>>,
+    cache_bypass: Option<Rc<CacheBypassConfig>>,
+    service: S,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum AuthError {
+    /// Credentials are not provided
+    Missing,
+    /// Credentials are provided but invalid
+    Invalid,
+}
+
+impl AuthError {
+    const fn status(self) -> StatusCode {
+        match self {
+            AuthError::Missing => StatusCode::UNAUTHORIZED,
+            AuthError::Invalid => StatusCode::FORBIDDEN,
+        }
+    }
+
+    const fn reason(self) -> &'static str {
+        match self {
+            AuthError::Missing => "missing",
+            AuthError::Invalid => "invalid",
+        }
+    }
+}
+
+/// Checks the request against all configured auth methods.
+///
+/// The request is authorized if any of the methods succeeds.
+fn authorize(
+    api_key: Option<&ApiKeyAuthConfig>,
+    hmac: Option<&HmacAuthConfig>,
+    req: &RequestHead,
+) -> Result<(), AuthError> {
+    let mut result = Err(AuthError::Missing);
+    let checks = [
+        api_key.map(|conf| check_api_key(conf, req)),
+        hmac.map(|conf| check_hmac(conf, req)),
+    ];
+    for check in checks.into_iter().flatten() {
+        match check {
+            Ok(()) => return Ok(()),
+            Err(AuthError::Invalid) => result = Err(AuthError::Invalid),
+            Err(AuthError::Missing) => {}
+        }
+    }
+    result
+}
+
+fn check_api_key(conf: &ApiKeyAuthConfig, req: &RequestHead) -> Result<(), AuthError> {
+    let from_header = conf
+        .header
+        .as_ref()
+        .and_then(|name| req.headers.get(name.as_str()))
+        .map(|value| value.as_bytes().to_vec());
+    let from_query = || {
+        let name = conf.query.as_ref()?;
+        form_urlencoded::parse(req.uri.query()?.as_bytes())
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v.into_owned().into_bytes())
+    };
+
+    let key = from_header.or_else(from_query).ok_or(AuthError::Missing)?;
+    // Compare with every key to not leak which one matched
+    let matched = conf.keys.iter().fold(false, |matched, expected| {
+        constant_time_eq(expected.as_bytes(), &key) | matched
+    });
+    matched.then_some(()).ok_or(AuthError::Invalid)
+}
+
+/// Validates HMAC-SHA256 signature of the string `{timestamp}\n{method}\n{path_and_query}`
+fn check_hmac(conf: &HmacAuthConfig, req: &RequestHead) -> Result<(), AuthError> {
+    let headers = &req.headers;
+    let (signature, timestamp) = match (
+        headers.get(conf.header.as_str()),
+        headers.get(conf.timestamp_header.as_str()),
+    ) {
+        (Some(signature), Some(timestamp)) => (signature, timestamp),
+        _ => return Err(AuthError::Missing),
+    };
+
+    let signature = hex::decode(signature.as_bytes()).map_err(|_| AuthError::Invalid)?;
+    let timestamp = timestamp
+        .to_str()
+        .ok()
+        .and_then(|ts| ts.parse::<u64>().ok())
+        .ok_or(AuthError::Invalid)?;
+    let now = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    if now.abs_diff(timestamp) > conf.max_skew {
+        return Err(AuthError::Invalid);
+    }
+
+    let path_and_query = req
+        .uri
+        .path_and_query()
+        .map(|pq| pq.as_str())
+        .unwrap_or("/");
+    let data = format!("{timestamp}\n{}\n{path_and_query}", req.method);
+    let expected =
+        hmac_sha256(conf.secret.as_bytes(), data.as_bytes()).map_err(|_| AuthError::Invalid)?;
+    if !constant_time_eq(&expected, &signature) {
+        return Err(AuthError::Invalid);
+    }
+    Ok(())
+}
+
+/// Normalizes a request path before matching it against protected routes.
+///
+/// The path is percent-decoded, empty and `.` segments are removed and `..` segments
+/// are resolved, so equivalent spellings of a path (e.g. `//admin`, `/%61dmin`, `/./admin`)
+/// are matched the same way.
+pub(crate) fn normalize_path(path: &str) -> String {
+    let path = percent_decode_str(path).decode_utf8_lossy();
+    let mut segments = Vec::new();
+    for segment in path.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                segments.pop();
+            }
+            segment => segments.push(segment),
+        }
+    }
+    format!("/{}", segments.join("/"))
+}
+
+/// Checks that the (normalized) `path` is the route `prefix` or nested under it.
+///
+/// The prefix is matched on segment boundaries: `/admin` covers `/admin/purge`
+/// but not `/administrator`.
+pub(crate) fn path_matches(path: &str, prefix: &str) -> bool {
+    let prefix = normalize_path(prefix);
+    let prefix = prefix.trim_end_matches('/');
+    prefix.is_empty()
+        || path == prefix
+        || path
+            .strip_prefix(prefix)
+            .is_some_and(|rest| rest.starts_with('/'))
+}
+
+impl<S, E> Service<WebRequest<E>> for AuthService<S>
+where
+    S: Service<WebRequest<E>, Response = WebResponse>,
+    E: ErrorRenderer,
+{
+    type Response = WebResponse;
+    type Error = S::Error;
+
+    forward_ready!(service);
+    forward_shutdown!(service);
+
+    async fn call(
+        &self,
+        req: WebRequest<E>,
+        ctx: ServiceCtx<'_, Self>,
+    ) -> Result<Self::Response, Self::Error> {
+        let path = normalize_path(req.uri().path());
+        let route = self.routes.iter().find(|r| path_matches(&path, &r.path));
+        if let Some(route) = route {
+            let result = authorize(route.api_key.as_ref(), route.hmac.as_ref(), req.head());
+            if let Err(err) = result {
+                auth_failure_counter_add!(1, "route" => route.path.clone(), "reason" => err.reason());
+                return Ok(req.into_response(Response::new(err.status())));
+            }
+        }
+
+        if let Some(conf) = &self.cache_bypass {
+            if req.headers().contains_key(conf.header.as_str()) {
+                match authorize(conf.api_key.as_ref(), conf.hmac.as_ref(), req.head()) {
+                    Ok(()) => {
+                        req.extensions_mut().insert(CacheBypassExt);
+                    }
+                    Err(err) => {
+                        auth_failure_counter_add!(1, "route" => "cache_bypass", "reason" => err.reason());
+                    }
+                }
+            }
+        }
+
+        ctx.call(&self.service, req).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::SystemTime;
+
+    use ntex::http::StatusCode;
+    use ntex::web::{self, test, App};
+
+    use super::{normalize_path, path_matches, Auth};
+    use crate::config::AuthConfig;
+    use crate::utils::crypto::hmac_sha256;
+
+    fn auth_config() -> AuthConfig {
+        serde_yaml::from_str(
+            r#"
+            routes:
+              - path: /admin
+                api_key:
+                  query: api_key
+                  keys: ["key1", "key2"]
+                hmac:
+                  secret: "hmac-secret"
+        "#,
+        )
+        .unwrap()
+    }
+
+    #[ntex::test]
+    async fn test_auth() {
+        let app = test::init_service(
+            App::new()
+                .wrap(Auth::new(Some(auth_config())))
+                .default_service(web::to(|| async { "ok" })),
+        )
+        .await;
+
+        let call = |req: test::TestRequest| {
+            let app = &app;
+            async move { test::call_service(app, req.to_request()).await.status() }
+        };
+
+        // Unprotected route
+        assert_eq!(
+            call(test::TestRequest::with_uri("/public")).await,
+            StatusCode::OK
+        );
+
+        // Missing credentials
+        let status = call(test::TestRequest::with_uri("/admin/purge")).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+        // API key in header and query
+        let req = test::TestRequest::with_uri("/admin/purge").header("X-Api-Key", "key2");
+        assert_eq!(call(req).await, StatusCode::OK);
+        let req = test::TestRequest::with_uri("/admin/purge?api_key=key1");
+        assert_eq!(call(req).await, StatusCode::OK);
+
+        // Invalid API key
+        let req = test::TestRequest::with_uri("/admin/purge").header("X-Api-Key", "key3");
+        assert_eq!(call(req).await, StatusCode::FORBIDDEN);
+        let req = test::TestRequest::with_uri("/admin/purge?api_key=key");
+        assert_eq!(call(req).await, StatusCode::FORBIDDEN);
+
+        // Equivalent spellings of the protected path
+        for uri in [
+            "/admin",
+            "//admin/purge",
+            "/%61dmin/purge",
+            "/./admin",
+            "/x/../admin",
+        ] {
+            let status = call(test::TestRequest::with_uri(uri)).await;
+            assert_eq!(status, StatusCode::UNAUTHORIZED, "{uri}");
+        }
+
+        // Prefix is matched on segment boundaries
+        let status = call(test::TestRequest::with_uri("/administrator")).await;
+        assert_eq!(status, StatusCode::OK);
+
+        // HMAC signature
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let sign = |ts: u64, path: &str| {
+            let data = format!("{ts}\nPOST\n{path}");
+            hex::encode(hmac_sha256(b"hmac-secret", data.as_bytes()).unwrap())
+        };
+        let req = test::TestRequest::post()
+            .uri("/admin/purge?key=abc")
+            .header("X-Timestamp", now.to_string())
+            .header("X-Signature", sign(now, "/admin/purge?key=abc"));
+        assert_eq!(call(req).await, StatusCode::OK);
+
+        // Signature for another path
+        let req = test::TestRequest::post()
+            .uri("/admin/purge?key=abc")
+            .header("X-Timestamp", now.to_string())
+            .header("X-Signature", sign(now, "/admin/purge"));
+        assert_eq!(call(req).await, StatusCode::FORBIDDEN);
+
+        // Expired timestamp
+        let ts = now - 3600;
+        let req = test::TestRequest::post()
+            .uri("/admin/purge")
+            .header("X-Timestamp", ts.to_string())
+            .header("X-Signature", sign(ts, "/admin/purge"));
+        assert_eq!(call(req).await, StatusCode::FORBIDDEN);
+    }
+
+    #[test]
+    fn test_path_matches() {
+        assert_eq!(normalize_path("//a/./b/%2e%2e/c/"), "/a/c");
+        assert_eq!(normalize_path("/../"), "/");
+
+        assert!(path_matches("/admin", "/admin"));
+        assert!(path_matches("/admin/purge", "/admin/"));
+        assert!(!path_matches("/administrator", "/admin"));
+        assert!(!path_matches("/", "/admin"));
+        assert!(path_matches("/anything", "/"));
+    }
+}
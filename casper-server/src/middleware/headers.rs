@@ -0,0 +1,145 @@
+use std::rc::Rc;
+
+use ntex::http::header::HeaderName;
+use ntex::service::{forward_ready, forward_shutdown, Middleware, Service, ServiceCtx};
+use ntex::web::{ErrorRenderer, WebRequest, WebResponse};
+
+#[derive(Debug)]
+enum Pattern {
+    Exact(String),
+    // Pattern with a trailing `*` matching any suffix
+    Prefix(String),
+}
+
+impl Pattern {
+    fn matches(&self, name: &HeaderName) -> bool {
+        // Header names are always lowercase
+        match self {
+            Pattern::Exact(exact) => name.as_str() == exact,
+            Pattern::Prefix(prefix) => name.as_str().starts_with(prefix.as_str()),
+        }
+    }
+}
+
+/// `ResponseHeaderDenylist` is a middleware to strip headers from client-facing responses.
+///
+/// Patterns are header names (case-insensitive), a trailing `*` matches any suffix
+/// (e.g. `X-Internal-*`). It must wrap all other middleware to see the final response.
+#[derive(Debug, Default)]
+pub struct ResponseHeaderDenylist {
+    patterns: Rc<[Pattern]>,
+}
+
+impl ResponseHeaderDenylist {
+    pub fn new(patterns: &[String]) -> Self {
+        let patterns = patterns
+            .iter()
+            .map(|pattern| {
+                let pattern = pattern.trim().to_ascii_lowercase();
+                match pattern.strip_suffix('*') {
+                    Some(prefix) => Pattern::Prefix(prefix.to_string()),
+                    None => Pattern::Exact(pattern),
+                }
+            })
+            .collect();
+        ResponseHeaderDenylist { patterns }
+    }
+}
+
+impl<S> Middleware<S> for ResponseHeaderDenylist {
+    type Service = ResponseHeaderDenylistService<S>;
+
+    fn create(&self, service: S) -> Self::Service {
+        ResponseHeaderDenylistService {
+            patterns: self.patterns.clone(),
+            service,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ResponseHeaderDenylistService<S> {
+    patterns: Rc<[Pattern]>,
+    service: S,
+}
+
+impl<S, E> Service<WebRequest<E>> for ResponseHeaderDenylistService<S>
+where
+    S: Service<WebRequest<E>, Response = WebResponse>,
+    E: ErrorRenderer,
+{
+    type Response = WebResponse;
+    type Error = S::Error;
+
+    forward_ready!(service);
+    forward_shutdown!(service);
+
+    async fn call(
+        &self,
+        req: WebRequest<E>,
+        ctx: ServiceCtx<'_, Self>,
+    ) -> Result<Self::Response, S::Error> {
+        let mut res = ctx.call(&self.service, req).await?;
+        if self.patterns.is_empty() {
+            return Ok(res);
+        }
+
+        let headers = res.headers_mut();
+        let denied = headers
+            .keys()
+            .filter(|name| self.patterns.iter().any(|p| p.matches(name)))
+            .cloned()
+            .collect::<Vec<_>>();
+        for name in denied {
+            headers.remove(name);
+        }
+        Ok(res)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ntex::http::StatusCode;
+    use ntex::web::{self, test, App, HttpResponse};
+
+    use super::ResponseHeaderDenylist;
+
+    #[ntex::test]
+    async fn test_response_header_denylist() {
+        let denylist = ["X-Internal-*", "server-timing"].map(|s| s.to_string());
+        let app = test::init_service(
+            App::new()
+                .wrap(ResponseHeaderDenylist::new(&denylist))
+                .service(web::resource("/").to(|| async {
+                    HttpResponse::Ok()
+                        .header("x-internal-debug", "1")
+                        .header("X-Internal-Trace", "abc")
+                        .header("x-internal", "kept")
+                        .header("server-timing", "app;dur=1")
+                        .header("x-request-id", "123")
+                        .finish()
+                }))
+                .default_service(web::to(|| async {
+                    HttpResponse::NotFound()
+                        .header("x-internal-error", "no route")
+                        .finish()
+                })),
+        )
+        .await;
+
+        let resp = test::call_service(&app, test::TestRequest::with_uri("/").to_request()).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let headers = resp.headers();
+        assert!(!headers.contains_key("x-internal-debug"));
+        assert!(!headers.contains_key("x-internal-trace"));
+        assert!(!headers.contains_key("server-timing"));
+        assert_eq!(headers.get("x-internal").unwrap(), "kept");
+        assert_eq!(headers.get("x-request-id").unwrap(), "123");
+
+        // Error responses are stripped too
+        let resp =
+            test::call_service(&app, test::TestRequest::with_uri("/missing").to_request()).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+        assert!(!resp.headers().contains_key("x-internal-error"));
+    }
+}
@@ -1,6 +1,11 @@
+use std::collections::BTreeMap;
 use std::error::Error;
 use std::fmt::Debug;
+use std::io::{self, Write};
+use std::sync::mpsc::{self, SyncSender, TrySendError};
+use std::sync::OnceLock;
 use std::task::{Context, Poll};
+use std::thread;
 use std::time::{Duration, Instant};
 
 use mlua::LuaSerdeExt;
@@ -8,19 +13,71 @@ use ntex::http::body::{Body, BodySize, MessageBody, ResponseBody};
 use ntex::service::{forward_ready, forward_shutdown, Middleware, Service, ServiceCtx};
 use ntex::util::Bytes;
 use ntex::web::{WebRequest, WebResponse};
+use opentelemetry::Value as OTValue;
 use serde::Serialize;
+use serde_json::Value as JsonValue;
 use tracing::error;
 
+use crate::config::LogFormat;
 use crate::context::AppContext;
 use crate::metrics;
-use crate::types::LuaContext;
+use crate::types::{LabelsExt, LuaContext};
 
-#[derive(Debug)]
-pub struct Logger;
+// Maximum number of log lines waiting to be written, new lines are dropped when it's full
+const MAX_QUEUED_LINES: usize = 10_000;
+
+/// Writer of log lines in a background thread (workers are never blocked by a slow output)
+#[derive(Clone)]
+struct LogWriter(SyncSender<Vec<u8>>);
+
+impl LogWriter {
+    fn new(mut writer: impl Write + Send + 'static) -> Self {
+        let (tx, rx) = mpsc::sync_channel::<Vec<u8>>(MAX_QUEUED_LINES);
+        thread::Builder::new()
+            .name("access-log".into())
+            .spawn(move || {
+                for line in rx {
+                    if let Err(err) = writer.write_all(&line) {
+                        error!("failed to write access log: {err}");
+                    }
+                }
+            })
+            .expect("failed to spawn access log writer thread");
+        LogWriter(tx)
+    }
+
+    /// Returns the process-wide stdout writer (shared between workers)
+    fn stdout() -> Self {
+        static STDOUT: OnceLock<LogWriter> = OnceLock::new();
+        STDOUT.get_or_init(|| LogWriter::new(io::stdout())).clone()
+    }
+
+    /// Queues the line to be written (or drops it if the queue is full)
+    fn write(&self, line: Vec<u8>) {
+        if let Err(TrySendError::Disconnected(_)) = self.0.try_send(line) {
+            error!("failed to write access log: writer thread is gone");
+        }
+    }
+}
+
+pub struct Logger {
+    format: LogFormat,
+    writer: Option<LogWriter>,
+}
 
 impl Logger {
-    pub fn new() -> Self {
-        Logger
+    pub fn new(format: LogFormat) -> Self {
+        Logger {
+            format,
+            writer: None,
+        }
+    }
+
+    /// Sets the destination of JSON log lines (stdout by default)
+    #[cfg(test)]
+    fn with_writer(mut self, writer: impl Write + Send + 'static) -> Self {
+        self.writer = Some(LogWriter::new(writer));
+        self
     }
 }
 
@@ -28,7 +85,12 @@ impl<S> Middleware<S> for Logger {
     type Service = LoggerMiddleware<S>;
 
     fn create(&self, service: S) -> Self::Service {
-        LoggerMiddleware { service }
+        let json_writer = (self.format == LogFormat::Json)
+            .then(|| self.writer.clone().unwrap_or_else(LogWriter::stdout));
+        LoggerMiddleware {
+            service,
+            json_writer,
+        }
     }
 }
 
@@ -36,6 +98,8 @@ impl<S> Middleware<S> for Logger {
 struct LogData {
     // TODO: start time
     uri: String,
+    #[serde(skip)]
+    path: String,
     method: String,
     remote_addr: Option<String>,
     elapsed: Duration,
@@ -44,11 +108,66 @@ struct LogData {
     active_requests: u64,
     response_size: u64,
     error: Option<bool>,
+    #[serde(skip)]
+    labels: Option<LabelsExt>,
+}
+
+/// Access log line in JSON format
+#[derive(Serialize)]
+struct JsonLogLine<'a> {
+    method: &'a str,
+    uri: &'a str,
+    path: &'a str,
+    status: u16,
+    duration_ms: f64,
+    bytes: u64,
+    remote_addr: Option<&'a str>,
+    active_conns: u64,
+    active_requests: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<bool>,
+    labels: BTreeMap<&'a str, JsonValue>,
+}
+
+impl LogData {
+    fn to_json_line(&self) -> serde_json::Result<Vec<u8>> {
+        let labels = self
+            .labels
+            .iter()
+            .flat_map(|labels| &labels.0)
+            .map(|(key, value)| {
+                let value = match value {
+                    OTValue::Bool(b) => JsonValue::from(*b),
+                    OTValue::I64(i) => JsonValue::from(*i),
+                    OTValue::F64(n) => JsonValue::from(*n),
+                    value => JsonValue::from(value.as_str()),
+                };
+                (key.as_str(), value)
+            })
+            .collect();
+        let line = JsonLogLine {
+            method: &self.method,
+            uri: &self.uri,
+            path: &self.path,
+            status: self.status,
+            duration_ms: self.elapsed.as_secs_f64() * 1000.0,
+            bytes: self.response_size,
+            remote_addr: self.remote_addr.as_deref(),
+            active_conns: self.active_conns,
+            active_requests: self.active_requests,
+            error: self.error,
+            labels,
+        };
+        let mut buf = serde_json::to_vec(&line)?;
+        buf.push(b'\n');
+        Ok(buf)
+    }
 }
 
 /// Logger middleware
 pub struct LoggerMiddleware<S> {
     service: S,
+    json_writer: Option<LogWriter>,
 }
 
 impl LoggerMiddleware<()> {
@@ -91,8 +210,10 @@ where
         let start = Instant::now();
 
         let app_context: AppContext = req.app_state::<AppContext>().unwrap().clone();
-        let mut log_data = app_context.access_log.as_ref().map(|_| LogData {
+        let need_log = app_context.access_log.is_some() || self.json_writer.is_some();
+        let mut log_data = need_log.then(|| LogData {
             uri: req.uri().to_string(),
+            path: req.path().to_string(),
             method: req.method().to_string(),
             remote_addr: req.peer_addr().map(|addr| addr.to_string()),
             ..Default::default()
@@ -110,8 +231,10 @@ where
             log_data.status = res.status().as_u16();
 
             lua_context = res.response().extensions().get::<LuaContext>().cloned();
+            log_data.labels = res.response().extensions().get::<LabelsExt>().cloned();
         }
 
+        let json_writer = self.json_writer.clone();
         Ok(res.map_body(move |_, body| {
             ResponseBody::Other(Body::from_message(StreamLog {
                 body,
@@ -119,6 +242,7 @@ where
                 app_context,
                 lua_context,
                 log_data,
+                json_writer,
             }))
         }))
     }
@@ -131,14 +255,28 @@ pub struct StreamLog {
     app_context: AppContext,
     lua_context: Option<LuaContext>,
     log_data: Option<LogData>,
+    json_writer: Option<LogWriter>,
 }
 
 // This is where we execute log action, after streaming body
 impl Drop for StreamLog {
     fn drop(&mut self) {
-        if let (Some(mut log_data), Some(lua_ctx)) = (self.log_data.take(), self.lua_context.take())
-        {
-            log_data.response_size = self.body_size;
+        let Some(mut log_data) = self.log_data.take() else {
+            return;
+        };
+        log_data.response_size = self.body_size;
+
+        if let Some(writer) = &self.json_writer {
+            match log_data.to_json_line() {
+                Ok(line) => writer.write(line),
+                Err(err) => error!("failed to write access log: {err}"),
+            }
+        }
+
+        if let (Some(lua_ctx), true) = (
+            self.lua_context.take(),
+            self.app_context.access_log.is_some(),
+        ) {
             LoggerMiddleware::spawn_access_log(self.app_context.clone(), log_data, lua_ctx)
         }
     }
@@ -169,3 +307,84 @@ impl MessageBody for StreamLog {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use ntex::web::{self, test, App};
+
+    use super::*;
+    use crate::config::Config;
+    use crate::handler::handler;
+
+    #[derive(Clone, Default)]
+    struct SharedBuf(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuf {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[ntex::test]
+    async fn test_json_access_log() {
+        let config: Config = serde_yaml::from_str(
+            r#"
+            http:
+              filters: []
+              access_log_format: json
+              handler:
+                code: |
+                  local core = require("core")
+                  return function(req)
+                    local resp = core.Response.new({ status = 201, body = "hello" })
+                    resp:set_label("route", "test")
+                    return resp
+                  end
+        "#,
+        )
+        .unwrap();
+        let format = config.http.access_log_format;
+        let context = AppContext::builder()
+            .with_config(Arc::new(config))
+            .build()
+            .unwrap();
+
+        let buf = SharedBuf::default();
+        let app = test::init_service(
+            App::new()
+                .state(context)
+                .wrap(Logger::new(format).with_writer(buf.clone()))
+                .default_service(web::to(handler)),
+        )
+        .await;
+
+        let req = test::TestRequest::with_uri("/hello?a=b").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(test::read_body(resp).await, "hello");
+
+        // The line is written in background
+        let start = Instant::now();
+        while buf.0.lock().unwrap().is_empty() && start.elapsed() < Duration::from_secs(1) {
+            ntex::time::sleep(Duration::from_millis(5)).await;
+        }
+        let output = String::from_utf8(buf.0.lock().unwrap().clone()).unwrap();
+        assert_eq!(output.lines().count(), 1);
+        let line: serde_json::Value = serde_json::from_str(&output).unwrap();
+        assert_eq!(line["method"], "GET");
+        assert_eq!(line["uri"], "/hello?a=b");
+        assert_eq!(line["path"], "/hello");
+        assert!(line["active_requests"].is_u64());
+        assert!(line["active_conns"].is_u64());
+        assert_eq!(line["status"], 201);
+        assert_eq!(line["bytes"], 5);
+        assert!(line["duration_ms"].as_f64().unwrap() >= 0.0);
+        assert!(line.get("remote_addr").is_some());
+        assert_eq!(line["labels"]["route"], "test");
+    }
+}
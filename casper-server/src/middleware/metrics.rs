@@ -1,14 +1,19 @@
 use std::rc::Rc;
 
-use ntex::http::header::{HeaderValue, CONTENT_TYPE};
+use std::fmt::Write as _;
+
+use ntex::http::header::{HeaderValue, ACCEPT, CONTENT_TYPE};
 use ntex::http::Response;
 use ntex::service::{forward_ready, forward_shutdown, Middleware, Service, ServiceCtx};
 use ntex::web::{ErrorRenderer, WebRequest, WebResponse};
 
+use prometheus::proto::{LabelPair, MetricFamily, MetricType};
 use prometheus::{Encoder, TextEncoder, TEXT_FORMAT};
 
 use crate::metrics;
 
+const OPENMETRICS_FORMAT: &str = "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
 #[derive(Debug, Clone)]
 pub struct Metrics {
     endpoint: Rc<String>,
@@ -41,6 +46,9 @@ pub struct MetricsService<S> {
 
 impl<S> MetricsService<S> {
     async fn metrics_handler<E>(request: WebRequest<E>) -> WebResponse {
+        let openmetrics = (request.headers().get(ACCEPT))
+            .and_then(|accept| accept.to_str().ok())
+            .is_some_and(prefers_openmetrics);
         let data = tokio::task::spawn_blocking(move || {
             let mut buffer = Vec::<u8>::with_capacity(16384);
             let mut metric_families = prometheus::default_registry().gather();
@@ -57,6 +65,9 @@ impl<S> MetricsService<S> {
                 }
             }
 
+            if openmetrics {
+                return encode_openmetrics(&metric_families).into_bytes();
+            }
             TextEncoder::new()
                 .encode(&metric_families, &mut buffer)
                 .expect("Failed to encode metrics");
@@ -65,8 +76,12 @@ impl<S> MetricsService<S> {
         .await
         .expect("Failed to render metrics");
 
+        let content_type = match openmetrics {
+            true => OPENMETRICS_FORMAT,
+            false => TEXT_FORMAT,
+        };
         let response = Response::Ok()
-            .header(CONTENT_TYPE, HeaderValue::from_static(TEXT_FORMAT))
+            .header(CONTENT_TYPE, HeaderValue::from_static(content_type))
             .body(data);
 
         request.into_response(response)
@@ -97,3 +112,223 @@ where
         ctx.call(&self.inner, req).await
     }
 }
+
+/// Checks if the `Accept` header value prefers the OpenMetrics format over the Prometheus text one
+fn prefers_openmetrics(accept: &str) -> bool {
+    let (mut openmetrics_q, mut text_q) = (0.0f32, 0.0f32);
+    for item in accept.split(',') {
+        let mut parts = item.split(';');
+        let media_type = parts.next().unwrap_or_default().trim().to_ascii_lowercase();
+        let q = parts
+            .filter_map(|p| p.trim().strip_prefix("q="))
+            .find_map(|q| q.trim().parse::<f32>().ok())
+            .unwrap_or(1.0);
+        match media_type.as_str() {
+            "application/openmetrics-text" => openmetrics_q = openmetrics_q.max(q),
+            "text/plain" | "text/*" | "*/*" => text_q = text_q.max(q),
+            _ => {}
+        }
+    }
+    openmetrics_q > 0.0 && openmetrics_q >= text_q
+}
+
+/// Encodes metric families in the OpenMetrics text format
+fn encode_openmetrics(families: &[MetricFamily]) -> String {
+    let mut output = String::with_capacity(16384);
+    for family in families {
+        let name = family.get_name();
+        let (family_name, metric_type) = match family.get_field_type() {
+            // Counter samples must have the `_total` suffix (that the family name lacks)
+            MetricType::COUNTER => (name.strip_suffix("_total").unwrap_or(name), "counter"),
+            MetricType::GAUGE => (name, "gauge"),
+            MetricType::HISTOGRAM => (name, "histogram"),
+            MetricType::SUMMARY => (name, "summary"),
+            MetricType::UNTYPED => (name, "unknown"),
+        };
+        if !family.get_help().is_empty() {
+            let help = family.get_help().replace('\\', r"\\").replace('\n', r"\n");
+            _ = writeln!(output, "# HELP {family_name} {help}");
+        }
+        _ = writeln!(output, "# TYPE {family_name} {metric_type}");
+
+        for metric in family.get_metric() {
+            let labels = metric.get_label();
+            let mut sample = |suffix: &str, extra: Option<(&str, String)>, value: String| {
+                output.push_str(family_name);
+                output.push_str(suffix);
+                write_labels(&mut output, labels, extra);
+                output.push(' ');
+                output.push_str(&value);
+                if metric.get_timestamp_ms() != 0 {
+                    let timestamp = metric.get_timestamp_ms() as f64 / 1000.0;
+                    _ = write!(output, " {timestamp}");
+                }
+                output.push('\n');
+            };
+            match family.get_field_type() {
+                MetricType::COUNTER => sample(
+                    "_total",
+                    None,
+                    format_float(metric.get_counter().get_value()),
+                ),
+                MetricType::GAUGE => sample("", None, format_float(metric.get_gauge().get_value())),
+                MetricType::UNTYPED => {
+                    sample("", None, format_float(metric.get_untyped().get_value()))
+                }
+                MetricType::HISTOGRAM => {
+                    let histogram = metric.get_histogram();
+                    let mut has_inf = false;
+                    for bucket in histogram.get_bucket() {
+                        let le = format_float(bucket.get_upper_bound());
+                        has_inf |= bucket.get_upper_bound() == f64::INFINITY;
+                        let count = bucket.get_cumulative_count().to_string();
+                        sample("_bucket", Some(("le", le)), count);
+                    }
+                    let count = histogram.get_sample_count().to_string();
+                    if !has_inf {
+                        sample("_bucket", Some(("le", "+Inf".to_string())), count.clone());
+                    }
+                    sample("_count", None, count);
+                    sample("_sum", None, format_float(histogram.get_sample_sum()));
+                }
+                MetricType::SUMMARY => {
+                    let summary = metric.get_summary();
+                    for quantile in summary.get_quantile() {
+                        let q = format_float(quantile.get_quantile());
+                        sample(
+                            "",
+                            Some(("quantile", q)),
+                            format_float(quantile.get_value()),
+                        );
+                    }
+                    sample("_count", None, summary.get_sample_count().to_string());
+                    sample("_sum", None, format_float(summary.get_sample_sum()));
+                }
+            }
+        }
+    }
+    output.push_str("# EOF\n");
+    output
+}
+
+fn write_labels(output: &mut String, labels: &[LabelPair], extra: Option<(&str, String)>) {
+    let extra = extra.iter().map(|(name, value)| (*name, value.as_str()));
+    let mut labels = labels
+        .iter()
+        .map(|l| (l.get_name(), l.get_value()))
+        .chain(extra)
+        .peekable();
+    if labels.peek().is_none() {
+        return;
+    }
+    output.push('{');
+    for (i, (name, value)) in labels.enumerate() {
+        if i > 0 {
+            output.push(',');
+        }
+        let value = value
+            .replace('\\', r"\\")
+            .replace('"', r#"\""#)
+            .replace('\n', r"\n");
+        _ = write!(output, "{name}=\"{value}\"");
+    }
+    output.push('}');
+}
+
+/// Formats a float value in the canonical OpenMetrics representation
+fn format_float(value: f64) -> String {
+    match value {
+        f64::INFINITY => "+Inf".to_string(),
+        f64::NEG_INFINITY => "-Inf".to_string(),
+        _ if value.is_nan() => "NaN".to_string(),
+        _ => format!("{value:?}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ntex::http::header::{ACCEPT, CONTENT_TYPE};
+    use ntex::http::StatusCode;
+    use ntex::web::{self, test, App, HttpResponse};
+
+    use super::*;
+
+    #[test]
+    fn test_prefers_openmetrics() {
+        assert!(prefers_openmetrics("application/openmetrics-text"));
+        assert!(prefers_openmetrics(
+            "application/openmetrics-text;version=1.0.0;q=0.5,text/plain;version=0.0.4;q=0.3,*/*;q=0.2"
+        ));
+        assert!(!prefers_openmetrics("text/plain;version=0.0.4"));
+        assert!(!prefers_openmetrics("*/*"));
+        assert!(!prefers_openmetrics(
+            "application/openmetrics-text;q=0.2,text/plain"
+        ));
+        assert!(!prefers_openmetrics("application/openmetrics-text;q=0"));
+    }
+
+    #[ntex::test]
+    async fn test_metrics_content_negotiation() {
+        let counter = prometheus::register_int_counter_vec!(
+            "om_test_requests_total",
+            "Test \"requests\"",
+            &["path"]
+        )
+        .unwrap();
+        counter.with_label_values(&["/a\"b"]).inc_by(3);
+        let histogram = prometheus::register_histogram!(
+            "om_test_duration_seconds",
+            "Test duration",
+            vec![0.5, 1.0]
+        )
+        .unwrap();
+        histogram.observe(0.7);
+
+        let app = test::init_service(
+            App::new()
+                .wrap(Metrics::new("/metrics".to_string()))
+                .default_service(web::to(|| async { HttpResponse::Ok().finish() })),
+        )
+        .await;
+
+        // Default (Prometheus text) format
+        let req = test::TestRequest::with_uri("/metrics")
+            .header(ACCEPT, "text/plain")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.headers().get(CONTENT_TYPE).unwrap(), TEXT_FORMAT);
+        let body = String::from_utf8(test::read_body(resp).await.to_vec()).unwrap();
+        assert!(body.contains("# TYPE om_test_requests_total counter\n"));
+        assert!(!body.contains("# EOF"));
+
+        // OpenMetrics format
+        let req = test::TestRequest::with_uri("/metrics")
+            .header(
+                ACCEPT,
+                "application/openmetrics-text;version=1.0.0,text/plain;q=0.5",
+            )
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(
+            resp.headers().get(CONTENT_TYPE).unwrap(),
+            OPENMETRICS_FORMAT
+        );
+        let body = String::from_utf8(test::read_body(resp).await.to_vec()).unwrap();
+        assert!(body.contains(
+            "# HELP om_test_requests Test \"requests\"\n\
+             # TYPE om_test_requests counter\n\
+             om_test_requests_total{path=\"/a\\\"b\"} 3.0\n"
+        ));
+        assert!(body.contains(
+            "# TYPE om_test_duration_seconds histogram\n\
+             om_test_duration_seconds_bucket{le=\"0.5\"} 0\n\
+             om_test_duration_seconds_bucket{le=\"1.0\"} 1\n\
+             om_test_duration_seconds_bucket{le=\"+Inf\"} 1\n\
+             om_test_duration_seconds_count 1\n\
+             om_test_duration_seconds_sum 0.7\n"
+        ));
+        assert!(body.ends_with("# EOF\n"));
+    }
+}
@@ -1,7 +1,11 @@
+pub use auth::Auth;
+pub use headers::ResponseHeaderDenylist;
 pub use logger::Logger;
 pub use metrics::Metrics;
 pub use trace::RequestTracing;
 
+pub(crate) mod auth;
+mod headers;
 mod logger;
 mod metrics;
 mod trace;
@@ -0,0 +1,150 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use anyhow::{bail, Result};
+use parking_lot::RwLock;
+
+/// Immutable set of routing rules (path prefix -> target)
+#[derive(Debug, Default)]
+pub struct Routes {
+    // Sorted by prefix length (longest first)
+    entries: Vec<(String, String)>,
+}
+
+impl Routes {
+    pub fn new(routes: HashMap<String, String>) -> Result<Self> {
+        let mut entries = Vec::with_capacity(routes.len());
+        for (prefix, target) in routes {
+            if !prefix.starts_with('/') {
+                bail!("route path `{prefix}` must start with `/`");
+            }
+            entries.push((prefix, target));
+        }
+        entries.sort_by(|(a, _), (b, _)| b.len().cmp(&a.len()).then_with(|| a.cmp(b)));
+        Ok(Routes { entries })
+    }
+
+    /// Finds the route with the longest prefix matching the path (on a segment boundary).
+    ///
+    /// Returns the matched prefix and the route target.
+    pub fn lookup(&self, path: &str) -> Option<(&str, &str)> {
+        self.entries
+            .iter()
+            .find(|(prefix, _)| match path.strip_prefix(prefix.as_str()) {
+                Some(rest) => rest.is_empty() || rest.starts_with('/') || prefix.ends_with('/'),
+                None => false,
+            })
+            .map(|(prefix, target)| (prefix.as_str(), target.as_str()))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.entries.iter().map(|(p, t)| (p.as_str(), t.as_str()))
+    }
+}
+
+/// Routing table shared between workers.
+///
+/// Routes can be replaced at runtime, readers always see either the old or the new set.
+#[derive(Clone, Debug, Default)]
+pub struct RoutingTable(Arc<RwLock<Arc<Routes>>>);
+
+impl RoutingTable {
+    pub fn new(routes: HashMap<String, String>) -> Result<Self> {
+        Ok(RoutingTable(Arc::new(RwLock::new(Arc::new(Routes::new(
+            routes,
+        )?)))))
+    }
+
+    /// Returns the current set of routes
+    pub fn snapshot(&self) -> Arc<Routes> {
+        self.0.read().clone()
+    }
+
+    /// Atomically replaces all the routes
+    pub fn replace(&self, routes: HashMap<String, String>) -> Result<()> {
+        let routes = Arc::new(Routes::new(routes)?);
+        *self.0.write() = routes;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    fn lookup(table: &RoutingTable, path: &str) -> Option<String> {
+        let routes = table.snapshot();
+        routes.lookup(path).map(|(_, target)| target.to_string())
+    }
+
+    fn routes(items: &[(&str, &str)]) -> HashMap<String, String> {
+        items
+            .iter()
+            .map(|(p, t)| (p.to_string(), t.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_lookup() {
+        let table = RoutingTable::new(routes(&[
+            ("/", "default"),
+            ("/api", "api"),
+            ("/api/v2", "api_v2"),
+            ("/static/", "static"),
+        ]))
+        .unwrap();
+
+        assert_eq!(lookup(&table, "/api").as_deref(), Some("api"));
+        assert_eq!(lookup(&table, "/api/users").as_deref(), Some("api"));
+        assert_eq!(lookup(&table, "/api/v2/users").as_deref(), Some("api_v2"));
+        assert_eq!(lookup(&table, "/api/v20").as_deref(), Some("api"));
+        assert_eq!(lookup(&table, "/apix").as_deref(), Some("default"));
+        assert_eq!(lookup(&table, "/static/a.css").as_deref(), Some("static"));
+
+        table.replace(routes(&[("/api", "api")])).unwrap();
+        assert_eq!(lookup(&table, "/other"), None);
+
+        // Invalid routes are rejected and the table is unchanged
+        assert!(table.replace(routes(&[("api", "api")])).is_err());
+        assert_eq!(lookup(&table, "/api").as_deref(), Some("api"));
+    }
+
+    #[test]
+    fn test_concurrent_replace() {
+        let make_routes = |version: usize| {
+            let target = format!("v{version}");
+            routes(&[("/a", &target), ("/b", &target), ("/c", &target)])
+        };
+        let table = RoutingTable::new(make_routes(0)).unwrap();
+
+        let readers = (0..4)
+            .map(|_| {
+                let table = table.clone();
+                thread::spawn(move || {
+                    let mut last_version = 0;
+                    for _ in 0..10000 {
+                        // All routes in a snapshot belong to the same version
+                        let routes = table.snapshot();
+                        let targets = routes.iter().map(|(_, t)| t).collect::<Vec<_>>();
+                        assert_eq!(targets.len(), 3);
+                        assert!(targets.iter().all(|t| *t == targets[0]));
+                        // Versions never go back
+                        let version = targets[0][1..].parse::<usize>().unwrap();
+                        assert!(version >= last_version);
+                        last_version = version;
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+
+        for version in 1..=1000 {
+            table.replace(make_routes(version)).unwrap();
+        }
+        for reader in readers {
+            reader.join().unwrap();
+        }
+        assert_eq!(lookup(&table, "/a").as_deref(), Some("v1000"));
+    }
+}
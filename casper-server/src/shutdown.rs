@@ -0,0 +1,134 @@
+use std::io;
+use std::time::Duration;
+
+use ntex::server::Server;
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::time::{self, Instant};
+use tracing::{info, warn};
+
+/// Interval between checks of the number of in-flight requests
+const DRAIN_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Waits for `SIGTERM`, `SIGINT` or `SIGQUIT` signal
+///
+/// Returns `true` if the server should be stopped gracefully (not on `SIGQUIT`).
+async fn wait_for_signal() -> io::Result<bool> {
+    let mut sigterm = signal(SignalKind::terminate())?;
+    let mut sigint = signal(SignalKind::interrupt())?;
+    let mut sigquit = signal(SignalKind::quit())?;
+    tokio::select! {
+        _ = sigterm.recv() => info!("received SIGTERM, shutting down"),
+        _ = sigint.recv() => info!("received SIGINT, shutting down"),
+        _ = sigquit.recv() => {
+            info!("received SIGQUIT, shutting down immediately");
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// Waits until all in-flight requests complete or the timeout elapses.
+///
+/// Returns `true` if there are no more active requests.
+pub async fn drain_requests(timeout: Duration) -> bool {
+    let deadline = Instant::now() + timeout;
+    loop {
+        let active = crate::metrics::global().active_requests_counter.get();
+        if active == 0 {
+            return true;
+        }
+        if Instant::now() >= deadline {
+            warn!("shutdown timeout elapsed with {active} request(s) in flight");
+            return false;
+        }
+        time::sleep(DRAIN_POLL_INTERVAL).await;
+    }
+}
+
+/// Gracefully stops the server on `SIGTERM` or `SIGINT` (or immediately on `SIGQUIT`).
+pub async fn stop_on_signal(server: Server, timeout: Duration) {
+    match wait_for_signal().await {
+        Ok(true) => graceful_stop(server, timeout).await,
+        Ok(false) => server.stop(false).await,
+        Err(err) => warn!("failed to install signal handlers: {err}"),
+    }
+}
+
+/// Stops accepting new connections and gives in-flight requests (including sending their
+/// streamed bodies) up to `timeout` to complete before stopping the server.
+async fn graceful_stop(server: Server, timeout: Duration) {
+    server.pause().await;
+    drain_requests(timeout).await;
+    // Requests are already drained (or the timeout elapsed), so do not wait for them again
+    server.stop(false).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+    use std::time::Duration;
+
+    use ntex::http::{client::Client, HttpService};
+    use ntex::server::Server;
+    use ntex::web::{self, App};
+    use tokio::time::Instant;
+
+    use super::{drain_requests, graceful_stop};
+
+    #[ntex::test]
+    async fn test_drain_requests() {
+        // In-flight request completes during draining
+        let guard = active_request_guard!();
+        ntex::rt::spawn(async move {
+            ntex::time::sleep(Duration::from_millis(200)).await;
+            drop(guard);
+        });
+        let start = Instant::now();
+        assert!(drain_requests(Duration::from_secs(5)).await);
+        assert!(start.elapsed() >= Duration::from_millis(200));
+
+        // In-flight request is held open longer than the timeout
+        let _guard = active_request_guard!();
+        let start = Instant::now();
+        assert!(!drain_requests(Duration::from_millis(100)).await);
+        assert!(start.elapsed() >= Duration::from_millis(100));
+    }
+
+    #[ntex::test]
+    async fn test_graceful_stop() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = Server::build()
+            .listen("test", listener, |_| {
+                let handler = web::to(|| async {
+                    let _guard = active_request_guard!();
+                    ntex::time::sleep(Duration::from_millis(300)).await;
+                    "ok"
+                });
+                HttpService::build().finish(App::new().default_service(handler))
+            })
+            .unwrap()
+            .workers(1)
+            .disable_signals()
+            .run();
+
+        // Start a request and stop the server while it's in flight
+        let request = ntex::rt::spawn(async move {
+            let mut resp = (Client::new().get(format!("http://{addr}/")))
+                .send()
+                .await
+                .unwrap();
+            resp.body().await.unwrap()
+        });
+        let start = Instant::now();
+        while crate::metrics::global().active_requests_counter.get() == 0 {
+            assert!(start.elapsed() < Duration::from_secs(5));
+            ntex::time::sleep(Duration::from_millis(10)).await;
+        }
+        graceful_stop(server, Duration::from_secs(5)).await;
+
+        // The request completed before the server stopped (without waiting for the timeout)
+        assert_eq!(request.await.unwrap(), "ok");
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+}
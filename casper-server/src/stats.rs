@@ -0,0 +1,159 @@
+use std::time::{Duration, Instant};
+
+use prometheus::proto::{Metric, MetricFamily};
+use tracing::info;
+
+use crate::config::Config;
+
+/// Aggregated values read from the metrics registry
+#[derive(Clone, Copy, Debug, Default)]
+struct Totals {
+    requests: f64,
+    storage_requests: f64,
+    storage_gets: f64,
+    storage_hits: f64,
+    storage_errors: f64,
+    active_connections: f64,
+    lua_used_memory: f64,
+}
+
+impl Totals {
+    /// Collects current values from the default prometheus registry
+    fn collect() -> Self {
+        let mut totals = Totals::default();
+        for family in prometheus::default_registry().gather() {
+            match family.get_name() {
+                "http_requests_total" => totals.requests = sum_values(&family, |_| true),
+                "storage_requests_total" => {
+                    totals.storage_requests = sum_values(&family, |_| true);
+                    totals.storage_gets = sum_values(&family, |m| has_label(m, "operation", "get"));
+                }
+                "storage_results_total" => {
+                    totals.storage_hits = sum_values(&family, |m| has_label(m, "status", "hit"));
+                    totals.storage_errors =
+                        sum_values(&family, |m| has_label(m, "status", "error"));
+                }
+                "http_connections_current" => {
+                    totals.active_connections = sum_values(&family, |_| true)
+                }
+                "lua_used_memory_bytes" => totals.lua_used_memory = sum_values(&family, |_| true),
+                _ => {}
+            }
+        }
+        totals
+    }
+}
+
+fn sum_values(family: &MetricFamily, filter: impl Fn(&Metric) -> bool) -> f64 {
+    family
+        .get_metric()
+        .iter()
+        .filter(|m| filter(m))
+        .map(|m| m.get_counter().get_value() + m.get_gauge().get_value())
+        .sum()
+}
+
+fn has_label(metric: &Metric, name: &str, value: &str) -> bool {
+    metric
+        .get_label()
+        .iter()
+        .any(|l| l.get_name() == name && l.get_value() == value)
+}
+
+/// Formats a stats line from the difference between two snapshots
+fn format_stats(prev: &Totals, curr: &Totals, elapsed: Duration) -> String {
+    let ratio = |a: f64, b: f64| if b > 0.0 { a / b } else { 0.0 };
+    format!(
+        "requests_per_sec={:.2} hit_ratio={:.3} active_connections={} lua_used_memory={} storage_error_rate={:.3}",
+        ratio(curr.requests - prev.requests, elapsed.as_secs_f64()),
+        ratio(
+            curr.storage_hits - prev.storage_hits,
+            curr.storage_gets - prev.storage_gets
+        ),
+        curr.active_connections as u64,
+        curr.lua_used_memory as u64,
+        ratio(
+            curr.storage_errors - prev.storage_errors,
+            curr.storage_requests - prev.storage_requests
+        ),
+    )
+}
+
+async fn run_reporter(interval: Duration, mut emit: impl FnMut(String)) {
+    let mut prev = Totals::collect();
+    let mut prev_time = Instant::now();
+    loop {
+        tokio::time::sleep(interval).await;
+        let curr = Totals::collect();
+        emit(format_stats(&prev, &curr, prev_time.elapsed()));
+        prev = curr;
+        prev_time = Instant::now();
+    }
+}
+
+/// Starts a background task to periodically log aggregated stats (if enabled)
+pub fn init(config: &Config) {
+    let reporter_conf = config
+        .metrics
+        .as_ref()
+        .and_then(|conf| conf.stats_reporter.as_ref());
+    let interval = match reporter_conf {
+        Some(conf) if conf.enabled && conf.interval > 0.0 => Duration::from_secs_f64(conf.interval),
+        _ => return,
+    };
+
+    ntex::rt::spawn(run_reporter(interval, |line| {
+        info!(target: "casper::stats", "{line}");
+    }));
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn test_format_stats() {
+        let prev = Totals::default();
+        let curr = Totals {
+            requests: 20.0,
+            storage_requests: 10.0,
+            storage_gets: 8.0,
+            storage_hits: 6.0,
+            storage_errors: 1.0,
+            active_connections: 3.0,
+            lua_used_memory: 1024.0,
+        };
+        let line = format_stats(&prev, &curr, Duration::from_secs(10));
+        assert_eq!(
+            line,
+            "requests_per_sec=2.00 hit_ratio=0.750 active_connections=3 lua_used_memory=1024 storage_error_rate=0.100"
+        );
+    }
+
+    #[ntex::test]
+    async fn test_reporter() {
+        // Make sure metrics are registered
+        crate::metrics::global();
+
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        ntex::rt::spawn(run_reporter(Duration::from_millis(10), move |line| {
+            _ = tx.send(line);
+        }));
+
+        let line = tokio::time::timeout(Duration::from_secs(1), rx.recv())
+            .await
+            .expect("reporter did not emit stats in time")
+            .unwrap();
+        for field in [
+            "requests_per_sec=",
+            "hit_ratio=",
+            "active_connections=",
+            "lua_used_memory=",
+            "storage_error_rate=",
+        ] {
+            assert!(line.contains(field), "`{field}` is missing in `{line}`");
+        }
+    }
+}
@@ -1,32 +1,163 @@
 use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::num::NonZeroUsize;
+#[cfg(test)]
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
-use std::time::SystemTime;
+use std::time::{Duration, SystemTime};
 
+use anyhow::{anyhow, bail};
 use linked_hash_map::LinkedHashMap;
-use ntex::http::body::Body;
-use ntex::http::{Response, StatusCode};
+use ntex::http::body::{Body, MessageBody};
+use ntex::http::{Response, StatusCode, Version};
 use ntex::util::Bytes;
+use rand::Rng;
 use serde::Deserialize;
 use tokio::sync::Mutex;
+use tracing::warn;
+
+use crate::http::buffer_body;
+use crate::storage::{decode_headers, encode_headers, Item, ItemKey, Key, Storage, StoredItem};
+use crate::types::{StoredMetaExt, SurrogateKeysExt};
 
-use crate::storage::{decode_headers, encode_headers, Item, ItemKey, Key, Storage};
+// Number of counters to start purging expired ones when a new counter is created
+const MAX_COUNTERS_BEFORE_PURGE: usize = 10_000;
 
 // Memory backend configuration
-#[derive(Deserialize)]
+#[derive(Default, Deserialize)]
 pub struct Config {
     /// Store up to `max_size` bytes (soft limit)
     pub max_size: usize,
+
+    /// Store up to `max_entries` items (optional, must be positive)
+    #[serde(default)]
+    pub max_entries: Option<NonZeroUsize>,
+
+    /// Inject faults to operations for chaos testing (optional).
+    ///
+    /// Ignored unless `enabled` is explicitly set.
+    #[serde(default)]
+    pub fault_injection: Option<FaultInjectionConfig>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct FaultInjectionConfig {
+    /// Enables fault injection (must never be set in production)
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// Latency (in seconds) to add to an operation
+    #[serde(default)]
+    pub latency: f64,
+
+    /// Probability (0.0 - 1.0) to add latency to an operation
+    #[serde(default = "FaultInjectionConfig::default_probability")]
+    pub latency_probability: f64,
+
+    /// Probability (0.0 - 1.0) to fail an operation
+    #[serde(default)]
+    pub error_probability: f64,
+
+    /// Operation timeout (in seconds), operations with latency above it fail (optional)
+    #[serde(default)]
+    pub timeout: Option<f64>,
+
+    /// Operations to inject faults to (all by default)
+    #[serde(default)]
+    pub operations: Option<Vec<Operation>>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Operation {
+    Get,
+    Store,
+    Delete,
+    Touch,
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Operation::Get => f.write_str("get"),
+            Operation::Store => f.write_str("store"),
+            Operation::Delete => f.write_str("delete"),
+            Operation::Touch => f.write_str("touch"),
+        }
+    }
+}
+
+impl FaultInjectionConfig {
+    const fn default_probability() -> f64 {
+        1.0
+    }
+
+    /// Returns the config if fault injection is explicitly enabled
+    fn enabled(config: Option<&Self>) -> Option<Self> {
+        let config = config?;
+        if !config.enabled {
+            warn!("fault injection is ignored (`enabled` is not set)");
+            return None;
+        }
+        warn!("fault injection is enabled: {config:?}");
+        Some(config.clone())
+    }
+
+    /// Injects latency and/or error to the operation (randomly)
+    async fn inject(&self, op: Operation) -> anyhow::Result<()> {
+        if matches!(&self.operations, Some(ops) if !ops.contains(&op)) {
+            return Ok(());
+        }
+        let (delay, fail) = {
+            let mut rng = rand::thread_rng();
+            let latency_probability = self.latency_probability.clamp(0.0, 1.0);
+            let error_probability = self.error_probability.clamp(0.0, 1.0);
+            (
+                rng.gen_bool(latency_probability),
+                rng.gen_bool(error_probability),
+            )
+        };
+        if delay && self.latency > 0.0 {
+            let latency = Duration::from_secs_f64(self.latency);
+            match self.timeout.map(Duration::from_secs_f64) {
+                Some(timeout) if latency > timeout => {
+                    tokio::time::sleep(timeout).await;
+                    bail!("{op} operation timed out (injected fault)");
+                }
+                _ => tokio::time::sleep(latency).await,
+            }
+        }
+        if fail {
+            bail!("{op} operation failed (injected fault)");
+        }
+        Ok(())
+    }
 }
 
 struct Value {
     status: StatusCode,
+    version: Option<Version>,
     headers: Vec<u8>,
     body: Bytes,
+    stored_at: SystemTime,
     expires: SystemTime,
     surrogate_keys: Vec<Key>,
 }
 
 impl Value {
+    fn from_item(item: Item<'_>) -> Result<Self, flexbuffers::SerializationError> {
+        let now = SystemTime::now();
+        Ok(Value {
+            status: item.status,
+            version: item.version,
+            headers: encode_headers(&item.headers)?,
+            body: item.body,
+            stored_at: now,
+            expires: now + item.ttl,
+            surrogate_keys: item.surrogate_keys,
+        })
+    }
+
     /// Calculates size (in bytes) of this Value
     fn size(&self) -> usize {
         let mut size = std::mem::size_of::<Self>();
@@ -42,38 +173,77 @@ impl Value {
 pub struct MemoryBackend {
     name: String,
     inner: Arc<Mutex<MemoryBackendImpl>>,
+    fault_injection: Option<Arc<FaultInjectionConfig>>,
+    #[cfg(test)]
+    connected: Arc<AtomicBool>,
 }
 
 impl MemoryBackend {
     pub fn new(config: &Config, name: impl Into<Option<String>>) -> Self {
         let name = name.into().unwrap_or_else(|| "memory".to_string());
-        let inner = Arc::new(Mutex::new(MemoryBackendImpl::new(config.max_size)));
-        MemoryBackend { name, inner }
+        let max_entries = config.max_entries.map(NonZeroUsize::get);
+        let inner = MemoryBackendImpl::new(config.max_size, max_entries);
+        let inner = Arc::new(Mutex::new(inner));
+        let fault_injection = FaultInjectionConfig::enabled(config.fault_injection.as_ref());
+        MemoryBackend {
+            name,
+            inner,
+            fault_injection: fault_injection.map(Arc::new),
+            #[cfg(test)]
+            connected: Arc::new(AtomicBool::new(true)),
+        }
+    }
+
+    /// Simulates connection loss (until the next `connect` call)
+    #[cfg(test)]
+    pub(crate) fn disconnect(&self) {
+        self.connected.store(false, Ordering::Relaxed);
+    }
+
+    /// Injects faults to the operation (if enabled)
+    #[inline]
+    async fn inject_fault(&self, op: Operation) -> anyhow::Result<()> {
+        match &self.fault_injection {
+            Some(fault_injection) => fault_injection.inject(op).await,
+            None => Ok(()),
+        }
     }
 }
 
 struct MemoryBackendImpl {
     max_size: usize,
+    max_entries: Option<usize>,
     size: usize,
     cache: LinkedHashMap<Key, Value>,
     index: HashMap<Key, HashSet<Key>>,
+    // Counters (value and expiration time) are kept apart from responses
+    counters: HashMap<Key, (u64, SystemTime)>,
 }
 
 impl MemoryBackendImpl {
     /// Creates a new instance that can hold up to `max_size` bytes (soft limit)
-    pub fn new(max_size: usize) -> Self {
+    /// and optionally up to `max_entries` items
+    pub fn new(max_size: usize, max_entries: Option<usize>) -> Self {
         MemoryBackendImpl {
             max_size,
+            max_entries,
             size: 0,
             cache: LinkedHashMap::new(),
             index: HashMap::new(),
+            counters: HashMap::new(),
         }
     }
 
-    /// Inserts key/value to the cache while maintaining `max_size`
+    /// Inserts key/value to the cache while maintaining `max_size` and `max_entries`
     pub fn insert(&mut self, key: Key, val: Value) {
+        // Replace the existing value (if any) to keep size accounting correct
+        self.remove(&key);
+
         // Ensure that we have free space to store the value
-        while !self.cache.is_empty() && self.size + val.size() > self.max_size {
+        while !self.cache.is_empty()
+            && (self.size + val.size() > self.max_size
+                || matches!(self.max_entries, Some(max) if self.cache.len() >= max))
+        {
             self.pop_lru();
         }
 
@@ -116,6 +286,36 @@ impl MemoryBackendImpl {
         }
     }
 
+    /// Updates expiration time of the unexpired value, returning `true` if it exists
+    fn touch(&mut self, key: &Key, ttl: Duration) -> bool {
+        let now = SystemTime::now();
+        match self.cache.get_refresh(key) {
+            Some(value) if value.expires > now => {
+                value.expires = now + ttl;
+                return true;
+            }
+            Some(_) => {}
+            None => return false,
+        }
+        self.remove(key);
+        false
+    }
+
+    /// Increments the counter, (re)starting it if missing or expired
+    fn incr_counter(&mut self, key: Key, ttl: Duration) -> u64 {
+        let now = SystemTime::now();
+        if !self.counters.contains_key(&key) && self.counters.len() >= MAX_COUNTERS_BEFORE_PURGE {
+            self.counters.retain(|_, (_, expires)| *expires > now);
+        }
+        let (count, expires) = self.counters.entry(key).or_insert((0, now + ttl));
+        if *expires <= now {
+            *count = 0;
+            *expires = now + ttl;
+        }
+        *count += 1;
+        *count
+    }
+
     /// Removes value from the cache by `key`
     fn remove(&mut self, key: &Key) -> Option<Value> {
         if let Some(value) = self.cache.remove(key) {
@@ -130,6 +330,14 @@ impl MemoryBackendImpl {
         None
     }
 
+    /// Removes all values from the cache
+    fn clear(&mut self) {
+        self.cache = LinkedHashMap::new();
+        self.index = HashMap::new();
+        self.counters = HashMap::new();
+        self.size = 0;
+    }
+
     /// Removes all values from the cache that have the same surrogate key
     fn remove_by_skey(&mut self, sk: &Key) {
         if let Some(set) = self.index.remove(sk) {
@@ -155,17 +363,51 @@ impl Storage for MemoryBackend {
     }
 
     async fn connect(&self) -> Result<(), Self::Error> {
+        #[cfg(test)]
+        self.connected.store(true, Ordering::Relaxed);
         Ok(())
     }
 
+    #[cfg(test)]
+    fn is_connected(&self) -> bool {
+        self.connected.load(Ordering::Relaxed)
+    }
+
     async fn get_response(&self, key: Key) -> Result<Option<Response<Self::Body>>, Self::Error> {
         self.get_responses([key]).await.remove(0)
     }
 
+    async fn has_response(&self, key: Key) -> Result<bool, Self::Error> {
+        self.inject_fault(Operation::Get).await?;
+        Ok(self.inner.lock().await.get_unexpired(&key).is_some())
+    }
+
+    async fn clear(&self) -> Result<(), Self::Error> {
+        self.inner.lock().await.clear();
+        Ok(())
+    }
+
+    async fn ping(&self) -> Result<Duration, Self::Error> {
+        Ok(Duration::ZERO)
+    }
+
+    async fn touch(&self, key: Key, ttl: Duration) -> Result<bool, Self::Error> {
+        self.inject_fault(Operation::Touch).await?;
+        Ok(self.inner.lock().await.touch(&key, ttl))
+    }
+
+    async fn incr_counter(&self, key: Key, ttl: Duration) -> Result<u64, Self::Error> {
+        self.inject_fault(Operation::Store).await?;
+        Ok(self.inner.lock().await.incr_counter(key, ttl))
+    }
+
     async fn get_responses(
         &self,
         keys: impl IntoIterator<Item = Key>,
     ) -> Vec<Result<Option<Response<Self::Body>>, Self::Error>> {
+        if let Err(err) = self.inject_fault(Operation::Get).await {
+            return keys.into_iter().map(|_| Err(anyhow!("{err}"))).collect();
+        }
         let mut memory = self.inner.lock().await;
         let mut responses = Vec::new();
         for key in keys {
@@ -177,6 +419,17 @@ impl Storage for MemoryBackend {
 
                     let mut resp = Response::with_body(value.status, body);
                     *resp.headers_mut() = headers;
+                    resp.extensions_mut().insert(StoredMetaExt {
+                        stored_at: value.stored_at,
+                        expires_at: Some(value.expires),
+                        body_size: value.body.len(),
+                        version: value.version,
+                    });
+                    if !value.surrogate_keys.is_empty() {
+                        let surrogate_keys = value.surrogate_keys.clone();
+                        resp.extensions_mut()
+                            .insert(SurrogateKeysExt(surrogate_keys));
+                    }
 
                     Ok::<_, Self::Error>(resp)
                 })
@@ -194,6 +447,9 @@ impl Storage for MemoryBackend {
         &self,
         keys: impl IntoIterator<Item = ItemKey>,
     ) -> Vec<Result<(), Self::Error>> {
+        if let Err(err) = self.inject_fault(Operation::Delete).await {
+            return keys.into_iter().map(|_| Err(anyhow!("{err}"))).collect();
+        }
         let mut memory = self.inner.lock().await;
         let mut results = Vec::new();
         for key in keys {
@@ -214,23 +470,44 @@ impl Storage for MemoryBackend {
         self.store_responses([item]).await.remove(0)
     }
 
+    async fn store_response_nx(&self, item: Item<'_>) -> Result<bool, Self::Error> {
+        self.inject_fault(Operation::Store).await?;
+        let mut memory = self.inner.lock().await;
+        if memory.get_unexpired(&item.key).is_some() {
+            return Ok(false);
+        }
+        let key = item.key.clone();
+        memory.insert(key, Value::from_item(item)?);
+        Ok(true)
+    }
+
+    async fn store_response_stream(
+        &self,
+        item: Item<'_>,
+        body: impl MessageBody,
+    ) -> Result<StoredItem, Self::Error> {
+        // The whole body is kept in memory anyway
+        let body = buffer_body(body)
+            .await
+            .map_err(|err| anyhow!("failed to read body: {err}"))?;
+        self.store_item(Item { body, ..item }).await
+    }
+
     async fn store_responses(
         &self,
         items: impl IntoIterator<Item = Item<'_>>,
     ) -> Vec<Result<usize, Self::Error>> {
+        if let Err(err) = self.inject_fault(Operation::Store).await {
+            return items.into_iter().map(|_| Err(anyhow!("{err}"))).collect();
+        }
         let mut memory = self.inner.lock().await;
         let mut results = Vec::new();
         for item in items {
             let result = (|| {
-                let value = Value {
-                    status: item.status,
-                    headers: encode_headers(&item.headers)?,
-                    body: item.body,
-                    expires: SystemTime::now() + item.ttl,
-                    surrogate_keys: item.surrogate_keys,
-                };
+                let key = item.key.clone();
+                let value = Value::from_item(item)?;
                 let size = value.headers.len() + value.body.len();
-                memory.insert(item.key, value);
+                memory.insert(key, value);
                 Ok(size)
             })();
             results.push(result);
@@ -241,11 +518,13 @@ impl Storage for MemoryBackend {
 
 #[cfg(test)]
 mod tests {
-    use std::time::Duration;
+    use std::num::NonZeroUsize;
+    use std::time::{Duration, Instant};
 
     use ntex::http::header::{HeaderName, HeaderValue};
     use ntex::http::Response;
     use ntex::util::Bytes;
+    use serde_json::json;
 
     use super::{Config, MemoryBackend};
     use crate::http::buffer_body;
@@ -257,7 +536,13 @@ mod tests {
 
     #[ntex::test]
     async fn test_backend() {
-        let memory = MemoryBackend::new(&Config { max_size: 1024 }, None);
+        let memory = MemoryBackend::new(
+            &Config {
+                max_size: 1024,
+                ..Default::default()
+            },
+            None,
+        );
         let mut resp = make_response("hello, world");
 
         resp.headers_mut().insert(
@@ -272,7 +557,8 @@ mod tests {
             .await
             .unwrap();
 
-        // Fetch it back
+        // Check presence and fetch it back
+        assert!(memory.has_response("key1".into()).await.unwrap());
         let mut resp = memory.get_response("key1".into()).await.unwrap().unwrap();
         assert_eq!(
             resp.headers().get("Hello"),
@@ -288,13 +574,20 @@ mod tests {
             .unwrap();
 
         // Try to fetch it back
+        assert!(!memory.has_response("key1".into()).await.unwrap());
         let resp = memory.get_response("key1".into()).await.unwrap();
         assert!(matches!(resp, None));
     }
 
     #[ntex::test]
     async fn test_backend_ttl() {
-        let memory = MemoryBackend::new(&Config { max_size: 1024 }, None);
+        let memory = MemoryBackend::new(
+            &Config {
+                max_size: 1024,
+                ..Default::default()
+            },
+            None,
+        );
         let mut resp = make_response("hello, world");
 
         resp.headers_mut().insert(
@@ -313,13 +606,90 @@ mod tests {
         tokio::time::sleep(ttl).await;
 
         // Try to fetch it back
+        assert!(!memory.has_response("key2".into()).await.unwrap());
         let resp = memory.get_response("key2".into()).await.unwrap();
         assert!(matches!(resp, None));
     }
 
+    #[ntex::test]
+    async fn test_touch() {
+        let memory = MemoryBackend::new(
+            &Config {
+                max_size: 1024,
+                ..Default::default()
+            },
+            None,
+        );
+
+        let ttl = Duration::from_millis(50);
+        let item = Item::new("key", make_response("hello, world"), ttl);
+        memory.store_response(item).await.unwrap();
+
+        // Extend TTL and check that the item survives past its original TTL
+        let touched = memory.touch("key".into(), Duration::from_secs(5)).await;
+        assert!(touched.unwrap());
+        tokio::time::sleep(ttl * 2).await;
+        assert!(memory.has_response("key".into()).await.unwrap());
+
+        // Missing key
+        assert!(!memory.touch("missing".into(), ttl).await.unwrap());
+    }
+
+    #[ntex::test]
+    async fn test_store_response_nx() {
+        let memory = MemoryBackend::new(
+            &Config {
+                max_size: 1024,
+                ..Default::default()
+            },
+            None,
+        );
+
+        let ttl = Duration::from_millis(50);
+        let item = Item::new("key", make_response("first"), ttl);
+        assert!(memory.store_response_nx(item).await.unwrap());
+        let item = Item::new("key", make_response("second"), ttl);
+        assert!(!memory.store_response_nx(item).await.unwrap());
+        let mut resp = memory.get_response("key".into()).await.unwrap().unwrap();
+        assert_eq!(buffer_body(resp.take_body()).await.unwrap(), "first");
+
+        // Expired items are replaced
+        tokio::time::sleep(ttl).await;
+        let item = Item::new("key", make_response("third"), ttl);
+        assert!(memory.store_response_nx(item).await.unwrap());
+    }
+
+    #[ntex::test]
+    async fn test_incr_counter() {
+        let memory = MemoryBackend::new(
+            &Config {
+                max_size: 1024,
+                ..Default::default()
+            },
+            None,
+        );
+
+        let ttl = Duration::from_millis(50);
+        for i in 1..=3 {
+            assert_eq!(memory.incr_counter("key".into(), ttl).await.unwrap(), i);
+        }
+        // Counters do not clash with responses
+        assert!(!memory.has_response("key".into()).await.unwrap());
+
+        // Counter restarts after expiration
+        tokio::time::sleep(ttl * 2).await;
+        assert_eq!(memory.incr_counter("key".into(), ttl).await.unwrap(), 1);
+    }
+
     #[ntex::test]
     async fn test_surrogate_keys() {
-        let memory = MemoryBackend::new(&Config { max_size: 1024 }, None);
+        let memory = MemoryBackend::new(
+            &Config {
+                max_size: 1024,
+                ..Default::default()
+            },
+            None,
+        );
         let resp = make_response("hello, world");
 
         let surrogate_keys = vec!["abc"];
@@ -344,4 +714,119 @@ mod tests {
         let resp = memory.get_response("key1".into()).await.unwrap();
         assert!(matches!(resp, None));
     }
+
+    #[ntex::test]
+    async fn test_max_entries() {
+        let max_entries = 100;
+        let config = Config {
+            max_size: 1024 * 1024,
+            max_entries: NonZeroUsize::new(max_entries),
+            ..Default::default()
+        };
+        let memory = MemoryBackend::new(&config, None);
+
+        let ttl = Duration::from_secs(10);
+        for i in 0..max_entries + 10 {
+            let item = Item::new(format!("key{i}"), make_response("x"), ttl);
+            memory.store_response(item).await.unwrap();
+        }
+        assert_eq!(memory.inner.lock().await.cache.len(), max_entries);
+
+        // The oldest items must be evicted
+        for i in 0..max_entries + 10 {
+            let exists = memory.has_response(format!("key{i}").into()).await.unwrap();
+            assert_eq!(exists, i >= 10, "key{i}");
+        }
+
+        // Accessing an item makes it recently used
+        assert!(memory.has_response("key10".into()).await.unwrap());
+        let item = Item::new("new_key", make_response("x"), ttl);
+        memory.store_response(item).await.unwrap();
+        assert!(memory.has_response("key10".into()).await.unwrap());
+        assert!(!memory.has_response("key11".into()).await.unwrap());
+
+        // Zero limit is rejected
+        let config = json!({"max_size": 1024, "max_entries": 0});
+        assert!(serde_json::from_value::<Config>(config).is_err());
+    }
+
+    #[ntex::test]
+    async fn test_clear() {
+        let config = Config {
+            max_size: 1024,
+            ..Default::default()
+        };
+        let memory = MemoryBackend::new(&config, None);
+
+        let ttl = Duration::from_secs(10);
+        for i in 0..5 {
+            let item =
+                Item::new_with_skeys(format!("key{i}"), make_response("x"), vec!["abc"], ttl);
+            memory.store_response(item).await.unwrap();
+        }
+
+        memory.clear().await.unwrap();
+
+        for i in 0..5 {
+            let resp = memory.get_response(format!("key{i}").into()).await.unwrap();
+            assert!(resp.is_none());
+        }
+        let inner = memory.inner.lock().await;
+        assert_eq!(inner.size, 0);
+        assert!(inner.index.is_empty());
+    }
+
+    #[ntex::test]
+    async fn test_fault_injection() {
+        let make_backend = |config: serde_json::Value| {
+            let config = serde_json::from_value::<Config>(config).unwrap();
+            MemoryBackend::new(&config, None)
+        };
+
+        // Fault injection must be explicitly enabled
+        let memory = make_backend(json!({
+            "max_size": 1024,
+            "fault_injection": {"error_probability": 1.0},
+        }));
+        let item = Item::new("key", make_response("hello"), Duration::from_secs(10));
+        memory.store_response(item).await.unwrap();
+
+        // Errors (only for the `store` operation)
+        let memory = make_backend(json!({
+            "max_size": 1024,
+            "fault_injection": {"enabled": true, "error_probability": 1.0, "operations": ["store"]},
+        }));
+        let item = Item::new("key", make_response("hello"), Duration::from_secs(10));
+        let err = memory.store_response(item).await.unwrap_err();
+        assert_eq!(err.to_string(), "store operation failed (injected fault)");
+        assert!(memory.get_response("key".into()).await.unwrap().is_none());
+
+        // Latency
+        let memory = make_backend(json!({
+            "max_size": 1024,
+            "fault_injection": {"enabled": true, "latency": 0.1},
+        }));
+        let start = Instant::now();
+        assert!(memory.get_response("key".into()).await.unwrap().is_none());
+        assert!(start.elapsed() >= Duration::from_millis(100));
+
+        // Latency above timeout
+        let memory = make_backend(json!({
+            "max_size": 1024,
+            "fault_injection": {"enabled": true, "latency": 10.0, "timeout": 0.1},
+        }));
+        let start = Instant::now();
+        let err = memory.get_response("key".into()).await.unwrap_err();
+        assert_eq!(err.to_string(), "get operation timed out (injected fault)");
+        assert!(start.elapsed() < Duration::from_secs(1));
+
+        // Nothing is injected with zero probabilities
+        let memory = make_backend(json!({
+            "max_size": 1024,
+            "fault_injection": {"enabled": true, "latency": 10.0, "latency_probability": 0.0},
+        }));
+        let item = Item::new("key", make_response("hello"), Duration::from_secs(10));
+        memory.store_response(item).await.unwrap();
+        assert!(memory.get_response("key".into()).await.unwrap().is_some());
+    }
 }
@@ -1,9 +1,12 @@
+use std::time::Duration;
+
 use anyhow::{anyhow, bail, Context, Result};
 use memory::MemoryBackend;
+use ntex::http::body::MessageBody;
 use ntex::http::Response;
 use redis::RedisBackend;
 
-use super::{Body, Item, ItemKey, Key, Storage};
+use super::{Body, Item, ItemKey, Key, Storage, StorageStats, StoredItem};
 
 #[derive(Clone)]
 pub enum Backend {
@@ -71,6 +74,30 @@ impl Storage for Backend {
         }
     }
 
+    #[inline]
+    fn is_connected(&self) -> bool {
+        match self {
+            Backend::Memory(inner) => inner.is_connected(),
+            Backend::Redis(inner) => Storage::is_connected(inner),
+        }
+    }
+
+    #[inline]
+    fn stats(&self) -> Option<StorageStats> {
+        match self {
+            Backend::Memory(inner) => inner.stats(),
+            Backend::Redis(inner) => Storage::stats(inner),
+        }
+    }
+
+    #[inline]
+    async fn reconnect(&self) -> Result<(), Self::Error> {
+        match self {
+            Backend::Memory(inner) => inner.reconnect().await,
+            Backend::Redis(inner) => Storage::reconnect(inner).await,
+        }
+    }
+
     #[inline]
     async fn get_response(&self, key: Key) -> Result<Option<Response<Self::Body>>, Self::Error> {
         match self {
@@ -95,6 +122,74 @@ impl Storage for Backend {
         }
     }
 
+    #[inline]
+    async fn store_response_nx(&self, item: Item<'_>) -> Result<bool, Self::Error> {
+        match self {
+            Backend::Memory(inner) => inner.store_response_nx(item).await,
+            Backend::Redis(inner) => inner.store_response_nx(item).await,
+        }
+    }
+
+    #[inline]
+    async fn store_response_stream(
+        &self,
+        item: Item<'_>,
+        body: impl MessageBody,
+    ) -> Result<StoredItem, Self::Error> {
+        match self {
+            Backend::Memory(inner) => inner.store_response_stream(item, body).await,
+            Backend::Redis(inner) => inner.store_response_stream(item, body).await,
+        }
+    }
+
+    #[inline]
+    async fn clear(&self) -> Result<(), Self::Error> {
+        match self {
+            Backend::Memory(inner) => inner.clear().await,
+            Backend::Redis(inner) => inner.clear().await,
+        }
+    }
+
+    #[inline]
+    async fn ping(&self) -> Result<Duration, Self::Error> {
+        match self {
+            Backend::Memory(inner) => inner.ping().await,
+            Backend::Redis(inner) => inner.ping().await,
+        }
+    }
+
+    #[inline]
+    async fn touch(&self, key: Key, ttl: Duration) -> Result<bool, Self::Error> {
+        match self {
+            Backend::Memory(inner) => inner.touch(key, ttl).await,
+            Backend::Redis(inner) => inner.touch(key, ttl).await,
+        }
+    }
+
+    #[inline]
+    async fn incr_counter(&self, key: Key, ttl: Duration) -> Result<u64, Self::Error> {
+        match self {
+            Backend::Memory(inner) => inner.incr_counter(key, ttl).await,
+            Backend::Redis(inner) => inner.incr_counter(key, ttl).await,
+        }
+    }
+
+    #[inline]
+    async fn store_item(&self, item: Item<'_>) -> Result<StoredItem, Self::Error> {
+        match self {
+            Backend::Memory(inner) => inner.store_item(item).await,
+            Backend::Redis(inner) => inner.store_item(item).await,
+        }
+    }
+
+    #[inline]
+    async fn has_response(&self, key: Key) -> Result<bool, Self::Error> {
+        match self {
+            Backend::Memory(inner) => inner.has_response(key).await,
+            Backend::Redis(inner) => inner.has_response(key).await,
+        }
+    }
+
     #[inline]
     async fn get_responses(
         &self,
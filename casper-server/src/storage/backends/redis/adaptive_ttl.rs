@@ -0,0 +1,123 @@
+use std::time::{Duration, Instant};
+
+use parking_lot::Mutex;
+
+use super::config::AdaptiveTtlConfig;
+
+// Minimum TTL (in seconds) to grow from when the base TTL is zero
+const MIN_STEP: f64 = 0.1;
+
+/// TTL of the internal (surrogate keys) cache that adapts to the cache miss rate.
+///
+/// At the end of each window the TTL is doubled (up to `max_ttl`) if the miss rate
+/// exceeded the threshold, or halved (down to the base TTL) if it dropped below half of it.
+#[derive(Debug)]
+pub(super) struct AdaptiveTtl {
+    base_ttl: f64,
+    config: AdaptiveTtlConfig,
+    state: Mutex<State>,
+}
+
+#[derive(Debug)]
+struct State {
+    ttl: f64,
+    window_start: Instant,
+    hits: u64,
+    misses: u64,
+}
+
+impl AdaptiveTtl {
+    pub(super) fn new(base_ttl: f64, config: AdaptiveTtlConfig, now: Instant) -> Self {
+        AdaptiveTtl {
+            base_ttl,
+            config,
+            state: Mutex::new(State {
+                ttl: base_ttl,
+                window_start: now,
+                hits: 0,
+                misses: 0,
+            }),
+        }
+    }
+
+    /// Returns the current (effective) TTL in seconds
+    pub(super) fn ttl(&self) -> f64 {
+        self.state.lock().ttl
+    }
+
+    /// Records the internal cache lookup result at the given time
+    pub(super) fn record(&self, hit: bool, now: Instant) {
+        let mut state = self.state.lock();
+        let window = Duration::from_secs_f64(self.config.window);
+        if now.saturating_duration_since(state.window_start) >= window {
+            let total = state.hits + state.misses;
+            if total > 0 {
+                let miss_rate = state.misses as f64 / total as f64;
+                let threshold = self.config.miss_rate_threshold;
+                if miss_rate > threshold {
+                    state.ttl = (state.ttl * 2.0).max(MIN_STEP).min(self.config.max_ttl);
+                } else if miss_rate < threshold / 2.0 {
+                    state.ttl = (state.ttl / 2.0).max(self.base_ttl);
+                    if state.ttl < MIN_STEP {
+                        state.ttl = self.base_ttl;
+                    }
+                }
+            }
+            state.window_start = now;
+            state.hits = 0;
+            state.misses = 0;
+        }
+
+        match hit {
+            true => state.hits += 1,
+            false => state.misses += 1,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::AdaptiveTtl;
+    use crate::storage::backends::redis::config::AdaptiveTtlConfig;
+
+    #[test]
+    fn test_adaptive_ttl() {
+        let config = AdaptiveTtlConfig {
+            max_ttl: 1.0,
+            miss_rate_threshold: 0.5,
+            window: 10.0,
+        };
+        let mut now = Instant::now();
+        let ttl = AdaptiveTtl::new(0.0, config, now);
+
+        // Simulates a window with the given number of hits and misses
+        let mut run_window = |hits: usize, misses: usize| {
+            for i in 0..hits + misses {
+                ttl.record(i < hits, now);
+            }
+            now += Duration::from_secs(10);
+            // Trigger the window evaluation
+            ttl.record(true, now);
+            ttl.ttl()
+        };
+
+        // High miss rate grows TTL up to the limit
+        assert_eq!(run_window(0, 10), 0.1);
+        assert_eq!(run_window(2, 8), 0.2);
+        assert_eq!(run_window(3, 7), 0.4);
+        assert_eq!(run_window(4, 6), 0.8);
+        assert_eq!(run_window(0, 10), 1.0);
+        assert_eq!(run_window(0, 10), 1.0);
+
+        // Moderate miss rate keeps TTL
+        assert_eq!(run_window(6, 4), 1.0);
+
+        // Low miss rate shrinks TTL back to the base one
+        assert_eq!(run_window(9, 1), 0.5);
+        assert_eq!(run_window(10, 0), 0.25);
+        assert_eq!(run_window(10, 0), 0.125);
+        assert_eq!(run_window(10, 0), 0.0);
+    }
+}
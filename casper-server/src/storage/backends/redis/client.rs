@@ -1,49 +1,101 @@
 use std::error::Error as StdError;
-use std::io;
+use std::future::{poll_fn, Future};
+use std::io::{self, Write as _};
 use std::mem;
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::Arc;
+use std::sync::{Arc, Weak};
 use std::time::{Duration, Instant, SystemTime};
 
-use anyhow::{anyhow, Context, Result};
+use anyhow::{anyhow, bail, Context, Result};
 use base64::Engine as _;
 use bitflags::bitflags;
-use fred::clients::Pool as RedisPool;
-use fred::error::Error as RedisError;
-use fred::interfaces::{ClientLike, KeysInterface};
+use fred::clients::{Pipeline, Pool as RedisPool};
+use fred::error::{Error as RedisError, ErrorKind as RedisErrorKind};
+use fred::interfaces::{ClientLike, KeysInterface, MetricsInterface, TransactionInterface};
 use fred::types::config::{PerformanceConfig, ReconnectPolicy};
-use fred::types::{Expiration, Key as RedisKey, SetOptions, Value as RedisValue};
+use fred::types::scripts::Script;
+use fred::types::{
+    ClientState, Expiration, ExpireOptions, FromValue, Key as RedisKey, SetOptions,
+    Value as RedisValue,
+};
 use futures::future::{try_join, try_join_all};
-use futures::stream::{self, StreamExt, TryStreamExt};
+use futures::stream::{self, LocalBoxStream, Stream, StreamExt, TryStreamExt};
 use moka::future::Cache;
-use ntex::http::body::{Body, SizedStream};
+use ntex::http::body::{Body, BodySize, MessageBody, SizedStream};
+use ntex::http::header::{HeaderValue, WARNING};
 use ntex::http::{Response, StatusCode};
-use ntex::util::Bytes;
+use ntex::util::{Bytes, BytesMut};
 use once_cell::sync::Lazy;
-use opentelemetry::{global, metrics::Counter};
+use opentelemetry::global;
+use opentelemetry::metrics::{Counter, Histogram, Meter, UpDownCounter};
+use opentelemetry::KeyValue;
+use parking_lot::Mutex;
+use rand::Rng;
+use scopeguard::defer;
 use serde::{Deserialize, Serialize};
-use tokio::time::timeout;
-
+use tokio::sync::Semaphore;
+use tokio::time::{timeout, timeout_at};
+use tracing::{debug, warn};
+use zstd::stream::write::Encoder as ZstdEncoder;
+
+use super::adaptive_ttl::AdaptiveTtl;
+use super::config::{ExcessSurrogateKeys, ServerConfig};
+use super::latency::{LatencyRecorder, NetworkLatency};
 use super::Config;
-use crate::storage::{decode_headers, encode_headers, Item, ItemKey, Key, Storage};
-use crate::types::EncryptedExt;
+use crate::http::{buffer_body, CoalescedStream};
+use crate::storage::{
+    decode_headers, decode_version, encode_headers, encode_version, Item, ItemKey, Key, Storage,
+    StorageStats, StoredItem,
+};
+use crate::types::{EncryptedExt, StoredMetaExt, SurrogateKeysExt};
 use crate::utils::aes::{aes256_decrypt, aes256_encrypt, AESDecoder};
 use crate::utils::zstd::{compress_with_zstd, decompress_with_zstd, ZstdDecoder};
 
-// TODO: Define format version
+// Version of the stored `ResponseItem` format (prepended to the encoded item).
+// Must be bumped on any incompatible change in the `ResponseItem` layout.
+const FORMAT_VERSION: u8 = 1;
 
-const SURROGATE_KEYS_TTL: i64 = 86400; // 1 day
+// Prefix of the unversioned `ResponseItem` (encoded name of its first field)
+// stored by releases before the format version was introduced
+const LEGACY_FORMAT_PREFIX: &[u8] = b"headers\0";
 
 // Do not compress data less than 100 bytes
 const COMPRESSION_THRESHOLD: usize = 100;
 
+// Stores the response item (`KEYS[1]`), its chunks and metadata if the item does not exist.
+// `ARGV[1]` is TTL (in seconds) followed by the values of the keys.
+static STORE_NX_SCRIPT: Lazy<Script> = Lazy::new(|| {
+    Script::from_lua(
+        r#"
+if redis.call('EXISTS', KEYS[1]) == 1 then
+    return 0
+end
+for i = #KEYS, 1, -1 do
+    redis.call('SET', KEYS[i], ARGV[i + 1], 'EX', ARGV[1])
+end
+return 1
+"#,
+    )
+});
+
+// `Warning` header value for responses served without checking surrogate keys (RFC 7234).
+// They are possibly stale, but no revalidation was attempted (that would be `111`).
+const STALE_WARNING: HeaderValue = HeaderValue::from_static("110 - \"Response is Stale\"");
+
 #[derive(Clone)]
 pub struct RedisBackend {
     name: String,
     config: Arc<Config>,
-    pool: RedisPool,
+    pool: Arc<RedisPool>,
     spawned_connect: Arc<AtomicBool>,
     internal_cache: Cache<Key, (SurrogateKeyItem, Instant)>,
+    internal_cache_ttl: Option<Arc<AdaptiveTtl>>,
+    store_semaphore: Option<Arc<Semaphore>>,
+    fetch_latency: Arc<LatencyRecorder>,
+    store_latency: Arc<LatencyRecorder>,
+    network_latency: Arc<NetworkLatency>,
+    #[cfg(test)]
+    fail_surrogate_fetches: Arc<AtomicBool>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -57,6 +109,25 @@ struct ResponseItem {
     body_length: usize,
     num_chunks: u32,
     flags: Flags,
+    // Encoded HTTP version (missing in items stored by older releases)
+    #[serde(default)]
+    version: Option<u8>,
+    // TTL (in seconds) the item was stored with (missing in items stored by older releases).
+    // Touching the item does not update it, so the expiration time derived from it
+    // is a lower bound.
+    #[serde(default)]
+    ttl: Option<u64>,
+    // Random nonce of the body chunk keys, so concurrent writes of the same key never share
+    // chunks (missing in items stored by older releases)
+    #[serde(default)]
+    nonce: Option<u32>,
+}
+
+/// Small metadata stored next to the response item to check it without fetching the body
+#[derive(Debug, Serialize, Deserialize)]
+struct ResponseMeta {
+    timestamp: u64,
+    surrogate_keys: Vec<Key>,
 }
 
 #[derive(Debug, Clone, Copy, Serialize, Deserialize)]
@@ -90,23 +161,115 @@ const HEADERS_COMPRESSED: Flags = Flags::HEADERS_COMPRESSED;
 const BODY_COMPRESSED: Flags = Flags::BODY_COMPRESSED;
 const ENCRYPTED: Flags = Flags::ENCRYPTED;
 
+// Pools of all backends (by name) to report their state
+type PoolRegistry = Mutex<Vec<(String, Weak<RedisPool>)>>;
+
 struct RedisMetrics {
+    pub pools: Arc<PoolRegistry>,
     pub internal_cache_counter: Counter<u64>,
+    pub surrogate_key_fetches_counter: Counter<u64>,
+    pub store_queue_depth: UpDownCounter<i64>,
+    pub retries_counter: Counter<u64>,
+    pub compression_ratio_histogram: Histogram<f64>,
 }
 
 static METRICS: Lazy<RedisMetrics> = Lazy::new(RedisMetrics::new);
 
 impl RedisMetrics {
     fn new() -> Self {
-        let meter = global::meter("redis");
+        Self::with_meter(global::meter("redis"))
+    }
+
+    fn with_meter(meter: Meter) -> Self {
+        let pools: Arc<PoolRegistry> = Arc::default();
+        let pools2 = pools.clone();
+        meter
+            .u64_observable_gauge("redis_pool_connections")
+            .with_description(
+                "Current number of Redis connections in the pool by state (active, idle or disconnected).",
+            )
+            .with_callback(move |instr| {
+                for (name, pool) in live_pools(&pools2) {
+                    let stats = PoolStats::collect(&pool);
+                    for (state, n) in [
+                        ("active", stats.active),
+                        ("idle", stats.idle),
+                        ("disconnected", stats.disconnected),
+                    ] {
+                        let attributes = [
+                            KeyValue::new("name", name.clone()),
+                            KeyValue::new("state", state),
+                        ];
+                        instr.observe(n, &attributes);
+                    }
+                }
+            })
+            .build();
+        let pools2 = pools.clone();
+        meter
+            .u64_observable_gauge("redis_pool_size")
+            .with_description("Number of Redis clients in the pool.")
+            .with_callback(move |instr| {
+                for (name, pool) in live_pools(&pools2) {
+                    instr.observe(pool.size() as u64, &[KeyValue::new("name", name)]);
+                }
+            })
+            .build();
+
         RedisMetrics {
+            pools,
             internal_cache_counter: meter
                 .u64_counter("redis_internal_cache_requests")
                 .with_description("Total number of Redis requests served from the internal cache.")
                 .build(),
+            surrogate_key_fetches_counter: meter
+                .u64_counter("redis_surrogate_key_fetches")
+                .with_description("Total number of surrogate keys fetched from Redis.")
+                .build(),
+            store_queue_depth: meter
+                .i64_up_down_counter("redis_store_queue_depth")
+                .with_description("Number of Redis store operations waiting for a free slot.")
+                .build(),
+            retries_counter: meter
+                .u64_counter("redis_retries")
+                .with_description(
+                    "Total number of Redis operations retried after a transient error.",
+                )
+                .build(),
+            compression_ratio_histogram: meter
+                .f64_histogram("storage_compression_ratio")
+                .with_description(
+                    "Ratio of the original to the compressed body size of stored responses.",
+                )
+                .with_boundaries(vec![1.0, 1.5, 2.0, 3.0, 5.0, 10.0, 20.0, 50.0])
+                .build(),
         }
     }
 
+    fn register_pool(&self, name: &str, pool: &Arc<RedisPool>) {
+        let mut pools = self.pools.lock();
+        pools.retain(|(_, pool)| pool.strong_count() > 0);
+        pools.push((name.to_owned(), Arc::downgrade(pool)));
+    }
+
+    fn store_queue_depth_add(&self, name: &str, delta: i64) {
+        let attributes = [opentelemetry::KeyValue::new("name", name.to_owned())];
+        self.store_queue_depth.add(delta, &attributes);
+    }
+
+    fn retries_counter_inc(&self, name: &str, operation: &'static str) {
+        let attributes = [
+            opentelemetry::KeyValue::new("name", name.to_owned()),
+            opentelemetry::KeyValue::new("operation", operation),
+        ];
+        self.retries_counter.add(1, &attributes);
+    }
+
+    fn surrogate_key_fetches_add(&self, name: &str, count: u64) {
+        let attributes = [opentelemetry::KeyValue::new("name", name.to_owned())];
+        self.surrogate_key_fetches_counter.add(count, &attributes);
+    }
+
     fn internal_cache_counter_inc(&self, name: &str, status: &'static str) {
         let attributes = [
             opentelemetry::KeyValue::new("name", name.to_owned()),
@@ -114,25 +277,91 @@ impl RedisMetrics {
         ];
         self.internal_cache_counter.add(1, &attributes);
     }
+
+    fn compression_ratio_rec(&self, name: &str, original_len: usize, compressed_len: usize) {
+        let attributes = [opentelemetry::KeyValue::new("name", name.to_owned())];
+        let ratio = original_len as f64 / compressed_len.max(1) as f64;
+        self.compression_ratio_histogram.record(ratio, &attributes);
+    }
+}
+
+/// Returns pools of the backends that are still alive
+fn live_pools(pools: &PoolRegistry) -> Vec<(String, Arc<RedisPool>)> {
+    let pools = pools.lock();
+    pools
+        .iter()
+        .filter_map(|(name, pool)| Some((name.clone(), pool.upgrade()?)))
+        .collect()
+}
+
+/// Connection stats of a Redis pool
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+struct PoolStats {
+    /// Connected clients with commands waiting to be sent
+    active: u64,
+    /// Connected clients without pending commands
+    idle: u64,
+    disconnected: u64,
+}
+
+impl PoolStats {
+    fn collect(pool: &RedisPool) -> Self {
+        let mut stats = PoolStats::default();
+        for client in pool.clients() {
+            if !client.is_connected() {
+                stats.disconnected += 1;
+            } else if client.command_queue_len() > 0 {
+                stats.active += 1;
+            } else {
+                stats.idle += 1;
+            }
+        }
+        stats
+    }
 }
 
 impl RedisBackend {
     /// Creates a new Redis backend instance without connecting to the server.
     pub fn new(config: Config, name: impl Into<Option<String>>) -> Result<Self> {
+        if config.surrogate_keys_ttl <= 0 {
+            bail!("`surrogate_keys_ttl` must be positive");
+        }
+        if config.max_concurrent_stores == Some(0) {
+            bail!("`max_concurrent_stores` must be positive");
+        }
+        if let Some(adaptive) = &config.adaptive_internal_cache_ttl {
+            if adaptive.max_ttl < config.internal_cache_ttl {
+                bail!("`adaptive_internal_cache_ttl.max_ttl` must be not less than `internal_cache_ttl`");
+            }
+            if !(adaptive.miss_rate_threshold > 0.0 && adaptive.miss_rate_threshold <= 1.0) {
+                bail!("`adaptive_internal_cache_ttl.miss_rate_threshold` must be in (0, 1]");
+            }
+            if adaptive.window <= 0.0 {
+                bail!("`adaptive_internal_cache_ttl.window` must be positive");
+            }
+        }
+
         let (redis_config, conn_config) = config.clone().into_fred_configs()?;
 
         // Use default performance config and connection config (with tcp nodelay)
         let perf_config = PerformanceConfig::default();
         let policy = ReconnectPolicy::default();
-        let pool = RedisPool::new(
+        let pool = Arc::new(RedisPool::new(
             redis_config,
             Some(perf_config),
             Some(conn_config),
             Some(policy),
             config.pool_size,
-        )?;
+        )?);
 
         let internal_cache_size = config.internal_cache_size;
+        let internal_cache_ttl = config.adaptive_internal_cache_ttl.map(|adaptive| {
+            let base_ttl = config.internal_cache_ttl;
+            Arc::new(AdaptiveTtl::new(base_ttl, adaptive, Instant::now()))
+        });
+        let store_semaphore = config
+            .max_concurrent_stores
+            .map(|n| Arc::new(Semaphore::new(n)));
         let backend = RedisBackend {
             name: name.into().unwrap_or_else(|| "redis".to_string()),
             config: Arc::new(config),
@@ -146,7 +375,15 @@ impl RedisBackend {
                         .unwrap_or(u32::MAX)
                 })
                 .build(),
+            internal_cache_ttl,
+            store_semaphore,
+            fetch_latency: Arc::default(),
+            store_latency: Arc::default(),
+            network_latency: Arc::default(),
+            #[cfg(test)]
+            fail_surrogate_fetches: Arc::new(AtomicBool::new(false)),
         };
+        METRICS.register_pool(&backend.name, &backend.pool);
 
         Ok(backend)
     }
@@ -177,6 +414,63 @@ impl RedisBackend {
         }
     }
 
+    /// Connects to the server (regardless of the lazy mode), restarting connection tasks
+    /// of the disconnected clients.
+    pub async fn reconnect(&self) -> Result<()> {
+        self.spawned_connect.store(true, Ordering::SeqCst);
+        for client in self.pool.clients() {
+            if client.state() == ClientState::Disconnected {
+                let _handle = client.connect();
+            }
+        }
+        self.wait_for_connect()
+            .await
+            .context("Failed to reconnect to Redis")
+    }
+
+    /// Returns `true` if all clients in the pool are connected
+    pub fn is_connected(&self) -> bool {
+        self.pool
+            .clients()
+            .iter()
+            .all(|client| client.is_connected())
+    }
+
+    /// Returns fetch and store latency percentiles along with the network latency
+    /// of all commands reported by the Redis clients
+    pub fn stats(&self) -> StorageStats {
+        // Client metrics are reset on read, so they are accumulated by the backend
+        for client in self.pool.clients() {
+            let network = client.take_network_latency_metrics();
+            (self.network_latency).add(network.avg, network.max, network.samples);
+        }
+        let (network_avg, network_max) = self.network_latency.stats();
+        StorageStats {
+            fetch: self.fetch_latency.stats(),
+            store: self.store_latency.stats(),
+            network_avg,
+            network_max,
+        }
+    }
+
+    /// Returns the (possibly adapted) TTL of the internal cache entries in seconds
+    #[inline]
+    fn internal_cache_ttl(&self) -> f64 {
+        match &self.internal_cache_ttl {
+            Some(adaptive) => adaptive.ttl(),
+            None => self.config.internal_cache_ttl,
+        }
+    }
+
+    /// Records the internal cache lookup result
+    fn internal_cache_record(&self, hit: bool) {
+        let status = if hit { "hit" } else { "miss" };
+        METRICS.internal_cache_counter_inc(&self.name, status);
+        if let Some(adaptive) = &self.internal_cache_ttl {
+            adaptive.record(hit, Instant::now());
+        }
+    }
+
     #[inline]
     fn lazy_connect(&self) {
         // Non-lazy instances should be already connected
@@ -187,50 +481,157 @@ impl RedisBackend {
 
     async fn get_response_inner(&self, key: Key) -> Result<Option<Response<Body>>> {
         // Fetch response item
-        let res: Option<Vec<u8>> = self.pool.get(make_redis_key(&key)).await?;
-        let response_item: ResponseItem = match res {
-            Some(res) => flexbuffers::from_slice(&res)?,
-            None => return Ok(None),
+        let prefer_replica = self.config.prefer_replica_reads;
+        let redis_key = make_redis_key(&self.config.key_prefix, &key);
+        let res: Option<Vec<u8>> = read_value(&self.pool, prefer_replica, redis_key).await?;
+        self.make_response(key, res).await
+    }
+
+    /// Fetches response items in a single pipeline
+    /// and then builds responses checking surrogate keys of each item.
+    ///
+    /// Must be used only in centralized mode, as in clustered mode keys can be in different shards.
+    async fn get_responses_pipelined(&self, keys: Vec<Key>) -> Vec<Result<Option<Response<Body>>>> {
+        let fetch_timeout = self.get_fetch_timeout();
+        let prefer_replica = self.config.prefer_replica_reads;
+        let start = Instant::now();
+        let num_keys = keys.len() as u64;
+        defer! { self.fetch_latency.record_n(start.elapsed(), num_keys); }
+        let redis_keys = keys
+            .iter()
+            .map(|key| make_redis_key(&self.config.key_prefix, key))
+            .collect::<Vec<_>>();
+        // The whole fetch (including retries) is bounded by the fetch timeout as for a single key
+        let deadline = tokio::time::Instant::now() + fetch_timeout;
+        let fetch = self.retry_transient("get", || async {
+            let values = read_values(&self.pool, prefer_replica, redis_keys.clone()).await?;
+            (values.into_iter())
+                .map(|value| value.convert::<Option<Vec<u8>>>())
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(anyhow::Error::new)
+        });
+        let items = timeout_at(deadline, fetch)
+            .await
+            .map_err(anyhow::Error::new)
+            .and_then(|x| x);
+        let items = match items {
+            Ok(items) => items,
+            Err(err) => {
+                return keys
+                    .into_iter()
+                    .map(|key| {
+                        Err(anyhow!("{err:#}")).with_context(|| {
+                            format!("Failed to fetch Response for key `{}`", hex::encode(key))
+                        })
+                    })
+                    .collect();
+            }
+        };
+
+        stream::iter(keys.into_iter().zip(items))
+            .map(|(key, res)| async move {
+                timeout_at(deadline, self.make_response(key.clone(), res))
+                    .await
+                    .map_err(anyhow::Error::new)
+                    .and_then(|x| x)
+                    .with_context(|| {
+                        format!("Failed to fetch Response for key `{}`", hex::encode(key))
+                    })
+            })
+            .buffered(Self::MAX_CONCURRENCY)
+            .collect()
+            .await
+    }
+
+    /// Builds a response from the fetched (raw) response item.
+    ///
+    /// Returns `None` if the item does not exist or is invalidated by one of its surrogate keys.
+    async fn make_response(
+        &self,
+        key: Key,
+        res: Option<Vec<u8>>,
+    ) -> Result<Option<Response<Body>>> {
+        let response_item = match res.as_deref().map(decode_response_item).transpose()? {
+            Some(Some(response_item)) => response_item,
+            // Unknown format version is treated as a cache miss
+            Some(None) | None => return Ok(None),
         };
 
+        let surrogate_keys = response_item.surrogate_keys.clone();
+        match self
+            .is_invalidated(response_item.timestamp, surrogate_keys)
+            .await
+        {
+            Ok(true) => return Ok(None),
+            Ok(false) => {}
+            Err(err) if self.config.serve_stale_on_surrogate_error => {
+                warn!(
+                    name = self.name,
+                    "serving response without checking surrogate keys: {err:#}"
+                );
+                let mut resp = self.decode_response(key, response_item).await?;
+                if let Some(resp) = resp.as_mut().filter(|_| self.config.stale_warning) {
+                    resp.headers_mut().append(WARNING, STALE_WARNING);
+                }
+                return Ok(resp);
+            }
+            Err(err) => return Err(err),
+        }
+
+        self.decode_response(key, response_item).await
+    }
+
+    /// Checks whether a response item stored at `timestamp` is invalidated by (or misses)
+    /// one of its surrogate keys.
+    async fn is_invalidated(&self, timestamp: u64, mut surrogate_keys: Vec<Key>) -> Result<bool> {
+        let prefer_replica = self.config.prefer_replica_reads;
+
         // Check surrogate keys in the internal cache first
-        let mut surrogate_keys = response_item.surrogate_keys;
         if self.config.internal_cache_size > 0 {
-            let int_cache_ttl = self.config.internal_cache_ttl;
+            let int_cache_ttl = self.internal_cache_ttl();
 
             let mut surrogate_keys_new = Vec::with_capacity(surrogate_keys.len());
             for sk in surrogate_keys {
                 match self.internal_cache.get(&sk).await {
                     // If we have a cached key that indicates expired record then don't go to Redis
-                    Some((sk_item, _)) if response_item.timestamp <= sk_item.timestamp => {
-                        METRICS.internal_cache_counter_inc(&self.name, "hit");
-                        return Ok(None);
+                    Some((sk_item, _)) if timestamp <= sk_item.timestamp => {
+                        self.internal_cache_record(true);
+                        return Ok(true);
                     }
                     // Filter surrogate keys that fetched earlier and not expired
                     Some((_, t)) if t.elapsed().as_secs_f64() <= int_cache_ttl => {
-                        METRICS.internal_cache_counter_inc(&self.name, "hit");
+                        self.internal_cache_record(true);
                     }
                     _ => {
                         surrogate_keys_new.push(sk);
-                        METRICS.internal_cache_counter_inc(&self.name, "miss");
+                        self.internal_cache_record(false);
                     }
                 }
             }
             surrogate_keys = surrogate_keys_new;
         }
 
+        // Bound the number of surrogate keys checked per read
+        let max_keys = self.config.max_surrogate_keys_per_read;
+        if self.config.excess_surrogate_keys == ExcessSurrogateKeys::Warn {
+            if let Some(max_keys) = max_keys.filter(|&n| surrogate_keys.len() > n) {
+                warn!(
+                    name = self.name,
+                    "skipping {} of {} surrogate keys exceeding the per-read limit",
+                    surrogate_keys.len() - max_keys,
+                    surrogate_keys.len(),
+                );
+                surrogate_keys.truncate(max_keys);
+            }
+        }
+
         // Fetch surrogate keys
         if !surrogate_keys.is_empty() {
-            // We cannot use "mget" operation in sharded mode because keys can be in different shards
-            let skeys_vals = stream::iter(surrogate_keys.clone())
-                .map(|sk| self.pool.get(make_redis_key(&sk)))
-                .buffered(Self::MAX_CONCURRENCY)
-                .collect::<Vec<Result<RedisValue, RedisError>>>()
-                .await;
+            let skeys_vals = self
+                .fetch_surrogate_keys(&surrogate_keys, prefer_replica)
+                .await?;
 
             for (sk, sk_value) in surrogate_keys.into_iter().zip(skeys_vals) {
-                let sk_value =
-                    sk_value.with_context(|| format!("Failed to fetch surrogate key {sk:?}"))?;
                 if let Some(sk_data) = sk_value.as_bytes() {
                     let sk_item: SurrogateKeyItem = flexbuffers::from_slice(sk_data)?;
 
@@ -242,27 +643,55 @@ impl RedisBackend {
                     }
 
                     // Check that the response item having this key is not expired
-                    if response_item.timestamp <= sk_item.timestamp {
-                        return Ok(None);
+                    if timestamp <= sk_item.timestamp {
+                        return Ok(true);
                     }
                 } else {
                     // If one of the keys is missing then we cannot proceed
                     // Probably the missing key was evicted
-                    return Ok(None);
+                    return Ok(true);
                 }
             }
         }
 
+        Ok(false)
+    }
+
+    /// Decodes the response item (with checked surrogate keys) into a response
+    async fn decode_response(
+        &self,
+        key: Key,
+        response_item: ResponseItem,
+    ) -> Result<Option<Response<Body>>> {
+        let prefer_replica = self.config.prefer_replica_reads;
         let status = StatusCode::from_u16(response_item.status_code)?;
         let flags = response_item.flags;
+        let stored_at = SystemTime::UNIX_EPOCH + Duration::from_secs(response_item.timestamp);
+        let stored_meta = StoredMetaExt {
+            stored_at,
+            expires_at: (response_item.ttl).map(|ttl| stored_at + Duration::from_secs(ttl)),
+            body_size: response_item.body_length,
+            version: response_item.version.and_then(decode_version),
+        };
+        let surrogate_keys = (!response_item.surrogate_keys.is_empty())
+            .then(|| SurrogateKeysExt(response_item.surrogate_keys));
         let mut raw_headers = response_item.headers;
 
         // Decrypt headers if required
+        // AES-GCM is authenticated, so a corrupted (or tampered) item fails to decrypt
+        // and is treated as a cache miss.
         let encryption_key = self.config.encryption_key.as_ref();
         raw_headers = match (flags.contains(ENCRYPTED), encryption_key) {
-            (true, Some(key)) => aes256_decrypt(raw_headers, key.clone())
-                .await
-                .context("failed to decrypt headers")?,
+            (true, Some(key)) => match aes256_decrypt(raw_headers, key.clone()).await {
+                Ok(raw_headers) => raw_headers,
+                Err(err) => {
+                    warn!(
+                        name = self.name,
+                        "failed to decrypt response headers: {err}"
+                    );
+                    return Ok(None);
+                }
+            },
             (true, None) => return Err(anyhow!("response is encrypted")),
             (false, _) => raw_headers,
         };
@@ -276,12 +705,18 @@ impl RedisBackend {
         // Decode them
         let headers = decode_headers(&raw_headers).context("failed to decode headers")?;
 
-        // If we have only one chunk, decode it in-place
-        if response_item.num_chunks == 1 {
+        // If we have only one chunk, decode it in-place (unless streaming body is forced)
+        if response_item.num_chunks == 1 && !self.config.force_streaming_body {
             let mut body = response_item.body;
             // Decrypt body
             if flags.contains(ENCRYPTED) {
-                body = aes256_decrypt(body, encryption_key.unwrap().clone()).await?;
+                match aes256_decrypt(body, encryption_key.unwrap().clone()).await {
+                    Ok(decrypted) => body = decrypted,
+                    Err(err) => {
+                        warn!(name = self.name, "failed to decrypt response body: {err}");
+                        return Ok(None);
+                    }
+                }
             }
             // Decompress body
             if flags.contains(BODY_COMPRESSED) {
@@ -294,18 +729,27 @@ impl RedisBackend {
             if flags.contains(ENCRYPTED) {
                 resp.extensions_mut().insert(EncryptedExt(true));
             }
+            resp.extensions_mut().insert(stored_meta);
+            if let Some(surrogate_keys) = surrogate_keys {
+                resp.extensions_mut().insert(surrogate_keys);
+            }
             return Ok(Some(resp));
         }
 
-        // Make body stream to fetch chunks from Redis
+        // Make body stream to fetch (the rest of) chunks from Redis
         let num_chunks = response_item.num_chunks as usize;
+        let nonce = response_item.nonce;
         // First chunk is stored in the response item, skip it
-        let chunks_stream = stream::iter(vec![(self.pool.clone(), key); num_chunks - 1])
+        let chunk_keys = (1..num_chunks as u32).map(|n| {
+            (
+                self.pool.clone(),
+                make_chunk_key(&self.config.key_prefix, &key, nonce, n),
+            )
+        });
+        let chunks_stream = stream::iter(chunk_keys.collect::<Vec<_>>())
             .enumerate()
-            .then(move |(i, (client, key))| async move {
-                let chunk_key = make_chunk_key(&key, i as u32 + 1);
-                let data = client
-                    .get::<Option<Vec<u8>>, _>(chunk_key)
+            .then(move |(i, (client, chunk_key))| async move {
+                let data = read_value::<Option<Vec<u8>>>(&client, prefer_replica, chunk_key)
                     .await
                     .map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
                 match data {
@@ -320,32 +764,8 @@ impl RedisBackend {
 
         // Decrypt and/or decompress the body if required
         let body_size = response_item.body_length as u64;
-        let body = match (flags.contains(ENCRYPTED), flags.contains(BODY_COMPRESSED)) {
-            (true, true) => {
-                // Decrypt and decompress
-                let body_stream = AESDecoder::new(body_stream, encryption_key.unwrap().clone());
-                let body_stream =
-                    ZstdDecoder::new(body_stream).map_err(|err| Box::new(err) as Box<dyn StdError>);
-                Body::Message(Box::new(SizedStream::new(body_size, Box::pin(body_stream))))
-            }
-            (true, false) => {
-                // Decrypt only
-                let body_stream = AESDecoder::new(body_stream, encryption_key.unwrap().clone())
-                    .map_err(|err| Box::new(err) as Box<dyn StdError>);
-                Body::Message(Box::new(SizedStream::new(body_size, Box::pin(body_stream))))
-            }
-            (false, true) => {
-                // Decompress only
-                let body_stream =
-                    ZstdDecoder::new(body_stream).map_err(|err| Box::new(err) as Box<dyn StdError>);
-                Body::Message(Box::new(SizedStream::new(body_size, Box::pin(body_stream))))
-            }
-            (false, false) => {
-                // Do nothing
-                let body_stream = body_stream.map_err(|err| Box::new(err) as Box<dyn StdError>);
-                Body::Message(Box::new(SizedStream::new(body_size, Box::pin(body_stream))))
-            }
-        };
+        let chunk_size = self.config.body_chunk_size;
+        let body = make_body_stream(body_stream, &flags, encryption_key, body_size, chunk_size);
 
         // Construct a new Response object
         let mut resp = Response::with_body(status, body);
@@ -353,12 +773,124 @@ impl RedisBackend {
         if flags.contains(ENCRYPTED) {
             resp.extensions_mut().insert(EncryptedExt(true));
         }
+        resp.extensions_mut().insert(stored_meta);
+        if let Some(surrogate_keys) = surrogate_keys {
+            resp.extensions_mut().insert(surrogate_keys);
+        }
         Ok(Some(resp))
     }
 
+    /// Fetches values of the surrogate keys.
+    ///
+    /// In centralized mode all keys are fetched using a single `MGET` operation.
+    /// We cannot use it in clustered mode because keys can be in different shards,
+    /// so each key is fetched separately. Keys beyond `max_surrogate_keys_per_read`
+    /// are fetched in a single pipeline to bound the per-read fan-out.
+    async fn fetch_surrogate_keys(
+        &self,
+        surrogate_keys: &[Key],
+        prefer_replica: bool,
+    ) -> Result<Vec<RedisValue>> {
+        METRICS.surrogate_key_fetches_add(&self.name, surrogate_keys.len() as u64);
+        #[cfg(test)]
+        if self.fail_surrogate_fetches.load(Ordering::Relaxed) {
+            bail!("Failed to fetch surrogate keys: injected error");
+        }
+        if let ServerConfig::Centralized { .. } = self.config.server {
+            let redis_keys = surrogate_keys
+                .iter()
+                .map(|sk| make_redis_key(&self.config.key_prefix, sk))
+                .collect::<Vec<_>>();
+            let values: Vec<RedisValue> = if prefer_replica {
+                self.pool.replicas().mget(redis_keys).await
+            } else {
+                self.pool.mget(redis_keys).await
+            }
+            .context("Failed to fetch surrogate keys")?;
+            return Ok(values);
+        }
+
+        let (surrogate_keys, excess_keys) = match self.config.max_surrogate_keys_per_read {
+            Some(max_keys) if surrogate_keys.len() > max_keys => surrogate_keys.split_at(max_keys),
+            _ => (surrogate_keys, &[][..]),
+        };
+        let excess_values = async {
+            let redis_keys = excess_keys
+                .iter()
+                .map(|sk| make_redis_key(&self.config.key_prefix, sk))
+                .collect::<Vec<_>>();
+            read_values(&self.pool, prefer_replica, redis_keys)
+                .await
+                .context("Failed to fetch surrogate keys")
+        };
+
+        let values = stream::iter(surrogate_keys)
+            .map(|sk| async move {
+                read_value(
+                    &self.pool,
+                    prefer_replica,
+                    make_redis_key(&self.config.key_prefix, sk),
+                )
+                .await
+                .with_context(|| format!("Failed to fetch surrogate key {sk:?}"))
+            })
+            .buffered(Self::MAX_CONCURRENCY)
+            .try_collect::<Vec<_>>();
+
+        let (mut values, excess_values) = try_join(values, excess_values).await?;
+        values.extend(excess_values);
+        Ok(values)
+    }
+
+    /// Checks that the response item exists in Redis and is not invalidated by its surrogate keys.
+    ///
+    /// Unlike `get_response_inner`, it does not fetch the response item (with the body),
+    /// only checks its existence and reads the response metadata.
+    /// Items stored by older releases have no metadata and are checked for existence only.
+    async fn has_response_inner(&self, key: Key, prefer_replica: bool) -> Result<bool> {
+        async fn send<C: KeysInterface>(
+            pipeline: Pipeline<C>,
+            redis_key: RedisKey,
+            meta_key: RedisKey,
+        ) -> Result<(bool, Option<Vec<u8>>), RedisError> {
+            pipeline.exists::<(), _>(redis_key).await?;
+            pipeline.get::<(), _>(meta_key).await?;
+            pipeline.all().await
+        }
+
+        // Both keys share the same hash slot (in clustered mode)
+        let prefix = &self.config.key_prefix;
+        let (redis_key, meta_key) = (make_redis_key(prefix, &key), make_meta_key(prefix, &key));
+        let client = self.pool.next();
+        let (exists, meta) = match prefer_replica {
+            true => send(client.replicas().pipeline(), redis_key, meta_key).await?,
+            false => send(client.pipeline(), redis_key, meta_key).await?,
+        };
+        let meta = match (exists, meta) {
+            (false, _) => return Ok(false),
+            (true, None) => return Ok(true),
+            (true, Some(meta)) => flexbuffers::from_slice::<ResponseMeta>(&meta)?,
+        };
+
+        match self
+            .is_invalidated(meta.timestamp, meta.surrogate_keys)
+            .await
+        {
+            Ok(invalidated) => Ok(!invalidated),
+            // Consistent with `get_response_inner` serving such responses
+            Err(_) if self.config.serve_stale_on_surrogate_error => Ok(true),
+            Err(err) => Err(err),
+        }
+    }
+
     async fn delete_responses_inner(&self, key: ItemKey) -> Result<()> {
         match key {
-            ItemKey::Primary(key) => Ok(self.pool.del(make_redis_key(&key)).await?),
+            ItemKey::Primary(key) => {
+                let prefix = &self.config.key_prefix;
+                // Both keys share the same hash slot (in clustered mode)
+                let keys = vec![make_redis_key(prefix, &key), make_meta_key(prefix, &key)];
+                Ok(self.pool.del(keys).await?)
+            }
             ItemKey::Surrogate(skey) => {
                 let sk_item = SurrogateKeyItem {
                     timestamp: current_timestamp(),
@@ -375,9 +907,9 @@ impl RedisBackend {
                 Ok(self
                     .pool
                     .set(
-                        make_redis_key(&skey),
+                        make_redis_key(&self.config.key_prefix, &skey),
                         RedisValue::Bytes(sk_item_enc.into()),
-                        Some(Expiration::EX(SURROGATE_KEYS_TTL)),
+                        Some(Expiration::EX(self.config.surrogate_keys_ttl)),
                         None,
                         false,
                     )
@@ -386,22 +918,108 @@ impl RedisBackend {
         }
     }
 
-    async fn store_response_inner(&self, item: Item<'_>) -> Result<usize> {
+    async fn store_response_inner(&self, item: Item<'_>) -> Result<StoredItem> {
+        if !self.is_body_size_cacheable(item.body.len()) {
+            return Ok(StoredItem::too_large());
+        }
+        let key = item.key.clone();
+        let ttl = self.effective_ttl(item.ttl);
+        let (response_item, chunks) = self.make_response_item(item).await?;
+
+        let num_chunks = response_item.num_chunks;
+        let stored_bytes = (self.write_response_item(&key, response_item, &chunks, ttl)).await?;
+
+        Ok(StoredItem {
+            size: stored_bytes,
+            num_chunks,
+            too_large: false,
+        })
+    }
+
+    /// Writes body chunks (first) and the response item, updating its surrogate keys.
+    ///
+    /// Returns number of written bytes.
+    async fn write_response_item(
+        &self,
+        key: &Key,
+        response_item: ResponseItem,
+        chunks: &[Bytes],
+        ttl: u64,
+    ) -> Result<usize> {
         let mut stored_bytes = 0;
+        for (n, chunk) in (1..).zip(chunks) {
+            self.store_chunk(key, response_item.nonce, n, chunk, ttl)
+                .await?;
+            stored_bytes += chunk.len();
+        }
+        stored_bytes += self.store_response_item(key, response_item, ttl).await?;
+        Ok(stored_bytes)
+    }
+
+    /// Stores a response only if its item does not exist.
+    ///
+    /// The response item and its chunks are stored atomically, so losing a race neither
+    /// leaves orphan chunks nor overwrites chunks of the existing item.
+    /// Existing items invalidated by their surrogate keys are treated as absent and overwritten.
+    async fn store_response_nx_inner(&self, item: Item<'_>) -> Result<bool> {
+        if !self.is_body_size_cacheable(item.body.len()) {
+            bail!("body is too large to cache");
+        }
+        let key = item.key.clone();
+        let ttl = self.effective_ttl(item.ttl);
+        let (response_item, chunks) = self.make_response_item(item).await?;
+        let response_item_enc = encode_response_item(&response_item)?;
+
+        let response_meta_enc = encode_response_meta(&response_item)?;
+
+        // All keys share the same hash slot (in clustered mode)
+        let prefix = &self.config.key_prefix;
+        let mut keys = vec![make_redis_key(prefix, &key)];
+        let mut args = vec![
+            RedisValue::Integer(ttl as i64),
+            RedisValue::Bytes(response_item_enc.into()),
+        ];
+        for (n, chunk) in (1..).zip(&chunks) {
+            keys.push(make_chunk_key(prefix, &key, response_item.nonce, n));
+            args.push(RedisValue::Bytes(chunk.to_vec().into()));
+        }
+        keys.push(make_meta_key(prefix, &key));
+        args.push(RedisValue::Bytes(response_meta_enc.into()));
+        let stored: bool =
+            (STORE_NX_SCRIPT.evalsha_with_reload(self.pool.next(), keys, args)).await?;
+
+        if !stored {
+            // Check the existing item on primary (it could be just stored by a concurrent fill)
+            if self.has_response_inner(key.clone(), false).await? {
+                return Ok(false);
+            }
+            // The existing item is invalidated (or expired meanwhile)
+            self.write_response_item(&key, response_item, &chunks, ttl)
+                .await?;
+            return Ok(true);
+        }
+
+        let timestamp = response_item.timestamp;
+        self.update_surrogate_keys(response_item.surrogate_keys, timestamp)
+            .await?;
+        Ok(true)
+    }
+
+    /// Makes a response item (compressing and encrypting it if needed) splitting its body
+    /// to chunks.
+    ///
+    /// Returns the response item (with the first chunk) and the rest of the chunks.
+    async fn make_response_item(&self, item: Item<'_>) -> Result<(ResponseItem, Vec<Bytes>)> {
         let mut headers = Bytes::from(encode_headers(&item.headers)?);
         let mut body = item.body;
         let body_length = body.len();
 
-        let max_ttl = self.config.max_ttl;
-        let ttl = max_ttl
-            .map(|max_ttl| std::cmp::max(max_ttl, item.ttl.as_secs()))
-            .unwrap_or(item.ttl.as_secs());
-
-        // If compression level is set, compress the body and headers and update flags
+        // If compression level is set (or compression is forced), compress the body and headers
+        // and update flags
         let mut flags = Flags::default();
-        if let Some(level) = self.config.compression_level {
+        if let Some(level) = self.compression_level(item.compress) {
             let (headers_comp, body_comp);
-            if body.len() < COMPRESSION_THRESHOLD {
+            if body.len() < COMPRESSION_THRESHOLD && item.compress.is_none() {
                 // Compress only headers if the body is too small
                 headers_comp = compress_with_zstd(headers.clone(), level).await?;
                 body_comp = body.clone();
@@ -411,6 +1029,7 @@ impl RedisBackend {
                     compress_with_zstd(body.clone(), level),
                 )
                 .await?;
+                METRICS.compression_ratio_rec(&self.name, body.len(), body_comp.len());
             }
             if headers_comp.len() < headers.len() {
                 headers = headers_comp;
@@ -432,64 +1051,247 @@ impl RedisBackend {
             flags.insert(ENCRYPTED);
         }
 
-        // Split body to chunks and save chunks first
+        // Split body to chunks
         let max_chunk_size = self.config.max_body_chunk_size;
-        let mut num_chunks = 1;
+        let mut chunks = Vec::new();
         if max_chunk_size > 0 && body.len() > max_chunk_size {
             let body_tail = body.split_off(max_chunk_size);
-            for (i, chunk) in body_tail.chunks(max_chunk_size).enumerate() {
+            chunks = (0..body_tail.len())
+                .step_by(max_chunk_size)
+                .map(|i| body_tail.slice(i..body_tail.len().min(i + max_chunk_size)))
+                .collect();
+        }
+
+        let response_item = ResponseItem {
+            status_code: item.status.as_u16(),
+            timestamp: current_timestamp(),
+            surrogate_keys: item.surrogate_keys,
+            headers,
+            body,
+            body_length, // Original length before compression
+            num_chunks: chunks.len() as u32 + 1,
+            flags,
+            version: item.version.map(encode_version),
+            ttl: Some(self.effective_ttl(item.ttl)),
+            nonce: Some(rand::random()),
+        };
+        Ok((response_item, chunks))
+    }
+
+    /// Stores a response reading its body as a stream.
+    ///
+    /// Body is (optionally) compressed and split into chunks on the fly, every complete chunk is
+    /// stored as soon as it's ready. The response item (with the first chunk) is stored last,
+    /// so a partially stored body is never visible.
+    /// Encrypted or non-chunked bodies cannot be stored incrementally and are buffered.
+    ///
+    /// Only Redis writes are subject to the store concurrency limit and timeout,
+    /// reading the body is not.
+    ///
+    /// If `max_cacheable_body_size` is set, bodies of known size above it are skipped without
+    /// reading, and bodies of unknown size are buffered (up to the limit) to not leave
+    /// partially stored chunks.
+    async fn store_response_stream_inner(
+        &self,
+        item: Item<'_>,
+        mut body: impl MessageBody,
+    ) -> Result<StoredItem> {
+        let max_chunk_size = self.config.max_body_chunk_size;
+        let encrypt = item.encrypt && self.config.encryption_key.is_some();
+        let max_body_size = self.config.max_cacheable_body_size;
+        let body_size = match body.size() {
+            BodySize::Sized(size) => Some(size as usize),
+            BodySize::Empty | BodySize::None => Some(0),
+            BodySize::Stream => None,
+        };
+        if !self.is_body_size_cacheable(body_size.unwrap_or_default()) {
+            return Ok(StoredItem::too_large());
+        }
+        if max_chunk_size == 0 || encrypt || (max_body_size.is_some() && body_size.is_none()) {
+            let body = match buffer_body_with_limit(body, max_body_size).await {
+                Ok(Some(body)) => body,
+                Ok(None) => return Ok(StoredItem::too_large()),
+                Err(err) => bail!("failed to read body: {err}"),
+            };
+            return self.store_item(Item { body, ..item }).await;
+        }
+
+        let mut headers = Bytes::from(encode_headers(&item.headers)?);
+        let ttl = self.effective_ttl(item.ttl);
+        let nonce = Some(rand::random());
+
+        let mut flags = Flags::default();
+        let mut encoder = None;
+        if let Some(level) = self.compression_level(item.compress) {
+            let headers_comp = compress_with_zstd(headers.clone(), level).await?;
+            if headers_comp.len() < headers.len() {
+                headers = headers_comp;
+                flags |= HEADERS_COMPRESSED;
+            }
+            // Body size is unknown, so it's always compressed
+            encoder = Some(ZstdEncoder::new(Vec::new(), level)?);
+            flags |= BODY_COMPRESSED;
+        }
+
+        let mut first_chunk = None;
+        let mut buffer = BytesMut::new();
+        let (mut body_length, mut num_chunks, mut stored_bytes) = (0, 1, 0);
+        loop {
+            let data = poll_fn(|cx| body.poll_next_chunk(cx))
+                .await
+                .transpose()
+                .map_err(|err| anyhow!("failed to read body: {err}"))?;
+            let is_last = data.is_none();
+            match (data, &mut encoder) {
+                // Already stored chunks are left to expire
+                (Some(data), _) if !self.is_body_size_cacheable(body_length + data.len()) => {
+                    return Ok(StoredItem::too_large());
+                }
+                (Some(data), Some(encoder)) => {
+                    body_length += data.len();
+                    encoder.write_all(&data)?;
+                    buffer.extend_from_slice(&mem::take(encoder.get_mut()));
+                }
+                (Some(data), None) => {
+                    body_length += data.len();
+                    buffer.extend_from_slice(&data);
+                }
+                (None, Some(_)) => {
+                    let encoder = encoder.take().unwrap();
+                    buffer.extend_from_slice(&encoder.finish()?);
+                }
+                (None, None) => {}
+            }
+
+            // Store complete chunks (and the rest of the body at the end)
+            while buffer.len() >= max_chunk_size || (is_last && !buffer.is_empty()) {
+                let chunk = buffer.split_to(buffer.len().min(max_chunk_size)).freeze();
+                if first_chunk.is_none() {
+                    // First chunk is kept in the response item
+                    first_chunk = Some(chunk);
+                    continue;
+                }
+                let store = self.store_chunk(&item.key, nonce, num_chunks, &chunk, ttl);
+                self.limit_store(item.key.clone(), store).await?;
                 num_chunks += 1;
-                // Store chunk in Redis
-                self.pool
-                    .set::<(), _, _>(
-                        make_chunk_key(&item.key, i as u32 + 1),
-                        RedisValue::Bytes(chunk.to_vec().into()),
-                        Some(Expiration::EX(ttl as i64)),
-                        None,
-                        false,
-                    )
-                    .await?;
                 stored_bytes += chunk.len();
             }
+            if is_last {
+                break;
+            }
         }
 
-        let timestamp = current_timestamp();
         let response_item = ResponseItem {
             status_code: item.status.as_u16(),
-            timestamp,
-            surrogate_keys: item.surrogate_keys.clone(),
+            timestamp: current_timestamp(),
+            surrogate_keys: item.surrogate_keys,
             headers,
-            body,
-            body_length, // Original length before compression
+            body: first_chunk.unwrap_or_default(),
+            body_length,
             num_chunks,
             flags,
+            version: item.version.map(encode_version),
+            ttl: Some(ttl),
+            nonce,
         };
-        let response_item_enc = flexbuffers::to_vec(&response_item)?;
-        let response_item_size = response_item_enc.len();
+        let store = self.store_response_item(&item.key, response_item, ttl);
+        stored_bytes += self.limit_store(item.key.clone(), store).await?;
+
+        Ok(StoredItem {
+            size: stored_bytes,
+            num_chunks,
+            too_large: false,
+        })
+    }
+
+    /// Checks the (original) body size against the maximum cacheable size
+    #[inline]
+    fn is_body_size_cacheable(&self, body_length: usize) -> bool {
+        self.config
+            .max_cacheable_body_size
+            .is_none_or(|max_size| body_length <= max_size)
+    }
 
-        // Store response item
+    /// Returns compression level to use taking into account the item override
+    fn compression_level(&self, compress: Option<bool>) -> Option<i32> {
+        match compress {
+            Some(false) => None,
+            Some(true) => Some(self.config.compression_level.unwrap_or(0)),
+            None => self.config.compression_level,
+        }
+    }
+
+    /// Stores a body chunk (except the first one, that is kept in the response item)
+    async fn store_chunk(
+        &self,
+        key: &Key,
+        nonce: Option<u32>,
+        n: u32,
+        chunk: &[u8],
+        ttl: u64,
+    ) -> Result<()> {
         self.pool
             .set::<(), _, _>(
-                make_redis_key(&item.key),
-                RedisValue::Bytes(response_item_enc.into()),
+                make_chunk_key(&self.config.key_prefix, key, nonce, n),
+                RedisValue::Bytes(chunk.to_vec().into()),
                 Some(Expiration::EX(ttl as i64)),
                 None,
                 false,
             )
             .await?;
-        stored_bytes += response_item_size;
+        Ok(())
+    }
+
+    /// Stores the response item (after all body chunks) and updates its surrogate keys.
+    ///
+    /// Returns size of the stored response item.
+    async fn store_response_item(
+        &self,
+        key: &Key,
+        response_item: ResponseItem,
+        ttl: u64,
+    ) -> Result<usize> {
+        let response_item_enc = encode_response_item(&response_item)?;
+        let response_meta_enc = encode_response_meta(&response_item)?;
+        let response_item_size = response_item_enc.len() + response_meta_enc.len();
+
+        // Store response metadata and item (both keys share the same hash slot)
+        let prefix = &self.config.key_prefix;
+        let pipeline = self.pool.next().pipeline();
+        let expiration = Some(Expiration::EX(ttl as i64));
+        let meta = RedisValue::Bytes(response_meta_enc.into());
+        (pipeline.set::<(), _, _>(
+            make_meta_key(prefix, key),
+            meta,
+            expiration.clone(),
+            None,
+            false,
+        ))
+        .await?;
+        let item = RedisValue::Bytes(response_item_enc.into());
+        (pipeline.set::<(), _, _>(make_redis_key(prefix, key), item, expiration, None, false))
+            .await?;
+        pipeline.all::<()>().await?;
+
+        let timestamp = response_item.timestamp;
+        self.update_surrogate_keys(response_item.surrogate_keys, timestamp)
+            .await?;
+
+        Ok(response_item_size)
+    }
 
-        // Update surrogate keys
-        let int_cache_ttl = self.config.internal_cache_ttl;
-        try_join_all(item.surrogate_keys.into_iter().map(|skey| async move {
+    /// Stores surrogate keys of a new response item (unless they are known)
+    async fn update_surrogate_keys(&self, surrogate_keys: Vec<Key>, timestamp: u64) -> Result<()> {
+        let int_cache_ttl = self.internal_cache_ttl();
+        try_join_all(surrogate_keys.into_iter().map(|skey| async move {
             let refresh_ttl = match self.internal_cache.get(&skey).await {
                 Some((_, t)) if t.elapsed().as_secs_f64() <= int_cache_ttl => {
                     // Do nothing, key is known
-                    METRICS.internal_cache_counter_inc(&self.name, "hit");
+                    self.internal_cache_record(true);
                     true
                 }
                 _ => {
-                    METRICS.internal_cache_counter_inc(&self.name, "miss");
+                    self.internal_cache_record(false);
                     // We set timestamp to the current time to not accidentally serve stalled items
                     // in case of surrogate key loss.
                     // Minus 1 second is needed to keep the current response fresh, because we invalidate
@@ -503,9 +1305,9 @@ impl RedisBackend {
                     let is_executed: RedisValue = self
                         .pool
                         .set(
-                            make_redis_key(&skey),
+                            make_redis_key(&self.config.key_prefix, &skey),
                             RedisValue::Bytes(sk_item_enc.into()),
-                            Some(Expiration::EX(SURROGATE_KEYS_TTL)),
+                            Some(Expiration::EX(self.config.surrogate_keys_ttl)),
                             Some(SetOptions::NX),
                             false,
                         )
@@ -515,56 +1317,212 @@ impl RedisBackend {
             };
             if refresh_ttl && rand::random::<u8>() % 100 < 1 {
                 // Refresh TTL with 1% probability
+                // Add jitter to spread out expiration of keys refreshed at the same time
+                let ttl = jittered_ttl(self.config.surrogate_keys_ttl, &mut rand::thread_rng());
                 self.pool
-                    .expire::<(), _>(make_redis_key(&skey), SURROGATE_KEYS_TTL, None)
+                    .expire::<(), _>(make_redis_key(&self.config.key_prefix, &skey), ttl, None)
                     .await?;
             }
             anyhow::Ok(())
         }))
         .await?;
-
-        Ok(stored_bytes)
+        Ok(())
     }
 
     fn get_fetch_timeout(&self) -> Duration {
         Duration::from_secs_f32(self.config.timeouts.fetch_timeout)
     }
 
-    fn get_store_timeout(&self) -> Duration {
-        Duration::from_secs_f32(self.config.timeouts.store_timeout)
-    }
-}
+    /// Sets a new TTL of the response item and all its chunks, extending TTL of its
+    /// surrogate keys (if needed) to not expire before the item.
+    ///
+    /// `EXPIRE GT` requires Redis 7.0 or later.
+    async fn touch_inner(&self, key: Key, ttl: Duration) -> Result<bool> {
+        let ttl = self.effective_ttl(ttl) as i64;
+        let prefix = &self.config.key_prefix;
+        let redis_key = make_redis_key(prefix, &key);
+
+        // The item is fetched to know the number of chunks and surrogate keys
+        let res: Option<Vec<u8>> = self.pool.get(redis_key.clone()).await?;
+        let response_item = match res.as_deref().map(decode_response_item).transpose()? {
+            Some(Some(response_item)) => response_item,
+            Some(None) | None => return Ok(false),
+        };
 
-impl Storage for RedisBackend {
-    type Body = Body;
-    type Error = anyhow::Error;
+        let sk_ttl = ttl.max(self.config.surrogate_keys_ttl);
+        let pipeline = self.pool.next().pipeline();
+        pipeline.expire::<(), _>(redis_key, ttl, None).await?;
+        let meta_key = make_meta_key(prefix, &key);
+        pipeline.expire::<(), _>(meta_key, ttl, None).await?;
+        for n in 1..response_item.num_chunks {
+            let chunk_key = make_chunk_key(prefix, &key, response_item.nonce, n);
+            pipeline.expire::<(), _>(chunk_key, ttl, None).await?;
+        }
+        for skey in &response_item.surrogate_keys {
+            let sk_key = make_redis_key(prefix, skey);
+            (pipeline.expire::<(), _>(sk_key, sk_ttl, Some(ExpireOptions::GT))).await?;
+        }
+        let touched: Vec<bool> = pipeline.all().await?;
+        // The item could expire after fetching it
+        Ok(touched.first().copied().unwrap_or_default())
+    }
 
-    fn name(&self) -> String {
-        self.name.clone()
+    /// Increments the counter and sets its expiration (if not set) in a single transaction.
+    ///
+    /// `EXPIRE NX` requires Redis 7.0 or later.
+    async fn incr_counter_inner(&self, key: Key, ttl: Duration) -> Result<u64> {
+        let redis_key = make_counter_key(&self.config.key_prefix, &key);
+        let ttl = ttl.as_millis().max(1) as i64;
+        let trx = self.pool.next().multi();
+        trx.incr::<(), _>(redis_key.clone()).await?;
+        trx.pexpire::<(), _>(redis_key, ttl, Some(ExpireOptions::NX))
+            .await?;
+        let (count, _): (u64, bool) = trx.exec(true).await?;
+        Ok(count)
     }
 
-    fn backend_type(&self) -> &'static str {
-        "redis"
+    /// Returns TTL (in seconds) to store items with, taking into account `max_ttl` option
+    fn effective_ttl(&self, ttl: Duration) -> u64 {
+        let max_ttl = self.config.max_ttl;
+        max_ttl
+            .map(|max_ttl| std::cmp::max(max_ttl, ttl.as_secs()))
+            .unwrap_or(ttl.as_secs())
     }
 
-    async fn connect(&self) -> Result<(), Self::Error> {
-        RedisBackend::connect(self).await
+    fn get_store_timeout(&self) -> Duration {
+        Duration::from_secs_f32(self.config.timeouts.store_timeout)
+    }
+
+    /// Runs the operation retrying it (up to `retries` times) if it fails with a transient error
+    async fn retry_transient<T, Fut>(
+        &self,
+        operation: &'static str,
+        f: impl Fn() -> Fut,
+    ) -> Result<T>
+    where
+        Fut: Future<Output = Result<T>>,
+    {
+        let mut backoff = Duration::from_secs_f32(self.config.retry_backoff);
+        let mut attempt = 0;
+        loop {
+            match f().await {
+                Err(err) if attempt < self.config.retries && is_transient_error(&err) => {
+                    attempt += 1;
+                    METRICS.retries_counter_inc(&self.name, operation);
+                    debug!(name = self.name, "retrying {operation} operation: {err:#}");
+                    tokio::time::sleep(backoff).await;
+                    backoff *= 2;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Runs the store operation limiting the number of concurrent stores and bounding
+    /// it (including waiting for a free slot) by the store timeout
+    async fn limit_store<T>(&self, key: Key, store: impl Future<Output = Result<T>>) -> Result<T> {
+        self.lazy_connect();
+        let store_timeout = self.get_store_timeout();
+        let start = Instant::now();
+        defer! { self.store_latency.record(start.elapsed()); }
+        let store = async {
+            let _permit = match &self.store_semaphore {
+                Some(semaphore) => {
+                    METRICS.store_queue_depth_add(&self.name, 1);
+                    defer! { METRICS.store_queue_depth_add(&self.name, -1); }
+                    Some(semaphore.acquire().await?)
+                }
+                None => None,
+            };
+            store.await
+        };
+        timeout(store_timeout, store)
+            .await
+            .map_err(anyhow::Error::new)
+            .and_then(|x| x)
+            .with_context(|| format!("Failed to store Response with key `{}`", hex::encode(key)))
+    }
+}
+
+impl Storage for RedisBackend {
+    type Body = Body;
+    type Error = anyhow::Error;
+
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn backend_type(&self) -> &'static str {
+        "redis"
+    }
+
+    async fn connect(&self) -> Result<(), Self::Error> {
+        RedisBackend::connect(self).await
+    }
+
+    fn is_connected(&self) -> bool {
+        RedisBackend::is_connected(self)
+    }
+
+    fn stats(&self) -> Option<StorageStats> {
+        Some(RedisBackend::stats(self))
+    }
+
+    async fn reconnect(&self) -> Result<(), Self::Error> {
+        RedisBackend::reconnect(self).await
     }
 
     async fn get_response(&self, key: Key) -> Result<Option<Response<Self::Body>>, Self::Error> {
         self.lazy_connect();
         let fetch_timeout = self.get_fetch_timeout();
-        timeout(fetch_timeout, self.get_response_inner(key.clone()))
+        let start = Instant::now();
+        defer! { self.fetch_latency.record(start.elapsed()); }
+        let fetch = self.retry_transient("get", || self.get_response_inner(key.clone()));
+        timeout(fetch_timeout, fetch)
             .await
             .map_err(anyhow::Error::new)
             .and_then(|x| x)
             .with_context(|| format!("Failed to fetch Response for key `{}`", hex::encode(key)))
     }
 
+    async fn get_responses(
+        &self,
+        keys: impl IntoIterator<Item = Key>,
+    ) -> Vec<Result<Option<Response<Self::Body>>, Self::Error>> {
+        self.lazy_connect();
+        let keys = keys.into_iter().collect::<Vec<_>>();
+        match self.config.server {
+            // Pipeline all fetches to make a single round trip
+            ServerConfig::Centralized { .. } if keys.len() > 1 => {
+                self.get_responses_pipelined(keys).await
+            }
+            _ => {
+                stream::iter(keys.into_iter().map(|key| self.get_response(key)))
+                    .buffered(Self::MAX_CONCURRENCY)
+                    .collect()
+                    .await
+            }
+        }
+    }
+
+    async fn has_response(&self, key: Key) -> Result<bool, Self::Error> {
+        self.lazy_connect();
+        let fetch_timeout = self.get_fetch_timeout();
+        timeout(
+            fetch_timeout,
+            self.has_response_inner(key.clone(), self.config.prefer_replica_reads),
+        )
+        .await
+        .map_err(anyhow::Error::new)
+        .and_then(|x| x)
+        .with_context(|| format!("Failed to check Response for key `{}`", hex::encode(key)))
+    }
+
     async fn delete_responses(&self, key: ItemKey) -> Result<(), Self::Error> {
         self.lazy_connect();
         let store_timeout = self.get_store_timeout();
-        timeout(store_timeout, self.delete_responses_inner(key.clone()))
+        let delete = self.retry_transient("delete", || self.delete_responses_inner(key.clone()));
+        timeout(store_timeout, delete)
             .await
             .map_err(anyhow::Error::new)
             .and_then(|x| x)
@@ -572,17 +1530,236 @@ impl Storage for RedisBackend {
     }
 
     async fn store_response(&self, item: Item<'_>) -> Result<usize, Self::Error> {
+        Ok(self.store_item(item).await?.size)
+    }
+
+    async fn store_response_stream(
+        &self,
+        item: Item<'_>,
+        body: impl MessageBody,
+    ) -> Result<StoredItem, Self::Error> {
         self.lazy_connect();
+        self.store_response_stream_inner(item, body).await
+    }
+
+    async fn store_response_nx(&self, item: Item<'_>) -> Result<bool, Self::Error> {
+        let key = item.key.clone();
+        self.limit_store(key, self.store_response_nx_inner(item))
+            .await
+    }
+
+    async fn store_item(&self, item: Item<'_>) -> Result<StoredItem, Self::Error> {
         let key = item.key.clone();
+        let store = self.retry_transient("store", || self.store_response_inner(item.clone()));
+        self.limit_store(key, store).await
+    }
+
+    async fn clear(&self) -> Result<(), Self::Error> {
+        // Flushing a (possibly shared) Redis database is too dangerous to be allowed
+        bail!("clearing Redis storage is unsupported")
+    }
+
+    async fn ping(&self) -> Result<Duration, Self::Error> {
+        self.lazy_connect();
+        let start = Instant::now();
+        timeout(self.get_fetch_timeout(), self.pool.ping::<()>(None))
+            .await
+            .map_err(anyhow::Error::new)
+            .and_then(|r| r.map_err(anyhow::Error::new))
+            .context("Failed to ping Redis")?;
+        Ok(start.elapsed())
+    }
+
+    async fn touch(&self, key: Key, ttl: Duration) -> Result<bool, Self::Error> {
+        self.lazy_connect();
         let store_timeout = self.get_store_timeout();
-        timeout(store_timeout, self.store_response_inner(item))
+        timeout(store_timeout, self.touch_inner(key.clone(), ttl))
             .await
             .map_err(anyhow::Error::new)
             .and_then(|x| x)
-            .with_context(|| format!("Failed to store Response with key `{}`", hex::encode(key)))
+            .with_context(|| format!("Failed to touch Response with key `{}`", hex::encode(key)))
+    }
+
+    async fn incr_counter(&self, key: Key, ttl: Duration) -> Result<u64, Self::Error> {
+        self.lazy_connect();
+        let store_timeout = self.get_store_timeout();
+        timeout(store_timeout, self.incr_counter_inner(key.clone(), ttl))
+            .await
+            .map_err(anyhow::Error::new)
+            .and_then(|x| x)
+            .with_context(|| {
+                format!(
+                    "Failed to increment counter with key `{}`",
+                    hex::encode(key)
+                )
+            })
+    }
+}
+
+/// Constructs a (sized) streaming body that decrypts and/or decompresses data if required
+fn make_body_stream<S>(
+    body_stream: S,
+    flags: &Flags,
+    encryption_key: Option<&Bytes>,
+    body_size: u64,
+    chunk_size: Option<usize>,
+) -> Body
+where
+    S: Stream<Item = Result<Bytes, io::Error>> + 'static,
+{
+    let body_stream: LocalBoxStream<'static, Result<Bytes, Box<dyn StdError>>> =
+        match (flags.contains(ENCRYPTED), flags.contains(BODY_COMPRESSED)) {
+            (true, true) => {
+                // Decrypt and decompress
+                let body_stream = AESDecoder::new(body_stream, encryption_key.unwrap().clone());
+                ZstdDecoder::new(body_stream)
+                    .map_err(|err| Box::new(err) as Box<dyn StdError>)
+                    .boxed_local()
+            }
+            (true, false) => {
+                // Decrypt only
+                AESDecoder::new(body_stream, encryption_key.unwrap().clone())
+                    .map_err(|err| Box::new(err) as Box<dyn StdError>)
+                    .boxed_local()
+            }
+            (false, true) => {
+                // Decompress only
+                ZstdDecoder::new(body_stream)
+                    .map_err(|err| Box::new(err) as Box<dyn StdError>)
+                    .boxed_local()
+            }
+            (false, false) => {
+                // Do nothing
+                body_stream
+                    .map_err(|err| Box::new(err) as Box<dyn StdError>)
+                    .boxed_local()
+            }
+        };
+    // Merge small (decoded) chunks
+    let body_stream = match chunk_size {
+        Some(chunk_size) => CoalescedStream::new(body_stream, chunk_size).boxed_local(),
+        None => body_stream,
+    };
+    Body::Message(Box::new(SizedStream::new(body_size, Box::pin(body_stream))))
+}
+
+/// Fetches a value from Redis, routing the request to a replica node if requested
+async fn read_value<R: FromValue>(
+    pool: &RedisPool,
+    prefer_replica: bool,
+    key: RedisKey,
+) -> Result<R, RedisError> {
+    if prefer_replica {
+        pool.replicas().get(key).await
+    } else {
+        pool.get(key).await
     }
 }
 
+/// Reads the whole body, returns `None` if it's larger than `max_size`
+async fn buffer_body_with_limit(
+    mut body: impl MessageBody,
+    max_size: Option<usize>,
+) -> Result<Option<Bytes>, Box<dyn StdError>> {
+    let Some(max_size) = max_size else {
+        return buffer_body(body).await.map(Some);
+    };
+    let mut bytes = BytesMut::new();
+    while let Some(chunk) = poll_fn(|cx| body.poll_next_chunk(cx)).await {
+        let chunk = chunk?;
+        if bytes.len() + chunk.len() > max_size {
+            return Ok(None);
+        }
+        bytes.extend_from_slice(&chunk);
+    }
+    Ok(Some(bytes.freeze()))
+}
+
+/// Fetches values of the keys in a single pipeline
+async fn read_values(
+    pool: &RedisPool,
+    prefer_replica: bool,
+    keys: Vec<RedisKey>,
+) -> Result<Vec<RedisValue>, RedisError> {
+    async fn send<C: KeysInterface>(
+        pipeline: Pipeline<C>,
+        keys: Vec<RedisKey>,
+    ) -> Result<Vec<RedisValue>, RedisError> {
+        for key in keys {
+            pipeline.get::<(), _>(key).await?;
+        }
+        pipeline.all().await
+    }
+
+    if keys.is_empty() {
+        return Ok(Vec::new());
+    }
+    let client = pool.next();
+    if prefer_replica {
+        send(client.replicas().pipeline(), keys).await
+    } else {
+        send(client.pipeline(), keys).await
+    }
+}
+
+/// Encodes the response item prepending the format version
+fn encode_response_item(item: &ResponseItem) -> Result<Vec<u8>> {
+    let mut data = vec![FORMAT_VERSION];
+    let mut serializer = flexbuffers::FlexbufferSerializer::new();
+    item.serialize(&mut serializer)?;
+    data.extend_from_slice(serializer.view());
+    Ok(data)
+}
+
+/// Encodes metadata of the response item (to check it without fetching the item)
+fn encode_response_meta(item: &ResponseItem) -> Result<Vec<u8>> {
+    let meta = ResponseMeta {
+        timestamp: item.timestamp,
+        surrogate_keys: item.surrogate_keys.clone(),
+    };
+    Ok(flexbuffers::to_vec(meta)?)
+}
+
+/// Decodes the response item checking the format version
+///
+/// Returns `None` if the format version is unknown.
+fn decode_response_item(data: &[u8]) -> Result<Option<ResponseItem>> {
+    match data.split_first() {
+        Some((&FORMAT_VERSION, data)) => Ok(Some(flexbuffers::from_slice(data)?)),
+        // TODO: Remove in the next release
+        // Unversioned items stored by older releases (start with the first field name),
+        // they have no chunk keys nonce, so their chunks are read using the old key layout
+        _ if data.starts_with(LEGACY_FORMAT_PREFIX) => Ok(Some(flexbuffers::from_slice(data)?)),
+        _ => Ok(None),
+    }
+}
+
+/// Checks if the error is caused by a transient Redis failure that is worth retrying
+///
+/// Timeouts of the operation itself (as opposed to Redis command timeouts) are never retried.
+fn is_transient_error(err: &anyhow::Error) -> bool {
+    err.chain()
+        .filter_map(|err| err.downcast_ref::<RedisError>())
+        .any(|err| {
+            matches!(
+                err.kind(),
+                RedisErrorKind::IO
+                    | RedisErrorKind::Cluster
+                    | RedisErrorKind::Timeout
+                    | RedisErrorKind::Canceled
+                    | RedisErrorKind::Backpressure
+            )
+        })
+}
+
+/// Returns TTL randomly extended by up to 10%
+///
+/// Jitter is never negative: surrogate keys must not expire before the items they invalidate.
+fn jittered_ttl(ttl: i64, rng: &mut impl Rng) -> i64 {
+    let jitter = ttl.max(0) / 10;
+    ttl + rng.gen_range(0..=jitter)
+}
+
 #[inline]
 fn current_timestamp() -> u64 {
     SystemTime::now()
@@ -592,28 +1769,62 @@ fn current_timestamp() -> u64 {
 }
 
 #[inline]
-fn make_redis_key(key: impl AsRef<[u8]>) -> RedisKey {
-    RedisKey::from(base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(key))
+fn make_redis_key(prefix: &str, key: impl AsRef<[u8]>) -> RedisKey {
+    let key = base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(key);
+    RedisKey::from(format!("{prefix}{key}"))
+}
+
+#[inline]
+fn make_chunk_key(prefix: &str, key: impl AsRef<[u8]>, nonce: Option<u32>, n: u32) -> RedisKey {
+    // The hash tag must cover the whole response item key (including prefix)
+    // to keep chunks in the same shard in clustered mode
+    let key = base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(key);
+    match nonce {
+        Some(nonce) => RedisKey::from(format!("{{{prefix}{key}}}|{nonce:08x}|{n}")),
+        None => RedisKey::from(format!("{{{prefix}{key}}}|{n}")),
+    }
 }
 
 #[inline]
-fn make_chunk_key(key: impl AsRef<[u8]>, n: u32) -> RedisKey {
+fn make_meta_key(prefix: &str, key: impl AsRef<[u8]>) -> RedisKey {
+    // Shares the hash tag with chunks (never clashes with them as they end with a number)
     let key = base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(key);
-    RedisKey::from(format!("{{{}}}|{}", key, n))
+    RedisKey::from(format!("{{{prefix}{key}}}|meta"))
+}
+
+#[inline]
+fn make_counter_key(prefix: &str, key: impl AsRef<[u8]>) -> RedisKey {
+    // Encoded keys never contain `:`, so counters cannot clash with response items
+    let key = base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(key);
+    RedisKey::from(format!("{prefix}counter:{key}"))
 }
 
 #[cfg(test)]
 mod tests {
+    use std::cell::Cell;
     use std::sync::atomic::{AtomicUsize, Ordering};
-    use std::time::Duration;
-
+    use std::task::Poll;
+    use std::time::{Duration, SystemTime};
+
+    use anyhow::anyhow;
+    use fred::error::{Error as RedisError, ErrorKind as RedisErrorKind};
+    use fred::interfaces::{KeysInterface, MetricsInterface};
+    use fred::types::{Expiration, Key as RedisKey, Value as RedisValue};
+    use futures::future::{join_all, poll_fn};
+    use ntex::http::body::{BodySize, BodyStream, MessageBody, SizedStream};
     use ntex::http::header::{HeaderName, HeaderValue};
-    use ntex::http::Response;
+    use ntex::http::{Response, Version};
     use ntex::util::Bytes;
+    use opentelemetry::metrics::MeterProvider as _;
 
-    use super::{Config, RedisBackend};
+    use super::{
+        decode_response_item, encode_response_item, jittered_ttl, live_pools, make_chunk_key,
+        make_meta_key, make_redis_key, read_value, Config, ExcessSurrogateKeys, Flags,
+        RedisBackend, RedisMetrics, ResponseItem, ServerConfig, FORMAT_VERSION,
+    };
     use crate::http::buffer_body;
     use crate::storage::{Item, ItemKey, Key, Storage};
+    use crate::types::StoredMetaExt;
 
     fn make_response(body: impl Into<Bytes>) -> Response<Bytes> {
         Response::Ok().message_body(body.into())
@@ -643,7 +1854,8 @@ mod tests {
             .await
             .unwrap();
 
-        // Fetch it back
+        // Check presence and fetch it back
+        assert!(backend.has_response(key.clone()).await.unwrap());
         let mut resp = backend.get_response(key.clone()).await.unwrap().unwrap();
         assert_eq!(
             resp.headers().get("Hello"),
@@ -659,10 +1871,338 @@ mod tests {
             .unwrap();
 
         // Try to fetch it back
+        assert!(!backend.has_response(key.clone()).await.unwrap());
         let resp = backend.get_response(key.clone()).await.unwrap();
         assert!(matches!(resp, None));
     }
 
+    #[ntex::test]
+    async fn test_has_response() {
+        let backend = RedisBackend::new(Config::default(), None).unwrap();
+        backend.connect().await.unwrap();
+
+        let key = make_uniq_key();
+        let skey = make_uniq_key();
+        let mut item = Item::new(
+            key.clone(),
+            make_response("a".repeat(100_000)),
+            Duration::from_secs(3),
+        );
+        item.surrogate_keys = vec![skey.clone()];
+        backend.store_response(item).await.unwrap();
+
+        // The response item (with the body) is never fetched
+        for client in backend.pool.clients() {
+            client.take_res_size_metrics();
+        }
+        assert!(backend.has_response(key.clone()).await.unwrap());
+        assert!(!backend.has_response(make_uniq_key()).await.unwrap());
+        let res_size_max = (backend.pool.clients().iter())
+            .map(|client| client.take_res_size_metrics().max)
+            .max()
+            .unwrap_or_default();
+        assert!(res_size_max < 1000, "fetched {res_size_max} bytes");
+
+        // Surrogate keys are respected
+        (backend.delete_responses(ItemKey::Surrogate(skey)))
+            .await
+            .unwrap();
+        assert!(!backend.has_response(key.clone()).await.unwrap());
+
+        // Metadata is removed along with the item
+        (backend.delete_responses(ItemKey::Primary(key.clone())))
+            .await
+            .unwrap();
+        let meta: Option<Vec<u8>> = (backend.pool.get(make_meta_key("", &key))).await.unwrap();
+        assert!(meta.is_none());
+    }
+
+    #[ntex::test]
+    async fn test_get_responses() {
+        let config = Config {
+            max_body_chunk_size: 4,
+            ..Default::default()
+        };
+        let backend = RedisBackend::new(config, None).unwrap();
+        backend.connect().await.unwrap();
+
+        let keys = (0..5).map(|_| make_uniq_key()).collect::<Vec<_>>();
+        let skey = make_uniq_key();
+        for (i, key) in keys.iter().enumerate().filter(|(i, _)| i % 2 == 0) {
+            backend
+                .store_response(Item::new_with_skeys(
+                    key.clone(),
+                    make_response(format!("hello, {i}")),
+                    vec![skey.clone()],
+                    Duration::from_secs(3),
+                ))
+                .await
+                .unwrap();
+        }
+
+        // Fetch present (even) and absent (odd) keys in one call
+        let results = backend.get_responses(keys.clone()).await;
+        assert_eq!(results.len(), keys.len());
+        for (i, result) in results.into_iter().enumerate() {
+            match result.unwrap() {
+                Some(mut resp) if i % 2 == 0 => {
+                    let body = buffer_body(resp.take_body()).await.unwrap();
+                    assert_eq!(body, format!("hello, {i}"));
+                    assert!(resp.extensions().get::<StoredMetaExt>().is_some());
+                }
+                None if i % 2 == 1 => {}
+                _ => panic!("unexpected result for key #{i}"),
+            }
+        }
+
+        // Surrogate keys are checked as well
+        backend
+            .delete_responses(ItemKey::Surrogate(skey))
+            .await
+            .unwrap();
+        assert!(!backend.has_response(keys[0].clone()).await.unwrap());
+        let results = backend.get_responses(keys).await;
+        assert!(results.into_iter().all(|r| r.unwrap().is_none()));
+    }
+
+    #[ntex::test]
+    async fn test_pool_metrics() {
+        let (provider, registry) = crate::metrics::test_meter_provider();
+        let metrics = RedisMetrics::with_meter(provider.meter("redis"));
+
+        let config = Config {
+            pool_size: 2,
+            ..Default::default()
+        };
+        let backend = RedisBackend::new(config, "pool_metrics".to_string()).unwrap();
+        metrics.register_pool(&backend.name, &backend.pool);
+        assert_eq!(live_pools(&metrics.pools).len(), 1);
+
+        let connections = |state: &str| {
+            let families = registry.gather();
+            let family =
+                (families.iter()).find(|family| family.get_name() == "redis_pool_connections")?;
+            let has_label = |m: &prometheus::proto::Metric, name: &str, value: &str| {
+                (m.get_label().iter()).any(|l| l.get_name() == name && l.get_value() == value)
+            };
+            (family.get_metric().iter())
+                .find(|m| has_label(m, "name", "pool_metrics") && has_label(m, "state", state))
+                .map(|m| m.get_gauge().get_value())
+        };
+        // Backend is not connected yet
+        assert_eq!(connections("disconnected"), Some(2.0));
+        assert_eq!(connections("active"), Some(0.0));
+        assert_eq!(connections("idle"), Some(0.0));
+
+        // Dropped backends are not reported
+        drop(backend);
+        assert!(live_pools(&metrics.pools).is_empty());
+    }
+
+    #[ntex::test]
+    async fn test_retry_transient() {
+        let config = Config {
+            retries: 2,
+            retry_backoff: 0.001,
+            ..Default::default()
+        };
+        let backend = RedisBackend::new(config, "retry_transient".to_string()).unwrap();
+        let attempts = Cell::new(0);
+        let fail_times = |n, kind: RedisErrorKind| {
+            attempts.set(0);
+            let attempts = &attempts;
+            move || {
+                let kind = kind.clone();
+                async move {
+                    attempts.set(attempts.get() + 1);
+                    if attempts.get() <= n {
+                        return Err(anyhow::Error::new(RedisError::new(kind, "injected error")));
+                    }
+                    Ok(attempts.get())
+                }
+            }
+        };
+
+        // Transient error succeeds on retry
+        let result = backend
+            .retry_transient("get", fail_times(2, RedisErrorKind::IO))
+            .await;
+        assert_eq!(result.unwrap(), 3);
+
+        // Retries are bounded
+        let result = backend
+            .retry_transient("get", fail_times(3, RedisErrorKind::Cluster))
+            .await;
+        assert!(result.is_err());
+        assert_eq!(attempts.get(), 3);
+
+        // Other errors are not retried
+        let result = backend
+            .retry_transient("get", fail_times(1, RedisErrorKind::InvalidArgument))
+            .await;
+        assert!(result.is_err());
+        assert_eq!(attempts.get(), 1);
+
+        let result = backend
+            .retry_transient("get", || async {
+                attempts.set(attempts.get() + 1);
+                Err::<(), _>(anyhow!("not a redis error"))
+            })
+            .await;
+        assert!(result.is_err());
+        assert_eq!(attempts.get(), 2);
+    }
+
+    #[test]
+    fn test_format_version() {
+        let item = ResponseItem {
+            headers: Bytes::from_static(b"headers"),
+            status_code: 200,
+            timestamp: 1,
+            surrogate_keys: vec![Key::from_static(b"skey")],
+            body: Bytes::from_static(b"body"),
+            body_length: 4,
+            num_chunks: 1,
+            flags: Flags::empty(),
+            version: None,
+            ttl: None,
+            nonce: None,
+        };
+
+        // Current version decodes
+        let data = encode_response_item(&item).unwrap();
+        assert_eq!(data[0], FORMAT_VERSION);
+        let decoded = decode_response_item(&data).unwrap().unwrap();
+        assert_eq!(decoded.body, item.body);
+        assert_eq!(decoded.surrogate_keys, item.surrogate_keys);
+
+        // Unknown version is skipped
+        let mut data = data;
+        data[0] = FORMAT_VERSION + 1;
+        assert!(decode_response_item(&data).unwrap().is_none());
+        assert!(decode_response_item(&[]).unwrap().is_none());
+
+        // Items stored without HTTP version still decode
+        #[derive(serde::Serialize)]
+        struct LegacyResponseItem {
+            headers: Bytes,
+            status_code: u16,
+            timestamp: u64,
+            surrogate_keys: Vec<Key>,
+            body: Bytes,
+            body_length: usize,
+            num_chunks: u32,
+            flags: Flags,
+        }
+        let legacy = LegacyResponseItem {
+            headers: item.headers,
+            status_code: item.status_code,
+            timestamp: item.timestamp,
+            surrogate_keys: item.surrogate_keys,
+            body: item.body,
+            body_length: item.body_length,
+            num_chunks: item.num_chunks,
+            flags: item.flags,
+        };
+        let mut serializer = flexbuffers::FlexbufferSerializer::new();
+        serde::Serialize::serialize(&legacy, &mut serializer).unwrap();
+        let data = [&[FORMAT_VERSION], serializer.view()].concat();
+        let decoded = decode_response_item(&data).unwrap().unwrap();
+        assert_eq!(decoded.body_length, 4);
+        assert_eq!(decoded.version, None);
+
+        // Unversioned items stored by older releases still decode (using the old chunk keys)
+        let decoded = decode_response_item(serializer.view()).unwrap().unwrap();
+        assert_eq!(decoded.body_length, 4);
+        assert_eq!(decoded.nonce, None);
+        assert_eq!(
+            make_chunk_key("", b"key", decoded.nonce, 1),
+            RedisKey::from("{a2V5}|1")
+        );
+    }
+
+    #[ntex::test]
+    async fn test_unknown_format_version() {
+        let backend = RedisBackend::new(Config::default(), None).unwrap();
+        backend.connect().await.unwrap();
+
+        let key = make_uniq_key();
+        backend
+            .store_response(Item::new(
+                key.clone(),
+                make_response("hello"),
+                Duration::from_secs(3),
+            ))
+            .await
+            .unwrap();
+
+        // Overwrite the stored item with an unknown format version
+        let mut data: Vec<u8> = backend.pool.get(make_redis_key("", &key)).await.unwrap();
+        data[0] = FORMAT_VERSION + 1;
+        backend
+            .pool
+            .set::<(), _, _>(
+                make_redis_key("", &key),
+                data,
+                Some(Expiration::EX(3)),
+                None,
+                false,
+            )
+            .await
+            .unwrap();
+
+        // Must be a graceful cache miss
+        let resp = backend.get_response(key).await.unwrap();
+        assert!(resp.is_none());
+    }
+
+    #[ntex::test]
+    async fn test_stored_meta() {
+        let backend = RedisBackend::new(Config::default(), None).unwrap();
+        backend.connect().await.unwrap();
+
+        let key = make_uniq_key();
+        backend
+            .store_response(Item::new(
+                key.clone(),
+                make_response("hello"),
+                Duration::from_secs(10),
+            ))
+            .await
+            .unwrap();
+
+        let resp = backend.get_response(key).await.unwrap().unwrap();
+        let meta = *resp.extensions().get::<StoredMetaExt>().unwrap();
+        let age = SystemTime::now().duration_since(meta.stored_at).unwrap();
+        assert!(age <= Duration::from_secs(2), "stored_at is {age:?} ago");
+        let ttl = meta.expires_at.unwrap().duration_since(SystemTime::now());
+        assert!(ttl.unwrap() > Duration::from_secs(8));
+        assert_eq!(meta.body_size, 5);
+    }
+
+    #[ntex::test]
+    async fn test_stored_version() {
+        let backend = RedisBackend::new(Config::default(), None).unwrap();
+        backend.connect().await.unwrap();
+
+        let key = make_uniq_key();
+        let mut item = Item::new(key.clone(), make_response("hello"), Duration::from_secs(10));
+        item.version = Some(Version::HTTP_2);
+        backend.store_response(item).await.unwrap();
+
+        let resp = backend.get_response(key).await.unwrap().unwrap();
+        let meta = *resp.extensions().get::<StoredMetaExt>().unwrap();
+        assert_eq!(meta.version, Some(Version::HTTP_2));
+
+        // Version is unknown if not provided
+        let key = make_uniq_key();
+        let item = Item::new(key.clone(), make_response("hello"), Duration::from_secs(10));
+        backend.store_response(item).await.unwrap();
+        let resp = backend.get_response(key).await.unwrap().unwrap();
+        let meta = *resp.extensions().get::<StoredMetaExt>().unwrap();
+        assert_eq!(meta.version, None);
+    }
+
     #[ntex::test]
     async fn test_chunked_body() {
         let mut config = Config::default();
@@ -674,10 +2214,11 @@ mod tests {
 
         // Cache response
         let resp = make_response("hello, world");
-        backend
-            .store_response(Item::new(key.clone(), resp, Duration::from_secs(3)))
+        let stored = backend
+            .store_item(Item::new(key.clone(), resp, Duration::from_secs(3)))
             .await
             .unwrap();
+        assert_eq!(stored.num_chunks, 6);
 
         // Fetch it back
         let mut resp = backend.get_response(key.clone()).await.unwrap().unwrap();
@@ -685,10 +2226,326 @@ mod tests {
         assert_eq!(String::from_utf8(body).unwrap(), "hello, world");
     }
 
+    #[ntex::test]
+    async fn test_max_cacheable_body_size() {
+        let config = Config {
+            max_body_chunk_size: 4,
+            max_cacheable_body_size: Some(10),
+            ..Default::default()
+        };
+        let backend = RedisBackend::new(config, None).unwrap();
+        backend.connect().await.unwrap();
+
+        // Below the limit
+        let key = make_uniq_key();
+        let item = Item::new(
+            key.clone(),
+            make_response("0123456789"),
+            Duration::from_secs(3),
+        );
+        let stored = backend.store_item(item).await.unwrap();
+        assert!(!stored.too_large && stored.size > 0);
+        assert!(backend.has_response(key).await.unwrap());
+
+        // Above the limit
+        let key = make_uniq_key();
+        let item = Item::new(
+            key.clone(),
+            make_response("0123456789a"),
+            Duration::from_secs(3),
+        );
+        let stored = backend.store_item(item).await.unwrap();
+        assert!(stored.too_large && stored.size == 0);
+        assert!(!backend.has_response(key).await.unwrap());
+
+        // Streamed body above the limit
+        let key = make_uniq_key();
+        let item = Item::new(key.clone(), make_response(""), Duration::from_secs(3));
+        let body = BodyStream::new(futures::stream::iter(
+            ["0123", "4567", "89ab"].map(|s| Ok::<_, std::io::Error>(Bytes::from(s))),
+        ));
+        let stored = backend.store_response_stream(item, body).await.unwrap();
+        assert!(stored.too_large);
+        assert!(!backend.has_response(key).await.unwrap());
+
+        // Streamed body of unknown size below the limit
+        let key = make_uniq_key();
+        let item = Item::new(key.clone(), make_response(""), Duration::from_secs(3));
+        let body = BodyStream::new(futures::stream::iter(
+            ["0123", "4567", "89"].map(|s| Ok::<_, std::io::Error>(Bytes::from(s))),
+        ));
+        let stored = backend.store_response_stream(item, body).await.unwrap();
+        assert!(!stored.too_large && stored.num_chunks == 3);
+        let mut resp = backend.get_response(key).await.unwrap().unwrap();
+        assert_eq!(buffer_body(resp.take_body()).await.unwrap(), "0123456789");
+
+        // Streamed body of known size above the limit is not read
+        let key = make_uniq_key();
+        let item = Item::new(key.clone(), make_response(""), Duration::from_secs(3));
+        let stream = futures::stream::poll_fn(|_| -> Poll<Option<Result<Bytes, _>>> {
+            panic!("body must not be read")
+        });
+        let body = SizedStream::new(11, Box::pin(stream));
+        let stored = backend.store_response_stream(item, body).await.unwrap();
+        assert!(stored.too_large);
+        assert!(!backend.has_response(key).await.unwrap());
+    }
+
+    #[ntex::test]
+    async fn test_store_response_stream() {
+        let config = Config {
+            max_body_chunk_size: 4096,
+            ..Default::default()
+        };
+        let backend = RedisBackend::new(config, None).unwrap();
+        backend.connect().await.unwrap();
+
+        // 1 MiB of (poorly compressible) pseudo-random body streamed in 1 KiB pieces
+        let mut state = 1u64;
+        let pieces = (0..1024)
+            .map(|_| {
+                (0..1024)
+                    .map(|_| {
+                        state = state.wrapping_mul(6364136223846793005).wrapping_add(1);
+                        (state >> 56) as u8
+                    })
+                    .collect::<Bytes>()
+            })
+            .collect::<Vec<_>>();
+        let expected = pieces.concat();
+
+        for compress in [false, true] {
+            let key = make_uniq_key();
+            let stream = futures::stream::iter(pieces.clone().into_iter().map(Ok));
+            let body = BodyStream::<_, std::io::Error>::new(stream);
+            let mut item = Item::new(key.clone(), make_response(""), Duration::from_secs(3));
+            item.compress = Some(compress);
+            let stored = backend.store_response_stream(item, body).await.unwrap();
+            assert!(stored.num_chunks > 1);
+            if !compress {
+                assert_eq!(stored.num_chunks as usize, expected.len() / 4096);
+            }
+
+            // Fetch it back
+            let mut resp = backend.get_response(key.clone()).await.unwrap().unwrap();
+            let body = buffer_body(resp.take_body()).await.unwrap();
+            assert_eq!(body.len(), expected.len());
+            assert!(body == expected, "body mismatch (compress: {compress})");
+        }
+
+        // A failed overwrite does not corrupt chunks of the stored item
+        let key = make_uniq_key();
+        let stream = futures::stream::iter(pieces.clone().into_iter().map(Ok));
+        let body = BodyStream::<_, std::io::Error>::new(stream);
+        let item = Item::new(key.clone(), make_response(""), Duration::from_secs(3));
+        backend.store_response_stream(item, body).await.unwrap();
+        let broken = (pieces.clone().into_iter().take(16).map(Ok))
+            .chain([Err(std::io::Error::other("connection reset"))]);
+        let body = BodyStream::new(futures::stream::iter(broken));
+        let item = Item::new(key.clone(), make_response(""), Duration::from_secs(3));
+        assert!(backend.store_response_stream(item, body).await.is_err());
+        let mut resp = backend.get_response(key).await.unwrap().unwrap();
+        let body = buffer_body(resp.take_body()).await.unwrap();
+        assert!(body == expected, "body must not be corrupted");
+    }
+
+    #[ntex::test]
+    async fn test_touch() {
+        let config = Config {
+            max_body_chunk_size: 2,
+            ..Default::default()
+        };
+        let backend = RedisBackend::new(config, None).unwrap();
+        backend.connect().await.unwrap();
+
+        // Chunked item with a short TTL
+        let key = make_uniq_key();
+        let skey = make_uniq_key();
+        let item = Item::new_with_skeys(
+            key.clone(),
+            make_response("hello"),
+            vec![skey.clone()],
+            Duration::from_secs(1),
+        );
+        let stored = backend.store_item(item).await.unwrap();
+        assert_eq!(stored.num_chunks, 3);
+
+        // All chunks must survive past the original TTL
+        let touched = backend.touch(key.clone(), Duration::from_secs(3)).await;
+        assert!(touched.unwrap());
+        tokio::time::sleep(Duration::from_millis(1500)).await;
+        let mut resp = backend.get_response(key.clone()).await.unwrap().unwrap();
+        let body = buffer_body(resp.take_body()).await.unwrap();
+        assert_eq!(body, "hello");
+
+        // Surrogate keys must not expire before the item
+        let touched = backend.touch(key, Duration::from_secs(86400 * 2)).await;
+        assert!(touched.unwrap());
+        let ttl: i64 = backend.pool.ttl(make_redis_key("", &skey)).await.unwrap();
+        assert!(ttl > 86400);
+
+        // Missing key
+        let touched = backend.touch(make_uniq_key(), Duration::from_secs(3)).await;
+        assert!(!touched.unwrap());
+    }
+
+    #[ntex::test]
+    async fn test_store_response_nx() {
+        let config = Config {
+            max_body_chunk_size: 2,
+            ..Default::default()
+        };
+        let backend = RedisBackend::new(config, None).unwrap();
+        backend.connect().await.unwrap();
+
+        for body in ["a", "hello"] {
+            // Concurrent fills of the same key (non-chunked and chunked bodies)
+            let key = make_uniq_key();
+            let item = |body| Item::new(key.clone(), make_response(body), Duration::from_secs(3));
+            let results = join_all([
+                backend.store_response_nx(item(body)),
+                backend.store_response_nx(item("world!!!")),
+            ])
+            .await;
+            assert_eq!(results.iter().filter(|r| *r.as_ref().unwrap()).count(), 1);
+            let winner = if *results[0].as_ref().unwrap() {
+                body
+            } else {
+                "world!!!"
+            };
+
+            // Second store does not overwrite the existing item
+            let stored = backend.store_response_nx(item("other")).await.unwrap();
+            assert!(!stored);
+            let mut resp = backend.get_response(key.clone()).await.unwrap().unwrap();
+            assert_eq!(buffer_body(resp.take_body()).await.unwrap(), winner);
+
+            // No orphan chunks are left by the losing store
+            let item_enc: Vec<u8> = read_value(&backend.pool, false, make_redis_key("", &key))
+                .await
+                .unwrap();
+            let nonce = decode_response_item(&item_enc).unwrap().unwrap().nonce;
+            let chunk: Option<Vec<u8>> =
+                read_value(&backend.pool, false, make_chunk_key("", &key, nonce, 3))
+                    .await
+                    .unwrap();
+            assert_eq!(chunk.is_some(), winner == "world!!!");
+        }
+
+        // Items invalidated by their surrogate keys are treated as absent
+        let key = make_uniq_key();
+        let skey = make_uniq_key();
+        let item = |body| {
+            let mut item = Item::new(key.clone(), make_response(body), Duration::from_secs(3));
+            item.surrogate_keys = vec![skey.clone()];
+            item
+        };
+        assert!(backend.store_response_nx(item("hello")).await.unwrap());
+        (backend.delete_responses(ItemKey::Surrogate(skey.clone())))
+            .await
+            .unwrap();
+        // Surrogate keys invalidate everything up to (and including) their timestamp
+        tokio::time::sleep(Duration::from_millis(1100)).await;
+        assert!(backend.store_response_nx(item("world!!!")).await.unwrap());
+        let mut resp = backend.get_response(key.clone()).await.unwrap().unwrap();
+        assert_eq!(buffer_body(resp.take_body()).await.unwrap(), "world!!!");
+        assert!(!backend.store_response_nx(item("other")).await.unwrap());
+    }
+
+    #[ntex::test]
+    async fn test_incr_counter() {
+        let backend = RedisBackend::new(Config::default(), None).unwrap();
+        backend.connect().await.unwrap();
+
+        let key = make_uniq_key();
+        let ttl = Duration::from_secs(1);
+        for i in 1..=3 {
+            assert_eq!(backend.incr_counter(key.clone(), ttl).await.unwrap(), i);
+        }
+        // Counters do not clash with responses
+        assert!(!backend.has_response(key.clone()).await.unwrap());
+
+        // Counter restarts after expiration (increments do not extend the TTL)
+        tokio::time::sleep(Duration::from_millis(1100)).await;
+        assert_eq!(backend.incr_counter(key, ttl).await.unwrap(), 1);
+    }
+
+    #[ntex::test]
+    async fn test_prefer_replica_reads() {
+        let config: Config = serde_json::from_value(serde_json::json!({
+            "prefer_replica_reads": true,
+            "max_body_chunk_size": 2,
+        }))
+        .unwrap();
+        assert!(config.prefer_replica_reads);
+        let backend = RedisBackend::new(config, None).unwrap();
+        assert!(backend.config.prefer_replica_reads);
+        backend.connect().await.unwrap();
+
+        let key = make_uniq_key();
+
+        // Cache response (writes always go to the primary)
+        let resp = make_response("hello, world");
+        let surrogate_keys = vec!["skey"];
+        backend
+            .store_response(Item::new_with_skeys(
+                key.clone(),
+                resp,
+                surrogate_keys,
+                Duration::from_secs(3),
+            ))
+            .await
+            .unwrap();
+
+        // Fetch it back (falls back to the primary when no replicas are available)
+        let mut resp = backend.get_response(key.clone()).await.unwrap().unwrap();
+        let body = buffer_body(resp.take_body()).await.unwrap().to_vec();
+        assert_eq!(String::from_utf8(body).unwrap(), "hello, world");
+    }
+
+    #[ntex::test]
+    async fn test_force_streaming_body() {
+        let config = Config {
+            max_body_chunk_size: 0, // Store body in one chunk
+            compression_level: Some(1),
+            force_streaming_body: true,
+            ..Default::default()
+        };
+        let backend = RedisBackend::new(config, None).unwrap();
+        backend.connect().await.unwrap();
+
+        let key = make_uniq_key();
+
+        // Cache 5 MB response
+        let body_len = 5 * 1024 * 1024;
+        let resp = make_response(vec![b'a'; body_len]);
+        let stored = backend
+            .store_item(Item::new(key.clone(), resp, Duration::from_secs(3)))
+            .await
+            .unwrap();
+        assert_eq!(stored.num_chunks, 1);
+
+        // Fetch it back as a stream
+        let mut resp = backend.get_response(key.clone()).await.unwrap().unwrap();
+        let mut body = resp.take_body();
+        assert_eq!(body.size(), BodySize::Sized(body_len as u64));
+        let mut received = 0;
+        while let Some(chunk) = poll_fn(|cx| body.poll_next_chunk(cx)).await {
+            let chunk = chunk.unwrap();
+            // The body must be decoded chunk by chunk
+            assert!(chunk.len() < body_len);
+            assert!(chunk.iter().all(|&b| b == b'a'));
+            received += chunk.len();
+        }
+        assert_eq!(received, body_len);
+    }
+
     #[ntex::test]
     async fn test_compression() {
-        let mut config = Config::default();
-        config.compression_level = Some(22);
+        let config = Config {
+            compression_level: Some(22),
+            ..Default::default()
+        };
         let backend = RedisBackend::new(config, None).unwrap();
         backend.connect().await.unwrap();
 
@@ -715,6 +2572,56 @@ mod tests {
         assert_eq!(String::from_utf8(body).unwrap(), "hello, world");
     }
 
+    #[ntex::test]
+    async fn test_compression_override() {
+        let config = Config {
+            compression_level: Some(1),
+            ..Default::default()
+        };
+        let backend = RedisBackend::new(config, None).unwrap();
+        backend.connect().await.unwrap();
+
+        // Stores the body and returns flags of the stored item
+        let store = |body: Vec<u8>, compress: Option<bool>| {
+            let backend = &backend;
+            async move {
+                let key = make_uniq_key();
+                let mut item = Item::new(
+                    key.clone(),
+                    make_response(body.clone()),
+                    Duration::from_secs(3),
+                );
+                item.compress = compress;
+                backend.store_response(item).await.unwrap();
+
+                let mut resp = backend.get_response(key.clone()).await.unwrap().unwrap();
+                let stored_body = buffer_body(resp.take_body()).await.unwrap();
+                assert_eq!(stored_body, body);
+
+                let data: Vec<u8> = backend.pool.get(make_redis_key("", &key)).await.unwrap();
+                decode_response_item(&data).unwrap().unwrap().flags
+            }
+        };
+        let (small, big) = (vec![b'a'; 50], vec![b'a'; 1000]);
+
+        // Default: small bodies are not compressed
+        assert!(!store(small.clone(), None)
+            .await
+            .contains(Flags::BODY_COMPRESSED));
+        assert!(store(big.clone(), None)
+            .await
+            .contains(Flags::BODY_COMPRESSED));
+
+        // Forced on: compress even small bodies
+        assert!(store(small, Some(true))
+            .await
+            .contains(Flags::BODY_COMPRESSED));
+
+        // Forced off: never compress
+        let flags = store(big, Some(false)).await;
+        assert!(!flags.intersects(Flags::BODY_COMPRESSED | Flags::HEADERS_COMPRESSED));
+    }
+
     #[ntex::test]
     async fn test_encryption() {
         let mut config = Config::default();
@@ -741,6 +2648,44 @@ mod tests {
         assert_eq!(String::from_utf8(body).unwrap(), "hello, world");
     }
 
+    #[ntex::test]
+    async fn test_encryption_tampered() {
+        let config = Config {
+            encryption_key: Some(Bytes::from_static(&[16; 32])),
+            ..Default::default()
+        };
+        let backend = RedisBackend::new(config, None).unwrap();
+        backend.connect().await.unwrap();
+
+        let key = make_uniq_key();
+        let mut item = Item::new(key.clone(), make_response("hello"), Duration::from_secs(3));
+        item.encrypt = true;
+        backend.store_response(item).await.unwrap();
+
+        // Flip the last byte of the encrypted body
+        let data: Vec<u8> = backend.pool.get(make_redis_key("", &key)).await.unwrap();
+        let mut response_item = decode_response_item(&data).unwrap().unwrap();
+        let mut body = response_item.body.to_vec();
+        *body.last_mut().unwrap() ^= 1;
+        response_item.body = Bytes::from(body);
+        let data = encode_response_item(&response_item).unwrap();
+        backend
+            .pool
+            .set::<(), _, _>(
+                make_redis_key("", &key),
+                data,
+                Some(Expiration::EX(3)),
+                None,
+                false,
+            )
+            .await
+            .unwrap();
+
+        // Must be a cache miss
+        let resp = backend.get_response(key).await.unwrap();
+        assert!(resp.is_none());
+    }
+
     #[ntex::test]
     async fn test_chunked_compression_encryption() {
         let mut config = Config::default();
@@ -803,4 +2748,381 @@ mod tests {
         let resp = backend.get_response(key.clone()).await.unwrap();
         assert!(matches!(resp, None));
     }
+
+    #[ntex::test]
+    async fn test_stats() {
+        let backend = RedisBackend::new(Config::default(), None).unwrap();
+        backend.connect().await.unwrap();
+
+        let key = make_uniq_key();
+        let item = Item::new(key.clone(), make_response("hello"), Duration::from_secs(3));
+        backend.store_response(item).await.unwrap();
+        backend.get_response(key.clone()).await.unwrap().unwrap();
+        backend.get_response(key).await.unwrap().unwrap();
+
+        let stats = Storage::stats(&backend).unwrap();
+        assert_eq!(stats.fetch.samples, 2);
+        assert_eq!(stats.store.samples, 1);
+        assert!(stats.fetch.p50 > Duration::ZERO && stats.fetch.p50 <= stats.fetch.p99);
+        assert!(stats.network_max >= stats.network_avg);
+    }
+
+    #[ntex::test]
+    async fn test_key_prefix() {
+        let make_backend = |prefix: &str| {
+            let config = Config {
+                key_prefix: prefix.to_string(),
+                ..Default::default()
+            };
+            RedisBackend::new(config, None).unwrap()
+        };
+        let backend_a = make_backend("a:");
+        let backend_b = make_backend("b:");
+        backend_a.connect().await.unwrap();
+        backend_b.connect().await.unwrap();
+
+        // Chunks must share the hash tag with the (prefixed) item key
+        assert_eq!(make_redis_key("a:", "key"), RedisKey::from("a:a2V5"));
+        assert_eq!(
+            make_chunk_key("a:", "key", None, 1),
+            RedisKey::from("{a:a2V5}|1")
+        );
+        assert_eq!(
+            make_chunk_key("a:", "key", Some(0xabc), 1),
+            RedisKey::from("{a:a2V5}|00000abc|1")
+        );
+
+        let key = make_uniq_key();
+        let skey = make_uniq_key();
+        for (backend, body) in [(&backend_a, "hello, a"), (&backend_b, "hello, b")] {
+            backend
+                .store_response(Item::new_with_skeys(
+                    key.clone(),
+                    make_response(body),
+                    vec![skey.clone()],
+                    Duration::from_secs(3),
+                ))
+                .await
+                .unwrap();
+        }
+
+        // Each backend sees only its own item
+        let mut resp = backend_a.get_response(key.clone()).await.unwrap().unwrap();
+        let body = buffer_body(resp.take_body()).await.unwrap();
+        assert_eq!(body, "hello, a");
+        let mut resp = backend_b.get_response(key.clone()).await.unwrap().unwrap();
+        let body = buffer_body(resp.take_body()).await.unwrap();
+        assert_eq!(body, "hello, b");
+
+        // Deleting by surrogate key does not affect another keyspace
+        backend_a
+            .delete_responses(ItemKey::Surrogate(skey))
+            .await
+            .unwrap();
+        assert!(backend_a.get_response(key.clone()).await.unwrap().is_none());
+        assert!(backend_b.get_response(key.clone()).await.unwrap().is_some());
+    }
+
+    #[ntex::test]
+    async fn test_surrogate_keys_centralized() {
+        // Disable internal cache to always fetch surrogate keys from Redis (using `MGET`)
+        let config = Config {
+            internal_cache_size: 0,
+            ..Default::default()
+        };
+        assert!(matches!(config.server, ServerConfig::Centralized { .. }));
+        let backend = RedisBackend::new(config, None).unwrap();
+        backend.connect().await.unwrap();
+
+        let (key1, key2) = (make_uniq_key(), make_uniq_key());
+        let skeys = (0..5).map(|_| make_uniq_key()).collect::<Vec<_>>();
+        for (key, skeys) in [(&key1, &skeys[..]), (&key2, &skeys[..1])] {
+            backend
+                .store_response(Item::new_with_skeys(
+                    key.clone(),
+                    make_response("hello, world"),
+                    skeys.to_vec(),
+                    Duration::from_secs(3),
+                ))
+                .await
+                .unwrap();
+        }
+
+        let mut resp = backend.get_response(key1.clone()).await.unwrap().unwrap();
+        let body = buffer_body(resp.take_body()).await.unwrap().to_vec();
+        assert_eq!(String::from_utf8(body).unwrap(), "hello, world");
+        assert!(backend.get_response(key2.clone()).await.unwrap().is_some());
+
+        // Invalidate one of the surrogate keys
+        backend
+            .delete_responses(ItemKey::Surrogate(skeys[3].clone()))
+            .await
+            .unwrap();
+        assert!(backend.get_response(key1).await.unwrap().is_none());
+        assert!(backend.get_response(key2).await.unwrap().is_some());
+    }
+
+    #[ntex::test]
+    async fn test_serve_stale_on_surrogate_error() {
+        for serve_stale in [false, true] {
+            let config = Config {
+                internal_cache_size: 0,
+                serve_stale_on_surrogate_error: serve_stale,
+                ..Default::default()
+            };
+            let backend = RedisBackend::new(config, None).unwrap();
+            backend.connect().await.unwrap();
+
+            let key = make_uniq_key();
+            backend
+                .store_response(Item::new_with_skeys(
+                    key.clone(),
+                    make_response("hello, world"),
+                    vec![make_uniq_key()],
+                    Duration::from_secs(3),
+                ))
+                .await
+                .unwrap();
+
+            // Fresh response has no warning
+            let resp = backend.get_response(key.clone()).await.unwrap().unwrap();
+            assert!(!resp.headers().contains_key("warning"));
+
+            backend
+                .fail_surrogate_fetches
+                .store(true, Ordering::Relaxed);
+            let result = backend.get_response(key.clone()).await;
+            if serve_stale {
+                let mut resp = result.unwrap().unwrap();
+                assert_eq!(
+                    resp.headers().get("warning").unwrap(),
+                    "110 - \"Response is Stale\""
+                );
+                let body = buffer_body(resp.take_body()).await.unwrap();
+                assert_eq!(body, "hello, world");
+            } else {
+                assert!(result.is_err());
+            }
+        }
+    }
+
+    #[ntex::test]
+    async fn test_surrogate_keys_mget_parity() {
+        let config = Config {
+            internal_cache_size: 0,
+            ..Default::default()
+        };
+        let backend = RedisBackend::new(config, None).unwrap();
+        backend.connect().await.unwrap();
+
+        let key = make_uniq_key();
+        let skeys = (0..20).map(|_| make_uniq_key()).collect::<Vec<_>>();
+        backend
+            .store_response(Item::new_with_skeys(
+                key.clone(),
+                make_response("hello, world"),
+                skeys.clone(),
+                Duration::from_secs(3),
+            ))
+            .await
+            .unwrap();
+
+        // Values fetched using `MGET` must match the ones fetched per key (including missing keys)
+        let mut skeys_to_fetch = skeys.clone();
+        skeys_to_fetch.insert(10, make_uniq_key());
+        let values = backend
+            .fetch_surrogate_keys(&skeys_to_fetch, false)
+            .await
+            .unwrap();
+        assert_eq!(values.len(), skeys_to_fetch.len());
+        for (sk, value) in skeys_to_fetch.iter().zip(values) {
+            let expected: RedisValue = read_value(&backend.pool, false, make_redis_key("", sk))
+                .await
+                .unwrap();
+            assert_eq!(value, expected);
+        }
+        assert!(backend.get_response(key.clone()).await.unwrap().is_some());
+
+        // Invalidate one of the surrogate keys
+        backend
+            .delete_responses(ItemKey::Surrogate(skeys[17].clone()))
+            .await
+            .unwrap();
+        assert!(backend.get_response(key).await.unwrap().is_none());
+    }
+
+    #[ntex::test]
+    async fn test_max_surrogate_keys_per_read() {
+        for mode in [ExcessSurrogateKeys::Batch, ExcessSurrogateKeys::Warn] {
+            let config = Config {
+                internal_cache_size: 0,
+                max_surrogate_keys_per_read: Some(4),
+                excess_surrogate_keys: mode,
+                ..Default::default()
+            };
+            let backend = RedisBackend::new(config, None).unwrap();
+            backend.connect().await.unwrap();
+
+            let key = make_uniq_key();
+            let skeys = (0..10).map(|_| make_uniq_key()).collect::<Vec<_>>();
+            backend
+                .store_response(Item::new_with_skeys(
+                    key.clone(),
+                    make_response("hello, world"),
+                    skeys.clone(),
+                    Duration::from_secs(3),
+                ))
+                .await
+                .unwrap();
+
+            // Keys beyond the limit are fetched in a single pipeline (in the original order)
+            let values = backend.fetch_surrogate_keys(&skeys, false).await.unwrap();
+            assert_eq!(values.len(), skeys.len());
+            assert!(values.iter().all(|v| v.as_bytes().is_some()));
+            assert!(backend.get_response(key.clone()).await.unwrap().is_some());
+
+            // Invalidate one of the excess surrogate keys
+            backend
+                .delete_responses(ItemKey::Surrogate(skeys[7].clone()))
+                .await
+                .unwrap();
+            let resp = backend.get_response(key.clone()).await.unwrap();
+            match mode {
+                ExcessSurrogateKeys::Batch => assert!(resp.is_none()),
+                // Excess keys are not checked
+                ExcessSurrogateKeys::Warn => assert!(resp.is_some()),
+            }
+
+            // Keys within the limit are always checked
+            backend
+                .delete_responses(ItemKey::Surrogate(skeys[1].clone()))
+                .await
+                .unwrap();
+            assert!(backend.get_response(key).await.unwrap().is_none());
+        }
+    }
+
+    #[ntex::test]
+    async fn test_max_concurrent_stores() {
+        let config = Config {
+            max_concurrent_stores: Some(2),
+            ..Default::default()
+        };
+        let backend = RedisBackend::new(config, None).unwrap();
+        backend.connect().await.unwrap();
+
+        let store = || {
+            let item = Item::new(
+                make_uniq_key(),
+                make_response("hello"),
+                Duration::from_secs(3),
+            );
+            backend.store_response(item)
+        };
+
+        // Take all slots, stores must wait for them
+        let semaphore = backend.store_semaphore.clone().unwrap();
+        let permits = semaphore.acquire_many(2).await.unwrap();
+        let mut stores = Box::pin(join_all((0..5).map(|_| store())));
+        let result = tokio::time::timeout(Duration::from_millis(200), &mut stores).await;
+        assert!(result.is_err(), "stores must wait for a free slot");
+
+        // Release the slots
+        drop(permits);
+        let results = stores.await;
+        assert!(results.iter().all(|r| r.is_ok()));
+        assert_eq!(semaphore.available_permits(), 2);
+    }
+
+    #[ntex::test]
+    async fn test_max_concurrent_stores_timeout() {
+        let mut config = Config {
+            max_concurrent_stores: Some(1),
+            ..Default::default()
+        };
+        config.timeouts.store_timeout = 0.1;
+        let backend = RedisBackend::new(config, None).unwrap();
+        backend.connect().await.unwrap();
+
+        // Waiting for a free slot is bounded by the store timeout
+        let semaphore = backend.store_semaphore.clone().unwrap();
+        let _permit = semaphore.acquire().await.unwrap();
+        let item = Item::new(
+            make_uniq_key(),
+            make_response("hello"),
+            Duration::from_secs(3),
+        );
+        assert!(backend.store_response(item).await.is_err());
+    }
+
+    #[test]
+    fn test_jittered_ttl() {
+        use rand::{rngs::StdRng, SeedableRng};
+
+        let mut rng = StdRng::seed_from_u64(42);
+        let ttls = (0..1000)
+            .map(|_| jittered_ttl(86400, &mut rng))
+            .collect::<Vec<_>>();
+        assert!(ttls.iter().all(|ttl| (86400..=95040).contains(ttl)));
+        assert!(ttls.iter().any(|&ttl| ttl > 86400));
+
+        // Small TTLs are never reduced
+        assert!((0..100).all(|_| jittered_ttl(1, &mut rng) == 1));
+    }
+
+    #[ntex::test]
+    async fn test_surrogate_keys_ttl() {
+        // Non-positive TTL is not allowed
+        let config = Config {
+            surrogate_keys_ttl: 0,
+            ..Default::default()
+        };
+        assert!(RedisBackend::new(config, None).is_err());
+
+        let two_days = 2 * 86400;
+        let config = Config {
+            surrogate_keys_ttl: two_days,
+            ..Default::default()
+        };
+        let backend = RedisBackend::new(config, None).unwrap();
+        backend.connect().await.unwrap();
+
+        let key = make_uniq_key();
+        let skey = make_uniq_key();
+
+        // Cache response with 2 days TTL
+        let resp = make_response("hello, world");
+        backend
+            .store_response(Item::new_with_skeys(
+                key.clone(),
+                resp,
+                vec![skey.clone()],
+                Duration::from_secs(two_days as u64),
+            ))
+            .await
+            .unwrap();
+
+        // Surrogate key must live as long as the response
+        let ttl: i64 = backend.pool.ttl(make_redis_key("", &skey)).await.unwrap();
+        assert!(ttl > 86400, "surrogate key ttl is {ttl}");
+        assert!(backend.get_response(key).await.unwrap().is_some());
+    }
+
+    #[test]
+    fn test_compression_ratio() {
+        let (provider, registry) = crate::metrics::test_meter_provider();
+        let metrics = RedisMetrics::with_meter(provider.meter("redis"));
+
+        metrics.compression_ratio_rec("redis", 1000, 250);
+        // Empty compressed data must not produce infinite ratio
+        metrics.compression_ratio_rec("redis", 10, 0);
+
+        let families = registry.gather();
+        let family = (families.iter())
+            .find(|family| family.get_name() == "storage_compression_ratio")
+            .unwrap();
+        let histogram = family.get_metric()[0].get_histogram();
+        assert_eq!(histogram.get_sample_count(), 2);
+        assert_eq!(histogram.get_sample_sum(), 14.0);
+    }
 }
@@ -20,11 +20,34 @@ pub struct Config {
     #[serde(default)]
     pub timeouts: TimeoutConfig,
 
+    /// Number of retries of fetch, store and delete operations failed with a transient error
+    /// (e.g. connection error or cluster resharding). Retries share the operation timeout.
+    #[serde(default)]
+    pub retries: u32,
+    /// Delay (in seconds) before the first retry, doubled for every next one
+    #[serde(
+        default = "Config::default_retry_backoff",
+        deserialize_with = "crate::config::deserialize_seconds"
+    )]
+    pub retry_backoff: f32,
+
     #[serde(default = "Config::default_pool_size")]
     pub pool_size: usize,
 
+    /// Send read operations to replica nodes (if available)
+    #[serde(default)]
+    pub prefer_replica_reads: bool,
+
     #[serde(default = "Config::default_max_body_chunk_size")]
     pub max_body_chunk_size: usize,
+    /// Merge small (decoded) chunks of stored bodies into chunks of at least this size (in bytes).
+    /// Chunks are returned as decoded by default.
+    pub body_chunk_size: Option<usize>,
+    /// Maximum (original) body size of responses to store, larger ones are skipped
+    pub max_cacheable_body_size: Option<usize>,
+    /// Always return streaming body (even for single-chunk items) to not keep decoded body in memory
+    #[serde(default)]
+    pub force_streaming_body: bool,
     pub compression_level: Option<i32>,
     pub max_ttl: Option<u64>,
 
@@ -36,11 +59,73 @@ pub struct Config {
     pub internal_cache_size: usize,
     #[serde(default = "Config::default_internal_cache_ttl")]
     pub internal_cache_ttl: f64,
+    /// Automatically lengthen `internal_cache_ttl` when the internal cache miss rate is high
+    pub adaptive_internal_cache_ttl: Option<AdaptiveTtlConfig>,
+
+    /// Maximum number of concurrent store operations (excess operations wait up to `store_timeout`)
+    pub max_concurrent_stores: Option<usize>,
+
+    /// Prefix for all keys to isolate keyspaces of different deployments sharing Redis
+    #[serde(default)]
+    pub key_prefix: String,
+
+    /// Serve responses (possibly stale) if their surrogate keys cannot be fetched due to an error.
+    /// Missing surrogate keys still invalidate responses.
+    #[serde(default)]
+    pub serve_stale_on_surrogate_error: bool,
+    /// Add `Warning: 110` header to responses served without checking surrogate keys
+    #[serde(default = "Config::default_stale_warning")]
+    pub stale_warning: bool,
+
+    /// TTL (in seconds) of surrogate keys, should be not less than TTL of responses
+    #[serde(default = "Config::default_surrogate_keys_ttl")]
+    pub surrogate_keys_ttl: i64,
+
+    /// Maximum number of surrogate keys fetched individually (concurrently) per read
+    pub max_surrogate_keys_per_read: Option<usize>,
+    /// How to check surrogate keys beyond `max_surrogate_keys_per_read`
+    #[serde(default)]
+    pub excess_surrogate_keys: ExcessSurrogateKeys,
 
     // Optional encryption key
     pub encryption_key: Option<Bytes>,
 }
 
+/// Handling of surrogate keys beyond the per-read limit
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+pub enum ExcessSurrogateKeys {
+    /// Fetch the excess keys in a single pipeline
+    #[default]
+    #[serde(rename = "batch")]
+    Batch,
+    /// Skip the excess keys (without checking them) and log a warning
+    #[serde(rename = "warn")]
+    Warn,
+}
+
+/// Adaptive TTL of the internal (surrogate keys) cache
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub struct AdaptiveTtlConfig {
+    /// Upper bound (in seconds) of the effective TTL
+    pub max_ttl: f64,
+    /// Miss rate (0..1) above which the TTL is lengthened
+    #[serde(default = "AdaptiveTtlConfig::default_miss_rate_threshold")]
+    pub miss_rate_threshold: f64,
+    /// Period (in seconds) to calculate the miss rate over
+    #[serde(default = "AdaptiveTtlConfig::default_window")]
+    pub window: f64,
+}
+
+impl AdaptiveTtlConfig {
+    const fn default_miss_rate_threshold() -> f64 {
+        0.5
+    }
+
+    const fn default_window() -> f64 {
+        10.0
+    }
+}
+
 #[derive(Clone, Debug, Deserialize)]
 pub enum ServerConfig {
     #[serde(rename = "centralized")]
@@ -127,14 +212,28 @@ impl Default for Config {
             username: None,
             password: None,
             timeouts: TimeoutConfig::default(),
+            retries: 0,
+            retry_backoff: Config::default_retry_backoff(),
             pool_size: Config::default_pool_size(),
+            prefer_replica_reads: false,
             max_body_chunk_size: Config::default_max_body_chunk_size(),
+            body_chunk_size: None,
+            max_cacheable_body_size: None,
+            force_streaming_body: false,
             compression_level: None,
             max_ttl: None,
             wait_for_connect: Some(0.0),
             lazy: false,
             internal_cache_size: Config::default_internal_cache_size(),
             internal_cache_ttl: Config::default_internal_cache_ttl(),
+            adaptive_internal_cache_ttl: None,
+            max_concurrent_stores: None,
+            key_prefix: String::new(),
+            serve_stale_on_surrogate_error: false,
+            stale_warning: Config::default_stale_warning(),
+            surrogate_keys_ttl: Config::default_surrogate_keys_ttl(),
+            max_surrogate_keys_per_read: None,
+            excess_surrogate_keys: ExcessSurrogateKeys::default(),
             encryption_key: None,
         }
     }
@@ -145,6 +244,10 @@ impl Config {
         2 * num_cpus::get()
     }
 
+    const fn default_retry_backoff() -> f32 {
+        0.01
+    }
+
     const fn default_max_body_chunk_size() -> usize {
         1024 * 1024 // 1 MB
     }
@@ -157,6 +260,14 @@ impl Config {
         0.0
     }
 
+    const fn default_stale_warning() -> bool {
+        true
+    }
+
+    const fn default_surrogate_keys_ttl() -> i64 {
+        86400 // 1 day
+    }
+
     pub(super) fn into_fred_configs(self) -> Result<(RedisConfig, ConnectionConfig)> {
         let redis_config = RedisConfig {
             fail_fast: !self.lazy,
@@ -0,0 +1,156 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use crate::storage::LatencyStats;
+
+// Upper bounds (in microseconds) of the histogram buckets, the last bucket is unbounded
+static BOUNDARIES: &[u64] = &[
+    100, 250, 500, 750, 1_000, 1_500, 2_000, 2_500, 3_000, 4_000, 5_000, 7_500, 10_000, 15_000,
+    20_000, 25_000, 30_000, 40_000, 50_000, 75_000, 100_000, 150_000, 200_000, 250_000, 300_000,
+    400_000, 500_000, 750_000, 1_000_000, 2_500_000, 5_000_000, 10_000_000,
+];
+
+/// Lock-free histogram of operation latencies.
+///
+/// Percentiles are estimated as upper bounds of the buckets (capped by the maximum
+/// observed latency), reading them does not reset the histogram.
+#[derive(Debug)]
+pub(super) struct LatencyRecorder {
+    buckets: Box<[AtomicU64]>,
+    max: AtomicU64,
+}
+
+impl Default for LatencyRecorder {
+    fn default() -> Self {
+        LatencyRecorder {
+            buckets: (0..=BOUNDARIES.len()).map(|_| AtomicU64::new(0)).collect(),
+            max: AtomicU64::new(0),
+        }
+    }
+}
+
+impl LatencyRecorder {
+    pub(super) fn record(&self, latency: Duration) {
+        self.record_n(latency, 1);
+    }
+
+    /// Records `n` operations completed with the same latency (e.g. commands of a pipeline)
+    pub(super) fn record_n(&self, latency: Duration, n: u64) {
+        if n == 0 {
+            return;
+        }
+        let micros = latency.as_micros().min(u64::MAX as u128) as u64;
+        let index = BOUNDARIES.partition_point(|&bound| bound < micros);
+        self.buckets[index].fetch_add(n, Ordering::Relaxed);
+        self.max.fetch_max(micros, Ordering::Relaxed);
+    }
+
+    pub(super) fn stats(&self) -> LatencyStats {
+        let counts = (self.buckets.iter())
+            .map(|count| count.load(Ordering::Relaxed))
+            .collect::<Vec<_>>();
+        let samples = counts.iter().sum::<u64>();
+        let max = self.max.load(Ordering::Relaxed);
+        // Nearest-rank percentile
+        let percentile = |p: f64| {
+            let rank = (p * samples as f64).ceil() as u64;
+            if rank == 0 {
+                return Duration::ZERO;
+            }
+            let mut seen = 0;
+            for (index, count) in counts.iter().enumerate() {
+                seen += count;
+                if seen >= rank {
+                    let bound = BOUNDARIES.get(index).copied().unwrap_or(max);
+                    return Duration::from_micros(bound.min(max));
+                }
+            }
+            Duration::from_micros(max)
+        };
+        LatencyStats {
+            p50: percentile(0.5),
+            p99: percentile(0.99),
+            samples,
+        }
+    }
+}
+
+/// Cumulative network latency of commands folded from the (resettable) client metrics
+#[derive(Debug, Default)]
+pub(super) struct NetworkLatency {
+    sum: AtomicU64,
+    samples: AtomicU64,
+    max: AtomicU64,
+}
+
+impl NetworkLatency {
+    /// Adds `samples` commands with the average and maximum latencies (in milliseconds)
+    pub(super) fn add(&self, avg_ms: f64, max_ms: i64, samples: u64) {
+        if samples == 0 {
+            return;
+        }
+        let sum = (avg_ms.max(0.0) * 1000.0 * samples as f64) as u64;
+        self.sum.fetch_add(sum, Ordering::Relaxed);
+        self.samples.fetch_add(samples, Ordering::Relaxed);
+        let max = max_ms.max(0) as u64 * 1000;
+        self.max.fetch_max(max, Ordering::Relaxed);
+    }
+
+    /// Returns the average and maximum latencies
+    pub(super) fn stats(&self) -> (Duration, Duration) {
+        let avg = match self.samples.load(Ordering::Relaxed) {
+            0 => 0,
+            samples => self.sum.load(Ordering::Relaxed) / samples,
+        };
+        let max = self.max.load(Ordering::Relaxed);
+        (Duration::from_micros(avg), Duration::from_micros(max))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_latency_recorder() {
+        let recorder = LatencyRecorder::default();
+        assert_eq!(recorder.stats(), LatencyStats::default());
+
+        for i in 1..=100 {
+            recorder.record(Duration::from_millis(i));
+        }
+        let stats = recorder.stats();
+        assert_eq!(stats.p50, Duration::from_millis(50));
+        assert_eq!(stats.p99, Duration::from_millis(100));
+        assert_eq!(stats.samples, 100);
+        // Reading stats does not reset them
+        assert_eq!(recorder.stats(), stats);
+
+        // Pipelined commands are recorded individually
+        recorder.record_n(Duration::from_millis(3), 100);
+        let stats = recorder.stats();
+        assert_eq!(stats.p50, Duration::from_millis(3));
+        assert_eq!(stats.samples, 200);
+
+        // Percentiles are capped by the maximum latency
+        let recorder = LatencyRecorder::default();
+        recorder.record(Duration::from_secs(60));
+        recorder.record(Duration::from_micros(1_100));
+        let stats = recorder.stats();
+        assert_eq!(stats.p50, Duration::from_micros(1_500));
+        assert_eq!(stats.p99, Duration::from_secs(60));
+    }
+
+    #[test]
+    fn test_network_latency() {
+        let network = NetworkLatency::default();
+        assert_eq!(network.stats(), (Duration::ZERO, Duration::ZERO));
+
+        network.add(2.0, 5, 10);
+        network.add(0.0, 0, 0);
+        network.add(4.0, 3, 10);
+        let stats = (Duration::from_millis(3), Duration::from_millis(5));
+        assert_eq!(network.stats(), stats);
+        assert_eq!(network.stats(), stats);
+    }
+}
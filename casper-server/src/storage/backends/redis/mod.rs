@@ -1,5 +1,7 @@
 pub use client::RedisBackend;
 pub use config::Config;
 
+mod adaptive_ttl;
 mod client;
 mod config;
+mod latency;
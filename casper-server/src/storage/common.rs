@@ -1,4 +1,5 @@
 use ntex::http::header::HeaderMap;
+use ntex::http::Version;
 use serde::{Deserialize, Serialize};
 
 pub fn encode_headers(headers: &HeaderMap) -> Result<Vec<u8>, flexbuffers::SerializationError> {
@@ -11,3 +12,26 @@ pub fn decode_headers(data: &[u8]) -> Result<HeaderMap, flexbuffers::Deserializa
     let deserializer = flexbuffers::Reader::get_root(data)?;
     HeaderMap::deserialize(deserializer)
 }
+
+/// Encodes HTTP version as a single number (e.g. `11` for HTTP/1.1)
+pub fn encode_version(version: Version) -> u8 {
+    match version {
+        Version::HTTP_09 => 9,
+        Version::HTTP_10 => 10,
+        Version::HTTP_2 => 20,
+        Version::HTTP_3 => 30,
+        _ => 11,
+    }
+}
+
+/// Decodes HTTP version encoded by [`encode_version`]
+pub fn decode_version(version: u8) -> Option<Version> {
+    match version {
+        9 => Some(Version::HTTP_09),
+        10 => Some(Version::HTTP_10),
+        11 => Some(Version::HTTP_11),
+        20 => Some(Version::HTTP_2),
+        30 => Some(Version::HTTP_3),
+        _ => None,
+    }
+}
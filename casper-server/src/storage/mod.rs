@@ -1,28 +1,35 @@
 use std::borrow::Cow;
 use std::fmt;
 use std::iter::IntoIterator;
+use std::ops::RangeInclusive;
 use std::time::Duration;
 
 use futures::stream::{self, StreamExt};
 pub(crate) use ntex::http::body::Body;
 use ntex::http::body::MessageBody;
-use ntex::http::{HeaderMap, Response, StatusCode};
+use ntex::http::{HeaderMap, Response, StatusCode, Version};
 use ntex::util::Bytes;
+use regex::Regex;
+use serde::{Deserialize, Deserializer};
 
 pub use backends::Backend;
-pub(crate) use common::{decode_headers, encode_headers};
+pub(crate) use common::{decode_headers, decode_version, encode_headers, encode_version};
 
 pub type Key = Bytes;
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct Item<'a> {
     pub key: Key,
     pub status: StatusCode,
+    /// HTTP version of the original response (if known)
+    pub version: Option<Version>,
     pub headers: Cow<'a, HeaderMap>,
     pub body: Bytes,
     pub surrogate_keys: Vec<Key>,
     pub ttl: Duration,
     pub encrypt: bool,
+    /// Overrides the backend compression settings (if supported)
+    pub compress: Option<bool>,
 }
 
 impl Item<'static> {
@@ -32,11 +39,13 @@ impl Item<'static> {
         Item {
             key: key.into(),
             status: response.status(),
+            version: None,
             headers: Cow::Owned(response.headers().clone()),
             body: body.as_ref().unwrap().clone(),
             surrogate_keys: Vec::new(),
             ttl,
             encrypt: false,
+            compress: None,
         }
     }
 
@@ -51,15 +60,248 @@ impl Item<'static> {
         Item {
             key: key.into(),
             status: response.status(),
+            version: None,
             headers: Cow::Owned(response.headers().clone()),
             body: body.as_ref().unwrap().clone(),
             surrogate_keys: surrogate_keys.into_iter().map(|sk| sk.into()).collect(),
             ttl,
             encrypt: false,
+            compress: None,
         }
     }
 }
 
+/// Information about a stored item
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct StoredItem {
+    /// Number of bytes written to the storage
+    pub size: usize,
+    /// Number of chunks the body was split into
+    pub num_chunks: u32,
+    /// The body exceeds the maximum size the backend is allowed to cache, nothing was stored
+    pub too_large: bool,
+}
+
+impl StoredItem {
+    pub(crate) fn too_large() -> Self {
+        StoredItem {
+            too_large: true,
+            ..Default::default()
+        }
+    }
+}
+
+/// Policy to decide whether a response qualifies to be stored
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct StorePolicy {
+    /// List of status codes allowed to be stored (any status if not set)
+    pub cacheable_statuses: Option<Vec<u16>>,
+    /// Minimum body size (in bytes) to store response
+    pub min_body_size: Option<usize>,
+    /// Maximum body size (in bytes) to store response
+    pub max_body_size: Option<usize>,
+    /// Ordered list of rules to pick TTL by request path (when TTL is not set explicitly)
+    #[serde(default)]
+    pub ttl_rules: Vec<TtlRule>,
+    /// TTL (in seconds) to use if TTL is not set explicitly and no rule matches
+    pub default_ttl: Option<f64>,
+    /// Minimum TTL (in seconds) to store response with
+    pub min_ttl: Option<f64>,
+    /// What to do with responses having TTL below `min_ttl`
+    #[serde(default)]
+    pub min_ttl_action: MinTtlAction,
+    /// Rules to store error responses for a short period of time (negative caching)
+    #[serde(default)]
+    pub negative_cache: Vec<NegativeCacheRule>,
+}
+
+/// Reason why the store policy refuses to store a response
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SkipReason {
+    /// The response status or body size does not qualify the policy
+    NotCacheable,
+    /// TTL is below `min_ttl` (and `min_ttl_action` is `skip`)
+    TtlTooShort,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MinTtlAction {
+    /// Do not store the response
+    #[default]
+    Skip,
+    /// Store the response with `min_ttl`
+    Clamp,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct TtlRule {
+    /// Regular expression to match request path
+    #[serde(deserialize_with = "deserialize_regex")]
+    pub path: Regex,
+    /// TTL (in seconds)
+    pub ttl: f64,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct NegativeCacheRule {
+    /// Error status code or range of status codes (e.g. `"500-599"`)
+    #[serde(deserialize_with = "deserialize_status_range")]
+    pub status: RangeInclusive<u16>,
+    /// TTL (in seconds)
+    pub ttl: f64,
+}
+
+fn deserialize_status_range<'de, D>(deserializer: D) -> Result<RangeInclusive<u16>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum StatusRange {
+        Status(u16),
+        Range(String),
+    }
+
+    let range = match StatusRange::deserialize(deserializer)? {
+        StatusRange::Status(status) => status..=status,
+        StatusRange::Range(range) => {
+            let parse = |s: &str| s.trim().parse::<u16>().map_err(serde::de::Error::custom);
+            match range.split_once('-') {
+                Some((start, end)) => parse(start)?..=parse(end)?,
+                None => parse(&range).map(|status| status..=status)?,
+            }
+        }
+    };
+    if range.is_empty() || *range.start() < 400 || *range.end() > 599 {
+        return Err(serde::de::Error::custom(format!(
+            "invalid error status range `{}-{}`",
+            range.start(),
+            range.end()
+        )));
+    }
+    Ok(range)
+}
+
+fn deserialize_regex<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Regex, D::Error> {
+    let pattern = String::deserialize(deserializer)?;
+    Regex::new(&pattern).map_err(serde::de::Error::custom)
+}
+
+impl StorePolicy {
+    /// Reads store policy from the `store_policy` field of the storage configuration
+    pub fn from_config(config: &serde_json::Value) -> Result<Self, serde_json::Error> {
+        let policy: StorePolicy = match config.get("store_policy") {
+            Some(policy) => serde_json::from_value(policy.clone())?,
+            None => return Ok(StorePolicy::default()),
+        };
+        let ttls = [policy.min_ttl, policy.default_ttl]
+            .into_iter()
+            .flatten()
+            .chain(policy.ttl_rules.iter().map(|r| r.ttl))
+            .chain(policy.negative_cache.iter().map(|r| r.ttl));
+        for ttl in ttls {
+            if !(ttl >= 0.0 && ttl.is_finite()) {
+                let err = format!("invalid ttl `{ttl}` (must be a non-negative number)");
+                return Err(serde::de::Error::custom(err));
+            }
+        }
+        Ok(policy)
+    }
+
+    /// Returns TTL of the first rule matching the request path or the default TTL
+    pub fn ttl_for_path(&self, path: Option<&str>) -> Option<Duration> {
+        let rule = path.and_then(|path| self.ttl_rules.iter().find(|r| r.path.is_match(path)));
+        let ttl = rule.map(|r| r.ttl).or(self.default_ttl)?;
+        Some(Duration::from_secs_f64(ttl.max(0.0)))
+    }
+
+    /// Applies `min_ttl` to the response TTL.
+    ///
+    /// Returns `SkipReason::TtlTooShort` if the response must not be stored.
+    pub fn apply_min_ttl(&self, ttl: Duration) -> Result<Duration, SkipReason> {
+        let min_ttl = match self.min_ttl {
+            Some(min_ttl) => Duration::from_secs_f64(min_ttl.max(0.0)),
+            None => return Ok(ttl),
+        };
+        match self.min_ttl_action {
+            _ if ttl >= min_ttl => Ok(ttl),
+            MinTtlAction::Skip => Err(SkipReason::TtlTooShort),
+            MinTtlAction::Clamp => Ok(min_ttl),
+        }
+    }
+
+    /// Returns TTL to store an error response with (negative caching).
+    ///
+    /// The explicitly set `negative_ttl` takes precedence over the `negative_cache` rules.
+    /// Returns `None` if the response is not an error or no rule matches.
+    pub fn negative_ttl(
+        &self,
+        status: StatusCode,
+        negative_ttl: Option<Duration>,
+    ) -> Option<Duration> {
+        if !status.is_client_error() && !status.is_server_error() {
+            return None;
+        }
+        negative_ttl.or_else(|| {
+            let status = status.as_u16();
+            let rule = self
+                .negative_cache
+                .iter()
+                .find(|r| r.status.contains(&status))?;
+            Some(Duration::from_secs_f64(rule.ttl.max(0.0)))
+        })
+    }
+
+    /// Returns TTL to store a response with the given status and body size.
+    ///
+    /// Error responses eligible for negative caching are stored with the negative TTL
+    /// (bypassing `cacheable_statuses` and `min_ttl`).
+    /// Returns the reason if the response must not be stored.
+    pub fn store_ttl(
+        &self,
+        status: StatusCode,
+        body_size: usize,
+        ttl: Duration,
+        negative_ttl: Option<Duration>,
+    ) -> Result<Duration, SkipReason> {
+        if let Some(negative_ttl) = self.negative_ttl(status, negative_ttl) {
+            return (self.is_body_size_allowed(body_size))
+                .then_some(negative_ttl)
+                .ok_or(SkipReason::NotCacheable);
+        }
+        if !self.is_cacheable(status, body_size) {
+            return Err(SkipReason::NotCacheable);
+        }
+        self.apply_min_ttl(ttl)
+    }
+
+    /// Checks that a response with the given status and body size can be stored
+    pub fn is_cacheable(&self, status: StatusCode, body_size: usize) -> bool {
+        if let Some(statuses) = &self.cacheable_statuses {
+            if !statuses.contains(&status.as_u16()) {
+                return false;
+            }
+        }
+        self.is_body_size_allowed(body_size)
+    }
+
+    /// Returns `true` if the policy restricts the body size (so it must be known to store)
+    pub fn has_body_size_limits(&self) -> bool {
+        self.min_body_size.is_some() || self.max_body_size.is_some()
+    }
+
+    fn is_body_size_allowed(&self, body_size: usize) -> bool {
+        if matches!(self.min_body_size, Some(min_size) if body_size < min_size) {
+            return false;
+        }
+        if matches!(self.max_body_size, Some(max_size) if body_size > max_size) {
+            return false;
+        }
+        true
+    }
+}
+
 #[derive(Clone)]
 pub enum ItemKey {
     Primary(Key),
@@ -75,6 +317,24 @@ impl fmt::Display for ItemKey {
     }
 }
 
+/// Latency percentiles of a storage operation
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct LatencyStats {
+    pub p50: Duration,
+    pub p99: Duration,
+    pub samples: u64,
+}
+
+/// Latency statistics of a storage backend
+#[derive(Clone, Copy, Debug, Default)]
+pub struct StorageStats {
+    pub fetch: LatencyStats,
+    pub store: LatencyStats,
+    /// Average and maximum network round-trip time of all commands (reported by the client)
+    pub network_avg: Duration,
+    pub network_max: Duration,
+}
+
 pub trait Storage {
     type Body: MessageBody;
     type Error;
@@ -93,10 +353,73 @@ pub trait Storage {
 
     async fn store_response(&self, item: Item<'_>) -> Result<usize, Self::Error>;
 
+    /// Stores a response reading its body chunk by chunk (`item.body` is ignored).
+    ///
+    /// Backends supporting chunked items write the body incrementally without buffering it.
+    async fn store_response_stream(
+        &self,
+        item: Item<'_>,
+        body: impl MessageBody,
+    ) -> Result<StoredItem, Self::Error>;
+
+    /// Stores a response only if no (unexpired) response exists under its key.
+    ///
+    /// Returns `true` if the response was stored, `false` if a response already existed.
+    async fn store_response_nx(&self, item: Item<'_>) -> Result<bool, Self::Error>;
+
+    /// Removes all responses from the storage
+    async fn clear(&self) -> Result<(), Self::Error>;
+
+    /// Checks that the storage is alive, returning the round-trip latency
+    async fn ping(&self) -> Result<Duration, Self::Error>;
+
+    /// Sets a new TTL of the response without re-storing it.
+    ///
+    /// Returns `true` if the response exists.
+    async fn touch(&self, key: Key, ttl: Duration) -> Result<bool, Self::Error>;
+
+    /// Atomically increments the counter stored under the key and returns its new value.
+    ///
+    /// A missing (or expired) counter starts from zero and expires after `ttl`,
+    /// subsequent increments do not extend its lifetime.
+    async fn incr_counter(&self, key: Key, ttl: Duration) -> Result<u64, Self::Error>;
+
     //
     // Provided implementation
     //
 
+    /// Returns `true` if the storage is connected and ready to serve requests
+    fn is_connected(&self) -> bool {
+        true
+    }
+
+    /// Returns latency statistics of the storage (if it tracks them)
+    fn stats(&self) -> Option<StorageStats> {
+        None
+    }
+
+    /// Forces (re)connection to the storage, e.g. if it was started in a disconnected state
+    async fn reconnect(&self) -> Result<(), Self::Error> {
+        self.connect().await
+    }
+
+    /// Stores a response returning details about the stored item.
+    ///
+    /// Backends splitting bodies into chunks override it to report the number of chunks.
+    async fn store_item(&self, item: Item<'_>) -> Result<StoredItem, Self::Error> {
+        let size = self.store_response(item).await?;
+        Ok(StoredItem {
+            size,
+            num_chunks: 1,
+            too_large: false,
+        })
+    }
+
+    /// Checks that a response exists in the storage without fetching it
+    async fn has_response(&self, key: Key) -> Result<bool, Self::Error> {
+        Ok(self.get_response(key).await?.is_some())
+    }
+
     async fn get_responses(
         &self,
         keys: impl IntoIterator<Item = Key>,
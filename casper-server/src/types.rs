@@ -1,11 +1,65 @@
+use std::collections::HashMap;
 use std::ops::Deref;
+use std::time::{Duration, SystemTime};
 
 use mlua::{IntoLua, Lua, Result as LuaResult, Table as LuaTable, Value};
+use ntex::http::header::HeaderValue;
+use ntex::http::Version;
+use opentelemetry::{Key as OTKey, Value as OTValue};
+
+use crate::storage::Key;
 
 // Value stored in response extensions to indicate that response is encrypted
 #[derive(Clone, Copy, Debug, Default)]
 pub struct EncryptedExt(pub bool);
 
+// Value stored in response extensions to indicate that response is for a `HEAD` request
+// (it never has a body and must not be cached as a full entry)
+#[derive(Clone, Copy, Debug)]
+pub struct HeadResponseExt;
+
+// Value stored in response extensions with TTL to store an error response with
+// (negative caching)
+#[derive(Clone, Copy, Debug)]
+pub struct NegativeTtlExt(pub Duration);
+
+// Value stored in request extensions to indicate that the (authenticated) request
+// must bypass the cache
+#[derive(Clone, Copy, Debug)]
+pub struct CacheBypassExt;
+
+// Value stored in response extensions with labels attached by Lua (used by logger)
+#[derive(Clone, Debug)]
+pub struct LabelsExt(pub HashMap<OTKey, OTValue>);
+
+// Value stored in response extensions with metadata of the stored (cached) item
+#[derive(Clone, Copy, Debug)]
+pub struct StoredMetaExt {
+    pub stored_at: SystemTime,
+    // `None` if the item never expires (or expiration time is unknown)
+    pub expires_at: Option<SystemTime>,
+    // Size of the original (uncompressed) body
+    pub body_size: usize,
+    // HTTP version of the original response (`None` for items stored without it)
+    pub version: Option<Version>,
+}
+
+// Value stored in response extensions with surrogate keys of the stored (cached) item
+#[derive(Clone, Debug)]
+pub struct SurrogateKeysExt(pub Vec<Key>);
+
+// Lua app data with the `Content-Type` for proxied and stored responses missing one
+#[derive(Clone, Debug)]
+pub(crate) struct DefaultContentType(pub(crate) HeaderValue);
+
+// Lua app data to enable `X-Cache-Age` and `X-Cache-Lifetime` headers on stored responses
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct CacheDebugHeaders;
+
+// Lua app data with the size of chunks assembled from streaming bodies read in Lua
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct BodyChunkSize(pub(crate) usize);
+
 #[derive(Clone, Debug)]
 pub(crate) struct LuaContext(pub(crate) LuaTable);
 
@@ -231,6 +231,22 @@ mod tests {
         assert_eq!(decrypted, data);
     }
 
+    #[ntex::test]
+    async fn test_decrypt_tampered() {
+        let key = Bytes::from_static(b"some key");
+
+        let encrypted = aes256_encrypt(Bytes::from_static(b"hello"), key.clone())
+            .await
+            .unwrap();
+        let mut tampered = encrypted.to_vec();
+        *tampered.last_mut().unwrap() ^= 1;
+        assert!(aes256_decrypt(tampered, key.clone()).await.is_err());
+
+        // Wrong key
+        let wrong_key = Bytes::from_static(b"another key");
+        assert!(aes256_decrypt(encrypted, wrong_key).await.is_err());
+    }
+
     #[ntex::test]
     async fn test_decrypt_stream() {
         let key = Bytes::from_static(b"some key");
@@ -0,0 +1,269 @@
+use serde_json::Value as JsonValue;
+
+#[derive(thiserror::Error, Debug)]
+pub enum JsonScanError {
+    #[error("invalid JSON object at byte {0}")]
+    Syntax(usize),
+    #[error("unexpected end of JSON object")]
+    Eof,
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum State {
+    Start,
+    KeyOrEnd,
+    Key,
+    InKey,
+    Colon,
+    ValueStart,
+    Value,
+    CommaOrEnd,
+    End,
+}
+
+/// Incremental scanner of a JSON object extracting only the requested top-level fields.
+///
+/// Values of other fields are skipped without buffering, so memory usage is bounded
+/// by the size of the requested values. Scanning stops as soon as all requested fields are found
+/// (the rest of the input is not validated).
+pub struct JsonFieldsScanner {
+    keys: Vec<String>,
+    values: Vec<Option<JsonValue>>,
+    found: usize,
+    state: State,
+    // Raw bytes of the current key or the captured value
+    buffer: Vec<u8>,
+    // Index of the requested key the current value is captured for
+    capture: Option<usize>,
+    depth: usize,
+    in_string: bool,
+    escaped: bool,
+    offset: usize,
+}
+
+impl JsonFieldsScanner {
+    pub fn new(keys: Vec<String>) -> Self {
+        let values = vec![None; keys.len()];
+        JsonFieldsScanner {
+            keys,
+            values,
+            found: 0,
+            state: State::Start,
+            buffer: Vec::new(),
+            capture: None,
+            depth: 0,
+            in_string: false,
+            escaped: false,
+            offset: 0,
+        }
+    }
+
+    /// Returns `true` if no more input is required
+    pub fn is_done(&self) -> bool {
+        self.state == State::End
+    }
+
+    /// Feeds the next chunk of input.
+    ///
+    /// Returns `true` if no more input is required.
+    pub fn feed(&mut self, data: &[u8]) -> Result<bool, JsonScanError> {
+        let mut i = 0;
+        while i < data.len() && self.state != State::End {
+            let b = data[i];
+            let pos = self.offset + i;
+            match self.state {
+                State::Start => match b {
+                    b'{' => self.state = State::KeyOrEnd,
+                    _ if b.is_ascii_whitespace() => {}
+                    _ => return Err(JsonScanError::Syntax(pos)),
+                },
+                State::KeyOrEnd | State::Key => match b {
+                    b'"' => {
+                        self.buffer.push(b);
+                        self.state = State::InKey;
+                    }
+                    b'}' if self.state == State::KeyOrEnd => self.state = State::End,
+                    _ if b.is_ascii_whitespace() => {}
+                    _ => return Err(JsonScanError::Syntax(pos)),
+                },
+                State::InKey => {
+                    self.buffer.push(b);
+                    if self.escaped {
+                        self.escaped = false;
+                    } else if b == b'\\' {
+                        self.escaped = true;
+                    } else if b == b'"' {
+                        let key: String = serde_json::from_slice(&self.buffer)?;
+                        self.buffer.clear();
+                        self.capture = self.keys.iter().position(|k| *k == key);
+                        self.state = State::Colon;
+                    }
+                }
+                State::Colon => match b {
+                    b':' => self.state = State::ValueStart,
+                    _ if b.is_ascii_whitespace() => {}
+                    _ => return Err(JsonScanError::Syntax(pos)),
+                },
+                State::ValueStart => match b {
+                    _ if b.is_ascii_whitespace() => {}
+                    b',' | b'}' | b']' | b':' => return Err(JsonScanError::Syntax(pos)),
+                    _ => {
+                        self.state = State::Value;
+                        // Process the first byte of the value again
+                        continue;
+                    }
+                },
+                State::Value => {
+                    if self.in_string {
+                        self.capture_byte(b);
+                        if self.escaped {
+                            self.escaped = false;
+                        } else if b == b'\\' {
+                            self.escaped = true;
+                        } else if b == b'"' {
+                            self.in_string = false;
+                            if self.depth == 0 {
+                                self.complete_value()?;
+                            }
+                        }
+                    } else {
+                        match b {
+                            b'"' => {
+                                self.capture_byte(b);
+                                self.in_string = true;
+                            }
+                            b'{' | b'[' => {
+                                self.capture_byte(b);
+                                self.depth += 1;
+                            }
+                            b'}' | b']' if self.depth > 0 => {
+                                self.capture_byte(b);
+                                self.depth -= 1;
+                                if self.depth == 0 {
+                                    self.complete_value()?;
+                                }
+                            }
+                            // End of a scalar value, process the delimiter again
+                            b',' | b'}' | b']' if self.depth == 0 => {
+                                self.complete_value()?;
+                                continue;
+                            }
+                            _ if b.is_ascii_whitespace() && self.depth == 0 => {
+                                self.complete_value()?;
+                            }
+                            _ => self.capture_byte(b),
+                        }
+                    }
+                }
+                State::CommaOrEnd => match b {
+                    b',' => self.state = State::Key,
+                    b'}' => self.state = State::End,
+                    _ if b.is_ascii_whitespace() => {}
+                    _ => return Err(JsonScanError::Syntax(pos)),
+                },
+                State::End => {}
+            }
+            i += 1;
+        }
+        self.offset += i;
+        Ok(self.is_done())
+    }
+
+    /// Finishes scanning and returns values of the requested fields (in the same order)
+    pub fn finish(self) -> Result<Vec<Option<JsonValue>>, JsonScanError> {
+        if !self.is_done() {
+            return Err(JsonScanError::Eof);
+        }
+        Ok(self.values)
+    }
+
+    #[inline]
+    fn capture_byte(&mut self, b: u8) {
+        if self.capture.is_some() {
+            self.buffer.push(b);
+        }
+    }
+
+    fn complete_value(&mut self) -> Result<(), JsonScanError> {
+        if let Some(i) = self.capture.take() {
+            let value = serde_json::from_slice(&self.buffer)?;
+            self.buffer.clear();
+            if self.values[i].replace(value).is_none() {
+                self.found += 1;
+            }
+        }
+        self.state = match self.found == self.keys.len() {
+            true => State::End,
+            false => State::CommaOrEnd,
+        };
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn scan(keys: &[&str], chunks: &[&str]) -> Result<Vec<Option<JsonValue>>, JsonScanError> {
+        let mut scanner = JsonFieldsScanner::new(keys.iter().map(|k| k.to_string()).collect());
+        for chunk in chunks {
+            if scanner.feed(chunk.as_bytes())? {
+                break;
+            }
+        }
+        scanner.finish()
+    }
+
+    #[test]
+    fn test_scan_fields() {
+        let data =
+            r#" { "a": {"x": [1, "]}", {}]}, "b\"c" : "s,}" , "n":-1.5e3,"t":true, "z":null } "#;
+
+        // Split input at every position
+        for i in 0..data.len() {
+            let (head, tail) = data.split_at(i);
+            let values = scan(&["n", "b\"c", "a", "t", "z", "missing"], &[head, tail]).unwrap();
+            assert_eq!(
+                values,
+                vec![
+                    Some(json!(-1500.0)),
+                    Some(json!("s,}")),
+                    Some(json!({"x": [1, "]}", {}]})),
+                    Some(json!(true)),
+                    Some(json!(null)),
+                    None,
+                ]
+            );
+        }
+
+        // Scanning stops when all fields are found
+        let values = scan(&["a"], &[r#"{"a": 1, "b": "#]).unwrap();
+        assert_eq!(values, vec![Some(json!(1))]);
+
+        assert_eq!(scan(&["a"], &["{}"]).unwrap(), vec![None]);
+    }
+
+    #[test]
+    fn test_scan_errors() {
+        assert!(matches!(
+            scan(&["a"], &["[1, 2]"]),
+            Err(JsonScanError::Syntax(0))
+        ));
+        assert!(matches!(
+            scan(&["a"], &[r#"{"b": 1"#]),
+            Err(JsonScanError::Eof)
+        ));
+        assert!(matches!(
+            scan(&["a"], &[r#"{"b": 1,}"#]),
+            Err(JsonScanError::Syntax(8))
+        ));
+        assert!(matches!(
+            scan(&["a"], &[r#"{"a": tru }"#]),
+            Err(JsonScanError::Json(_))
+        ));
+    }
+}
@@ -30,6 +30,8 @@ pub fn random_string(len: usize, charset: Option<&str>) -> String {
 }
 
 pub mod aes;
+pub mod crypto;
+pub mod json;
 pub mod zstd;
 
 #[cfg(test)]